@@ -4,8 +4,9 @@ use conduwuit::Result;
 use crate::{
 	appservice, appservice::AppserviceCommand, check, check::CheckCommand, command::Command,
 	debug, debug::DebugCommand, federation, federation::FederationCommand, media,
-	media::MediaCommand, query, query::QueryCommand, room, room::RoomCommand, server,
-	server::ServerCommand, user, user::UserCommand,
+	media::MediaCommand, notice, notice::NoticeCommand, policy, policy::PolicyCommand, query,
+	query::QueryCommand, report, report::ReportCommand, room, room::RoomCommand, server,
+	server::ServerCommand, token, token::TokenCommand, user, user::UserCommand,
 };
 
 #[derive(Debug, Parser)]
@@ -31,10 +32,26 @@ pub(super) enum AdminCommand {
 	/// - Commands for managing the server
 	Server(ServerCommand),
 
+	#[command(subcommand)]
+	/// - Commands for working through reports of events and rooms
+	Reports(ReportCommand),
+
+	#[command(subcommand)]
+	/// - Commands for managing the moderation policy lists the server enforces
+	Policies(PolicyCommand),
+
+	#[command(subcommand)]
+	/// - Commands for managing registration tokens
+	Tokens(TokenCommand),
+
 	#[command(subcommand)]
 	/// - Commands for managing media
 	Media(MediaCommand),
 
+	#[command(subcommand)]
+	/// - Commands for sending server notices to local users
+	Notices(NoticeCommand),
+
 	#[command(subcommand)]
 	/// - Commands for checking integrity
 	Check(CheckCommand),
@@ -55,10 +72,14 @@ pub(super) async fn process(command: AdminCommand, context: &Command<'_>) -> Res
 	match command {
 		| Appservices(command) => appservice::process(command, context).await?,
 		| Media(command) => media::process(command, context).await?,
+		| Notices(command) => notice::process(command, context).await?,
 		| Users(command) => user::process(command, context).await?,
 		| Rooms(command) => room::process(command, context).await?,
 		| Federation(command) => federation::process(command, context).await?,
 		| Server(command) => server::process(command, context).await?,
+		| Reports(command) => report::process(command, context).await?,
+		| Policies(command) => policy::process(command, context).await?,
+		| Tokens(command) => token::process(command, context).await?,
 		| Debug(command) => debug::process(command, context).await?,
 		| Query(command) => query::process(command, context).await?,
 		| Check(command) => check::process(command, context).await?,
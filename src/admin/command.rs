@@ -7,13 +7,14 @@ use futures::{
 	lock::Mutex,
 	Future, FutureExt,
 };
-use ruma::EventId;
+use ruma::{EventId, UserId};
 
 pub(crate) struct Command<'a> {
 	pub(crate) services: &'a Services,
 	pub(crate) body: &'a [&'a str],
 	pub(crate) timer: SystemTime,
 	pub(crate) reply_id: Option<&'a EventId>,
+	pub(crate) sender: Option<&'a UserId>,
 	pub(crate) output: Mutex<BufWriter<Vec<u8>>>,
 }
 
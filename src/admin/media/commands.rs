@@ -1,7 +1,9 @@
-use std::time::Duration;
+use std::{fmt::Write, time::Duration};
 
 use conduwuit::{
-	debug, debug_info, debug_warn, error, info, trace, utils::time::parse_timepoint_ago, Result,
+	debug, debug_info, debug_warn, error, info, trace,
+	utils::{bytes, math::usize_from_u64_truncated, time::parse_timepoint_ago},
+	Result,
 };
 use conduwuit_service::media::Dim;
 use ruma::{
@@ -338,6 +340,68 @@ pub(super) async fn delete_all_from_server(
 	)))
 }
 
+#[admin_command]
+pub(super) async fn quarantine(&self, mxc: OwnedMxcUri) -> Result<RoomMessageEventContent> {
+	let mxc: Mxc<'_> = mxc.as_str().try_into()?;
+	self.services
+		.media
+		.quarantine(&mxc, &self.services.globals.server_user);
+
+	Ok(RoomMessageEventContent::text_plain(format!("Quarantined {mxc}.")))
+}
+
+#[admin_command]
+pub(super) async fn unquarantine(&self, mxc: OwnedMxcUri) -> Result<RoomMessageEventContent> {
+	let mxc: Mxc<'_> = mxc.as_str().try_into()?;
+	if !self.services.media.is_quarantined(&mxc).await {
+		return Ok(RoomMessageEventContent::text_plain(format!("{mxc} is not quarantined.")));
+	}
+
+	self.services.media.unquarantine(&mxc);
+
+	Ok(RoomMessageEventContent::text_plain(format!("Released {mxc} from quarantine.")))
+}
+
+#[admin_command]
+pub(super) async fn list_user_uploads(
+	&self,
+	username: String,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &username)?;
+	let uploads = self.services.media.user_uploads(&user_id).await;
+
+	let mut total: u64 = 0;
+	let mut list = String::new();
+	for (mxc, size) in &uploads {
+		let quarantined = match Mxc::try_from(mxc.as_str()) {
+			| Ok(mxc) => self.services.media.is_quarantined(&mxc).await,
+			| Err(_) => false,
+		};
+
+		let size = size.map_or_else(
+			|| "unknown size".to_owned(),
+			|size| {
+				total = total.saturating_add(size);
+				bytes::pretty(usize_from_u64_truncated(size))
+			},
+		);
+
+		let quarantined = if quarantined { " (quarantined)" } else { "" };
+		writeln!(list, "{mxc}: {size}{quarantined}")?;
+	}
+
+	let quota = self.services.server.config.media_user_quota.map_or_else(
+		|| "no quota".to_owned(),
+		|quota| format!("quota {}", bytes::pretty(usize_from_u64_truncated(quota))),
+	);
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"{} uploads by {user_id} totalling {} ({quota}):\n```\n{list}```",
+		uploads.len(),
+		bytes::pretty(usize_from_u64_truncated(total)),
+	)))
+}
+
 #[admin_command]
 pub(super) async fn get_file_info(&self, mxc: OwnedMxcUri) -> Result<RoomMessageEventContent> {
 	let mxc: Mxc<'_> = mxc.as_str().try_into()?;
@@ -394,3 +458,28 @@ pub(super) async fn get_remote_thumbnail(
 	let out = format!("```\n{result:#?}\nreceived {len} bytes for file content.\n```");
 	Ok(RoomMessageEventContent::notice_markdown(out))
 }
+
+#[admin_command]
+pub(super) async fn migrate_storage(
+	&self,
+	from: String,
+	to: String,
+	delete_source: bool,
+) -> Result<RoomMessageEventContent> {
+	if from == to {
+		return Ok(RoomMessageEventContent::text_plain(
+			"The source and target providers must differ.",
+		));
+	}
+
+	let (copied, failed) = self
+		.services
+		.media
+		.migrate_storage(&from, &to, delete_source)
+		.await?;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Copied {copied} media files from {from} to {to}, {failed} failed. Check the logs for \
+		 failures before changing media_storage_provider to {to:?}."
+	)))
+}
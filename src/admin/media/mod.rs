@@ -63,6 +63,26 @@ pub(super) enum MediaCommand {
 		yes_i_want_to_delete_local_media: bool,
 	},
 
+	/// - Quarantines media so it is no longer served to clients or over
+	///   federation, keeping its files for later inspection. Remote media is
+	///   not fetched again either.
+	Quarantine {
+		/// The MXC URL to quarantine
+		mxc: OwnedMxcUri,
+	},
+
+	/// - Lifts the quarantine of media
+	Unquarantine {
+		/// The MXC URL to release
+		mxc: OwnedMxcUri,
+	},
+
+	/// - Lists the media uploaded by a local user with their sizes and the
+	///   total against `media_user_quota`
+	ListUserUploads {
+		username: String,
+	},
+
 	GetFileInfo {
 		/// The MXC URL to lookup info for.
 		mxc: OwnedMxcUri,
@@ -95,4 +115,19 @@ pub(super) enum MediaCommand {
 		#[arg(short, long, default_value("800"))]
 		height: u32,
 	},
+
+	/// - Copies all media files from one storage provider ("filesystem" or
+	///   "s3") to another, e.g. before changing `media_storage_provider`. Both
+	///   must be configured.
+	MigrateStorage {
+		/// The provider to copy from
+		from: String,
+
+		/// The provider to copy to
+		to: String,
+
+		/// Delete each file from the source provider once it was copied
+		#[arg(long)]
+		delete_source: bool,
+	},
 }
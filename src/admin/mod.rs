@@ -14,9 +14,13 @@ pub(crate) mod check;
 pub(crate) mod debug;
 pub(crate) mod federation;
 pub(crate) mod media;
+pub(crate) mod notice;
+pub(crate) mod policy;
 pub(crate) mod query;
+pub(crate) mod report;
 pub(crate) mod room;
 pub(crate) mod server;
+pub(crate) mod token;
 pub(crate) mod user;
 
 extern crate conduwuit_api as api;
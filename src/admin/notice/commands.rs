@@ -0,0 +1,124 @@
+use std::fmt::Write;
+
+use conduwuit::{utils::ReadyExt, warn, Err, Result};
+use futures::StreamExt;
+use ruma::{events::room::message::RoomMessageEventContent, OwnedUserId};
+
+use crate::{admin_command, utils::parse_active_local_user_id};
+
+#[admin_command]
+pub(super) async fn send(
+	&self,
+	user_id: String,
+	message: Vec<String>,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_active_local_user_id(self.services, &user_id).await?;
+	let content = notice_content(&message)?;
+	let event_id = self
+		.services
+		.server_notices
+		.send(&user_id, &content)
+		.await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Sent notice {event_id} to {user_id}."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn send_many(&self, message: Vec<String>) -> Result<RoomMessageEventContent> {
+	if self.body.len() < 2
+		|| !self.body[0].trim().starts_with("```")
+		|| self.body.last().unwrap_or(&"").trim() != "```"
+	{
+		return Err!("Expected code block in command body. Add --help for details.");
+	}
+
+	let content = notice_content(&message)?;
+	let mut user_ids = Vec::new();
+	for username in &self.body[1..self.body.len().saturating_sub(1)] {
+		let username = username.trim();
+		if username.is_empty() {
+			continue;
+		}
+
+		user_ids.push(parse_active_local_user_id(self.services, username).await?);
+	}
+
+	self.send_notices(user_ids, &content).await
+}
+
+#[admin_command]
+pub(super) async fn send_all(&self, message: Vec<String>) -> Result<RoomMessageEventContent> {
+	let content = notice_content(&message)?;
+	let notices = &self.services.server_notices;
+	let user_ids: Vec<OwnedUserId> = self
+		.services
+		.users
+		.list_local_users()
+		.ready_filter(|user_id| notices.can_receive(user_id))
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	self.send_notices(user_ids, &content).await
+}
+
+#[admin_command]
+pub(super) async fn usage_limit(
+	&self,
+	user_id: String,
+	limit_type: String,
+	message: Vec<String>,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_active_local_user_id(self.services, &user_id).await?;
+	let body = message.join(" ");
+	if body.is_empty() {
+		return Err!("The notice needs a message.");
+	}
+
+	let event_id = self
+		.services
+		.server_notices
+		.send_usage_limit(&user_id, &limit_type, body)
+		.await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Sent usage limit notice {event_id} to {user_id}."
+	)))
+}
+
+/// Sends the notice to each user, carrying on past failures.
+#[admin_command]
+async fn send_notices(
+	&self,
+	user_ids: Vec<OwnedUserId>,
+	content: &RoomMessageEventContent,
+) -> Result<RoomMessageEventContent> {
+	let (mut sent, mut failures) = (0_usize, String::new());
+	for user_id in user_ids {
+		match self.services.server_notices.send(&user_id, content).await {
+			| Ok(_) => sent = sent.saturating_add(1),
+			| Err(e) => {
+				warn!("Failed to send server notice to {user_id}: {e}");
+				writeln!(failures, "- {user_id}: {e}")?;
+			},
+		}
+	}
+
+	let mut reply = format!("Sent the notice to {sent} users.");
+	if !failures.is_empty() {
+		write!(reply, " Failed for:\n{failures}")?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(reply))
+}
+
+fn notice_content(message: &[String]) -> Result<RoomMessageEventContent> {
+	let message = message.join(" ");
+	if message.is_empty() {
+		return Err!("The notice needs a message.");
+	}
+
+	Ok(RoomMessageEventContent::text_markdown(message))
+}
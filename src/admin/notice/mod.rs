@@ -0,0 +1,46 @@
+mod commands;
+
+use clap::Subcommand;
+use conduwuit::Result;
+
+use crate::admin_command_dispatch;
+
+#[admin_command_dispatch]
+#[derive(Debug, Subcommand)]
+pub(super) enum NoticeCommand {
+	/// - Sends a server notice to a local user, in their notices room
+	Send {
+		user_id: String,
+
+		/// Message of the notice, which may use Markdown
+		message: Vec<String>,
+	},
+
+	/// - Sends a server notice to several local users
+	///
+	/// This command needs a newline separated list of users provided in a
+	/// Markdown code block below the command.
+	SendMany {
+		/// Message of the notice, which may use Markdown
+		message: Vec<String>,
+	},
+
+	/// - Sends a server notice to every active local user
+	SendAll {
+		/// Message of the notice, which may use Markdown
+		message: Vec<String>,
+	},
+
+	/// - Tells a local user they reached a usage limit, with an
+	///   `m.server_notice.usage_limit_reached` notice clients may act on
+	UsageLimit {
+		user_id: String,
+
+		/// Kind of limit which was reached
+		#[arg(long, default_value = "monthly_active_user")]
+		limit_type: String,
+
+		/// Message of the notice
+		message: Vec<String>,
+	},
+}
@@ -0,0 +1,114 @@
+use std::fmt::Write;
+
+use api::client::join_room_by_id_helper;
+use conduwuit::{info, Err, Result};
+use futures::StreamExt;
+use ruma::{events::room::message::RoomMessageEventContent, OwnedRoomId, OwnedRoomOrAliasId};
+
+use crate::admin_command;
+
+#[admin_command]
+pub(super) async fn list(&self) -> Result<RoomMessageEventContent> {
+	let policy_lists = &self.services.policy_lists;
+	let rooms: Vec<OwnedRoomId> = policy_lists.rooms().map(ToOwned::to_owned).collect().await;
+
+	if rooms.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("Not subscribed to any policy rooms."));
+	}
+
+	let mut list = String::new();
+	for room_id in &rooms {
+		writeln!(list, "- {room_id}: {} ban rules", policy_lists.count(room_id))?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Subscribed to {} policy rooms:\n{list}",
+		rooms.len()
+	)))
+}
+
+#[admin_command]
+pub(super) async fn subscribe(
+	&self,
+	room: OwnedRoomOrAliasId,
+) -> Result<RoomMessageEventContent> {
+	let services = self.services;
+	let (room_id, servers) = services
+		.rooms
+		.alias
+		.resolve_with_servers(&room, None)
+		.await?;
+
+	if services.policy_lists.is_subscribed(&room_id).await {
+		return Err!("Already subscribed to {room_id}.");
+	}
+
+	if !services
+		.rooms
+		.state_cache
+		.server_in_room(services.globals.server_name(), &room_id)
+		.await
+	{
+		join_room_by_id_helper(
+			services,
+			&services.globals.server_user,
+			&room_id,
+			Some("Subscribing to this policy room".to_owned()),
+			&servers,
+			None,
+			&None,
+		)
+		.await?;
+	}
+
+	services.policy_lists.subscribe(&room_id).await;
+	info!("Subscribed to policy room {room_id}");
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Subscribed to {room_id}, enforcing its {} ban rules.",
+		services.policy_lists.count(&room_id)
+	)))
+}
+
+#[admin_command]
+pub(super) async fn unsubscribe(
+	&self,
+	room: OwnedRoomOrAliasId,
+) -> Result<RoomMessageEventContent> {
+	let room_id = self.services.rooms.alias.resolve(&room).await?;
+	if !self.services.policy_lists.is_subscribed(&room_id).await {
+		return Err!("Not subscribed to {room_id}.");
+	}
+
+	self.services.policy_lists.unsubscribe(&room_id);
+	info!("Unsubscribed from policy room {room_id}");
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Unsubscribed from {room_id}."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn check(&self, entity: String) -> Result<RoomMessageEventContent> {
+	let rules = self.services.policy_lists.rules_matching(&entity);
+	if rules.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain(format!("No ban rules match {entity}.")));
+	}
+
+	let mut list = String::new();
+	for rule in &rules {
+		writeln!(
+			list,
+			"- {:?} `{}` in {}: {}",
+			rule.kind,
+			rule.entity,
+			rule.policy_room,
+			rule.reason.as_deref().unwrap_or("no reason given"),
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"{} ban rules match {entity}:\n{list}",
+		rules.len()
+	)))
+}
@@ -0,0 +1,32 @@
+mod commands;
+
+use clap::Subcommand;
+use conduwuit::Result;
+use ruma::OwnedRoomOrAliasId;
+
+use crate::admin_command_dispatch;
+
+#[admin_command_dispatch]
+#[derive(Debug, Subcommand)]
+pub(super) enum PolicyCommand {
+	/// - Lists the policy rooms the server is subscribed to
+	List,
+
+	/// - Subscribes the server to a policy room, enforcing its ban rules on
+	///   users, rooms and servers
+	///
+	/// The server user joins the room if no local user is in it.
+	Subscribe {
+		room: OwnedRoomOrAliasId,
+	},
+
+	/// - Unsubscribes the server from a policy room, lifting its bans
+	Unsubscribe {
+		room: OwnedRoomOrAliasId,
+	},
+
+	/// - Shows the ban rules matching a user ID, room ID or server name
+	Check {
+		entity: String,
+	},
+}
@@ -0,0 +1,370 @@
+use std::{
+	fmt::Write,
+	time::{Duration, UNIX_EPOCH},
+};
+
+use conduwuit::{err, info, pdu::PduBuilder, utils, utils::ReadyExt, Err, Result};
+use futures::StreamExt;
+use ruma::{
+	events::{
+		room::{
+			member::{MembershipState, RoomMemberEventContent},
+			message::RoomMessageEventContent,
+			power_levels::{RoomPowerLevels, RoomPowerLevelsEventContent},
+			redaction::RoomRedactionEventContent,
+		},
+		StateEventType,
+	},
+	CanonicalJsonValue, Mxc, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, RoomOrAliasId,
+	UserId,
+};
+use service::{
+	reports::{Report, Status, Target},
+	Services,
+};
+
+use crate::{admin_command, utils::parse_local_user_id};
+
+#[admin_command]
+pub(super) async fn list(&self, all: bool) -> Result<RoomMessageEventContent> {
+	let reports: Vec<(u64, Report)> = self
+		.services
+		.reports
+		.stream()
+		.ready_filter(|(_, report)| all || !report.is_resolved())
+		.collect()
+		.await;
+
+	if reports.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No reports."));
+	}
+
+	let mut list = String::new();
+	for (id, report) in &reports {
+		let score = report
+			.score()
+			.map_or_else(String::new, |score| format!(", score {score}"));
+
+		writeln!(
+			list,
+			"- {id} ({}): {}; {} reports{score}",
+			describe_status(&report.status),
+			describe_target(&report.target),
+			report.submissions.len(),
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"{} reports:\n{list}",
+		reports.len()
+	)))
+}
+
+#[admin_command]
+pub(super) async fn show(&self, id: u64) -> Result<RoomMessageEventContent> {
+	let report = self.services.reports.get(id).await?;
+
+	let mut out = format!(
+		"Report {id} of {}, {}. First reported {}.\n\n",
+		describe_target(&report.target),
+		describe_status(&report.status),
+		format_time(report.created_at),
+	);
+
+	for submission in &report.submissions {
+		let score = submission
+			.score
+			.map_or_else(String::new, |score| format!(" (score {score})"));
+
+		writeln!(
+			out,
+			"- {} at {}{score}: {}",
+			submission.reporter,
+			format_time(submission.reported_at),
+			submission.reason.as_deref().unwrap_or("no reason given"),
+		)?;
+	}
+
+	if let Target::Event { event_id, .. } = &report.target {
+		match self.services.rooms.timeline.get_pdu_json(event_id).await {
+			| Ok(json) => {
+				let json = serde_json::to_string_pretty(&json)?;
+				write!(out, "\nReported event:\n```json\n{json}\n```")?;
+			},
+			| Err(_) => write!(out, "\nThe reported event is no longer stored.")?,
+		}
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+pub(super) async fn claim(
+	&self,
+	id: u64,
+	user_id: Option<String>,
+) -> Result<RoomMessageEventContent> {
+	let user_id = match (user_id, self.sender) {
+		| (Some(user_id), _) => parse_local_user_id(self.services, &user_id)?,
+		| (None, Some(sender)) => sender.to_owned(),
+		| (None, None) => return Err!("Give the admin to assign the report to."),
+	};
+
+	self.services.reports.claim(id, &user_id).await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Report {id} is assigned to {user_id}."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn resolve(
+	&self,
+	id: u64,
+	note: Option<String>,
+) -> Result<RoomMessageEventContent> {
+	self.services.reports.resolve(id, self.sender, note).await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!("Report {id} is resolved.")))
+}
+
+#[admin_command]
+pub(super) async fn redact(
+	&self,
+	id: u64,
+	reason: Option<String>,
+) -> Result<RoomMessageEventContent> {
+	let (room_id, event_id, sender) = self.reported_event(id).await?;
+	let services = self.services;
+	let redactor = if services.globals.user_is_local(&sender) {
+		sender
+	} else {
+		moderator(services, &room_id, |power_levels, user_id| {
+			power_levels.user_can_redact_event_of_other(user_id)
+		})
+		.await?
+	};
+
+	let reason = reason.unwrap_or_else(|| {
+		format!("Redacted by the administrators of {}.", services.globals.server_name())
+	});
+
+	let state_lock = services.rooms.state.mutex.lock(&room_id).await;
+	let redaction_id = services
+		.rooms
+		.timeline
+		.build_and_append_pdu(
+			PduBuilder {
+				redacts: Some(event_id.clone()),
+				..PduBuilder::timeline(&RoomRedactionEventContent {
+					redacts: Some(event_id.clone()),
+					reason: Some(reason),
+				})
+			},
+			&redactor,
+			&room_id,
+			&state_lock,
+		)
+		.await?;
+
+	info!("Redacted {event_id} from report {id} as {redactor}");
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Redacted {event_id} as {redactor} with {redaction_id}."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn ban(
+	&self,
+	id: u64,
+	reason: Option<String>,
+) -> Result<RoomMessageEventContent> {
+	let (room_id, sender) = match self.services.reports.get(id).await?.target {
+		| Target::Event { room_id, sender, .. } => (room_id, sender),
+		| Target::Room { room_id } => {
+			let room = room_id.as_str().parse::<Box<RoomOrAliasId>>()?;
+			return self.ban_room(false, false, room).await;
+		},
+	};
+
+	let services = self.services;
+	let banner = moderator(services, &room_id, |power_levels, user_id| {
+		power_levels.user_can_ban(user_id)
+			&& power_levels.for_user(user_id) > power_levels.for_user(&sender)
+	})
+	.await?;
+
+	let state_lock = services.rooms.state.mutex.lock(&room_id).await;
+	let current = services
+		.rooms
+		.state_accessor
+		.get_member(&room_id, &sender)
+		.await
+		.unwrap_or_else(|_| RoomMemberEventContent::new(MembershipState::Ban));
+
+	services
+		.rooms
+		.timeline
+		.build_and_append_pdu(
+			PduBuilder::state(sender.to_string(), &RoomMemberEventContent {
+				membership: MembershipState::Ban,
+				reason,
+				displayname: None,
+				avatar_url: None,
+				is_direct: None,
+				join_authorized_via_users_server: None,
+				third_party_invite: None,
+				..current
+			}),
+			&banner,
+			&room_id,
+			&state_lock,
+		)
+		.await?;
+
+	info!("Banned {sender} from {room_id} from report {id} as {banner}");
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Banned {sender} from {room_id} as {banner}."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn shadow_ban(&self, id: u64) -> Result<RoomMessageEventContent> {
+	let (_, _, sender) = self.reported_event(id).await?;
+	if !self.services.globals.user_is_local(&sender) {
+		return Err!("{sender} is not a local user.");
+	}
+
+	if self.services.users.is_admin(&sender).await {
+		return Err!("{sender} is an admin.");
+	}
+
+	self.services.users.set_shadow_banned(&sender, true);
+	info!("Shadow-banned {sender} from report {id}");
+
+	Ok(RoomMessageEventContent::notice_markdown(format!("Shadow-banned {sender}.")))
+}
+
+#[admin_command]
+pub(super) async fn quarantine_media(&self, id: u64) -> Result<RoomMessageEventContent> {
+	let (_, event_id, _) = self.reported_event(id).await?;
+	let Ok(json) = self.services.rooms.timeline.get_pdu_json(&event_id).await else {
+		return Err!("The reported event is no longer stored.");
+	};
+
+	let mut urls = Vec::new();
+	if let Some(content) = json.get("content") {
+		media_urls(content, &mut urls);
+	}
+
+	let by = self.sender.unwrap_or(&*self.services.globals.server_user);
+	let mut quarantined = Vec::with_capacity(urls.len());
+	for url in urls {
+		if let Ok(mxc) = Mxc::try_from(url.as_str()) {
+			self.services.media.quarantine(&mxc, by);
+			quarantined.push(url);
+		}
+	}
+
+	if quarantined.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("The reported event has no media."));
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Quarantined {}.",
+		quarantined.join(", ")
+	)))
+}
+
+/// The room, ID and sender of the event a report is about.
+#[admin_command]
+async fn reported_event(&self, id: u64) -> Result<(OwnedRoomId, OwnedEventId, OwnedUserId)> {
+	match self.services.reports.get(id).await?.target {
+		| Target::Event { room_id, event_id, sender } => Ok((room_id, event_id, sender)),
+		| Target::Room { .. } => Err!("Report {id} is about a room, not an event."),
+	}
+}
+
+/// A local member of the room whom the power levels allow to act.
+async fn moderator<F>(services: &Services, room_id: &RoomId, allowed: F) -> Result<OwnedUserId>
+where
+	F: Fn(&RoomPowerLevels, &UserId) -> bool + Send,
+{
+	let power_levels: RoomPowerLevels = services
+		.rooms
+		.state_accessor
+		.room_state_get_content::<RoomPowerLevelsEventContent>(
+			room_id,
+			&StateEventType::RoomPowerLevels,
+			"",
+		)
+		.await?
+		.into();
+
+	let members: Vec<OwnedUserId> = services
+		.rooms
+		.state_cache
+		.local_users_in_room(room_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	members
+		.into_iter()
+		.filter(|user_id| allowed(&power_levels, user_id))
+		.max_by_key(|user_id| power_levels.for_user(user_id))
+		.ok_or_else(|| err!("No local user in {room_id} is allowed to do this."))
+}
+
+/// MXC URLs in the `url` and `thumbnail_url` fields of event content, at any
+/// depth to cover `info` and encrypted `file` objects.
+fn media_urls(value: &CanonicalJsonValue, urls: &mut Vec<String>) {
+	let CanonicalJsonValue::Object(object) = value else {
+		return;
+	};
+
+	for (key, value) in object {
+		match value {
+			| CanonicalJsonValue::String(url)
+				if (key == "url" || key == "thumbnail_url") && url.starts_with("mxc://") =>
+				urls.push(url.clone()),
+			| CanonicalJsonValue::Object(_) => media_urls(value, urls),
+			| _ => {},
+		}
+	}
+}
+
+fn describe_target(target: &Target) -> String {
+	match target {
+		| Target::Event { room_id, event_id, sender } =>
+			format!("event {event_id} by {sender} in {room_id}"),
+		| Target::Room { room_id } => format!("room {room_id}"),
+	}
+}
+
+fn describe_status(status: &Status) -> String {
+	match status {
+		| Status::Open => "open".to_owned(),
+		| Status::Assigned { to } => format!("claimed by {to}"),
+		| Status::Resolved { by, note, resolved_at } => {
+			let by = by
+				.as_ref()
+				.map_or_else(String::new, |by| format!(" by {by}"));
+			let note = note
+				.as_ref()
+				.map_or_else(String::new, |note| format!(": {note}"));
+
+			format!("resolved{by} at {}{note}", format_time(*resolved_at))
+		},
+	}
+}
+
+fn format_time(millis: u64) -> String {
+	let time = UNIX_EPOCH
+		.checked_add(Duration::from_millis(millis))
+		.unwrap_or(UNIX_EPOCH);
+
+	utils::time::format(time, "%Y-%m-%d %H:%M:%S UTC")
+}
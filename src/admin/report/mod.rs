@@ -0,0 +1,71 @@
+mod commands;
+
+use clap::Subcommand;
+use conduwuit::Result;
+
+use crate::admin_command_dispatch;
+
+#[admin_command_dispatch]
+#[derive(Debug, Subcommand)]
+pub(super) enum ReportCommand {
+	/// - Lists the reports which are open or claimed, newest first
+	List {
+		/// Also list resolved reports
+		#[arg(long)]
+		all: bool,
+	},
+
+	/// - Shows a report with every submission and the reported event
+	Show {
+		id: u64,
+	},
+
+	/// - Assigns a report to an admin; yourself if no user is given
+	Claim {
+		id: u64,
+
+		/// Admin to assign the report to
+		user_id: Option<String>,
+	},
+
+	/// - Resolves a report, closing it
+	Resolve {
+		id: u64,
+
+		/// What was done about the report
+		#[arg(long)]
+		note: Option<String>,
+	},
+
+	/// - Redacts the reported event, as its sender if they are local or else as
+	///   a local user allowed to redact it
+	Redact {
+		id: u64,
+
+		#[arg(long)]
+		reason: Option<String>,
+	},
+
+	/// - Bans the sender of the reported event from its room, as a local user
+	///   allowed to ban them. For a reported room, the room is banned as with
+	///   `rooms moderation ban-room`.
+	Ban {
+		id: u64,
+
+		#[arg(long)]
+		reason: Option<String>,
+	},
+
+	/// - Shadow-bans the local sender of the reported event: what they send
+	///   from then on is dropped without them being told
+	///
+	/// Lift it with `users unshadow-ban`.
+	ShadowBan {
+		id: u64,
+	},
+
+	/// - Quarantines the media the reported event refers to
+	QuarantineMedia {
+		id: u64,
+	},
+}
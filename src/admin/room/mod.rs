@@ -3,6 +3,7 @@ mod commands;
 mod directory;
 mod info;
 mod moderation;
+mod version;
 
 use clap::Subcommand;
 use conduwuit::Result;
@@ -10,7 +11,7 @@ use ruma::OwnedRoomId;
 
 use self::{
 	alias::RoomAliasCommand, directory::RoomDirectoryCommand, info::RoomInfoCommand,
-	moderation::RoomModerationCommand,
+	moderation::RoomModerationCommand, version::RoomVersionCommand,
 };
 use crate::admin_command_dispatch;
 
@@ -52,6 +53,10 @@ pub(super) enum RoomCommand {
 	/// - Manage the room directory
 	Directory(RoomDirectoryCommand),
 
+	#[command(subcommand)]
+	/// - Find and upgrade rooms on room versions flagged for upgrade
+	Version(RoomVersionCommand),
+
 	/// - Check if we know about a room
 	Exists {
 		room_id: OwnedRoomId,
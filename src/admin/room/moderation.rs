@@ -67,6 +67,22 @@ pub(crate) enum RoomModerationCommand {
 		room: Box<RoomOrAliasId>,
 	},
 
+	/// - Purges every trace of a room from the database to reclaim disk space
+	///
+	/// All local users are made to leave the room first. The room is banned
+	/// and incoming federation with it disabled so it is not fetched again.
+	/// This can not be undone.
+	///
+	/// Requires the `--yes-i-want-to-do-this` flag.
+	PurgeRoom {
+		/// The room in the format of `!roomid:example.com` or a room alias in
+		/// the format of `#roomalias:example.com`
+		room: Box<RoomOrAliasId>,
+
+		#[arg(long)]
+		yes_i_want_to_do_this: bool,
+	},
+
 	/// - List of all rooms we have banned
 	ListBannedRooms {
 		#[arg(long)]
@@ -77,7 +93,7 @@ pub(crate) enum RoomModerationCommand {
 }
 
 #[admin_command]
-async fn ban_room(
+pub(crate) async fn ban_room(
 	&self,
 	force: bool,
 	disable_federation: bool,
@@ -596,6 +612,67 @@ async fn unban_room(
 	))
 }
 
+#[admin_command]
+async fn purge_room(
+	&self,
+	room: Box<RoomOrAliasId>,
+	yes_i_want_to_do_this: bool,
+) -> Result<RoomMessageEventContent> {
+	if !yes_i_want_to_do_this {
+		return Ok(RoomMessageEventContent::notice_markdown(
+			"You must pass the --yes-i-want-to-do-this flag to ensure you really want to purge \
+			 the room. This can not be undone.",
+		));
+	}
+
+	let room_id = self.services.rooms.alias.resolve(&room).await?;
+	if self.services.admin.is_admin_room(&room_id).await {
+		return Ok(RoomMessageEventContent::text_plain("Not allowed to purge the admin room."));
+	}
+
+	self.services.rooms.metadata.ban_room(&room_id, true);
+	self.services.rooms.metadata.disable_room(&room_id, true);
+
+	let local_users: Vec<_> = self
+		.services
+		.rooms
+		.state_cache
+		.room_members(&room_id)
+		.ready_filter(|user| self.services.globals.user_is_local(user))
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	for local_user in &local_users {
+		debug!("Attempting leave for user {local_user} in room {room_id} before purging");
+		if let Err(e) = leave_room(self.services, local_user, &room_id, None).await {
+			warn!(%e, "Failed to leave room");
+		}
+	}
+
+	self.services
+		.admin
+		.send_text(&format!("Purging room {room_id}..."))
+		.await;
+
+	let services = self.services;
+	let total = services
+		.rooms
+		.purge
+		.purge_room(&room_id, |phase, removed| async move {
+			services
+				.admin
+				.send_text(&format!("Purged {phase}: {removed} rows removed."))
+				.await;
+		})
+		.await?;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Purged room {room_id}, {total} rows removed in total. The room stays banned; database \
+		 compaction reclaims the disk space over time."
+	)))
+}
+
 #[admin_command]
 async fn list_banned_rooms(&self, no_details: bool) -> Result<RoomMessageEventContent> {
 	let room_ids: Vec<OwnedRoomId> = self
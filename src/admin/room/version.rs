@@ -0,0 +1,268 @@
+use std::fmt::Write;
+
+use api::client::upgrade_room;
+use clap::Subcommand;
+use conduwuit::{info, warn, Err, Result};
+use futures::StreamExt;
+use ruma::{
+	events::{room::message::RoomMessageEventContent, StateEventType},
+	OwnedRoomId, OwnedUserId, RoomVersionId,
+};
+use service::Services;
+
+use crate::{admin_command, admin_command_dispatch};
+
+#[admin_command_dispatch]
+#[derive(Debug, Subcommand)]
+pub(crate) enum RoomVersionCommand {
+	/// - List the rooms local users are in whose room version is flagged for
+	///   upgrade, with the reason and the local users who may upgrade them
+	Outdated,
+
+	/// - Upgrade the rooms listed by `outdated` on behalf of a local user who
+	///   may upgrade each. Lists what would be done unless `--execute` is
+	///   given.
+	UpgradeOutdated {
+		/// Version to upgrade to; the version recommended to the upgrader by
+		/// the room version policies if not given
+		#[arg(long)]
+		version: Option<RoomVersionId>,
+
+		/// Perform the upgrades
+		#[arg(long)]
+		execute: bool,
+	},
+
+	/// - Send a server notice to the local users who may upgrade each room
+	///   listed by `outdated`, asking them to upgrade it
+	WarnOutdated,
+}
+
+/// A local room on a room version flagged for upgrade by the policy of its
+/// creator.
+struct Outdated {
+	room_id: OwnedRoomId,
+	version: RoomVersionId,
+	reason: &'static str,
+
+	/// Local members who may send the tombstone
+	upgraders: Vec<OwnedUserId>,
+}
+
+#[admin_command]
+async fn outdated(&self) -> Result<RoomMessageEventContent> {
+	let rooms = outdated_rooms(self.services).await;
+	if rooms.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No rooms are flagged for upgrade."));
+	}
+
+	let mut list = String::new();
+	for room in &rooms {
+		let upgraders = if room.upgraders.is_empty() {
+			"none".to_owned()
+		} else {
+			room.upgraders
+				.iter()
+				.map(ToString::to_string)
+				.collect::<Vec<_>>()
+				.join(", ")
+		};
+
+		writeln!(
+			list,
+			"- {} (version {}: {}); may upgrade: {upgraders}",
+			room.room_id, room.version, room.reason
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"{} rooms are flagged for upgrade:\n{list}",
+		rooms.len()
+	)))
+}
+
+#[admin_command]
+async fn upgrade_outdated(
+	&self,
+	version: Option<RoomVersionId>,
+	execute: bool,
+) -> Result<RoomMessageEventContent> {
+	let server = &self.services.server;
+	if let Some(version) = &version {
+		check_target(self.services, version)?;
+	}
+
+	let (mut upgraded, mut failed, mut list) = (0_usize, 0_usize, String::new());
+	for room in outdated_rooms(self.services).await {
+		let room_id = &room.room_id;
+		let Some(user_id) = room.upgraders.first() else {
+			writeln!(list, "- {room_id}: skipped, no local user may upgrade it")?;
+			continue;
+		};
+
+		let Some(version) = version
+			.clone()
+			.or_else(|| server.recommended_room_version(user_id))
+		else {
+			writeln!(list, "- {room_id}: skipped, no room version is recommended to {user_id}")?;
+			continue;
+		};
+
+		if let Some(reason) = server.room_version_deprecation(&version, user_id) {
+			writeln!(
+				list,
+				"- {room_id}: skipped, version {version} is flagged for upgrade for {user_id}: \
+				 {reason}"
+			)?;
+			continue;
+		}
+
+		if !execute {
+			writeln!(list, "- {room_id}: would upgrade to version {version} as {user_id}")?;
+			continue;
+		}
+
+		match upgrade_room(self.services, user_id, room_id, &version).await {
+			| Ok(replacement) => {
+				info!("Upgraded {room_id} to {replacement} (version {version}) as {user_id}");
+				writeln!(list, "- {room_id}: upgraded to {replacement} as {user_id}")?;
+				upgraded = upgraded.saturating_add(1);
+			},
+			| Err(e) => {
+				warn!("Failed to upgrade {room_id} as {user_id}: {e}");
+				writeln!(list, "- {room_id}: failed as {user_id}: {e}")?;
+				failed = failed.saturating_add(1);
+			},
+		}
+	}
+
+	if list.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No rooms are flagged for upgrade."));
+	}
+
+	let summary = if execute {
+		format!("Upgraded {upgraded} rooms, {failed} failed:")
+	} else {
+		"Dry run; pass --execute to perform these upgrades:".to_owned()
+	};
+
+	Ok(RoomMessageEventContent::notice_markdown(format!("{summary}\n{list}")))
+}
+
+#[admin_command]
+async fn warn_outdated(&self) -> Result<RoomMessageEventContent> {
+	let notices = &self.services.server_notices;
+	if !notices.enabled() {
+		return Err!("Server notices are not enabled; see `server_notices.enable`.");
+	}
+
+	let (mut sent, mut failed) = (0_usize, 0_usize);
+	for room in outdated_rooms(self.services).await {
+		for user_id in &room.upgraders {
+			let upgrade = self
+				.services
+				.server
+				.recommended_room_version(user_id)
+				.map_or_else(
+					|| "from your client's room settings".to_owned(),
+					|version| {
+						format!(
+							"with `/upgraderoom {version}` or from your client's room settings"
+						)
+					},
+				);
+
+			let content = RoomMessageEventContent::text_markdown(format!(
+				"The room {} uses room version {}, which should be upgraded: {}. As you may \
+				 upgrade it, please do so {upgrade}.",
+				room.room_id, room.version, room.reason,
+			));
+
+			match notices.send(user_id, &content).await {
+				| Ok(_) => sent = sent.saturating_add(1),
+				| Err(e) => {
+					warn!("Failed to warn {user_id} about {}: {e}", room.room_id);
+					failed = failed.saturating_add(1);
+				},
+			}
+		}
+	}
+
+	Ok(RoomMessageEventContent::notice_plain(format!(
+		"Sent {sent} notices, {failed} failed."
+	)))
+}
+
+/// Fails if rooms may not be upgraded to the version by the admin command.
+fn check_target(services: &Services, version: &RoomVersionId) -> Result {
+	if !services.server.supported_room_version(version) {
+		return Err!("Room version {version} is not supported.");
+	}
+
+	Ok(())
+}
+
+/// Rooms local users are joined to whose version is flagged for upgrade by
+/// the policy of their creator. The admin room is left out; it is upgraded by
+/// hand.
+async fn outdated_rooms(services: &Services) -> Vec<Outdated> {
+	let admin_room = services.admin.get_admin_room().await.ok();
+	let rooms: Vec<OwnedRoomId> = services
+		.rooms
+		.state_cache
+		.server_rooms(services.globals.server_name())
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	let mut outdated = Vec::new();
+	for room_id in rooms {
+		if admin_room.as_ref() == Some(&room_id) {
+			continue;
+		}
+
+		let Ok(version) = services.rooms.state.get_room_version(&room_id).await else {
+			continue;
+		};
+
+		let Ok(create) = services
+			.rooms
+			.state_accessor
+			.room_state_get(&room_id, &StateEventType::RoomCreate, "")
+			.await
+		else {
+			continue;
+		};
+
+		let Some(reason) = services
+			.server
+			.room_version_deprecation(&version, &create.sender)
+		else {
+			continue;
+		};
+
+		let members: Vec<OwnedUserId> = services
+			.rooms
+			.state_cache
+			.local_users_in_room(&room_id)
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		let mut upgraders = Vec::new();
+		for user_id in members {
+			if services
+				.rooms
+				.state_accessor
+				.user_can_upgrade(&room_id, &user_id)
+				.await
+			{
+				upgraders.push(user_id);
+			}
+		}
+
+		outdated.push(Outdated { room_id, version, reason, upgraders });
+	}
+
+	outdated
+}
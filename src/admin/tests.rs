@@ -1,5 +1,15 @@
 #![cfg(test)]
 
+use std::{path::PathBuf, sync::Arc};
+
+use conduwuit::{
+	config::Config,
+	log::{capture, Log, LogLevelReloadHandles},
+	Result, Server,
+};
+use service::{admin::CommandInput, Services};
+use tokio::runtime::Handle;
+
 #[test]
 fn get_help_short() { get_help_inner("-h"); }
 
@@ -24,3 +34,58 @@ fn get_help_inner(input: &str) {
 	assert!(error.contains("Commands:"));
 	assert!(error.contains("Options:"));
 }
+
+/// Directory removed with everything in it once dropped.
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+	pub(crate) fn new(name: &str) -> Self {
+		let path = std::env::temp_dir()
+			.join(format!("conduwuit-admin-{name}-test-{}", std::process::id()));
+
+		std::fs::create_dir_all(&path).expect("created temporary directory");
+		Self(path)
+	}
+}
+
+impl Drop for TempDir {
+	fn drop(&mut self) { std::fs::remove_dir_all(&self.0).ok(); }
+}
+
+/// Services of `server_name` over a new database in `dir`, started the way
+/// the maintenance commands start them.
+pub(crate) async fn offline_services(dir: &TempDir, server_name: &str) -> Result<Arc<Services>> {
+	let config_path = dir.0.join("conduwuit.toml");
+	std::fs::write(
+		&config_path,
+		format!(
+			"[global]\nserver_name = {server_name:?}\ndatabase_path = {:?}\n",
+			dir.0.join("database").display().to_string()
+		),
+	)?;
+
+	let config =
+		Config::load([config_path.as_path()].into_iter()).and_then(|raw| Config::new(&raw))?;
+
+	let log = Log {
+		reload: LogLevelReloadHandles::default(),
+		capture: Arc::new(capture::State::new()),
+	};
+
+	let server = Arc::new(Server::new(config, Some(Handle::current()), log));
+
+	Services::build(server).await?.start_offline().await
+}
+
+/// Runs an admin command as typed in the admin room, without the prefix.
+pub(crate) async fn run_command(services: &Arc<Services>, command: &str) {
+	let input = CommandInput {
+		command: command.to_owned(),
+		reply_id: None,
+		sender: None,
+	};
+
+	crate::processor::dispatch(services.clone(), input)
+		.await
+		.expect("command succeeded");
+}
@@ -0,0 +1,129 @@
+use std::{
+	fmt::Write,
+	time::{Duration, UNIX_EPOCH},
+};
+
+use conduwuit::{utils, utils::time::parse_duration, Result};
+use futures::StreamExt;
+use ruma::events::room::message::RoomMessageEventContent;
+use service::registration_tokens::{self, TokenInfo};
+
+use crate::admin_command;
+
+#[admin_command]
+pub(super) async fn create(
+	&self,
+	token: Option<String>,
+	uses_allowed: Option<u64>,
+	expires_in: Option<String>,
+) -> Result<RoomMessageEventContent> {
+	let token = token.unwrap_or_else(registration_tokens::generate);
+	let info = TokenInfo {
+		uses_allowed,
+		expiry_time: expires_in.as_deref().map(expiry_time).transpose()?,
+		..TokenInfo::default()
+	};
+
+	self.services.registration_tokens.create(&token, &info)?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Created registration token `{token}`: {}",
+		describe(&info)
+	)))
+}
+
+#[admin_command]
+pub(super) async fn list(&self) -> Result<RoomMessageEventContent> {
+	let tokens: Vec<(String, TokenInfo)> = self
+		.services
+		.registration_tokens
+		.stream()
+		.map(|(token, info)| (token.to_owned(), info))
+		.collect()
+		.await;
+
+	if tokens.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No registration tokens."));
+	}
+
+	let mut list = String::new();
+	for (token, info) in &tokens {
+		writeln!(list, "- `{token}`: {}", describe(info))?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"{} registration tokens:\n{list}",
+		tokens.len()
+	)))
+}
+
+#[admin_command]
+pub(super) async fn update(
+	&self,
+	token: String,
+	uses_allowed: Option<u64>,
+	unlimited_uses: bool,
+	expires_in: Option<String>,
+	never_expires: bool,
+) -> Result<RoomMessageEventContent> {
+	let uses_allowed = match (uses_allowed, unlimited_uses) {
+		| (_, true) => Some(None),
+		| (Some(uses_allowed), false) => Some(Some(uses_allowed)),
+		| (None, false) => None,
+	};
+
+	let expiry_time = match (expires_in, never_expires) {
+		| (_, true) => Some(None),
+		| (Some(expires_in), false) => Some(Some(expiry_time(&expires_in)?)),
+		| (None, false) => None,
+	};
+
+	let info = self
+		.services
+		.registration_tokens
+		.set_limits(&token, uses_allowed, expiry_time)?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Updated registration token `{token}`: {}",
+		describe(&info)
+	)))
+}
+
+#[admin_command]
+pub(super) async fn delete(&self, token: String) -> Result<RoomMessageEventContent> {
+	if !self.services.registration_tokens.delete(&token) {
+		return Ok(RoomMessageEventContent::text_plain("Registration token does not exist."));
+	}
+
+	Ok(RoomMessageEventContent::text_plain("Deleted registration token."))
+}
+
+/// Absolute expiry time in milliseconds of a token expiring in `expires_in`.
+fn expiry_time(expires_in: &str) -> Result<u64> {
+	let millis: u64 = parse_duration(expires_in)?.as_millis().try_into()?;
+
+	Ok(utils::millis_since_unix_epoch().saturating_add(millis))
+}
+
+fn describe(info: &TokenInfo) -> String {
+	let uses_allowed = info
+		.uses_allowed
+		.map_or_else(|| "unlimited".to_owned(), |uses| uses.to_string());
+
+	let expiry = match info.expiry_time {
+		| None => "never expires".to_owned(),
+		| Some(expiry) if expiry <= utils::millis_since_unix_epoch() => "expired".to_owned(),
+		| Some(expiry) => {
+			let expiry = UNIX_EPOCH
+				.checked_add(Duration::from_millis(expiry))
+				.unwrap_or(UNIX_EPOCH);
+
+			format!("expires {}", utils::time::format(expiry, "%Y-%m-%d %H:%M:%S UTC"))
+		},
+	};
+
+	format!(
+		"{} completed and {} pending of {uses_allowed} uses, {expiry}",
+		info.completed, info.pending
+	)
+}
@@ -0,0 +1,59 @@
+mod commands;
+
+use clap::Subcommand;
+use conduwuit::Result;
+
+use crate::admin_command_dispatch;
+
+#[admin_command_dispatch]
+#[derive(Debug, Subcommand)]
+pub(super) enum TokenCommand {
+	/// - Creates a registration token, which is generated if not given
+	Create {
+		/// The token; 64 characters of A-Z, a-z, 0-9, '.', '_', '~' and '-'
+		/// at most
+		#[arg(long)]
+		token: Option<String>,
+
+		/// - Number of registrations the token may be used for; unlimited if
+		///   not given
+		#[arg(long)]
+		uses_allowed: Option<u64>,
+
+		/// - Relative time (e.g. 30m, 7d) after which the token expires; never
+		///   if not given
+		#[arg(long)]
+		expires_in: Option<String>,
+	},
+
+	/// - Lists the registration tokens with their limits and usage
+	List,
+
+	/// - Changes the limits of a registration token
+	Update {
+		token: String,
+
+		/// - New number of registrations the token may be used for, including
+		///   those already completed
+		#[arg(long, conflicts_with("unlimited_uses"))]
+		uses_allowed: Option<u64>,
+
+		/// - Removes the limit on the number of uses
+		#[arg(long)]
+		unlimited_uses: bool,
+
+		/// - Relative time from now (e.g. 30m, 7d) after which the token
+		///   expires
+		#[arg(long, conflicts_with("never_expires"))]
+		expires_in: Option<String>,
+
+		/// - Removes the expiry time
+		#[arg(long)]
+		never_expires: bool,
+	},
+
+	/// - Deletes a registration token; registrations in progress with it fail
+	Delete {
+		token: String,
+	},
+}
@@ -881,6 +881,34 @@ pub(super) async fn get_room_tags(
 	)))
 }
 
+#[admin_command]
+pub(super) async fn shadow_ban(&self, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	if self.services.users.is_admin(&user_id).await {
+		return Ok(RoomMessageEventContent::text_plain("Admins cannot be shadow-banned."));
+	}
+
+	self.services.users.set_shadow_banned(&user_id, true);
+
+	Ok(RoomMessageEventContent::text_plain(format!("{user_id} is shadow-banned.")))
+}
+
+#[admin_command]
+pub(super) async fn unshadow_ban(&self, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	if !self.services.users.is_shadow_banned(&user_id).await {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"{user_id} is not shadow-banned."
+		)));
+	}
+
+	self.services.users.set_shadow_banned(&user_id, false);
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Lifted the shadow-ban of {user_id}."
+	)))
+}
+
 #[admin_command]
 pub(super) async fn redact_event(
 	&self,
@@ -1,4 +1,5 @@
 mod commands;
+mod tests;
 
 use clap::Subcommand;
 use conduwuit::Result;
@@ -119,6 +120,18 @@ pub(super) enum UserCommand {
 		room_id: Box<RoomId>,
 	},
 
+	/// - Shadow-bans a local user: the messages, state events, redactions and
+	///   invites they send are dropped while they are answered as if they were
+	///   sent
+	ShadowBan {
+		user_id: String,
+	},
+
+	/// - Lifts the shadow-ban of a local user
+	UnshadowBan {
+		user_id: String,
+	},
+
 	/// - Attempts to forcefully redact the specified event ID from the sender
 	///   user
 	///
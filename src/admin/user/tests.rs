@@ -0,0 +1,42 @@
+#![cfg(test)]
+
+use ruma::owned_user_id;
+
+use crate::tests::{offline_services, run_command, TempDir};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn deactivate_leaves_directory() {
+	let dir = TempDir::new("deactivate");
+	let services = offline_services(&dir, "example.com")
+		.await
+		.expect("started services offline");
+
+	let alice = owned_user_id!("@alice:example.com");
+	services
+		.users
+		.create(&alice, Some("password"))
+		.expect("created");
+	services
+		.users
+		.set_displayname(&alice, Some("Alice".to_owned()));
+	assert!(services.user_directory.entry(&alice).await.is_ok());
+
+	run_command(&services, "users deactivate @alice:example.com").await;
+
+	assert!(services
+		.users
+		.is_deactivated(&alice)
+		.await
+		.expect("known user"));
+	assert!(
+		services.user_directory.entry(&alice).await.is_err(),
+		"clearing the profile did not put them back"
+	);
+
+	services
+		.users
+		.set_displayname(&alice, Some("Alice again".to_owned()));
+	assert!(services.user_directory.entry(&alice).await.is_err());
+
+	services.stop_offline();
+}
@@ -0,0 +1,44 @@
+use axum::{async_trait, extract::FromRequestParts};
+use conduwuit::{err, Err, Error, Result};
+use http::request::Parts;
+use ruma::{OwnedRoomId, OwnedUserId, RoomId, UserId};
+use service::Services;
+
+use crate::{router::extract::User, State};
+
+/// Extractor authenticating a server admin by their access token.
+pub(crate) struct Admin {
+	pub(crate) sender_user: OwnedUserId,
+}
+
+#[async_trait]
+impl FromRequestParts<State> for Admin {
+	type Rejection = Error;
+
+	async fn from_request_parts(parts: &mut Parts, services: &State) -> Result<Self> {
+		let User { sender_user } = User::from_request_parts(parts, services).await?;
+		if !services.users.is_admin(&sender_user).await {
+			return Err!(Request(Forbidden("You are not a server admin.")));
+		}
+
+		Ok(Self { sender_user })
+	}
+}
+
+/// Parses a user ID from a path parameter, requiring it to be local.
+pub(super) fn local_user_id(services: &Services, user_id: &str) -> Result<OwnedUserId> {
+	let user_id = UserId::parse(user_id)
+		.map_err(|e| err!(Request(InvalidParam("Invalid user ID {user_id:?}: {e}"))))?;
+
+	if !services.globals.user_is_local(&user_id) {
+		return Err!(Request(InvalidParam("Can only look up local users.")));
+	}
+
+	Ok(user_id)
+}
+
+/// Parses a room ID from a path parameter.
+pub(super) fn room_id(room_id: &str) -> Result<OwnedRoomId> {
+	RoomId::parse(room_id)
+		.map_err(|e| err!(Request(InvalidParam("Invalid room ID {room_id:?}: {e}"))))
+}
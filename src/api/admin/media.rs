@@ -0,0 +1,152 @@
+use axum::{
+	extract::{Path, State},
+	response::IntoResponse,
+};
+use conduwuit::{err, Result};
+use ruma::{Mxc, OwnedServerName, OwnedUserId, ServerName};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::{
+	args::{local_user_id, Admin},
+	notice,
+};
+use crate::router::extract::Query;
+
+const DEFAULT_LIMIT: usize = 100;
+
+#[derive(Deserialize)]
+pub(crate) struct ListUserMedia {
+	#[serde(default)]
+	from: usize,
+
+	#[serde(default = "default_limit")]
+	limit: usize,
+}
+
+#[derive(Serialize)]
+struct Media {
+	media_id: String,
+	media_length: Option<u64>,
+	quarantined_by: Option<OwnedUserId>,
+}
+
+/// # `DELETE /_synapse/admin/v1/media/{serverName}/{mediaId}`
+///
+/// Deletes a media file and its thumbnails from the database and the media
+/// directory.
+pub(crate) async fn delete_media_route(
+	State(services): State<crate::State>,
+	Admin { sender_user }: Admin,
+	Path((server_name, media_id)): Path<(String, String)>,
+) -> Result<impl IntoResponse> {
+	let server_name = parse_server_name(&server_name)?;
+	let mxc = Mxc {
+		server_name: &server_name,
+		media_id: &media_id,
+	};
+
+	services
+		.media
+		.delete(&mxc)
+		.await
+		.map_err(|_| err!(Request(NotFound("Media not found."))))?;
+
+	notice(&services, &format!("{sender_user} deleted media {mxc}")).await;
+
+	Ok(axum::Json(json!({
+		"deleted_media": [media_id],
+		"total": 1,
+	})))
+}
+
+/// # `POST /_synapse/admin/v1/media/quarantine/{serverName}/{mediaId}`
+///
+/// Stops serving a media file without deleting it.
+pub(crate) async fn quarantine_media_route(
+	State(services): State<crate::State>,
+	Admin { sender_user }: Admin,
+	Path((server_name, media_id)): Path<(String, String)>,
+) -> Result<impl IntoResponse> {
+	let server_name = parse_server_name(&server_name)?;
+	let mxc = Mxc {
+		server_name: &server_name,
+		media_id: &media_id,
+	};
+
+	services.media.quarantine(&mxc, &sender_user);
+
+	notice(&services, &format!("{sender_user} quarantined media {mxc}")).await;
+
+	Ok(axum::Json(json!({})))
+}
+
+/// # `POST /_synapse/admin/v1/media/unquarantine/{serverName}/{mediaId}`
+///
+/// Serves a quarantined media file again.
+pub(crate) async fn unquarantine_media_route(
+	State(services): State<crate::State>,
+	Admin { sender_user }: Admin,
+	Path((server_name, media_id)): Path<(String, String)>,
+) -> Result<impl IntoResponse> {
+	let server_name = parse_server_name(&server_name)?;
+	let mxc = Mxc {
+		server_name: &server_name,
+		media_id: &media_id,
+	};
+
+	services.media.unquarantine(&mxc);
+
+	notice(&services, &format!("{sender_user} released media {mxc} from quarantine")).await;
+
+	Ok(axum::Json(json!({})))
+}
+
+/// # `GET /_synapse/admin/v1/users/{userId}/media`
+///
+/// Lists the media uploaded by a local user with their sizes.
+pub(crate) async fn list_user_media_route(
+	State(services): State<crate::State>,
+	_: Admin,
+	Path(user_id): Path<String>,
+	Query(query): Query<ListUserMedia>,
+) -> Result<impl IntoResponse> {
+	let user_id = local_user_id(&services, &user_id)?;
+	let uploads = services.media.user_uploads(&user_id).await;
+
+	let total = uploads.len();
+	let limit = query.limit.min(DEFAULT_LIMIT.saturating_mul(10));
+	let next = query.from.saturating_add(limit);
+
+	let mut media = Vec::with_capacity(limit.min(total));
+	for (mxc, media_length) in uploads.into_iter().skip(query.from).take(limit) {
+		let quarantined_by = match Mxc::try_from(mxc.as_str()) {
+			| Ok(mxc) => services.media.quarantined_by(&mxc).await.ok(),
+			| Err(_) => None,
+		};
+
+		media.push(Media {
+			media_id: mxc.media_id().unwrap_or_default().to_owned(),
+			media_length,
+			quarantined_by,
+		});
+	}
+
+	let mut response = json!({
+		"media": media,
+		"total": total,
+	});
+
+	if next < total {
+		response["next_token"] = next.to_string().into();
+	}
+
+	Ok(axum::Json(response))
+}
+
+fn parse_server_name(server_name: &str) -> Result<OwnedServerName> {
+	ServerName::parse(server_name)
+		.map_err(|e| err!(Request(InvalidParam("Invalid server name: {e}"))))
+}
+
+fn default_limit() -> usize { DEFAULT_LIMIT }
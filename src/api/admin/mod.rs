@@ -0,0 +1,31 @@
+//! Synapse-compatible admin API, served under both `/_synapse/admin` and
+//! `/_conduwuit/admin`. All endpoints except the server version require the
+//! access token of a server admin.
+
+pub(crate) mod args;
+mod media;
+mod rooms;
+mod users;
+
+use axum::{response::IntoResponse, Json};
+use conduwuit::{info, Result};
+use serde_json::json;
+use service::Services;
+
+pub(super) use self::{media::*, rooms::*, users::*};
+
+/// # `GET /_synapse/admin/v1/server_version`
+pub(crate) async fn server_version_route() -> Result<impl IntoResponse> {
+	Ok(Json(json!({
+		"server_version": conduwuit::version::version(),
+	})))
+}
+
+/// Logs an action taken through the admin API and reports it to the admin
+/// room if `admin_room_notices` is enabled.
+async fn notice(services: &Services, message: &str) {
+	info!("{message}");
+	if services.server.config.admin_room_notices {
+		services.admin.send_text(message).await;
+	}
+}
@@ -0,0 +1,314 @@
+use axum::{
+	extract::{Path, State},
+	response::IntoResponse,
+};
+use conduwuit::{debug, utils::ReadyExt, warn, Err, Result};
+use futures::{FutureExt, StreamExt};
+use ruma::{OwnedRoomAliasId, OwnedRoomId, OwnedUserId, RoomId, RoomVersionId};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use service::Services;
+
+use super::{
+	args::{room_id, Admin},
+	notice,
+};
+use crate::{
+	client::leave_room,
+	router::extract::{Json, Query},
+};
+
+const DEFAULT_LIMIT: usize = 100;
+
+#[derive(Deserialize)]
+pub(crate) struct ListRooms {
+	#[serde(default)]
+	from: usize,
+
+	#[serde(default = "default_limit")]
+	limit: usize,
+
+	/// Substring matched against the room ID, name and canonical alias.
+	search_term: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Room {
+	room_id: OwnedRoomId,
+	name: Option<String>,
+	canonical_alias: Option<OwnedRoomAliasId>,
+	joined_members: u64,
+	joined_local_members: usize,
+	version: Option<RoomVersionId>,
+	public: bool,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct SetBlock {
+	block: bool,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct DeleteRoom {
+	#[serde(default)]
+	block: bool,
+
+	#[serde(default = "default_purge")]
+	purge: bool,
+
+	message: Option<String>,
+}
+
+/// # `GET /_synapse/admin/v1/rooms`
+///
+/// Lists rooms known to the server, largest first.
+pub(crate) async fn list_rooms_route(
+	State(services): State<crate::State>,
+	_: Admin,
+	Query(query): Query<ListRooms>,
+) -> Result<impl IntoResponse> {
+	let services = &*services;
+	let search_term = query.search_term.as_deref().map(str::to_lowercase);
+	let mut rooms: Vec<Room> = services
+		.rooms
+		.metadata
+		.iter_ids()
+		.then(|room_id| get_room(services, room_id))
+		.ready_filter(|room| {
+			search_term.as_deref().is_none_or(|term| {
+				room.room_id.as_str().to_lowercase().contains(term)
+					|| room
+						.name
+						.as_deref()
+						.is_some_and(|name| name.to_lowercase().contains(term))
+					|| room
+						.canonical_alias
+						.as_ref()
+						.is_some_and(|alias| alias.as_str().to_lowercase().contains(term))
+			})
+		})
+		.collect()
+		.await;
+
+	rooms.sort_by(|a, b| b.joined_members.cmp(&a.joined_members));
+
+	let total = rooms.len();
+	let limit = query.limit.min(DEFAULT_LIMIT.saturating_mul(10));
+	let next = query.from.saturating_add(limit);
+	let rooms: Vec<_> = rooms.into_iter().skip(query.from).take(limit).collect();
+
+	let mut response = json!({
+		"rooms": rooms,
+		"offset": query.from,
+		"total_rooms": total,
+	});
+
+	if next < total {
+		response["next_batch"] = next.into();
+	}
+
+	Ok(axum::Json(response))
+}
+
+/// # `GET /_synapse/admin/v1/rooms/{roomId}`
+///
+/// Returns information about a room known to the server.
+pub(crate) async fn get_room_route(
+	State(services): State<crate::State>,
+	_: Admin,
+	Path(room_id): Path<String>,
+) -> Result<impl IntoResponse> {
+	let room_id = self::room_id(&room_id)?;
+	if !services.rooms.metadata.exists(&room_id).await {
+		return Err!(Request(NotFound("Room not found.")));
+	}
+
+	let room = get_room(&services, &room_id).await;
+	let mut response = serde_json::to_value(room)?;
+	response["topic"] = services
+		.rooms
+		.state_accessor
+		.get_room_topic(&room_id)
+		.await
+		.ok()
+		.into();
+	response["encryption"] = services
+		.rooms
+		.state_accessor
+		.get_room_encryption(&room_id)
+		.await
+		.ok()
+		.map(|algorithm| algorithm.to_string())
+		.into();
+	response["blocked"] = services.rooms.metadata.is_banned(&room_id).await.into();
+
+	Ok(axum::Json(response))
+}
+
+/// # `GET /_synapse/admin/v1/rooms/{roomId}/members`
+///
+/// Lists the joined members of a room.
+pub(crate) async fn get_room_members_route(
+	State(services): State<crate::State>,
+	_: Admin,
+	Path(room_id): Path<String>,
+) -> Result<impl IntoResponse> {
+	let room_id = self::room_id(&room_id)?;
+	if !services.rooms.metadata.exists(&room_id).await {
+		return Err!(Request(NotFound("Room not found.")));
+	}
+
+	let members: Vec<OwnedUserId> = services
+		.rooms
+		.state_cache
+		.room_members(&room_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	Ok(axum::Json(json!({
+		"total": members.len(),
+		"members": members,
+	})))
+}
+
+/// # `GET /_synapse/admin/v1/rooms/{roomId}/block`
+///
+/// Returns whether local users are prevented from joining a room.
+pub(crate) async fn get_room_block_route(
+	State(services): State<crate::State>,
+	_: Admin,
+	Path(room_id): Path<String>,
+) -> Result<impl IntoResponse> {
+	let room_id = self::room_id(&room_id)?;
+
+	Ok(axum::Json(json!({
+		"block": services.rooms.metadata.is_banned(&room_id).await,
+	})))
+}
+
+/// # `PUT /_synapse/admin/v1/rooms/{roomId}/block`
+///
+/// Blocks or unblocks a room. Blocking prevents local users from joining the
+/// room without evicting current members; the room need not be known yet.
+pub(crate) async fn set_room_block_route(
+	State(services): State<crate::State>,
+	Admin { sender_user }: Admin,
+	Path(room_id): Path<String>,
+	Json(body): Json<SetBlock>,
+) -> Result<impl IntoResponse> {
+	let room_id = self::room_id(&room_id)?;
+	if body.block && services.admin.is_admin_room(&room_id).await {
+		return Err!(Request(Forbidden("Not allowed to block the admin room.")));
+	}
+
+	services.rooms.metadata.ban_room(&room_id, body.block);
+
+	let action = if body.block { "blocked" } else { "unblocked" };
+	notice(&services, &format!("{sender_user} {action} room {room_id}")).await;
+
+	Ok(axum::Json(json!({ "block": body.block })))
+}
+
+/// # `DELETE /_synapse/admin/v1/rooms/{roomId}`
+///
+/// Makes all local users leave a room, removes its local aliases and
+/// unpublishes it from the room directory. With `block` the room is also
+/// banned so local users cannot join it again. Unless `purge` is false, all
+/// of the room's data is then removed from the database.
+pub(crate) async fn delete_room_route(
+	State(services): State<crate::State>,
+	Admin { sender_user }: Admin,
+	Path(room_id): Path<String>,
+	Json(body): Json<DeleteRoom>,
+) -> Result<impl IntoResponse> {
+	let room_id = self::room_id(&room_id)?;
+	if services.admin.is_admin_room(&room_id).await {
+		return Err!(Request(Forbidden("Not allowed to delete the admin room.")));
+	}
+
+	if body.block {
+		services.rooms.metadata.ban_room(&room_id, true);
+	}
+
+	let local_users: Vec<OwnedUserId> = services
+		.rooms
+		.state_cache
+		.local_users_in_room(&room_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	let mut kicked_users = Vec::with_capacity(local_users.len());
+	let mut failed_to_kick_users = Vec::new();
+	for user_id in local_users {
+		debug!("Attempting leave for user {user_id} in room {room_id}");
+		match leave_room(&services, &user_id, &room_id, body.message.clone())
+			.boxed()
+			.await
+		{
+			| Ok(()) => kicked_users.push(user_id),
+			| Err(e) => {
+				warn!(%user_id, %room_id, "Failed to leave room: {e}");
+				failed_to_kick_users.push(user_id);
+			},
+		}
+	}
+
+	let local_aliases: Vec<OwnedRoomAliasId> = services
+		.rooms
+		.alias
+		.local_aliases_for_room(&room_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	for alias in &local_aliases {
+		services
+			.rooms
+			.alias
+			.remove_alias(alias, &services.globals.server_user)
+			.await
+			.ok();
+	}
+
+	services.rooms.directory.set_not_public(&room_id);
+
+	if body.purge {
+		services
+			.rooms
+			.purge
+			.purge_room(&room_id, |_, _| async {})
+			.boxed()
+			.await?;
+	}
+
+	let action = if body.purge { "purged" } else { "deleted" };
+	notice(&services, &format!("{sender_user} {action} room {room_id}")).await;
+
+	Ok(axum::Json(json!({
+		"kicked_users": kicked_users,
+		"failed_to_kick_users": failed_to_kick_users,
+		"local_aliases": local_aliases,
+		"new_room_id": null,
+	})))
+}
+
+async fn get_room(services: &Services, room_id: &RoomId) -> Room {
+	let state_accessor = &services.rooms.state_accessor;
+	let state_cache = &services.rooms.state_cache;
+
+	Room {
+		room_id: room_id.to_owned(),
+		name: state_accessor.get_name(room_id).await.ok(),
+		canonical_alias: state_accessor.get_canonical_alias(room_id).await.ok(),
+		joined_members: state_cache.room_joined_count(room_id).await.unwrap_or(0),
+		joined_local_members: state_cache.local_users_in_room(room_id).count().await,
+		version: services.rooms.state.get_room_version(room_id).await.ok(),
+		public: services.rooms.directory.is_public_room(room_id).await,
+	}
+}
+
+fn default_limit() -> usize { DEFAULT_LIMIT }
+
+fn default_purge() -> bool { true }
@@ -0,0 +1,225 @@
+use axum::{
+	extract::{Path, State},
+	response::IntoResponse,
+};
+use conduwuit::{utils, Err, Result};
+use futures::{FutureExt, StreamExt};
+use ruma::{OwnedMxcUri, OwnedRoomId, OwnedUserId, UserId};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use service::Services;
+
+use super::{
+	args::{local_user_id, Admin},
+	notice,
+};
+use crate::{
+	client::{full_user_deactivate, leave_all_rooms},
+	router::extract::{Json, Query},
+};
+
+const DEFAULT_LIMIT: usize = 100;
+
+const AUTO_GEN_PASSWORD_LENGTH: usize = 25;
+
+#[derive(Deserialize)]
+pub(crate) struct ListUsers {
+	#[serde(default)]
+	from: usize,
+
+	#[serde(default = "default_limit")]
+	limit: usize,
+
+	/// Substring matched against the user ID and displayname.
+	name: Option<String>,
+
+	#[serde(default)]
+	deactivated: bool,
+
+	admins: Option<bool>,
+}
+
+#[derive(Serialize)]
+struct User {
+	name: OwnedUserId,
+	admin: bool,
+	deactivated: bool,
+	displayname: Option<String>,
+	avatar_url: Option<OwnedMxcUri>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ResetPassword {
+	new_password: Option<String>,
+
+	#[serde(default = "default_logout_devices")]
+	logout_devices: bool,
+}
+
+/// # `GET /_synapse/admin/v2/users`
+///
+/// Lists local user accounts, ordered by user ID. Deactivated accounts are
+/// only included when `deactivated=true` is given.
+pub(crate) async fn list_users_route(
+	State(services): State<crate::State>,
+	_: Admin,
+	Query(query): Query<ListUsers>,
+) -> Result<impl IntoResponse> {
+	let services = &*services;
+	let name = query.name.as_deref().map(str::to_lowercase);
+	let users: Vec<User> = services
+		.users
+		.stream()
+		.filter(|user_id| {
+			let is_local = services.globals.user_is_local(user_id);
+			async move { is_local && **user_id != *services.globals.server_user }
+		})
+		.then(|user_id| get_user(services, user_id))
+		.filter(|user| {
+			let matched = (query.deactivated || !user.deactivated)
+				&& query.admins.is_none_or(|admins| admins == user.admin)
+				&& name.as_deref().is_none_or(|name| {
+					user.name.as_str().to_lowercase().contains(name)
+						|| user
+							.displayname
+							.as_deref()
+							.is_some_and(|displayname| displayname.to_lowercase().contains(name))
+				});
+
+			async move { matched }
+		})
+		.collect()
+		.await;
+
+	let total = users.len();
+	let limit = query.limit.min(DEFAULT_LIMIT.saturating_mul(10));
+	let next = query.from.saturating_add(limit);
+	let users: Vec<_> = users.into_iter().skip(query.from).take(limit).collect();
+
+	let mut response = json!({
+		"users": users,
+		"total": total,
+	});
+
+	if next < total {
+		response["next_token"] = next.to_string().into();
+	}
+
+	Ok(axum::Json(response))
+}
+
+/// # `GET /_synapse/admin/v2/users/{userId}`
+///
+/// Returns information about a local user account.
+pub(crate) async fn get_user_route(
+	State(services): State<crate::State>,
+	_: Admin,
+	Path(user_id): Path<String>,
+) -> Result<impl IntoResponse> {
+	let user_id = local_user_id(&services, &user_id)?;
+	if !services.users.exists(&user_id).await {
+		return Err!(Request(NotFound("User not found.")));
+	}
+
+	Ok(axum::Json(get_user(&services, &user_id).await))
+}
+
+/// # `POST /_synapse/admin/v1/deactivate/{userId}`
+///
+/// Deactivates a local user account, removes their profile and makes them
+/// leave all rooms.
+pub(crate) async fn deactivate_user_route(
+	State(services): State<crate::State>,
+	Admin { sender_user }: Admin,
+	Path(user_id): Path<String>,
+) -> Result<impl IntoResponse> {
+	let user_id = local_user_id(&services, &user_id)?;
+	if !services.users.exists(&user_id).await {
+		return Err!(Request(NotFound("User not found.")));
+	}
+
+	if user_id == services.globals.server_user {
+		return Err!(Request(Forbidden("Not allowed to deactivate the server service account.")));
+	}
+
+	let all_joined_rooms: Vec<OwnedRoomId> = services
+		.rooms
+		.state_cache
+		.rooms_joined(&user_id)
+		.map(Into::into)
+		.collect()
+		.await;
+
+	full_user_deactivate(&services, &user_id, &all_joined_rooms)
+		.boxed()
+		.await?;
+	leave_all_rooms(&services, &user_id).boxed().await;
+
+	notice(&services, &format!("{sender_user} deactivated user {user_id}")).await;
+
+	Ok(axum::Json(json!({
+		"id_server_unbind_result": "success",
+	})))
+}
+
+/// # `POST /_synapse/admin/v1/reset_password/{userId}`
+///
+/// Sets a new password for a local user account. When no password is given a
+/// random one is generated and returned.
+pub(crate) async fn reset_password_route(
+	State(services): State<crate::State>,
+	Admin { sender_user }: Admin,
+	Path(user_id): Path<String>,
+	Json(body): Json<ResetPassword>,
+) -> Result<impl IntoResponse> {
+	let user_id = local_user_id(&services, &user_id)?;
+	if !services.users.is_active(&user_id).await {
+		return Err!(Request(NotFound("User not found or deactivated.")));
+	}
+
+	if user_id == services.globals.server_user {
+		return Err!(Request(Forbidden(
+			"Not allowed to set the password for the server account. Please use the emergency \
+			 password config option."
+		)));
+	}
+
+	let generated = body.new_password.is_none();
+	let new_password = body
+		.new_password
+		.unwrap_or_else(|| utils::random_string(AUTO_GEN_PASSWORD_LENGTH));
+
+	services
+		.users
+		.set_password(&user_id, Some(new_password.as_str()))?;
+
+	if body.logout_devices {
+		services
+			.users
+			.all_device_ids(&user_id)
+			.for_each(|device_id| services.users.remove_device(&user_id, device_id))
+			.await;
+	}
+
+	notice(&services, &format!("{sender_user} reset the password for user {user_id}")).await;
+
+	Ok(axum::Json(if generated {
+		json!({ "new_password": new_password })
+	} else {
+		json!({})
+	}))
+}
+
+async fn get_user(services: &Services, user_id: &UserId) -> User {
+	User {
+		name: user_id.to_owned(),
+		admin: services.users.is_admin(user_id).await,
+		deactivated: !services.users.is_active(user_id).await,
+		displayname: services.users.displayname(user_id).await.ok(),
+		avatar_url: services.users.avatar_url(user_id).await.ok(),
+	}
+}
+
+fn default_limit() -> usize { DEFAULT_LIMIT }
+
+fn default_logout_devices() -> bool { true }
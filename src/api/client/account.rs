@@ -3,17 +3,17 @@ use std::fmt::Write;
 use axum::extract::State;
 use axum_client_ip::InsecureClientIp;
 use conduwuit::{
-	debug_info, error, info, is_equal_to, utils, utils::ReadyExt, warn, Error, PduBuilder, Result,
+	debug_info, err, error, info, is_equal_to, result::LogErr, utils, utils::ReadyExt, warn, Err,
+	Error, PduBuilder, Result,
 };
 use futures::{FutureExt, StreamExt};
 use register::RegistrationKind;
 use ruma::{
 	api::client::{
 		account::{
-			change_password, check_registration_token_validity, deactivate, get_3pids,
+			change_password, check_registration_token_validity, deactivate,
 			get_username_availability,
 			register::{self, LoginType},
-			request_3pid_management_token_via_email, request_3pid_management_token_via_msisdn,
 			whoami, ThirdPartyIdRemovalStatus,
 		},
 		error::ErrorKind,
@@ -26,9 +26,11 @@ use ruma::{
 		},
 		GlobalAccountDataEventType, StateEventType,
 	},
-	push, OwnedRoomId, UserId,
+	push,
+	thirdparty::Medium,
+	OwnedRoomId, OwnedSessionId, UserId,
 };
-use service::Services;
+use service::{email::Purpose, Services};
 
 use super::{join_room_by_id_helper, DEVICE_ID_LENGTH, SESSION_ID_LENGTH, TOKEN_LENGTH};
 use crate::Ruma;
@@ -131,8 +133,7 @@ pub(crate) async fn register_route(
 
 	if is_guest
 		&& (!services.globals.allow_guest_registration()
-			|| (services.globals.allow_registration()
-				&& services.globals.registration_token.is_some()))
+			|| (services.globals.allow_registration() && services.registration_tokens.required()))
 	{
 		info!(
 			"Guest registration disabled / registration enabled with token configured, \
@@ -231,7 +232,7 @@ pub(crate) async fn register_route(
 
 	// UIAA
 	let mut uiaainfo;
-	let skip_auth = if services.globals.registration_token.is_some() {
+	let skip_auth = if services.registration_tokens.required() {
 		// Registration token required
 		uiaainfo = UiaaInfo {
 			flows: vec![AuthFlow {
@@ -255,6 +256,16 @@ pub(crate) async fn register_route(
 		body.appservice_info.is_some() || is_guest
 	};
 
+	// Email address validated during registration, if one is required
+	let require_email = services.server.config.email.require_for_registration;
+	if require_email {
+		for flow in &mut uiaainfo.flows {
+			flow.stages.push(AuthType::EmailIdentity);
+		}
+	}
+
+	let mut email = None;
+	let mut registration_token = None;
 	if !skip_auth {
 		if let Some(auth) = &body.auth {
 			let (worked, uiaainfo) = services
@@ -270,7 +281,16 @@ pub(crate) async fn register_route(
 			if !worked {
 				return Err(Error::Uiaa(uiaainfo));
 			}
-		// Success!
+
+			// Success!
+			let session = uiaainfo.session.as_deref().expect("session is always set");
+			if require_email {
+				email = Some(validated_email(&services, session).await?);
+			}
+
+			// Taken last, so that the use held for it is given back by the session
+			// expiring if registering fails before.
+			registration_token = services.uiaa.take_registration_token(session);
 		} else if let Some(json) = body.json_body {
 			uiaainfo.session = Some(utils::random_string(SESSION_ID_LENGTH));
 			services.uiaa.create(
@@ -289,7 +309,14 @@ pub(crate) async fn register_route(
 	let password = if is_guest { None } else { body.password.as_deref() };
 
 	// Create user
-	services.users.create(&user_id, password)?;
+	if let Err(e) = services.users.create(&user_id, password) {
+		release_registration_token(&services, registration_token.as_deref());
+		return Err(e);
+	}
+
+	if let Some(token) = &registration_token {
+		services.registration_tokens.complete(token).log_err().ok();
+	}
 
 	// Default to pretty displayname
 	let mut displayname = user_id.localpart().to_owned();
@@ -307,6 +334,13 @@ pub(crate) async fn register_route(
 		.users
 		.set_displayname(&user_id, Some(displayname.clone()));
 
+	if let Some((sid, address)) = &email {
+		services
+			.users
+			.add_threepid(&user_id, &Medium::Email, address);
+		services.email.consume(sid).await;
+	}
+
 	// Initial account data
 	services
 		.account_data
@@ -510,18 +544,19 @@ pub(crate) async fn register_route(
 ///   last seen ts)
 /// - Forgets to-device events
 /// - Triggers device list updates
+///
+/// Without an access token, the password of the account an email address
+/// belongs to is reset after validating the address through
+/// `/account/password/email/requestToken`.
 #[tracing::instrument(skip_all, fields(%client), name = "change_password")]
 pub(crate) async fn change_password_route(
 	State(services): State<crate::State>,
 	InsecureClientIp(client): InsecureClientIp,
 	body: Ruma<change_password::v3::Request>,
 ) -> Result<change_password::v3::Response> {
-	// Authentication for this endpoint was made optional, but we need
-	// authentication currently
-	let sender_user = body
-		.sender_user
-		.as_ref()
-		.ok_or_else(|| Error::BadRequest(ErrorKind::MissingToken, "Missing access token."))?;
+	let Some(sender_user) = body.sender_user.as_ref() else {
+		return reset_password(&services, body).await;
+	};
 	let sender_device = body.sender_device.as_ref().expect("user is authenticated");
 
 	let mut uiaainfo = UiaaInfo {
@@ -583,6 +618,105 @@ pub(crate) async fn change_password_route(
 	Ok(change_password::v3::Response {})
 }
 
+/// Resets the password of the account an email address validated in UIAA
+/// belongs to, logging out all its devices if asked to.
+async fn reset_password(
+	services: &Services,
+	body: Ruma<change_password::v3::Request>,
+) -> Result<change_password::v3::Response> {
+	if !services.email.enabled() || !services.server.config.email.password_reset {
+		return Err(Error::BadRequest(ErrorKind::MissingToken, "Missing access token."));
+	}
+
+	// Nobody is logged in yet, so sessions are kept for the empty user like
+	// during registration
+	let unauthenticated = UserId::parse_with_server_name("", services.globals.server_name())
+		.expect("we know this is valid");
+
+	let mut uiaainfo = UiaaInfo {
+		flows: vec![AuthFlow { stages: vec![AuthType::EmailIdentity] }],
+		completed: Vec::new(),
+		params: Box::default(),
+		session: None,
+		auth_error: None,
+	};
+
+	let session = if let Some(auth) = &body.auth {
+		let (worked, uiaainfo) = services
+			.uiaa
+			.try_auth(&unauthenticated, "".into(), auth, &uiaainfo)
+			.await?;
+
+		if !worked {
+			return Err(Error::Uiaa(uiaainfo));
+		}
+
+		uiaainfo.session.expect("session is always set")
+	} else if let Some(json) = body.json_body {
+		uiaainfo.session = Some(utils::random_string(SESSION_ID_LENGTH));
+		services
+			.uiaa
+			.create(&unauthenticated, "".into(), &uiaainfo, &json);
+
+		return Err(Error::Uiaa(uiaainfo));
+	} else {
+		return Err(Error::BadRequest(ErrorKind::NotJson, "Not json."));
+	};
+
+	let Some((sid, client_secret)) = services.uiaa.take_threepid_creds(&session) else {
+		return Err!(Request(ThreepidAuthFailed("No email address was validated.")));
+	};
+
+	let address = services
+		.email
+		.validated(&sid, &client_secret, Some(Purpose::PasswordReset))
+		.await?;
+
+	let user_id = services
+		.users
+		.find_from_threepid(&Medium::Email, &address)
+		.await
+		.map_err(|_| {
+			err!(Request(ThreepidNotFound("Email address is not bound to any account.")))
+		})?;
+
+	if services
+		.users
+		.is_deactivated(&user_id)
+		.await
+		.unwrap_or(true)
+	{
+		return Err!(Request(UserDeactivated("The user has been deactivated")));
+	}
+
+	services
+		.users
+		.set_password(&user_id, Some(&body.new_password))?;
+	services.email.consume(&sid).await;
+
+	if body.logout_devices {
+		services
+			.users
+			.all_device_ids(&user_id)
+			.for_each(|id| services.users.remove_device(&user_id, id))
+			.await;
+	}
+
+	info!("User {user_id} reset their password by email.");
+
+	if services.server.config.admin_room_notices {
+		services
+			.admin
+			.send_message(RoomMessageEventContent::notice_plain(format!(
+				"User {user_id} reset their password by email."
+			)))
+			.await
+			.ok();
+	}
+
+	Ok(change_password::v3::Response {})
+}
+
 /// # `GET _matrix/client/r0/account/whoami`
 ///
 /// Get `user_id` of the sender user.
@@ -688,69 +822,57 @@ pub(crate) async fn deactivate_route(
 	})
 }
 
-/// # `GET _matrix/client/v3/account/3pid`
-///
-/// Get a list of third party identifiers associated with this account.
-///
-/// - Currently always returns empty list
-pub(crate) async fn third_party_route(
-	body: Ruma<get_3pids::v3::Request>,
-) -> Result<get_3pids::v3::Response> {
-	let _sender_user = body.sender_user.as_ref().expect("user is authenticated");
+/// Finds the email address validated during the registration UIAA session,
+/// which must not be bound to an account yet.
+async fn validated_email(services: &Services, session: &str) -> Result<(OwnedSessionId, String)> {
+	let Some((sid, client_secret)) = services.uiaa.take_threepid_creds(session) else {
+		return Err!(Request(ThreepidAuthFailed("No email address was validated.")));
+	};
 
-	Ok(get_3pids::v3::Response::new(Vec::new()))
-}
+	let address = services
+		.email
+		.validated(&sid, &client_secret, Some(Purpose::Registration))
+		.await?;
 
-/// # `POST /_matrix/client/v3/account/3pid/email/requestToken`
-///
-/// "This API should be used to request validation tokens when adding an email
-/// address to an account"
-///
-/// - 403 signals that The homeserver does not allow the third party identifier
-///   as a contact option.
-pub(crate) async fn request_3pid_management_token_via_email_route(
-	_body: Ruma<request_3pid_management_token_via_email::v3::Request>,
-) -> Result<request_3pid_management_token_via_email::v3::Response> {
-	Err(Error::BadRequest(
-		ErrorKind::ThreepidDenied,
-		"Third party identifier is not allowed",
-	))
+	if services
+		.users
+		.find_from_threepid(&Medium::Email, &address)
+		.await
+		.is_ok()
+	{
+		return Err!(Request(ThreepidInUse("Email address is already in use.")));
+	}
+
+	Ok((sid, address))
 }
 
-/// # `POST /_matrix/client/v3/account/3pid/msisdn/requestToken`
-///
-/// "This API should be used to request validation tokens when adding an phone
-/// number to an account"
-///
-/// - 403 signals that The homeserver does not allow the third party identifier
-///   as a contact option.
-pub(crate) async fn request_3pid_management_token_via_msisdn_route(
-	_body: Ruma<request_3pid_management_token_via_msisdn::v3::Request>,
-) -> Result<request_3pid_management_token_via_msisdn::v3::Response> {
-	Err(Error::BadRequest(
-		ErrorKind::ThreepidDenied,
-		"Third party identifier is not allowed",
-	))
+/// Gives back the use of a registration token held by a registration which
+/// failed to create the account.
+fn release_registration_token(services: &Services, token: Option<&str>) {
+	if let Some(token) = token {
+		services.registration_tokens.release(token).log_err().ok();
+	}
 }
 
 /// # `GET /_matrix/client/v1/register/m.login.registration_token/validity`
 ///
 /// Checks if the provided registration token is valid at the time of checking
 ///
-/// Currently does not have any ratelimiting, and this isn't very practical as
-/// there is only one registration token allowed.
+/// Rate limited together with registration to hinder guessing the token.
 pub(crate) async fn check_registration_token_validity(
 	State(services): State<crate::State>,
 	body: Ruma<check_registration_token_validity::v1::Request>,
 ) -> Result<check_registration_token_validity::v1::Response> {
-	let Some(reg_token) = services.globals.registration_token.clone() else {
+	if !services.registration_tokens.required() {
 		return Err(Error::BadRequest(
 			ErrorKind::forbidden(),
 			"Server does not allow token registration.",
 		));
-	};
+	}
+
+	let valid = services.registration_tokens.is_valid(&body.token).await;
 
-	Ok(check_registration_token_validity::v1::Response { valid: reg_token == body.token })
+	Ok(check_registration_token_validity::v1::Response { valid })
 }
 
 /// Runs through all the deactivation steps:
@@ -30,8 +30,8 @@ pub(crate) async fn get_capabilities_route(
 		available,
 	};
 
-	// we do not implement 3PID stuff
-	capabilities.thirdparty_id_changes = ThirdPartyIdChangesCapability { enabled: false };
+	capabilities.thirdparty_id_changes =
+		ThirdPartyIdChangesCapability { enabled: services.email.enabled() };
 
 	capabilities.get_login_token = GetLoginTokenCapability {
 		enabled: services.server.config.login_via_existing_session,
@@ -0,0 +1,127 @@
+use axum::{
+	extract::{Path, State},
+	response::{IntoResponse, Response},
+};
+use conduwuit::{utils, Err, Result};
+use ruma::{api::OutgoingResponse, RoomId, UserId};
+use serde::Deserialize;
+use serde_json::{json, value::RawValue as RawJsonValue};
+use service::{delayed_events::DELAY_ID_LENGTH, Services};
+
+use crate::{
+	router::extract::{Json, User},
+	RumaResponse,
+};
+
+/// Response of the endpoints sending an event, which answer with a delay ID
+/// instead when the event is sent later (MSC4140).
+pub(crate) enum Sent<T> {
+	Now(T),
+	Delayed(String),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Action {
+	Cancel,
+	Restart,
+	Send,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct UpdateBody {
+	action: Action,
+}
+
+impl<T> IntoResponse for Sent<T>
+where
+	T: OutgoingResponse,
+{
+	fn into_response(self) -> Response {
+		match self {
+			| Self::Now(response) => RumaResponse(response).into_response(),
+			| Self::Delayed(delay_id) =>
+				axum::Json(json!({ "delay_id": delay_id })).into_response(),
+		}
+	}
+}
+
+/// # `GET /_matrix/client/unstable/org.matrix.msc4140/delayed_events`
+///
+/// Lists the delayed events of the sender waiting to be sent, soonest first.
+pub(crate) async fn get_delayed_events_route(
+	State(services): State<crate::State>,
+	User { sender_user }: User,
+) -> Result<impl IntoResponse> {
+	let delayed_events: Vec<_> = services
+		.delayed_events
+		.delayed_events(&sender_user)
+		.await
+		.into_iter()
+		.map(|(delay_id, event)| {
+			let mut entry = json!({
+				"delay_id": delay_id,
+				"room_id": event.room_id,
+				"type": event.event_type,
+				"delay": event.delay,
+				"running_since": event.running_since,
+				"content": event.content,
+			});
+
+			if let Some(state_key) = event.state_key {
+				entry["state_key"] = state_key.into();
+			}
+
+			entry
+		})
+		.collect();
+
+	Ok(axum::Json(json!({ "delayed_events": delayed_events })))
+}
+
+/// # `POST /_matrix/client/unstable/org.matrix.msc4140/delayed_events/{delayId}`
+///
+/// Cancels a delayed event of the sender, starts its delay over, or sends it
+/// right away.
+pub(crate) async fn update_delayed_event_route(
+	State(services): State<crate::State>,
+	User { sender_user }: User,
+	Path(delay_id): Path<String>,
+	Json(body): Json<UpdateBody>,
+) -> Result<impl IntoResponse> {
+	let delayed_events = &services.delayed_events;
+	if delayed_events.get(&delay_id).await?.sender != sender_user {
+		return Err!(Request(NotFound("Delayed event {delay_id} does not exist.")));
+	}
+
+	match body.action {
+		| Action::Cancel => delayed_events.cancel(&delay_id).await?,
+		| Action::Restart => delayed_events.restart(&delay_id).await?,
+		| Action::Send => {
+			delayed_events.send(&delay_id).await?;
+		},
+	}
+
+	Ok(axum::Json(json!({})))
+}
+
+/// Queues an event the sender asked to be sent after a delay. Events of
+/// shadow-banned users are dropped, answering a made up delay ID.
+pub(crate) async fn delay_event(
+	services: &Services,
+	sender: &UserId,
+	room_id: &RoomId,
+	event_type: String,
+	state_key: Option<String>,
+	content: &RawJsonValue,
+	delay: u64,
+) -> Result<String> {
+	if services.users.is_shadow_banned(sender).await {
+		return Ok(utils::random_string(DELAY_ID_LENGTH));
+	}
+
+	services
+		.delayed_events
+		.schedule(sender, room_id, event_type, state_key, content.to_owned(), delay)
+		.await
+}
@@ -18,6 +18,7 @@ use ruma::{
 			get_content, get_content_as_filename, get_content_thumbnail, get_media_config,
 			get_media_preview,
 		},
+		error::ErrorKind,
 		media::create_content,
 	},
 	Mxc, UserId,
@@ -25,6 +26,10 @@ use ruma::{
 
 use crate::Ruma;
 
+/// Server notice sent when an upload is refused for exceeding the media quota.
+const MEDIA_QUOTA_NOTICE: &str = "You have reached your media storage quota, so further uploads \
+                                  will be refused. Contact the server admins to raise it.";
+
 /// # `GET /_matrix/client/v1/media/config`
 pub(crate) async fn get_media_config_route(
 	State(services): State<crate::State>,
@@ -41,6 +46,8 @@ pub(crate) async fn get_media_config_route(
 ///
 /// - Some metadata will be saved in the database
 /// - Media will be saved in the media/ directory
+/// - Uploads over the user's `media_user_quota` are refused, with a server
+///   notice telling the user
 #[tracing::instrument(
 	name = "media_upload",
 	level = "debug",
@@ -62,14 +69,27 @@ pub(crate) async fn create_content_route(
 		media_id: &utils::random_string(MXC_LENGTH),
 	};
 
-	services
+	let result = services
 		.media
 		.create(&mxc, Some(user), Some(&content_disposition), content_type, &body.file)
-		.await
-		.map(|()| create_content::v3::Response {
-			content_uri: mxc.to_string().into(),
-			blurhash: None,
-		})
+		.await;
+
+	if let Err(e) = &result {
+		if matches!(e.kind(), ErrorKind::TooLarge) {
+			if let Err(e) = services
+				.server_notices
+				.usage_limit_reached(user, "media_quota", MEDIA_QUOTA_NOTICE.to_owned())
+				.await
+			{
+				debug_warn!(%user, "Failed to send media quota notice: {e}");
+			}
+		}
+	}
+
+	result.map(|()| create_content::v3::Response {
+		content_uri: mxc.to_string().into(),
+		blurhash: None,
+	})
 }
 
 /// # `GET /_matrix/client/v1/media/thumbnail/{serverName}/{mediaId}`
@@ -456,6 +456,10 @@ pub(crate) async fn knock_room_route(
 		},
 	};
 
+	if services.users.is_shadow_banned(sender_user).await {
+		return Ok(knock_room::v3::Response::new(room_id));
+	}
+
 	knock_room_by_id_helper(&services, sender_user, &room_id, body.reason.clone(), &servers)
 		.boxed()
 		.await
@@ -503,6 +507,10 @@ pub(crate) async fn invite_user_route(
 	)
 	.await?;
 
+	if services.users.is_shadow_banned(sender_user).await {
+		return Ok(invite_user::v3::Response {});
+	}
+
 	if let invite_user::v3::InvitationRecipient::UserId { user_id } = &body.recipient {
 		let sender_ignored_recipient = services.users.user_is_ignored(sender_user, user_id);
 		let recipient_ignored_by_sender = services.users.user_is_ignored(user_id, sender_user);
@@ -855,6 +863,8 @@ pub async fn join_room_by_id_helper(
 		}
 	}
 
+	policy_check(services, sender_user, room_id).await?;
+
 	let server_in_room = services
 		.rooms
 		.state_cache
@@ -1531,6 +1541,26 @@ async fn make_join_request(
 	make_join_response_and_server
 }
 
+/// Checks the policy lists the server is subscribed to neither ban the user
+/// nor, unless they are an admin, the room.
+async fn policy_check(services: &Services, user_id: &UserId, room_id: &RoomId) -> Result {
+	if let Some(rule) = services.policy_lists.user_ban(user_id) {
+		debug_warn!("{user_id} is banned by policy list {}", rule.policy_room);
+		return Err!(Request(Forbidden("You are banned by a policy list of this server.")));
+	}
+
+	if let Some(rule) = services.policy_lists.room_ban(room_id) {
+		if !services.users.is_admin(user_id).await {
+			debug_warn!("{room_id} is banned by policy list {}", rule.policy_room);
+			return Err!(Request(Forbidden(
+				"This room is banned by a policy list of this server."
+			)));
+		}
+	}
+
+	Ok(())
+}
+
 pub(crate) async fn invite_helper(
 	services: &Services,
 	sender_user: &UserId,
@@ -1547,6 +1577,8 @@ pub(crate) async fn invite_helper(
 		return Err!(Request(Forbidden("Invites are not allowed on this server.")));
 	}
 
+	policy_check(services, sender_user, room_id).await?;
+
 	if !services.globals.user_is_local(user_id) {
 		let (pdu, pdu_json, invite_room_state) = {
 			let state_lock = services.rooms.state.mutex.lock(room_id).await;
@@ -5,6 +5,7 @@ pub(super) mod appservice;
 pub(super) mod backup;
 pub(super) mod capabilities;
 pub(super) mod context;
+pub(super) mod delayed_events;
 pub(super) mod device;
 pub(super) mod directory;
 pub(super) mod filter;
@@ -26,11 +27,13 @@ pub(super) mod search;
 pub(super) mod send;
 pub(super) mod session;
 pub(super) mod space;
+pub(super) mod sso;
 pub(super) mod state;
 pub(super) mod sync;
 pub(super) mod tag;
 pub(super) mod thirdparty;
 pub(super) mod threads;
+pub(super) mod threepid;
 pub(super) mod to_device;
 pub(super) mod typing;
 pub(super) mod unstable;
@@ -47,6 +50,7 @@ pub(super) use appservice::*;
 pub(super) use backup::*;
 pub(super) use capabilities::*;
 pub(super) use context::*;
+pub(super) use delayed_events::*;
 pub(super) use device::*;
 pub(super) use directory::*;
 pub(super) use filter::*;
@@ -65,16 +69,20 @@ pub(super) use read_marker::*;
 pub(super) use redact::*;
 pub(super) use relations::*;
 pub(super) use report::*;
+pub use room::upgrade_room;
 pub(super) use room::*;
+use ruma::OwnedEventId;
 pub(super) use search::*;
 pub(super) use send::*;
 pub(super) use session::*;
 pub(super) use space::*;
+pub(super) use sso::*;
 pub(super) use state::*;
 pub(super) use sync::*;
 pub(super) use tag::*;
 pub(super) use thirdparty::*;
 pub(super) use threads::*;
+pub(super) use threepid::*;
 pub(super) use to_device::*;
 pub(super) use typing::*;
 pub(super) use unstable::*;
@@ -91,3 +99,10 @@ const TOKEN_LENGTH: usize = 32;
 
 /// generated user session ID length
 const SESSION_ID_LENGTH: usize = service::uiaa::SESSION_ID_LENGTH;
+
+/// Event ID answered to a shadow-banned user for an event which was dropped.
+fn shadow_ban_event_id() -> OwnedEventId {
+	format!("${}", conduwuit::utils::random_string(43))
+		.try_into()
+		.expect("valid event ID")
+}
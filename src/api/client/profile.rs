@@ -40,6 +40,10 @@ pub(crate) async fn set_displayname_route(
 		return Err!(Request(Forbidden("You cannot update the profile of another user")));
 	}
 
+	if services.users.is_shadow_banned(&body.user_id).await {
+		return Ok(set_display_name::v3::Response {});
+	}
+
 	let all_joined_rooms: Vec<OwnedRoomId> = services
 		.rooms
 		.state_cache
@@ -129,6 +133,10 @@ pub(crate) async fn set_avatar_url_route(
 		return Err!(Request(Forbidden("You cannot update the profile of another user")));
 	}
 
+	if services.users.is_shadow_banned(&body.user_id).await {
+		return Ok(set_avatar_url::v3::Response {});
+	}
+
 	let all_joined_rooms: Vec<OwnedRoomId> = services
 		.rooms
 		.state_cache
@@ -1,4 +1,9 @@
-use axum::extract::State;
+mod tests;
+
+use axum::{
+	extract::{Query, State},
+	response::Html,
+};
 use conduwuit::{err, Err};
 use ruma::{
 	api::client::{
@@ -17,8 +22,9 @@ use ruma::{
 		InsertPushRuleError, PredefinedContentRuleId, PredefinedOverrideRuleId,
 		RemovePushRuleError, Ruleset,
 	},
-	CanonicalJsonObject, CanonicalJsonValue,
+	CanonicalJsonObject, CanonicalJsonValue, OwnedUserId,
 };
+use serde::Deserialize;
 use service::Services;
 
 use crate::{Error, Result, Ruma};
@@ -509,6 +515,46 @@ pub(crate) async fn set_pushers_route(
 	Ok(set_pusher::v3::Response::new())
 }
 
+#[derive(Deserialize)]
+pub(crate) struct Unsubscribe {
+	user_id: OwnedUserId,
+	pushkey: String,
+	token: String,
+}
+
+/// # `GET /_conduwuit/email/unsubscribe`
+///
+/// Asks for confirmation before removing an email pusher from the link in one
+/// of its notification digests, so that link scanners following it do not
+/// unsubscribe the user.
+pub(crate) async fn unsubscribe_email_page_route(
+	Query(_params): Query<Unsubscribe>,
+) -> Html<&'static str> {
+	Html(
+		"<!DOCTYPE html><title>Unsubscribe</title><p>Stop getting notifications by email at \
+		 this address?</p><form method=\"post\"><button \
+		 type=\"submit\">Unsubscribe</button></form>",
+	)
+}
+
+/// # `POST /_conduwuit/email/unsubscribe`
+///
+/// Removes an email pusher, once confirmed from the unsubscribe page.
+pub(crate) async fn unsubscribe_email_route(
+	State(services): State<crate::State>,
+	Query(params): Query<Unsubscribe>,
+) -> Result<Html<&'static str>> {
+	services
+		.pusher
+		.unsubscribe_email(&params.user_id, &params.pushkey, &params.token)
+		.await?;
+
+	Ok(Html(
+		"<!DOCTYPE html><title>Unsubscribed</title><p>You will no longer get notifications by \
+		 email at this address.</p>",
+	))
+}
+
 /// user somehow has bad push rules, these must always exist per spec.
 /// so recreate it and return server default silently
 async fn recreate_push_rules_and_return(
@@ -0,0 +1,21 @@
+#![cfg(test)]
+
+use axum::extract::Query;
+use ruma::owned_user_id;
+
+use super::{unsubscribe_email_page_route, Unsubscribe};
+
+#[tokio::test]
+async fn unsubscribe_page_only_asks() {
+	let params = Unsubscribe {
+		user_id: owned_user_id!("@alice:example.com"),
+		pushkey: "alice@example.com".to_owned(),
+		token: "token".to_owned(),
+	};
+
+	let page = unsubscribe_email_page_route(Query(params)).await.0;
+	assert!(
+		page.contains("<form method=\"post\">"),
+		"unsubscribing takes a POST from the page, so following the link does not"
+	);
+}
@@ -57,7 +57,10 @@ pub(crate) async fn set_read_marker_route(
 			.await?;
 	}
 
-	if let Some(event) = &body.read_receipt {
+	// public receipts of shadow-banned users are dropped; the private ones are
+	// only seen by themselves
+	let shadow_banned = services.users.is_shadow_banned(sender_user).await;
+	if let Some(event) = body.read_receipt.as_ref().filter(|_| !shadow_banned) {
 		let receipt_content = BTreeMap::from_iter([(
 			event.to_owned(),
 			BTreeMap::from_iter([(
@@ -133,6 +136,7 @@ pub(crate) async fn create_receipt_route(
 			.await?;
 	}
 
+	let shadow_banned = services.users.is_shadow_banned(sender_user).await;
 	match body.receipt_type {
 		| create_receipt::v3::ReceiptType::FullyRead => {
 			let fully_read_event = ruma::events::fully_read::FullyReadEvent {
@@ -150,6 +154,9 @@ pub(crate) async fn create_receipt_route(
 				)
 				.await?;
 		},
+		| create_receipt::v3::ReceiptType::Read if shadow_banned => {
+			// public receipts of shadow-banned users are dropped
+		},
 		| create_receipt::v3::ReceiptType::Read => {
 			let receipt_content = BTreeMap::from_iter([(
 				body.event_id.clone(),
@@ -17,6 +17,10 @@ pub(crate) async fn redact_event_route(
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
 	let body = body.body;
 
+	if services.users.is_shadow_banned(sender_user).await {
+		return Ok(redact_event::v3::Response { event_id: super::shadow_ban_event_id() });
+	}
+
 	let state_lock = services.rooms.state.mutex.lock(&body.room_id).await;
 
 	let event_id = services
@@ -2,7 +2,7 @@ use std::time::Duration;
 
 use axum::extract::State;
 use axum_client_ip::InsecureClientIp;
-use conduwuit::{info, utils::ReadyExt, Err};
+use conduwuit::{info, utils, utils::ReadyExt, Err};
 use rand::Rng;
 use ruma::{
 	api::client::{
@@ -16,7 +16,11 @@ use tokio::time::sleep;
 
 use crate::{
 	debug_info,
-	service::{pdu::PduEvent, Services},
+	service::{
+		pdu::PduEvent,
+		reports::{Submission, Target},
+		Services,
+	},
 	Error, Result, Ruma,
 };
 
@@ -58,18 +62,8 @@ pub(crate) async fn report_room_route(
 		)));
 	}
 
-	// send admin room message that we received the report with an @room ping for
-	// urgency
-	services
-		.admin
-		.send_message(message::RoomMessageEventContent::text_markdown(format!(
-			"@room Room report received from {} -\n\nRoom ID: {}\n\nReport Reason: {}",
-			sender_user.to_owned(),
-			body.room_id,
-			body.reason.as_deref().unwrap_or("")
-		)))
-		.await
-		.ok();
+	let target = Target::Room { room_id: body.room_id.clone() };
+	submit(&services, target, sender_user, body.reason.clone(), None).await?;
 
 	Ok(report_room::v3::Response {})
 }
@@ -112,24 +106,55 @@ pub(crate) async fn report_event_route(
 	)
 	.await?;
 
-	// send admin room message that we received the report with an @room ping for
-	// urgency
+	let target = Target::Event {
+		room_id: pdu.room_id.clone(),
+		event_id: pdu.event_id.clone(),
+		sender: pdu.sender.clone(),
+	};
+
+	let score = body.score.map(i64::from);
+	submit(&services, target, sender_user, body.reason.clone(), score).await?;
+
+	Ok(report_content::v3::Response {})
+}
+
+/// Adds the report to the moderation queue. The admin room is told about
+/// new reports only; further reports of the same target are gathered in the
+/// existing one.
+async fn submit(
+	services: &Services,
+	target: Target,
+	reporter: &UserId,
+	reason: Option<String>,
+	score: Option<i64>,
+) -> Result<()> {
+	let submission = Submission {
+		reporter: reporter.to_owned(),
+		reason,
+		score,
+		reported_at: utils::millis_since_unix_epoch(),
+	};
+
+	let subject = match &target {
+		| Target::Event { event_id, sender, .. } => format!("event {event_id} sent by {sender}"),
+		| Target::Room { room_id } => format!("room {room_id}"),
+	};
+
+	let (id, new) = services.reports.submit(target, submission).await?;
+	if !new {
+		debug_info!("Added report by {reporter} to report {id}");
+		return Ok(());
+	}
+
 	services
 		.admin
-		.send_message(message::RoomMessageEventContent::text_markdown(format!(
-			"@room Event report received from {} -\n\nEvent ID: {}\nRoom ID: {}\nSent By: \
-			 {}\n\nReport Score: {}\nReport Reason: {}",
-			sender_user.to_owned(),
-			pdu.event_id,
-			pdu.room_id,
-			pdu.sender,
-			body.score.unwrap_or_else(|| ruma::Int::from(0)),
-			body.reason.as_deref().unwrap_or("")
+		.send_message(message::RoomMessageEventContent::notice_markdown(format!(
+			"New report {id} by {reporter} of {subject}. See `!admin reports show {id}`."
 		)))
 		.await
 		.ok();
 
-	Ok(report_content::v3::Response {})
+	Ok(())
 }
 
 /// in the following order:
@@ -122,15 +122,26 @@ pub(crate) async fn create_room_route(
 
 	let room_version = match body.room_version.clone() {
 		| Some(room_version) =>
-			if services.server.supported_room_version(&room_version) {
-				room_version
-			} else {
+			if !services.server.supported_room_version(&room_version) {
 				return Err(Error::BadRequest(
 					ErrorKind::UnsupportedRoomVersion,
 					"This server does not support that room version.",
 				));
+			} else if !services.server.room_version_allowed(
+				&room_version,
+				sender_user,
+				body.appservice_info.is_some() || services.users.is_admin(sender_user).await,
+			) {
+				return Err!(Request(Forbidden(
+					"Creating rooms of version {room_version} is not allowed on this server."
+				)));
+			} else {
+				room_version
 			},
-		| None => services.server.config.default_room_version.clone(),
+		| None => services
+			.server
+			.recommended_room_version(sender_user)
+			.unwrap_or_else(|| services.server.config.default_room_version.clone()),
 	};
 
 	let create_content = match &body.creation_content {
@@ -419,6 +430,7 @@ pub(crate) async fn create_room_route(
 
 	// 8. Events implied by invite (and TODO: invite_3pid)
 	drop(state_lock);
+	let shadow_banned = services.users.is_shadow_banned(sender_user).await;
 	for user_id in &body.invite {
 		if services.users.user_is_ignored(sender_user, user_id).await {
 			return Err!(Request(Forbidden(
@@ -430,6 +442,11 @@ pub(crate) async fn create_room_route(
 			continue;
 		}
 
+		if shadow_banned {
+			// invites of shadow-banned users are dropped, pretend they worked
+			continue;
+		}
+
 		if let Err(e) =
 			invite_helper(&services, sender_user, user_id, &room_id, None, body.is_direct)
 				.boxed()
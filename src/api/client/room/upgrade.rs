@@ -1,7 +1,7 @@
 use std::cmp::max;
 
 use axum::extract::State;
-use conduwuit::{err, info, pdu::PduBuilder, Error, Result};
+use conduwuit::{err, info, pdu::PduBuilder, Err, Error, Result};
 use futures::StreamExt;
 use ruma::{
 	api::client::{error::ErrorKind, room::upgrade_room},
@@ -13,9 +13,10 @@ use ruma::{
 		},
 		StateEventType, TimelineEventType,
 	},
-	int, CanonicalJsonObject, RoomId, RoomVersionId,
+	int, CanonicalJsonObject, OwnedRoomId, RoomId, RoomVersionId, UserId,
 };
 use serde_json::{json, value::to_raw_value};
+use service::Services;
 
 use crate::Ruma;
 
@@ -46,10 +47,6 @@ pub(crate) async fn upgrade_room_route(
 	State(services): State<crate::State>,
 	body: Ruma<upgrade_room::v3::Request>,
 ) -> Result<upgrade_room::v3::Response> {
-	debug_assert!(
-		TRANSFERABLE_STATE_EVENTS.is_sorted(),
-		"TRANSFERABLE_STATE_EVENTS is not sorted"
-	);
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
 
 	if !services.server.supported_room_version(&body.new_version) {
@@ -59,6 +56,37 @@ pub(crate) async fn upgrade_room_route(
 		));
 	}
 
+	if !services.server.room_version_allowed(
+		&body.new_version,
+		sender_user,
+		body.appservice_info.is_some() || services.users.is_admin(sender_user).await,
+	) {
+		return Err!(Request(Forbidden(
+			"Upgrading rooms to version {} is not allowed on this server.",
+			body.new_version
+		)));
+	}
+
+	let replacement_room =
+		upgrade_room(&services, sender_user, &body.room_id, &body.new_version).await?;
+
+	Ok(upgrade_room::v3::Response { replacement_room })
+}
+
+/// Upgrades a room on behalf of a user who may send its tombstone, returning
+/// the replacement room. The version is not checked against the room version
+/// policy.
+pub async fn upgrade_room(
+	services: &Services,
+	sender_user: &UserId,
+	room_id: &RoomId,
+	new_version: &RoomVersionId,
+) -> Result<OwnedRoomId> {
+	debug_assert!(
+		TRANSFERABLE_STATE_EVENTS.is_sorted(),
+		"TRANSFERABLE_STATE_EVENTS is not sorted"
+	);
+
 	// Create a replacement room
 	let replacement_room = RoomId::new(services.globals.server_name());
 
@@ -68,7 +96,7 @@ pub(crate) async fn upgrade_room_route(
 		.get_or_create_shortroomid(&replacement_room)
 		.await;
 
-	let state_lock = services.rooms.state.mutex.lock(&body.room_id).await;
+	let state_lock = services.rooms.state.mutex.lock(room_id).await;
 
 	// Send a m.room.tombstone event to the old room to indicate that it is not
 	// intended to be used any further Fail if the sender does not have the required
@@ -82,7 +110,7 @@ pub(crate) async fn upgrade_room_route(
 				replacement_room: replacement_room.clone(),
 			}),
 			sender_user,
-			&body.room_id,
+			room_id,
 			&state_lock,
 		)
 		.await?;
@@ -95,13 +123,13 @@ pub(crate) async fn upgrade_room_route(
 	let mut create_event_content: CanonicalJsonObject = services
 		.rooms
 		.state_accessor
-		.room_state_get_content(&body.room_id, &StateEventType::RoomCreate, "")
+		.room_state_get_content(room_id, &StateEventType::RoomCreate, "")
 		.await
 		.map_err(|_| err!(Database("Found room without m.room.create event.")))?;
 
 	// Use the m.room.tombstone event as the predecessor
 	let predecessor = Some(ruma::events::room::create::PreviousRoom::new(
-		body.room_id.clone(),
+		room_id.to_owned(),
 		(*tombstone_event_id).to_owned(),
 	));
 
@@ -109,7 +137,7 @@ pub(crate) async fn upgrade_room_route(
 	// room_version
 	{
 		use RoomVersionId::*;
-		match body.new_version {
+		match *new_version {
 			| V1 | V2 | V3 | V4 | V5 | V6 | V7 | V8 | V9 | V10 => {
 				create_event_content.insert(
 					"creator".into(),
@@ -128,7 +156,7 @@ pub(crate) async fn upgrade_room_route(
 
 	create_event_content.insert(
 		"room_version".into(),
-		json!(&body.new_version)
+		json!(new_version)
 			.try_into()
 			.map_err(|_| Error::BadRequest(ErrorKind::BadJson, "Error forming creation event"))?,
 	);
@@ -203,7 +231,7 @@ pub(crate) async fn upgrade_room_route(
 		let event_content = match services
 			.rooms
 			.state_accessor
-			.room_state_get(&body.room_id, event_type, "")
+			.room_state_get(room_id, event_type, "")
 			.await
 		{
 			| Ok(v) => v.content.clone(),
@@ -228,11 +256,7 @@ pub(crate) async fn upgrade_room_route(
 	}
 
 	// Moves any local aliases to the new room
-	let mut local_aliases = services
-		.rooms
-		.alias
-		.local_aliases_for_room(&body.room_id)
-		.boxed();
+	let mut local_aliases = services.rooms.alias.local_aliases_for_room(room_id).boxed();
 
 	while let Some(alias) = local_aliases.next().await {
 		services
@@ -251,7 +275,7 @@ pub(crate) async fn upgrade_room_route(
 	let power_levels_event_content: RoomPowerLevelsEventContent = services
 		.rooms
 		.state_accessor
-		.room_state_get_content(&body.room_id, &StateEventType::RoomPowerLevels, "")
+		.room_state_get_content(room_id, &StateEventType::RoomPowerLevels, "")
 		.await
 		.map_err(|_| err!(Database("Found room without m.room.power_levels event.")))?;
 
@@ -278,13 +302,12 @@ pub(crate) async fn upgrade_room_route(
 				..power_levels_event_content
 			}),
 			sender_user,
-			&body.room_id,
+			room_id,
 			&state_lock,
 		)
 		.await?;
 
 	drop(state_lock);
 
-	// Return the replacement room id
-	Ok(upgrade_room::v3::Response { replacement_room })
+	Ok(replacement_room)
 }
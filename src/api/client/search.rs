@@ -1,63 +1,75 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use axum::extract::State;
 use conduwuit::{
-	at, is_true,
+	is_true,
 	result::FlatOk,
-	utils::{stream::ReadyExt, IterStream},
+	utils::{
+		math::usize_from_ruma,
+		stream::{ReadyExt, TryIgnore, WidebandExt},
+		IterStream,
+	},
 	Err, PduEvent, Result,
 };
-use futures::{future::OptionFuture, FutureExt, StreamExt, TryFutureExt};
+use futures::{StreamExt, TryFutureExt};
 use ruma::{
 	api::client::search::search_events::{
 		self,
-		v3::{Criteria, EventContextResult, ResultCategories, ResultRoomEvents, SearchResult},
+		v3::{
+			Criteria, EventContextResult, GroupingKey, OwnedRoomIdOrUserId, ResultCategories,
+			ResultGroup, ResultRoomEvents, SearchResult, UserProfile,
+		},
 	},
 	events::AnyStateEvent,
 	serde::Raw,
-	OwnedRoomId, RoomId, UInt, UserId,
+	OwnedRoomId, OwnedUserId, RoomId, UInt, UserId,
 };
 use search_events::v3::{Request, Response};
-use service::{rooms::search::RoomQuery, Services};
+use service::{
+	rooms::{
+		search::{Hit, Query},
+		timeline::PdusIterItem,
+	},
+	Services,
+};
 
-use crate::Ruma;
+use crate::{
+	client::message::{ignored_filter, visibility_filter},
+	Ruma,
+};
 
 type RoomStates = BTreeMap<OwnedRoomId, RoomState>;
 type RoomState = Vec<Raw<AnyStateEvent>>;
+type Groups = BTreeMap<GroupingKey, BTreeMap<OwnedRoomIdOrUserId, ResultGroup>>;
 
 const LIMIT_DEFAULT: usize = 10;
 const LIMIT_MAX: usize = 100;
-const BATCH_MAX: usize = 20;
+const CONTEXT_LIMIT_MAX: usize = 20;
 
 /// # `POST /_matrix/client/r0/search`
 ///
 /// Searches rooms for messages.
 ///
-/// - Only works if the user is currently joined to the room (TODO: Respect
-///   history visibility)
+/// - Searches all rooms the user is joined to, unless the filter gives rooms
+/// - Results are ranked with BM25 unless ordered by `recent`
+/// - Quoted words are matched as a phrase and words ending with `*` as a prefix
 pub(crate) async fn search_events_route(
 	State(services): State<crate::State>,
 	body: Ruma<Request>,
 ) -> Result<Response> {
 	let sender_user = body.sender_user();
 	let next_batch = body.next_batch.as_deref();
-	let room_events_result: OptionFuture<_> = body
-		.search_categories
-		.room_events
-		.as_ref()
-		.map(|criteria| category_room_events(&services, sender_user, next_batch, criteria))
-		.into();
+	let room_events = match body.search_categories.room_events.as_ref() {
+		| Some(criteria) =>
+			category_room_events(&services, sender_user, next_batch, criteria).await?,
+		| None => ResultRoomEvents::default(),
+	};
 
 	Ok(Response {
-		search_categories: ResultCategories {
-			room_events: room_events_result
-				.await
-				.unwrap_or_else(|| Ok(ResultRoomEvents::default()))?,
-		},
+		search_categories: ResultCategories { room_events },
 	})
 }
 
-#[allow(clippy::map_unwrap_or)]
 async fn category_room_events(
 	services: &Services,
 	sender_user: &UserId,
@@ -73,64 +85,49 @@ async fn category_room_events(
 		.unwrap_or(LIMIT_DEFAULT)
 		.min(LIMIT_MAX);
 
-	let next_batch: usize = next_batch
-		.map(str::parse)
-		.transpose()?
-		.unwrap_or(0)
-		.min(limit.saturating_mul(BATCH_MAX));
-
-	let rooms = filter
-		.rooms
-		.clone()
-		.map(IntoIterator::into_iter)
-		.map(IterStream::stream)
-		.map(StreamExt::boxed)
-		.unwrap_or_else(|| {
+	let rooms: Vec<OwnedRoomId> = match filter.rooms.clone() {
+		| Some(rooms) => rooms,
+		| None =>
 			services
 				.rooms
 				.state_cache
 				.rooms_joined(sender_user)
 				.map(ToOwned::to_owned)
-				.boxed()
-		});
+				.collect()
+				.await,
+	};
 
-	let results: Vec<_> = rooms
+	let rooms: Vec<_> = rooms
+		.into_iter()
+		.stream()
 		.filter_map(|room_id| async move {
 			check_room_visible(services, sender_user, &room_id, criteria)
 				.await
 				.is_ok()
 				.then_some(room_id)
 		})
-		.filter_map(|room_id| async move {
-			let query = RoomQuery {
-				room_id: &room_id,
-				user_id: Some(sender_user),
-				criteria,
-				skip: next_batch,
-				limit,
-			};
-
-			let (count, results) = services.rooms.search.search_pdus(&query).await.ok()?;
-
-			results
-				.collect::<Vec<_>>()
-				.map(|results| (room_id.clone(), count, results))
-				.map(Some)
-				.await
-		})
 		.collect()
 		.await;
 
-	let total: UInt = results
-		.iter()
-		.fold(0, |a: usize, (_, count, _)| a.saturating_add(*count))
-		.try_into()?;
+	let query = Query {
+		rooms: &rooms,
+		user_id: Some(sender_user),
+		criteria,
+		limit,
+		next_batch,
+	};
+
+	let found = services.rooms.search.search(&query).await?;
 
-	let state: RoomStates = results
+	let state: RoomStates = found
+		.hits
 		.iter()
+		.map(|hit| &hit.pdu.room_id)
+		.collect::<BTreeSet<_>>()
+		.into_iter()
 		.stream()
 		.ready_filter(|_| criteria.include_state.is_some_and(is_true!()))
-		.filter_map(|(room_id, ..)| async move {
+		.filter_map(|room_id| async move {
 			procure_room_state(services, room_id)
 				.map_ok(|state| (room_id.clone(), state))
 				.await
@@ -139,47 +136,167 @@ async fn category_room_events(
 		.collect()
 		.await;
 
-	let results: Vec<SearchResult> = results
-		.into_iter()
-		.map(at!(2))
-		.flatten()
+	let groups = group_results(criteria, &found.hits, found.next_batch.as_deref());
+
+	let results: Vec<SearchResult> = found
+		.hits
+		.iter()
 		.stream()
-		.map(|pdu| pdu.to_room_event())
-		.map(|result| SearchResult {
-			rank: None,
-			result: Some(result),
-			context: EventContextResult {
-				profile_info: BTreeMap::new(), //TODO
-				events_after: Vec::new(),      //TODO
-				events_before: Vec::new(),     //TODO
-				start: None,                   //TODO
-				end: None,                     //TODO
-			},
+		.then(|hit| async move {
+			SearchResult {
+				rank: Some(hit.rank),
+				result: Some(hit.pdu.to_room_event()),
+				context: event_context(services, sender_user, criteria, hit).await,
+			}
 		})
 		.collect()
 		.await;
 
-	let highlights = criteria
-		.search_term
-		.split_terminator(|c: char| !c.is_alphanumeric())
-		.map(str::to_lowercase)
-		.collect();
-
-	let next_batch = (results.len() >= limit)
-		.then_some(next_batch.saturating_add(results.len()))
-		.as_ref()
-		.map(ToString::to_string);
-
 	Ok(ResultRoomEvents {
-		count: Some(total),
-		next_batch,
+		count: Some(UInt::try_from(found.count)?),
+		next_batch: found.next_batch,
 		results,
 		state,
-		highlights,
-		groups: BTreeMap::new(), // TODO
+		highlights: found.highlights,
+		groups,
 	})
 }
 
+/// Fetches the events around a result and the profiles of their senders, as
+/// asked for by the `event_context` of the search.
+async fn event_context(
+	services: &Services,
+	user_id: &UserId,
+	criteria: &Criteria,
+	hit: &Hit,
+) -> EventContextResult {
+	let context = &criteria.event_context;
+	let room_id = &hit.pdu.room_id;
+
+	let events_before: Vec<_> = services
+		.rooms
+		.timeline
+		.pdus_rev(Some(user_id), room_id, Some(hit.count))
+		.ignore_err()
+		.wide_filter_map(|item| ignored_filter(services, item, user_id))
+		.wide_filter_map(|item| visibility_filter(services, item, user_id))
+		.take(usize_from_ruma(context.before_limit).min(CONTEXT_LIMIT_MAX))
+		.collect()
+		.await;
+
+	let events_after: Vec<_> = services
+		.rooms
+		.timeline
+		.pdus(Some(user_id), room_id, Some(hit.count))
+		.ignore_err()
+		.wide_filter_map(|item| ignored_filter(services, item, user_id))
+		.wide_filter_map(|item| visibility_filter(services, item, user_id))
+		.take(usize_from_ruma(context.after_limit).min(CONTEXT_LIMIT_MAX))
+		.collect()
+		.await;
+
+	let profile_info = if context.include_profile {
+		let senders: BTreeSet<_> = events_before
+			.iter()
+			.chain(events_after.iter())
+			.map(|(_, pdu)| &pdu.sender)
+			.chain(std::iter::once(&hit.pdu.sender))
+			.collect();
+
+		senders
+			.into_iter()
+			.stream()
+			.then(|sender| profile(services, room_id, sender))
+			.collect()
+			.await
+	} else {
+		BTreeMap::new()
+	};
+
+	let token = |events: &[PdusIterItem]| {
+		events
+			.last()
+			.map_or(hit.count, |(count, _)| *count)
+			.to_string()
+	};
+
+	EventContextResult {
+		start: Some(token(&events_before)),
+		end: Some(token(&events_after)),
+		profile_info,
+		events_before: events_before
+			.iter()
+			.map(|(_, pdu)| pdu.to_room_event())
+			.collect(),
+		events_after: events_after
+			.iter()
+			.map(|(_, pdu)| pdu.to_room_event())
+			.collect(),
+	}
+}
+
+/// Profile of a user in a room, falling back to their global profile if they
+/// have no membership in it.
+async fn profile(
+	services: &Services,
+	room_id: &RoomId,
+	user_id: &UserId,
+) -> (OwnedUserId, UserProfile) {
+	let profile = match services
+		.rooms
+		.state_accessor
+		.get_member(room_id, user_id)
+		.await
+	{
+		| Ok(member) => UserProfile {
+			displayname: member.displayname,
+			avatar_url: member.avatar_url,
+		},
+		| Err(_) => UserProfile {
+			displayname: services.users.displayname(user_id).await.ok(),
+			avatar_url: services.users.avatar_url(user_id).await.ok(),
+		},
+	};
+
+	(user_id.to_owned(), profile)
+}
+
+/// Groups the results of this page by room or sender, as asked for by the
+/// `groupings` of the search. Groups are ordered by their best result, and
+/// continue with the same token as the results.
+fn group_results(criteria: &Criteria, hits: &[Hit], next_batch: Option<&str>) -> Groups {
+	criteria
+		.groupings
+		.group_by
+		.iter()
+		.filter_map(|grouping| grouping.key.clone())
+		.map(|key| {
+			let mut groups: BTreeMap<OwnedRoomIdOrUserId, ResultGroup> = BTreeMap::new();
+			for hit in hits {
+				let id = match key {
+					| GroupingKey::RoomId => OwnedRoomIdOrUserId::RoomId(hit.pdu.room_id.clone()),
+					| GroupingKey::Sender => OwnedRoomIdOrUserId::UserId(hit.pdu.sender.clone()),
+					| _ => continue,
+				};
+
+				let order = UInt::try_from(groups.len().saturating_add(1)).ok();
+				groups
+					.entry(id)
+					.or_insert_with(|| ResultGroup {
+						next_batch: next_batch.map(ToOwned::to_owned),
+						order,
+						results: Vec::new(),
+					})
+					.results
+					.push(hit.pdu.event_id.clone());
+			}
+
+			(key, groups)
+		})
+		.filter(|(_, groups)| !groups.is_empty())
+		.collect()
+}
+
 async fn procure_room_state(services: &Services, room_id: &RoomId) -> Result<RoomState> {
 	let state_map = services
 		.rooms
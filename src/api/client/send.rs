@@ -2,9 +2,12 @@ use std::collections::BTreeMap;
 
 use axum::extract::State;
 use conduwuit::{err, Err};
-use ruma::{api::client::message::send_message_event, events::MessageLikeEventType};
+use ruma::{
+	api::client::message::send_message_event, events::MessageLikeEventType, OwnedEventId,
+};
 use serde_json::from_str;
 
+use super::{delay_event, Sent};
 use crate::{service::pdu::PduBuilder, utils, Result, Ruma};
 
 /// # `PUT /_matrix/client/v3/rooms/{roomId}/send/{eventType}/{txnId}`
@@ -16,10 +19,14 @@ use crate::{service::pdu::PduBuilder, utils, Result, Ruma};
 /// - The only requirement for the content is that it has to be valid json
 /// - Tries to send the event into the room, auth rules will determine if it is
 ///   allowed
+/// - Events of shadow-banned users are dropped, answering a made up event id
+/// - With the `org.matrix.msc4140.delay` query parameter, queues the event to
+///   be sent after that many milliseconds and answers with a delay id instead,
+///   which a repeated txn id answers again
 pub(crate) async fn send_message_event_route(
 	State(services): State<crate::State>,
 	body: Ruma<send_message_event::v3::Request>,
-) -> Result<send_message_event::v3::Response> {
+) -> Result<Sent<send_message_event::v3::Response>> {
 	let sender_user = body.sender_user();
 	let sender_device = body.sender_device.as_deref();
 	let appservice_info = body.appservice_info.as_ref();
@@ -53,13 +60,50 @@ pub(crate) async fn send_message_event_route(
 			)));
 		}
 
-		return Ok(send_message_event::v3::Response {
-			event_id: utils::string_from_bytes(&response)
-				.map(TryInto::try_into)
-				.map_err(|e| err!(Database("Invalid event_id in txnid data: {e:?}")))??,
+		let response = utils::string_from_bytes(&response)
+			.map_err(|e| err!(Database("Invalid event_id in txnid data: {e:?}")))?;
+
+		// delay ids are kept for delayed events, which never parse as event ids
+		return Ok(match OwnedEventId::try_from(response.as_str()) {
+			| Ok(event_id) => Sent::Now(send_message_event::v3::Response { event_id }),
+			| Err(_) => Sent::Delayed(response),
 		});
 	}
 
+	if let Some(delay) = body.delay {
+		let delay_id = delay_event(
+			&services,
+			sender_user,
+			&body.room_id,
+			body.event_type.to_string(),
+			None,
+			body.body.body.json(),
+			delay,
+		)
+		.await?;
+
+		services.transaction_ids.add_txnid(
+			sender_user,
+			sender_device,
+			&body.txn_id,
+			delay_id.as_bytes(),
+		);
+
+		return Ok(Sent::Delayed(delay_id));
+	}
+
+	if services.users.is_shadow_banned(sender_user).await {
+		let event_id = super::shadow_ban_event_id();
+		services.transaction_ids.add_txnid(
+			sender_user,
+			sender_device,
+			&body.txn_id,
+			event_id.as_bytes(),
+		);
+
+		return Ok(Sent::Now(send_message_event::v3::Response { event_id }));
+	}
+
 	let mut unsigned = BTreeMap::new();
 	unsigned.insert("transaction_id".to_owned(), body.txn_id.to_string().into());
 
@@ -92,5 +136,5 @@ pub(crate) async fn send_message_event_route(
 
 	drop(state_lock);
 
-	Ok(send_message_event::v3::Response { event_id })
+	Ok(Sent::Now(send_message_event::v3::Response { event_id }))
 }
@@ -11,7 +11,10 @@ use ruma::{
 			get_login_token,
 			get_login_types::{
 				self,
-				v3::{ApplicationServiceLoginType, PasswordLoginType, TokenLoginType},
+				v3::{
+					ApplicationServiceLoginType, IdentityProvider, IdentityProviderBrand,
+					PasswordLoginType, SsoLoginType, TokenLoginType,
+				},
 			},
 			login::{
 				self,
@@ -21,12 +24,13 @@ use ruma::{
 		},
 		uiaa,
 	},
+	thirdparty::Medium,
 	OwnedUserId, UserId,
 };
 use service::uiaa::SESSION_ID_LENGTH;
 
 use super::{DEVICE_ID_LENGTH, TOKEN_LENGTH};
-use crate::{utils, utils::hash, Error, Result, Ruma};
+use crate::{utils, Error, Result, Ruma};
 
 /// # `GET /_matrix/client/v3/login`
 ///
@@ -38,13 +42,32 @@ pub(crate) async fn get_login_types_route(
 	InsecureClientIp(client): InsecureClientIp,
 	_body: Ruma<get_login_types::v3::Request>,
 ) -> Result<get_login_types::v3::Response> {
-	Ok(get_login_types::v3::Response::new(vec![
+	let mut flows = vec![
 		get_login_types::v3::LoginType::Password(PasswordLoginType::default()),
 		get_login_types::v3::LoginType::ApplicationService(ApplicationServiceLoginType::default()),
 		get_login_types::v3::LoginType::Token(TokenLoginType {
 			get_login_token: services.server.config.login_via_existing_session,
 		}),
-	]))
+	];
+
+	if services.sso.enabled() {
+		let identity_providers = services
+			.server
+			.config
+			.oidc_providers
+			.iter()
+			.map(|provider| IdentityProvider {
+				id: provider.id.clone(),
+				name: provider.name.clone().unwrap_or_else(|| provider.id.clone()),
+				icon: provider.icon.clone(),
+				brand: provider.brand.as_deref().map(IdentityProviderBrand::from),
+			})
+			.collect();
+
+		flows.push(get_login_types::v3::LoginType::Sso(SsoLoginType { identity_providers }));
+	}
+
+	Ok(get_login_types::v3::Response::new(flows))
 }
 
 /// # `POST /_matrix/client/v3/login`
@@ -78,9 +101,15 @@ pub(crate) async fn login_route(
 			..
 		}) => {
 			debug!("Got password login type");
-			let user_id = if let Some(uiaa::UserIdentifier::UserIdOrLocalpart(user_id)) =
-				identifier
-			{
+			let user_id = if let Some(uiaa::UserIdentifier::Email { address }) = identifier {
+				let user_id = services
+					.users
+					.find_from_threepid(&Medium::Email, &address.to_lowercase())
+					.await
+					.map_err(|_| err!(Request(Forbidden("Wrong username or password."))))?;
+
+				Ok(user_id)
+			} else if let Some(uiaa::UserIdentifier::UserIdOrLocalpart(user_id)) = identifier {
 				UserId::parse_with_server_name(
 					user_id.to_lowercase(),
 					services.globals.server_name(),
@@ -93,25 +122,13 @@ pub(crate) async fn login_route(
 			}
 			.map_err(|_| Error::BadRequest(ErrorKind::InvalidUsername, "Username is invalid."))?;
 
-			let hash = services
-				.users
-				.password_hash(&user_id)
-				.await
-				.map_err(|_| err!(Request(Forbidden("Wrong username or password."))))?;
-
-			if hash.is_empty() {
-				return Err!(Request(UserDeactivated("The user has been deactivated")));
-			}
-
-			if hash::verify_password(password, &hash).is_err() {
-				return Err!(Request(Forbidden("Wrong username or password.")));
-			}
+			services.password.authenticate(&user_id, password).await?;
 
 			user_id
 		},
 		| login::v3::LoginInfo::Token(login::v3::Token { token }) => {
 			debug!("Got token login type");
-			if !services.server.config.login_via_existing_session {
+			if !services.server.config.login_via_existing_session && !services.sso.enabled() {
 				return Err!(Request(Unknown("Token login is not enabled.")));
 			}
 			services.users.find_from_login_token(token).await?
@@ -0,0 +1,246 @@
+use axum::{
+	extract::{Query, State},
+	response::{Html, IntoResponse},
+};
+use axum_client_ip::InsecureClientIp;
+use conduwuit::{debug_warn, info, utils, utils::html::Escape, Err, Result};
+use http::{
+	header::{CACHE_CONTROL, CONTENT_SECURITY_POLICY, COOKIE, REFERRER_POLICY, X_FRAME_OPTIONS},
+	HeaderMap,
+};
+use reqwest::Url;
+use ruma::{
+	api::client::session::{sso_login, sso_login_with_provider},
+	events::GlobalAccountDataEventType,
+	push, OwnedUserId, UserId,
+};
+use serde::Deserialize;
+use service::{
+	sso::{Identity, SESSION_COOKIE},
+	Services,
+};
+
+use super::TOKEN_LENGTH;
+use crate::Ruma;
+
+/// Length of the unusable password given to users registered through single
+/// sign-on, so they are not considered deactivated.
+const RANDOM_PASSWORD_LENGTH: usize = 64;
+
+#[derive(Deserialize)]
+pub(crate) struct CallbackParams {
+	code: Option<String>,
+	state: Option<String>,
+	error: Option<String>,
+	error_description: Option<String>,
+}
+
+/// # `GET /_matrix/client/v3/login/sso/redirect`
+///
+/// Redirects the user to the first configured identity provider.
+#[tracing::instrument(skip_all, fields(%client), name = "sso")]
+pub(crate) async fn sso_login_route(
+	State(services): State<crate::State>,
+	InsecureClientIp(client): InsecureClientIp,
+	body: Ruma<sso_login::v3::Request>,
+) -> Result<sso_login::v3::Response> {
+	let (location, cookie) = start(&services, None, &body.redirect_url).await?;
+
+	Ok(sso_login::v3::Response { location, cookie: Some(cookie) })
+}
+
+/// # `GET /_matrix/client/v3/login/sso/redirect/{idpId}`
+///
+/// Redirects the user to the given identity provider.
+#[tracing::instrument(skip_all, fields(%client), name = "sso")]
+pub(crate) async fn sso_login_with_provider_route(
+	State(services): State<crate::State>,
+	InsecureClientIp(client): InsecureClientIp,
+	body: Ruma<sso_login_with_provider::v3::Request>,
+) -> Result<sso_login_with_provider::v3::Response> {
+	let (location, cookie) = start(&services, Some(&body.idp_id), &body.redirect_url).await?;
+
+	Ok(sso_login_with_provider::v3::Response { location, cookie: Some(cookie) })
+}
+
+/// # `GET /_conduwuit/oidc/callback`
+///
+/// Where the identity provider sends the user back to. Maps the identity to a
+/// local user, registering it if allowed, and asks the user to confirm
+/// continuing to the client with a login token.
+#[tracing::instrument(skip_all, fields(%client), name = "sso")]
+pub(crate) async fn sso_callback_route(
+	State(services): State<crate::State>,
+	InsecureClientIp(client): InsecureClientIp,
+	headers: HeaderMap,
+	Query(params): Query<CallbackParams>,
+) -> Result<impl IntoResponse> {
+	if let Some(error) = params.error {
+		let description = params.error_description.unwrap_or_default();
+		return Err!(Request(Forbidden(
+			"Identity provider returned an error: {error} {description}"
+		)));
+	}
+
+	let (Some(code), Some(state)) = (params.code, params.state) else {
+		return Err!(Request(MissingParam("Missing code or state parameter.")));
+	};
+
+	if session_cookie(&headers) != Some(state.as_str()) {
+		return Err!(Request(Forbidden("Single sign-on was started in another browser.")));
+	}
+
+	let identity = services.sso.callback(&state, &code).await?;
+	let user_id = resolve_user(&services, &identity).await?;
+
+	if services
+		.users
+		.is_deactivated(&user_id)
+		.await
+		.unwrap_or(false)
+	{
+		return Err!(Request(UserDeactivated("The user has been deactivated")));
+	}
+
+	let mut redirect_url = services.sso.check_redirect_url(&identity.redirect_url)?;
+
+	let token = utils::random_string(TOKEN_LENGTH);
+	services.users.create_login_token(&user_id, &token);
+	redirect_url
+		.query_pairs_mut()
+		.append_pair("loginToken", &token);
+
+	info!(%user_id, provider = %identity.provider, "Signed in through single sign-on");
+
+	let headers = [
+		(CACHE_CONTROL, "no-store"),
+		(CONTENT_SECURITY_POLICY, "frame-ancestors 'none'"),
+		(REFERRER_POLICY, "no-referrer"),
+		(X_FRAME_OPTIONS, "DENY"),
+	];
+
+	Ok((headers, confirmation_page(&user_id, &redirect_url)))
+}
+
+/// Page asking the user whether to hand the login token to the client, so a
+/// link from someone else cannot silently sign them in elsewhere.
+fn confirmation_page(user_id: &UserId, redirect_url: &Url) -> Html<String> {
+	let host = redirect_url
+		.host_str()
+		.unwrap_or_else(|| redirect_url.scheme());
+
+	let host = Escape(host);
+	let user_id = Escape(user_id.as_str());
+	let url = Escape(redirect_url.as_str());
+
+	Html(format!(
+		"<!DOCTYPE html><html><head><meta charset=\"utf-8\"><meta name=\"referrer\" \
+		 content=\"no-referrer\"><title>Continue to {host}?</title></head><body><h1>Continue to \
+		 {host}?</h1><p>You are signing in as <b>{user_id}</b>. Continuing gives <b>{host}</b> \
+		 access to your account.</p><p>If you did not just try to sign in to an app at {host}, \
+		 close this page.</p><p><a href=\"{url}\">Continue</a></p></body></html>"
+	))
+}
+
+async fn start(
+	services: &Services,
+	provider: Option<&str>,
+	redirect_url: &str,
+) -> Result<(String, String)> {
+	if !services.sso.enabled() {
+		return Err!(Request(Unrecognized("Single sign-on is not enabled.")));
+	}
+
+	services.sso.check_redirect_url(redirect_url)?;
+
+	let path = services.sso.callback_path(provider)?;
+	let (location, state) = services.sso.authorize(provider, redirect_url).await?;
+	let cookie = format!(
+		"{SESSION_COOKIE}={state}; Path={path}; Max-Age=600; HttpOnly; Secure; SameSite=Lax"
+	);
+
+	Ok((location.into(), cookie))
+}
+
+/// Finds the user an identity signs in as, linking or registering it on
+/// first sign in when the provider allows it.
+async fn resolve_user(services: &Services, identity: &Identity) -> Result<OwnedUserId> {
+	if let Ok(user_id) = services.sso.linked_user(identity).await {
+		return Ok(user_id);
+	}
+
+	let provider = services.sso.provider(Some(&identity.provider))?;
+	let user_id = services.sso.map_user_id(identity)?;
+
+	if services.users.exists(&user_id).await {
+		if !provider.allow_existing_users {
+			return Err!(Request(Forbidden(
+				"{user_id} already exists and is not linked to this identity provider."
+			)));
+		}
+
+		services.sso.link_user(identity, &user_id);
+		info!(%user_id, provider = %provider.id, "Linked existing user to identity provider");
+
+		return Ok(user_id);
+	}
+
+	if !provider.register_users {
+		debug_warn!(%user_id, provider = %provider.id, "Refusing to register single sign-on user");
+		return Err!(Request(Forbidden(
+			"Registration through this identity provider is disabled."
+		)));
+	}
+
+	if services
+		.globals
+		.forbidden_usernames()
+		.is_match(user_id.localpart())
+	{
+		return Err!(Request(Exclusive("Username is forbidden.")));
+	}
+
+	if services.appservice.is_exclusive_user_id(&user_id).await {
+		return Err!(Request(Exclusive("Username is reserved by an appservice.")));
+	}
+
+	let password = utils::random_string(RANDOM_PASSWORD_LENGTH);
+	services.users.create(&user_id, Some(&password))?;
+
+	let displayname = services
+		.sso
+		.map_displayname(identity)
+		.unwrap_or_else(|| user_id.localpart().to_owned());
+
+	services.users.set_displayname(&user_id, Some(displayname));
+
+	services
+		.account_data
+		.update(
+			None,
+			&user_id,
+			GlobalAccountDataEventType::PushRules.to_string().into(),
+			&serde_json::to_value(ruma::events::push_rules::PushRulesEvent {
+				content: ruma::events::push_rules::PushRulesEventContent {
+					global: push::Ruleset::server_default(&user_id),
+				},
+			})
+			.expect("to json always works"),
+		)
+		.await?;
+
+	services.sso.link_user(identity, &user_id);
+	info!(%user_id, provider = %provider.id, "Registered user through single sign-on");
+
+	Ok(user_id)
+}
+
+fn session_cookie(headers: &HeaderMap) -> Option<&str> {
+	headers
+		.get_all(COOKIE)
+		.iter()
+		.filter_map(|value| value.to_str().ok())
+		.flat_map(|value| value.split(';'))
+		.filter_map(|pair| pair.trim().split_once('='))
+		.find_map(|(name, value)| (name == SESSION_COOKIE).then_some(value))
+}
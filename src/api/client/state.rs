@@ -16,18 +16,50 @@ use ruma::{
 };
 use service::Services;
 
+use super::{delay_event, Sent};
 use crate::{Ruma, RumaResponse};
 
 /// # `PUT /_matrix/client/*/rooms/{roomId}/state/{eventType}/{stateKey}`
 ///
-/// Sends a state event into the room.
+/// Sends a state event into the room, or queues it to be sent later when
+/// given the `org.matrix.msc4140.delay` query parameter.
 pub(crate) async fn send_state_event_for_key_route(
 	State(services): State<crate::State>,
 	body: Ruma<send_state_event::v3::Request>,
-) -> Result<send_state_event::v3::Response> {
+) -> Result<Sent<send_state_event::v3::Response>> {
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
 
-	Ok(send_state_event::v3::Response {
+	if let Some(delay) = body.delay {
+		allowed_to_send_state_event(
+			&services,
+			&body.room_id,
+			&body.event_type,
+			&body.state_key,
+			&body.body.body,
+		)
+		.await?;
+
+		let delay_id = delay_event(
+			&services,
+			sender_user,
+			&body.room_id,
+			body.event_type.to_string(),
+			Some(body.state_key.clone()),
+			body.body.body.json(),
+			delay,
+		)
+		.await?;
+
+		return Ok(Sent::Delayed(delay_id));
+	}
+
+	if services.users.is_shadow_banned(sender_user).await {
+		return Ok(Sent::Now(send_state_event::v3::Response {
+			event_id: super::shadow_ban_event_id(),
+		}));
+	}
+
+	Ok(Sent::Now(send_state_event::v3::Response {
 		event_id: send_state_event_for_key_helper(
 			&services,
 			sender_user,
@@ -42,7 +74,7 @@ pub(crate) async fn send_state_event_for_key_route(
 			},
 		)
 		.await?,
-	})
+	}))
 }
 
 /// # `PUT /_matrix/client/*/rooms/{roomId}/state/{eventType}`
@@ -51,10 +83,8 @@ pub(crate) async fn send_state_event_for_key_route(
 pub(crate) async fn send_state_event_for_empty_key_route(
 	State(services): State<crate::State>,
 	body: Ruma<send_state_event::v3::Request>,
-) -> Result<RumaResponse<send_state_event::v3::Response>> {
-	send_state_event_for_key_route(State(services), body)
-		.await
-		.map(RumaResponse)
+) -> Result<Sent<send_state_event::v3::Response>> {
+	send_state_event_for_key_route(State(services), body).await
 }
 
 /// # `GET /_matrix/client/v3/rooms/{roomid}/state`
@@ -1,3 +1,5 @@
+mod tests;
+
 use std::{
 	cmp::{self, Ordering},
 	collections::{BTreeMap, BTreeSet, HashMap, HashSet},
@@ -9,23 +11,28 @@ use conduwuit::{
 	debug, error, extract_variant, trace,
 	utils::{
 		math::{ruma_from_usize, usize_from_ruma},
+		stream::TryIgnore,
 		BoolExt, IterStream, ReadyExt, TryFutureExtExt,
 	},
-	warn, Error, Result,
+	warn, Error, PduEvent, Result,
 };
-use futures::{FutureExt, StreamExt, TryFutureExt};
+use futures::{pin_mut, FutureExt, StreamExt, TryFutureExt};
 use ruma::{
 	api::client::{
 		error::ErrorKind,
-		sync::sync_events::{self, DeviceLists, UnreadNotificationsCount},
+		sync::sync_events::{
+			self, v5::request::ReceiptsRoom, DeviceLists, UnreadNotificationsCount,
+		},
 	},
 	events::{
 		room::member::{MembershipState, RoomMemberEventContent},
-		AnyRawAccountDataEvent, AnySyncEphemeralRoomEvent, StateEventType, TimelineEventType,
+		AnyRawAccountDataEvent, AnySyncEphemeralRoomEvent, AnySyncStateEvent, StateEventType,
+		TimelineEventType,
 	},
 	serde::Raw,
 	state_res::TypeStateKey,
-	uint, DeviceId, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, UInt, UserId,
+	uint, CanonicalJsonValue, DeviceId, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, UInt,
+	UserId,
 };
 use service::{rooms::read_receipt::pack_receipts, PduCount};
 
@@ -54,6 +61,7 @@ pub(crate) async fn sync_events_v5_route(
 	debug_assert!(DEFAULT_BUMP_TYPES.is_sorted(), "DEFAULT_BUMP_TYPES is not sorted");
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
 	let sender_device = body.sender_device.as_ref().expect("user is authenticated");
+	let requested_bump_types = requested_bump_types(body.json_body.as_ref());
 	let mut body = body.body;
 
 	// Setup watchers, so if there's no response, we can wait for them
@@ -123,21 +131,31 @@ pub(crate) async fn sync_events_v5_route(
 		.collect()
 		.await;
 
-	let all_rooms: Vec<&RoomId> = all_joined_rooms
+	let mut all_rooms: Vec<&RoomId> = all_joined_rooms
 		.iter()
 		.map(AsRef::as_ref)
 		.chain(all_invited_rooms.iter().map(AsRef::as_ref))
 		.chain(all_knocked_rooms.iter().map(AsRef::as_ref))
 		.collect();
 
-	let all_joined_rooms = all_joined_rooms.iter().map(AsRef::as_ref).collect();
-	let all_invited_rooms = all_invited_rooms.iter().map(AsRef::as_ref).collect();
+	let mut all_joined_rooms: Vec<&RoomId> = all_joined_rooms.iter().map(AsRef::as_ref).collect();
+	let mut all_invited_rooms: Vec<&RoomId> =
+		all_invited_rooms.iter().map(AsRef::as_ref).collect();
 
 	let pos = next_batch.clone().to_string();
 
 	let mut todo_rooms: TodoRooms = BTreeMap::new();
 
 	let sync_info: SyncInfo<'_> = (sender_user, sender_device, globalsince, &body);
+
+	// Lists are sorted by recent activity
+	let list_rooms = list_rooms(&body, &all_invited_rooms, &all_joined_rooms, &all_rooms);
+	let bump_stamps = bump_stamps(services, sync_info, requested_bump_types, &list_rooms).await;
+	let by_bump_stamp = |room_id: &&RoomId| cmp::Reverse(bump_stamps.get(*room_id).copied());
+	all_rooms.sort_by_key(by_bump_stamp);
+	all_joined_rooms.sort_by_key(by_bump_stamp);
+	all_invited_rooms.sort_by_key(by_bump_stamp);
+
 	let mut response = sync_events::v5::Response {
 		txn_id: body.txn_id.clone(),
 		pos,
@@ -147,12 +165,12 @@ pub(crate) async fn sync_events_v5_route(
 			account_data: collect_account_data(services, sync_info).await,
 			e2ee: collect_e2ee(services, sync_info, &all_joined_rooms).await?,
 			to_device: collect_to_device(services, sync_info, next_batch).await,
-			receipts: collect_receipts(services).await,
+			receipts: sync_events::v5::response::Receipts::default(),
 			typing: sync_events::v5::response::Typing::default(),
 		},
 	};
 
-	handle_lists(
+	let windows = handle_lists(
 		services,
 		sync_info,
 		&all_invited_rooms,
@@ -166,26 +184,48 @@ pub(crate) async fn sync_events_v5_route(
 
 	fetch_subscriptions(services, sync_info, &known_rooms, &mut todo_rooms).await;
 
+	let receipt_rooms = if body.extensions.receipts.enabled == Some(true) {
+		let rooms = body.extensions.receipts.rooms.clone().map(|rooms| {
+			rooms
+				.into_iter()
+				.flat_map(|room| match room {
+					| ReceiptsRoom::Room(room_id) => vec![room_id],
+					| ReceiptsRoom::AllSubscribed =>
+						body.room_subscriptions.keys().cloned().collect(),
+				})
+				.collect()
+		});
+
+		extension_rooms(&body, &windows, body.extensions.receipts.lists.as_deref(), rooms)
+	} else {
+		BTreeSet::new()
+	};
+
 	response.rooms = process_rooms(
 		services,
-		sender_user,
+		sync_info,
 		next_batch,
 		&all_invited_rooms,
 		&todo_rooms,
+		&bump_stamps,
+		&receipt_rooms,
 		&mut response,
-		&body,
 	)
 	.await?;
 
+	response.extensions.typing =
+		collect_typing(services, sync_info, &windows, &all_joined_rooms).await?;
+
 	if response.rooms.iter().all(|(id, r)| {
 		r.timeline.is_empty()
 			&& r.required_state.is_empty()
 			&& !response.extensions.receipts.rooms.contains_key(id)
-	}) && response
-		.extensions
-		.to_device
-		.clone()
-		.is_none_or(|to| to.events.is_empty())
+	}) && response.extensions.typing.rooms.is_empty()
+		&& response
+			.extensions
+			.to_device
+			.clone()
+			.is_none_or(|to| to.events.is_empty())
 	{
 		// Hang a few seconds so requests are not spammed
 		// Stop hanging if new info arrives
@@ -204,6 +244,7 @@ pub(crate) async fn sync_events_v5_route(
 }
 
 type KnownRooms = BTreeMap<String, BTreeMap<OwnedRoomId, u64>>;
+type ListWindows = BTreeMap<String, BTreeSet<OwnedRoomId>>;
 pub(crate) type TodoRooms = BTreeMap<OwnedRoomId, (BTreeSet<TypeStateKey>, usize, u64)>;
 
 async fn fetch_subscriptions(
@@ -225,7 +266,7 @@ async fn fetch_subscriptions(
 		let limit: UInt = room.timeline_limit;
 
 		todo_room.0.extend(room.required_state.iter().cloned());
-		todo_room.1 = todo_room.1.max(usize_from_ruma(limit));
+		todo_room.1 = todo_room.1.max(usize_from_ruma(limit).min(100));
 		// 0 means unknown because it got out of date
 		todo_room.2 = todo_room.2.min(
 			known_rooms
@@ -264,7 +305,8 @@ async fn handle_lists<'a>(
 	todo_rooms: &'a mut TodoRooms,
 	known_rooms: &'a KnownRooms,
 	response: &'_ mut sync_events::v5::Response,
-) -> KnownRooms {
+) -> ListWindows {
+	let mut windows = ListWindows::new();
 	for (list_id, list) in &body.lists {
 		let active_rooms = match list.filters.clone().and_then(|f| f.is_invite) {
 			| Some(true) => all_invited_rooms,
@@ -325,6 +367,8 @@ async fn handle_lists<'a>(
 				count: ruma_from_usize(active_rooms.len()),
 			});
 
+		windows.insert(list_id.clone(), new_known_rooms.clone());
+
 		if let Some(conn_id) = &body.conn_id {
 			services.sync.update_snake_sync_known_rooms(
 				sender_user,
@@ -336,23 +380,25 @@ async fn handle_lists<'a>(
 			);
 		}
 	}
-	BTreeMap::default()
+	windows
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn process_rooms(
 	services: crate::State,
-	sender_user: &UserId,
+	sync_info: SyncInfo<'_>,
 	next_batch: u64,
 	all_invited_rooms: &[&RoomId],
 	todo_rooms: &TodoRooms,
+	bump_stamps: &BTreeMap<OwnedRoomId, UInt>,
+	receipt_rooms: &BTreeSet<OwnedRoomId>,
 	response: &mut sync_events::v5::Response,
-	body: &sync_events::v5::Request,
 ) -> Result<BTreeMap<OwnedRoomId, sync_events::v5::response::Room>> {
+	let (sender_user, _, _, body) = sync_info;
 	let mut rooms = BTreeMap::new();
 	for (room_id, (required_state_request, timeline_limit, roomsince)) in todo_rooms {
 		let roomsincecount = PduCount::Normal(*roomsince);
 
-		let mut timestamp: Option<_> = None;
 		let mut invite_state = None;
 		let (timeline_pdus, limited);
 		let new_room_id: &RoomId = (*room_id).as_ref();
@@ -397,41 +443,12 @@ async fn process_rooms(
 			);
 		}
 
-		let last_privateread_update = services
-			.rooms
-			.read_receipt
-			.last_privateread_update(sender_user, room_id)
-			.await > *roomsince;
-
-		let private_read_event = if last_privateread_update {
-			services
-				.rooms
-				.read_receipt
-				.private_read_get(room_id, sender_user)
-				.await
-				.ok()
+		let receipts = if receipt_rooms.contains(room_id) {
+			room_receipts(services, sender_user, room_id, *roomsince).await
 		} else {
-			None
+			Vec::new()
 		};
 
-		let mut receipts: Vec<Raw<AnySyncEphemeralRoomEvent>> = services
-			.rooms
-			.read_receipt
-			.readreceipts_since(room_id, *roomsince)
-			.filter_map(|(read_user, _ts, v)| async move {
-				services
-					.users
-					.user_is_ignored(read_user, sender_user)
-					.await
-					.or_some(v)
-			})
-			.collect()
-			.await;
-
-		if let Some(private_read_event) = private_read_event {
-			receipts.push(private_read_event);
-		}
-
 		let receipt_size = receipts.len();
 
 		if receipt_size > 0 {
@@ -482,29 +499,15 @@ async fn process_rooms(
 			.collect()
 			.await;
 
-		for (_, pdu) in timeline_pdus {
-			let ts = pdu.origin_server_ts;
-			if DEFAULT_BUMP_TYPES.binary_search(&pdu.kind).is_ok()
-				&& timestamp.is_none_or(|time| time <= ts)
-			{
-				timestamp = Some(ts);
-			}
-		}
-
-		let required_state = required_state_request
-			.iter()
-			.stream()
-			.filter_map(|state| async move {
-				services
-					.rooms
-					.state_accessor
-					.room_state_get(room_id, &state.0, &state.1)
-					.await
-					.map(|s| s.to_sync_state_event())
-					.ok()
-			})
-			.collect()
-			.await;
+		let required_state = required_state(
+			services,
+			sync_info,
+			room_id,
+			required_state_request,
+			&timeline_pdus,
+			*roomsince == 0,
+		)
+		.await;
 
 		// Heroes
 		let heroes: Vec<_> = services
@@ -623,7 +626,7 @@ async fn process_rooms(
 					.unwrap_or_else(|_| uint!(0)),
 			),
 			num_live: None, // Count events in timeline greater than global sync counter
-			bump_stamp: timestamp,
+			bump_stamp: bump_stamps.get(room_id).copied(),
 			heroes: Some(heroes),
 		});
 	}
@@ -880,7 +883,386 @@ async fn collect_to_device(
 	})
 }
 
-async fn collect_receipts(_services: crate::State) -> sync_events::v5::response::Receipts {
-	sync_events::v5::response::Receipts { rooms: BTreeMap::new() }
-	// TODO: get explicitly requested read receipts
+async fn room_receipts(
+	services: crate::State,
+	sender_user: &UserId,
+	room_id: &RoomId,
+	roomsince: u64,
+) -> Vec<Raw<AnySyncEphemeralRoomEvent>> {
+	let last_privateread_update = services
+		.rooms
+		.read_receipt
+		.last_privateread_update(sender_user, room_id)
+		.await > roomsince;
+
+	let private_read_event = if last_privateread_update {
+		services
+			.rooms
+			.read_receipt
+			.private_read_get(room_id, sender_user)
+			.await
+			.ok()
+	} else {
+		None
+	};
+
+	let mut receipts: Vec<Raw<AnySyncEphemeralRoomEvent>> = services
+		.rooms
+		.read_receipt
+		.readreceipts_since(room_id, roomsince)
+		.filter_map(|(read_user, _ts, v)| async move {
+			services
+				.users
+				.user_is_ignored(read_user, sender_user)
+				.await
+				.or_some(v)
+		})
+		.collect()
+		.await;
+
+	if let Some(private_read_event) = private_read_event {
+		receipts.push(private_read_event);
+	}
+
+	receipts
+}
+
+async fn collect_typing(
+	services: crate::State,
+	(sender_user, _, globalsince, body): SyncInfo<'_>,
+	windows: &ListWindows,
+	all_joined_rooms: &[&RoomId],
+) -> Result<sync_events::v5::response::Typing> {
+	let mut typing = sync_events::v5::response::Typing::default();
+	if !body.extensions.typing.enabled.unwrap_or(false) {
+		return Ok(typing);
+	}
+
+	let rooms = extension_rooms(
+		body,
+		windows,
+		body.extensions.typing.lists.as_deref(),
+		body.extensions.typing.rooms.clone(),
+	);
+
+	for room_id in rooms {
+		if !all_joined_rooms.contains(&&*room_id) {
+			continue;
+		}
+
+		let Ok(count) = services.rooms.typing.last_typing_update(&room_id).await else {
+			continue;
+		};
+
+		if count <= globalsince {
+			continue;
+		}
+
+		let event = services
+			.rooms
+			.typing
+			.typings_all(&room_id, sender_user)
+			.await?;
+
+		typing.rooms.insert(room_id, Raw::new(&event)?);
+	}
+
+	Ok(typing)
+}
+
+/// Rooms an extension applies to: those in the windows of the lists it
+/// names, where `*` or no names means every list, and the rooms it names,
+/// where no names means every subscription.
+fn extension_rooms(
+	body: &sync_events::v5::Request,
+	windows: &ListWindows,
+	lists: Option<&[String]>,
+	rooms: Option<Vec<OwnedRoomId>>,
+) -> BTreeSet<OwnedRoomId> {
+	let in_lists = windows
+		.iter()
+		.filter(|(list_id, _)| {
+			lists.is_none_or(|lists| lists.iter().any(|list| list == "*" || list == *list_id))
+		})
+		.flat_map(|(_, rooms)| rooms.iter().cloned());
+
+	let subscribed = rooms.unwrap_or_else(|| body.room_subscriptions.keys().cloned().collect());
+
+	in_lists.chain(subscribed).collect()
+}
+
+/// State events asked for by `required_state`. A state key of `*` matches
+/// every key of the type, and an event type of `*` every type. For members,
+/// `$ME` is the sender and `$LAZY` the senders of the timeline events whose
+/// membership the connection was not sent yet.
+async fn required_state(
+	services: crate::State,
+	(sender_user, sender_device, _, body): SyncInfo<'_>,
+	room_id: &RoomId,
+	required_state_request: &BTreeSet<TypeStateKey>,
+	timeline_pdus: &[(PduCount, PduEvent)],
+	initial: bool,
+) -> Vec<Raw<AnySyncStateEvent>> {
+	let (mut wanted, lazy) = named_state(required_state_request, sender_user);
+	if lazy {
+		let senders = timeline_pdus
+			.iter()
+			.map(|(_, pdu)| pdu.sender.clone())
+			.collect();
+
+		let members = services.sync.update_snake_lazy_members(
+			sender_user,
+			sender_device,
+			body.conn_id.clone(),
+			room_id,
+			initial,
+			senders,
+		);
+
+		wanted.extend(
+			members
+				.into_iter()
+				.map(|member| (StateEventType::RoomMember, member.to_string())),
+		);
+	}
+
+	let mut state = Vec::new();
+	if required_state_request
+		.iter()
+		.any(|(event_type, state_key)| is_wildcard(event_type, state_key))
+	{
+		let full_state = services
+			.rooms
+			.state_accessor
+			.room_state_full_pdus(room_id)
+			.await
+			.unwrap_or_default();
+
+		for pdu in full_state {
+			let Some(state_key) = pdu.state_key.as_deref() else {
+				continue;
+			};
+
+			let event_type = StateEventType::from(pdu.kind.to_string());
+			let matched = wildcard_matches(required_state_request, &event_type, state_key);
+			if wanted.remove(&(event_type, state_key.to_owned())) || matched {
+				state.push(pdu.to_sync_state_event());
+			}
+		}
+	}
+
+	for (event_type, state_key) in wanted {
+		if let Ok(pdu) = services
+			.rooms
+			.state_accessor
+			.room_state_get(room_id, &event_type, &state_key)
+			.await
+		{
+			state.push(pdu.to_sync_state_event());
+		}
+	}
+
+	state
+}
+
+/// State events `required_state` names, with `$ME` replaced by the sender,
+/// and whether it asks for `$LAZY` members. Wildcards are left out.
+fn named_state(
+	required_state_request: &BTreeSet<TypeStateKey>,
+	sender_user: &UserId,
+) -> (BTreeSet<(StateEventType, String)>, bool) {
+	let mut wanted = BTreeSet::new();
+	let mut lazy = false;
+	for (event_type, state_key) in required_state_request {
+		match state_key.as_str() {
+			| "$LAZY" if *event_type == StateEventType::RoomMember => lazy = true,
+			| "$ME" => {
+				wanted.insert((event_type.clone(), sender_user.to_string()));
+			},
+			| state_key if !is_wildcard(event_type, state_key) => {
+				wanted.insert((event_type.clone(), state_key.to_owned()));
+			},
+			| _ => {},
+		}
+	}
+
+	(wanted, lazy)
+}
+
+/// Whether a state event is matched by a wildcard of `required_state`.
+fn wildcard_matches(
+	required_state_request: &BTreeSet<TypeStateKey>,
+	event_type: &StateEventType,
+	state_key: &str,
+) -> bool {
+	required_state_request
+		.iter()
+		.any(|(wanted_type, wanted_key)| {
+			is_wildcard(wanted_type, wanted_key)
+				&& (wanted_type.to_string() == "*" || wanted_type == event_type)
+				&& (wanted_key == "*" || wanted_key == state_key)
+		})
+}
+
+fn is_wildcard(event_type: &StateEventType, state_key: &str) -> bool {
+	event_type.to_string() == "*" || state_key == "*"
+}
+
+/// Rooms which may be in the requested lists or are subscribed to, and so
+/// need a bump stamp.
+fn list_rooms<'a>(
+	body: &sync_events::v5::Request,
+	all_invited_rooms: &[&'a RoomId],
+	all_joined_rooms: &[&'a RoomId],
+	all_rooms: &[&'a RoomId],
+) -> Vec<&'a RoomId> {
+	let mut is_invite = body
+		.lists
+		.values()
+		.map(|list| list.filters.as_ref().and_then(|filters| filters.is_invite));
+
+	let (all, invited, joined) = (
+		is_invite.clone().any(|is_invite| is_invite.is_none()),
+		is_invite.clone().any(|is_invite| is_invite == Some(true)),
+		is_invite.any(|is_invite| is_invite == Some(false)),
+	);
+
+	if all {
+		return all_rooms.to_vec();
+	}
+
+	let mut rooms: Vec<_> = all_rooms
+		.iter()
+		.filter(|room_id| body.room_subscriptions.contains_key(**room_id))
+		.chain(all_invited_rooms.iter().filter(|_| invited))
+		.chain(all_joined_rooms.iter().filter(|_| joined))
+		.copied()
+		.collect();
+
+	rooms.sort_unstable();
+	rooms.dedup();
+	rooms
+}
+
+/// Event types bumping rooms, as asked for by the `bump_event_types` of the
+/// requested lists. Clients still send them although MSC4186 left them out of
+/// the request, so they are read from its JSON body.
+fn requested_bump_types(
+	json_body: Option<&CanonicalJsonValue>,
+) -> Option<Vec<TimelineEventType>> {
+	let Some(CanonicalJsonValue::Object(lists)) = json_body.and_then(|body| match body {
+		| CanonicalJsonValue::Object(body) => body.get("lists"),
+		| _ => None,
+	}) else {
+		return None;
+	};
+
+	let mut bump_types: Vec<TimelineEventType> = lists
+		.values()
+		.filter_map(|list| match list {
+			| CanonicalJsonValue::Object(list) => list.get("bump_event_types"),
+			| _ => None,
+		})
+		.filter_map(|bump_types| match bump_types {
+			| CanonicalJsonValue::Array(bump_types) => Some(bump_types),
+			| _ => None,
+		})
+		.flatten()
+		.filter_map(|bump_type| match bump_type {
+			| CanonicalJsonValue::String(bump_type) => Some(bump_type.as_str().into()),
+			| _ => None,
+		})
+		.collect();
+
+	bump_types.sort_unstable();
+	bump_types.dedup();
+	(!bump_types.is_empty()).then_some(bump_types)
+}
+
+/// The timestamp of the latest event of a bump type in each room, which
+/// lists are sorted by. The bump types are those requested, else those of the
+/// connection's previous requests, else `DEFAULT_BUMP_TYPES`. The stamps are
+/// only looked for again in rooms with new events since the connection's last
+/// request, or all of them if the bump types changed.
+async fn bump_stamps(
+	services: crate::State,
+	(sender_user, sender_device, _, body): SyncInfo<'_>,
+	requested_bump_types: Option<Vec<TimelineEventType>>,
+	rooms: &[&RoomId],
+) -> BTreeMap<OwnedRoomId, UInt> {
+	let (cached_bump_types, mut cached) =
+		services
+			.sync
+			.snake_bump_stamps(sender_user, sender_device, body.conn_id.clone());
+
+	let bump_types = requested_bump_types
+		.or_else(|| (!cached_bump_types.is_empty()).then_some(cached_bump_types.clone()))
+		.unwrap_or_else(|| DEFAULT_BUMP_TYPES.to_vec());
+
+	if bump_types != cached_bump_types {
+		cached.clear();
+	}
+
+	let mut bump_stamps = BTreeMap::new();
+	for &room_id in rooms {
+		let Ok(count) = services
+			.rooms
+			.timeline
+			.last_timeline_count(Some(sender_user), room_id)
+			.await
+		else {
+			continue;
+		};
+
+		let count = count.into_unsigned();
+		let bump_stamp = match cached.get(room_id) {
+			| Some(&(cached_count, bump_stamp)) if cached_count == count => bump_stamp,
+			| _ => latest_bump_stamp(services, sender_user, room_id, &bump_types).await,
+		};
+
+		bump_stamps.insert(room_id.to_owned(), (count, bump_stamp));
+	}
+
+	services.sync.update_snake_bump_stamps(
+		sender_user,
+		sender_device,
+		body.conn_id.clone(),
+		bump_types,
+		bump_stamps.clone(),
+	);
+
+	bump_stamps
+		.into_iter()
+		.filter_map(|(room_id, (_, bump_stamp))| Some((room_id, bump_stamp?)))
+		.collect()
+}
+
+/// Most events looked through for one of a bump type, before settling for
+/// the timestamp of the latest event.
+const BUMP_SEARCH_MAX: usize = 50;
+
+async fn latest_bump_stamp(
+	services: crate::State,
+	sender_user: &UserId,
+	room_id: &RoomId,
+	bump_types: &[TimelineEventType],
+) -> Option<UInt> {
+	let pdus = services
+		.rooms
+		.timeline
+		.pdus_rev(Some(sender_user), room_id, None)
+		.ignore_err()
+		.take(BUMP_SEARCH_MAX);
+
+	pin_mut!(pdus);
+	let mut latest = None;
+	while let Some((_, pdu)) = pdus.next().await {
+		if bump_types.binary_search(&pdu.kind).is_ok() {
+			return Some(pdu.origin_server_ts);
+		}
+
+		latest.get_or_insert(pdu.origin_server_ts);
+	}
+
+	latest
 }
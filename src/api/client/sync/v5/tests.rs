@@ -0,0 +1,161 @@
+#![cfg(test)]
+
+use std::collections::BTreeSet;
+
+use ruma::{
+	api::client::sync::sync_events::v5::{
+		request::{List, ListFilters, RoomSubscription},
+		Request,
+	},
+	events::{StateEventType, TimelineEventType},
+	owned_room_id, room_id, user_id, CanonicalJsonValue, OwnedRoomId,
+};
+use serde_json::json;
+
+use super::{
+	extension_rooms, list_rooms, named_state, requested_bump_types, wildcard_matches, ListWindows,
+};
+
+fn state_request(pairs: &[(StateEventType, &str)]) -> BTreeSet<(StateEventType, String)> {
+	pairs
+		.iter()
+		.map(|(event_type, state_key)| (event_type.clone(), (*state_key).to_owned()))
+		.collect()
+}
+
+#[test]
+fn extension_rooms_for_typing_and_receipts() {
+	let mut body = Request::new();
+	body.room_subscriptions
+		.insert(owned_room_id!("!subscribed:example.org"), RoomSubscription::default());
+
+	let windows = ListWindows::from([
+		("all".to_owned(), BTreeSet::from([owned_room_id!("!all:example.org")])),
+		("dms".to_owned(), BTreeSet::from([owned_room_id!("!dm:example.org")])),
+	]);
+
+	let rooms: Vec<OwnedRoomId> = extension_rooms(&body, &windows, None, None)
+		.into_iter()
+		.collect();
+	assert_eq!(
+		rooms,
+		[
+			owned_room_id!("!all:example.org"),
+			owned_room_id!("!dm:example.org"),
+			owned_room_id!("!subscribed:example.org"),
+		],
+		"no names means every list and every subscription"
+	);
+
+	let lists = ["dms".to_owned()];
+	let rooms: Vec<OwnedRoomId> =
+		extension_rooms(&body, &windows, Some(&lists[..]), Some(Vec::new()))
+			.into_iter()
+			.collect();
+	assert_eq!(rooms, [owned_room_id!("!dm:example.org")], "only the named lists");
+
+	let lists = ["*".to_owned()];
+	let named = vec![owned_room_id!("!named:example.org")];
+	let rooms = extension_rooms(&body, &windows, Some(&lists[..]), Some(named));
+	assert_eq!(rooms.len(), 3, "every list and only the named room");
+	assert!(rooms.contains(room_id!("!named:example.org")));
+	assert!(!rooms.contains(room_id!("!subscribed:example.org")));
+}
+
+#[test]
+fn required_state_names() {
+	let sender = user_id!("@alice:example.org");
+	let request = state_request(&[
+		(StateEventType::RoomMember, "$ME"),
+		(StateEventType::RoomMember, "$LAZY"),
+		(StateEventType::RoomName, ""),
+		(StateEventType::RoomTopic, "*"),
+	]);
+
+	let (wanted, lazy) = named_state(&request, sender);
+	assert!(lazy, "$LAZY members are asked for");
+	assert_eq!(
+		wanted,
+		state_request(&[
+			(StateEventType::RoomMember, "@alice:example.org"),
+			(StateEventType::RoomName, ""),
+		]),
+		"$ME is the sender and wildcards are left out"
+	);
+
+	let (_, lazy) = named_state(&state_request(&[(StateEventType::RoomName, "$LAZY")]), sender);
+	assert!(!lazy, "$LAZY only applies to members");
+}
+
+#[test]
+fn required_state_wildcards() {
+	let request = state_request(&[
+		(StateEventType::RoomTopic, "*"),
+		(StateEventType::from("*"), "@alice:example.org"),
+	]);
+
+	assert!(wildcard_matches(&request, &StateEventType::RoomTopic, ""));
+	assert!(wildcard_matches(&request, &StateEventType::RoomMember, "@alice:example.org"));
+	assert!(!wildcard_matches(&request, &StateEventType::RoomMember, "@bob:example.org"));
+	assert!(!wildcard_matches(&request, &StateEventType::RoomName, ""));
+}
+
+#[test]
+fn bump_types_from_lists() {
+	let body: CanonicalJsonValue = serde_json::from_value(json!({
+		"lists": {
+			"all": {
+				"ranges": [[0, 10]],
+				"bump_event_types": ["m.room.message", "m.sticker"],
+			},
+			"dms": {
+				"ranges": [[0, 10]],
+				"bump_event_types": ["m.room.message", "m.room.encrypted"],
+			},
+		},
+	}))
+	.expect("valid JSON");
+
+	let mut expected = vec![
+		TimelineEventType::RoomEncrypted,
+		TimelineEventType::RoomMessage,
+		TimelineEventType::Sticker,
+	];
+	expected.sort_unstable();
+	assert_eq!(requested_bump_types(Some(&body)), Some(expected), "lists are merged");
+
+	let body: CanonicalJsonValue =
+		serde_json::from_value(json!({ "lists": { "all": { "ranges": [[0, 10]] } } }))
+			.expect("valid JSON");
+	assert_eq!(requested_bump_types(Some(&body)), None, "none asked for");
+	assert_eq!(requested_bump_types(None), None);
+}
+
+#[test]
+fn bump_stamp_rooms() {
+	let joined = room_id!("!joined:example.org");
+	let invited = room_id!("!invited:example.org");
+	let knocked = room_id!("!knocked:example.org");
+	let all_rooms = [joined, invited, knocked];
+
+	let mut body = Request::new();
+	assert!(
+		list_rooms(&body, &[invited], &[joined], &all_rooms).is_empty(),
+		"no lists need no bump stamps"
+	);
+
+	let mut filters = ListFilters::default();
+	filters.is_invite = Some(true);
+	let mut list = List::default();
+	list.filters = Some(filters);
+	body.lists.insert("invites".to_owned(), list);
+	body.room_subscriptions
+		.insert(knocked.to_owned(), RoomSubscription::default());
+
+	let mut rooms = list_rooms(&body, &[invited], &[joined], &all_rooms);
+	rooms.sort_unstable();
+	assert_eq!(rooms, [invited, knocked], "invites and subscriptions");
+
+	body.lists.insert("all".to_owned(), List::default());
+	assert_eq!(list_rooms(&body, &[invited], &[joined], &all_rooms), all_rooms);
+}
@@ -0,0 +1,306 @@
+use axum::{
+	extract::{Query, State},
+	response::{Html, IntoResponse, Redirect},
+	Json,
+};
+use axum_client_ip::InsecureClientIp;
+use conduwuit::{info, utils, Err, Error, Result};
+use futures::StreamExt;
+use ruma::{
+	api::client::{
+		account::{
+			add_3pid, delete_3pid, get_3pids, request_3pid_management_token_via_email,
+			request_3pid_management_token_via_msisdn, request_password_change_token_via_email,
+			request_registration_token_via_email, ThirdPartyIdRemovalStatus,
+		},
+		error::ErrorKind,
+		uiaa::{AuthFlow, AuthType, UiaaInfo},
+	},
+	thirdparty::{Medium, ThirdPartyIdentifier},
+	ClientSecret, MilliSecondsSinceUnixEpoch, OwnedClientSecret, OwnedSessionId, UInt,
+};
+use serde::Deserialize;
+use serde_json::json;
+use service::{email::Purpose, Services};
+
+use super::SESSION_ID_LENGTH;
+use crate::Ruma;
+
+#[derive(Deserialize)]
+pub(crate) struct SubmitToken {
+	sid: OwnedSessionId,
+	client_secret: OwnedClientSecret,
+	token: String,
+}
+
+/// # `GET _matrix/client/v3/account/3pid`
+///
+/// Get a list of third party identifiers associated with this account.
+pub(crate) async fn third_party_route(
+	State(services): State<crate::State>,
+	body: Ruma<get_3pids::v3::Request>,
+) -> Result<get_3pids::v3::Response> {
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+
+	let threepids = services
+		.users
+		.threepids(sender_user)
+		.map(|(medium, address, added_at)| {
+			let added_at = MilliSecondsSinceUnixEpoch(UInt::new_saturating(added_at));
+			ThirdPartyIdentifier {
+				address,
+				medium,
+				validated_at: added_at,
+				added_at,
+			}
+		})
+		.collect()
+		.await;
+
+	Ok(get_3pids::v3::Response::new(threepids))
+}
+
+/// # `POST /_matrix/client/v3/account/3pid/add`
+///
+/// Adds an email address validated through
+/// `/account/3pid/email/requestToken` to this account.
+///
+/// - Requires UIAA to verify user password
+#[tracing::instrument(skip_all, fields(%client), name = "add_3pid")]
+pub(crate) async fn add_3pid_route(
+	State(services): State<crate::State>,
+	InsecureClientIp(client): InsecureClientIp,
+	body: Ruma<add_3pid::v3::Request>,
+) -> Result<add_3pid::v3::Response> {
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+	let sender_device = body.sender_device.as_ref().expect("user is authenticated");
+
+	let mut uiaainfo = UiaaInfo {
+		flows: vec![AuthFlow { stages: vec![AuthType::Password] }],
+		completed: Vec::new(),
+		params: Box::default(),
+		session: None,
+		auth_error: None,
+	};
+
+	if let Some(auth) = &body.auth {
+		let (worked, uiaainfo) = services
+			.uiaa
+			.try_auth(sender_user, sender_device, auth, &uiaainfo)
+			.await?;
+
+		if !worked {
+			return Err(Error::Uiaa(uiaainfo));
+		}
+
+		// Success!
+	} else if let Some(json) = body.json_body {
+		uiaainfo.session = Some(utils::random_string(SESSION_ID_LENGTH));
+		services
+			.uiaa
+			.create(sender_user, sender_device, &uiaainfo, &json);
+
+		return Err(Error::Uiaa(uiaainfo));
+	} else {
+		return Err(Error::BadRequest(ErrorKind::NotJson, "Not json."));
+	}
+
+	let address = services
+		.email
+		.validated(&body.sid, &body.client_secret, Some(Purpose::AddThreepid))
+		.await?;
+
+	if services
+		.users
+		.find_from_threepid(&Medium::Email, &address)
+		.await
+		.is_ok()
+	{
+		return Err!(Request(ThreepidInUse("Email address is already in use.")));
+	}
+
+	services
+		.users
+		.add_threepid(sender_user, &Medium::Email, &address);
+	services.email.consume(&body.sid).await;
+
+	info!("User {sender_user} added an email address to their account.");
+
+	Ok(add_3pid::v3::Response::new())
+}
+
+/// # `POST /_matrix/client/v3/account/3pid/delete`
+///
+/// Removes a third party identifier from this account, along with the email
+/// pusher sending to it.
+pub(crate) async fn delete_3pid_route(
+	State(services): State<crate::State>,
+	body: Ruma<delete_3pid::v3::Request>,
+) -> Result<delete_3pid::v3::Response> {
+	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
+
+	let address = body.address.to_lowercase();
+	if !services
+		.users
+		.remove_threepid(sender_user, &body.medium, &address)
+		.await
+	{
+		return Err!(Request(ThreepidNotFound("Third party identifier is not on this account.")));
+	}
+
+	if body.medium == Medium::Email {
+		services
+			.pusher
+			.remove_email_pusher(sender_user, &address)
+			.await;
+	}
+
+	Ok(delete_3pid::v3::Response {
+		id_server_unbind_result: ThirdPartyIdRemovalStatus::NoSupport,
+	})
+}
+
+/// # `POST /_matrix/client/v3/account/3pid/email/requestToken`
+///
+/// "This API should be used to request validation tokens when adding an email
+/// address to an account"
+///
+/// - 403 signals that The homeserver does not allow the third party identifier
+///   as a contact option.
+pub(crate) async fn request_3pid_management_token_via_email_route(
+	State(services): State<crate::State>,
+	body: Ruma<request_3pid_management_token_via_email::v3::Request>,
+) -> Result<request_3pid_management_token_via_email::v3::Response> {
+	let (sid, submit_url) = request_token(
+		&services,
+		Purpose::AddThreepid,
+		&body.client_secret,
+		&body.email,
+		body.send_attempt,
+		body.next_link.as_deref(),
+	)
+	.await?;
+
+	Ok(request_3pid_management_token_via_email::v3::Response { sid, submit_url })
+}
+
+/// # `POST /_matrix/client/v3/account/3pid/msisdn/requestToken`
+///
+/// "This API should be used to request validation tokens when adding an phone
+/// number to an account"
+///
+/// - 403 signals that The homeserver does not allow the third party identifier
+///   as a contact option.
+pub(crate) async fn request_3pid_management_token_via_msisdn_route(
+	_body: Ruma<request_3pid_management_token_via_msisdn::v3::Request>,
+) -> Result<request_3pid_management_token_via_msisdn::v3::Response> {
+	Err(Error::BadRequest(
+		ErrorKind::ThreepidDenied,
+		"Third party identifier is not allowed",
+	))
+}
+
+/// # `POST /_matrix/client/v3/register/email/requestToken`
+///
+/// Sends a validation token to an email address not yet used by any account,
+/// to register with it.
+pub(crate) async fn request_registration_token_via_email_route(
+	State(services): State<crate::State>,
+	body: Ruma<request_registration_token_via_email::v3::Request>,
+) -> Result<request_registration_token_via_email::v3::Response> {
+	if !services.globals.allow_registration() {
+		return Err!(Request(Forbidden("Registration has been disabled.")));
+	}
+
+	let (sid, submit_url) = request_token(
+		&services,
+		Purpose::Registration,
+		&body.client_secret,
+		&body.email,
+		body.send_attempt,
+		body.next_link.as_deref(),
+	)
+	.await?;
+
+	Ok(request_registration_token_via_email::v3::Response { sid, submit_url })
+}
+
+/// # `POST /_matrix/client/v3/account/password/email/requestToken`
+///
+/// Sends a validation token to an email address of an account, to reset its
+/// password with `/account/password`.
+pub(crate) async fn request_password_change_token_via_email_route(
+	State(services): State<crate::State>,
+	body: Ruma<request_password_change_token_via_email::v3::Request>,
+) -> Result<request_password_change_token_via_email::v3::Response> {
+	let (sid, submit_url) = request_token(
+		&services,
+		Purpose::PasswordReset,
+		&body.client_secret,
+		&body.email,
+		body.send_attempt,
+		body.next_link.as_deref(),
+	)
+	.await?;
+
+	Ok(request_password_change_token_via_email::v3::Response { sid, submit_url })
+}
+
+/// # `GET /_conduwuit/email/validate`
+///
+/// Validates an email address from the link sent to it, then redirects to the
+/// `next_link` given by the client, if any and still allowed by
+/// `next_link_allowlist`.
+pub(crate) async fn validate_email_route(
+	State(services): State<crate::State>,
+	Query(params): Query<SubmitToken>,
+) -> Result<impl IntoResponse> {
+	let next_link = services
+		.email
+		.submit_token(&params.sid, &params.client_secret, &params.token)
+		.await?;
+
+	if let Some(next_link) = next_link {
+		return Ok(Redirect::to(next_link.as_str()).into_response());
+	}
+
+	Ok(Html(
+		"<!DOCTYPE html><title>Email validated</title><p>Your email address has been validated. \
+		 You can now return to your client.</p>",
+	)
+	.into_response())
+}
+
+/// # `POST /_conduwuit/email/validate`
+///
+/// Validates an email address with the token sent to it; the `submit_url` of
+/// `requestToken` responses.
+pub(crate) async fn submit_email_token_route(
+	State(services): State<crate::State>,
+	Json(params): Json<SubmitToken>,
+) -> Result<impl IntoResponse> {
+	services
+		.email
+		.submit_token(&params.sid, &params.client_secret, &params.token)
+		.await?;
+
+	Ok(Json(json!({ "success": true })))
+}
+
+async fn request_token(
+	services: &Services,
+	purpose: Purpose,
+	client_secret: &ClientSecret,
+	email: &str,
+	send_attempt: UInt,
+	next_link: Option<&str>,
+) -> Result<(OwnedSessionId, Option<String>)> {
+	let sid = services
+		.email
+		.request_token(purpose, client_secret, email, send_attempt, next_link)
+		.await?;
+
+	let submit_url = services.email.submit_url()?;
+
+	Ok((sid, Some(submit_url.into())))
+}
@@ -27,6 +27,10 @@ pub(crate) async fn create_typing_event_route(
 		return Err!(Request(Forbidden("You are not in this room.")));
 	}
 
+	if services.users.is_shadow_banned(sender_user).await {
+		return Ok(create_typing_event::v3::Response {});
+	}
+
 	if let Typing::Yes(duration) = body.state {
 		let duration = utils::clamp(
 			duration.as_millis().try_into().unwrap_or(u64::MAX),
@@ -1,18 +1,40 @@
 use std::time::{Duration, SystemTime};
 
-use axum::extract::State;
+use axum::{extract::State, response::IntoResponse};
 use base64::{engine::general_purpose, Engine as _};
 use conduwuit::{utils, Err};
 use hmac::{Hmac, Mac};
-use ruma::{api::client::voip::get_turn_server_info, SecondsSinceUnixEpoch, UserId};
+use ruma::{
+	api::client::voip::get_turn_server_info, OwnedRoomId, OwnedServerName, SecondsSinceUnixEpoch,
+	UserId,
+};
+use serde::Deserialize;
+use serde_json::json;
+use service::ratelimit::{Key, Kind};
 use sha1::Sha1;
 
-use crate::{Result, Ruma};
+use crate::{
+	router::extract::{ClientIp, Json},
+	Result, Ruma,
+};
 
 const RANDOM_USER_ID_LENGTH: usize = 10;
 
 type HmacSha1 = Hmac<Sha1>;
 
+#[derive(Deserialize)]
+pub(crate) struct SfuRequest {
+	room: OwnedRoomId,
+	openid_token: OpenIdToken,
+	device_id: String,
+}
+
+#[derive(Deserialize)]
+struct OpenIdToken {
+	access_token: String,
+	matrix_server_name: OwnedServerName,
+}
+
 /// # `GET /_matrix/client/r0/voip/turnServer`
 ///
 /// TODO: Returns information about the recommended turn server.
@@ -66,3 +88,37 @@ pub(crate) async fn turn_server_route(
 		ttl: Duration::from_secs(services.globals.turn_ttl()),
 	})
 }
+
+/// # `POST /_conduwuit/livekit/sfu/get`
+///
+/// Hands a LiveKit access token for the call of a room to a member of it,
+/// authenticated by an OpenID token from their homeserver. Compatible with
+/// lk-jwt-service, which MatrixRTC clients find through the `rtc_foci` of
+/// `/.well-known/matrix/client`.
+///
+/// - Rate limited per client IP, as it takes no access token
+pub(crate) async fn livekit_sfu_route(
+	State(services): State<crate::State>,
+	ClientIp(client): ClientIp,
+	Json(body): Json<SfuRequest>,
+) -> Result<impl IntoResponse> {
+	let livekit = &services.livekit;
+	let Some(url) = livekit.url().filter(|_| livekit.is_enabled()) else {
+		return Err!(Request(NotFound("No LiveKit SFU is configured.")));
+	};
+
+	services.ratelimit.check(Kind::Livekit, Key::Ip(client))?;
+
+	let user_id = livekit
+		.openid_user(&body.openid_token.matrix_server_name, &body.openid_token.access_token)
+		.await?;
+
+	let jwt = livekit
+		.access_token(&user_id, &body.device_id, &body.room)
+		.await?;
+
+	Ok(axum::Json(json!({
+		"url": url,
+		"jwt": jwt,
+	})))
+}
@@ -1,32 +1,40 @@
 use axum::{extract::State, response::IntoResponse, Json};
 use ruma::api::client::{
-	discovery::{
-		discover_homeserver::{self, HomeserverInfo, SlidingSyncProxyInfo},
-		discover_support::{self, Contact},
-	},
+	discovery::discover_support::{self, Contact},
 	error::ErrorKind,
 };
+use serde_json::json;
 
 use crate::{Error, Result, Ruma};
 
 /// # `GET /.well-known/matrix/client`
 ///
 /// Returns the .well-known URL if it is configured, otherwise returns 404.
+/// Advertises the built-in LiveKit authorization service as a MatrixRTC focus
+/// (MSC4143) when a LiveKit SFU is configured.
 pub(crate) async fn well_known_client(
 	State(services): State<crate::State>,
-	_body: Ruma<discover_homeserver::Request>,
-) -> Result<discover_homeserver::Response> {
+) -> Result<impl IntoResponse> {
 	let client_url = match services.server.config.well_known.client.as_ref() {
 		| Some(url) => url.to_string(),
 		| None => return Err(Error::BadRequest(ErrorKind::NotFound, "Not found.")),
 	};
 
-	Ok(discover_homeserver::Response {
-		homeserver: HomeserverInfo { base_url: client_url.clone() },
-		identity_server: None,
-		sliding_sync_proxy: Some(SlidingSyncProxyInfo { url: client_url }),
-		tile_server: None,
-	})
+	let mut response = json!({
+		"m.homeserver": { "base_url": client_url },
+		"org.matrix.msc3575.proxy": { "url": client_url },
+	});
+
+	if services.livekit.is_enabled() {
+		let livekit_service_url =
+			format!("{}/_conduwuit/livekit", client_url.trim_end_matches('/'));
+		response["org.matrix.msc4143.rtc_foci"] = json!([{
+			"type": "livekit",
+			"livekit_service_url": livekit_service_url,
+		}]);
+	}
+
+	Ok(Json(response))
 }
 
 /// # `GET /.well-known/matrix/support`
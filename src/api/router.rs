@@ -1,6 +1,8 @@
 mod args;
 mod auth;
+pub(crate) mod extract;
 mod handler;
+mod ratelimit;
 mod request;
 mod response;
 pub mod state;
@@ -9,7 +11,7 @@ use std::str::FromStr;
 
 use axum::{
 	response::{IntoResponse, Redirect},
-	routing::{any, get, post},
+	routing::{any, delete, get, post, put},
 	Router,
 };
 use conduwuit::{err, Server};
@@ -17,7 +19,7 @@ use http::{uri, Uri};
 
 use self::handler::RouterExt;
 pub(super) use self::{args::Args as Ruma, response::RumaResponse, state::State};
-use crate::{client, server};
+use crate::{admin, client, server};
 
 pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 	let config = &server.config;
@@ -35,14 +37,25 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 		.ruma_route(&client::get_login_types_route)
 		.ruma_route(&client::login_route)
 		.ruma_route(&client::login_token_route)
+		.ruma_route(&client::sso_login_route)
+		.ruma_route(&client::sso_login_with_provider_route)
+		.route("/_conduwuit/oidc/callback", get(client::sso_callback_route))
 		.ruma_route(&client::whoami_route)
 		.ruma_route(&client::logout_route)
 		.ruma_route(&client::logout_all_route)
 		.ruma_route(&client::change_password_route)
 		.ruma_route(&client::deactivate_route)
 		.ruma_route(&client::third_party_route)
+		.ruma_route(&client::add_3pid_route)
+		.ruma_route(&client::delete_3pid_route)
 		.ruma_route(&client::request_3pid_management_token_via_email_route)
 		.ruma_route(&client::request_3pid_management_token_via_msisdn_route)
+		.ruma_route(&client::request_registration_token_via_email_route)
+		.ruma_route(&client::request_password_change_token_via_email_route)
+		.route(
+			"/_conduwuit/email/validate",
+			get(client::validate_email_route).post(client::submit_email_token_route),
+		)
 		.ruma_route(&client::check_registration_token_validity)
 		.ruma_route(&client::get_capabilities_route)
 		.ruma_route(&client::get_pushrules_all_route)
@@ -117,8 +130,32 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 		.ruma_route(&client::get_protocols_route)
 		.route("/_matrix/client/unstable/thirdparty/protocols",
 			get(client::get_protocols_route_unstable))
-		.ruma_route(&client::send_message_event_route)
-		.ruma_route(&client::send_state_event_for_key_route)
+		// These answer with a delay ID rather than the Ruma response when the event is
+		// delayed (MSC4140)
+		.route(
+			"/_matrix/client/r0/rooms/:room_id/send/:event_type/:txn_id",
+			put(client::send_message_event_route),
+		)
+		.route(
+			"/_matrix/client/v3/rooms/:room_id/send/:event_type/:txn_id",
+			put(client::send_message_event_route),
+		)
+		.route(
+			"/_matrix/client/r0/rooms/:room_id/state/:event_type/:state_key",
+			put(client::send_state_event_for_key_route),
+		)
+		.route(
+			"/_matrix/client/v3/rooms/:room_id/state/:event_type/:state_key",
+			put(client::send_state_event_for_key_route),
+		)
+		.route(
+			"/_matrix/client/unstable/org.matrix.msc4140/delayed_events",
+			get(client::get_delayed_events_route),
+		)
+		.route(
+			"/_matrix/client/unstable/org.matrix.msc4140/delayed_events/:delay_id",
+			post(client::update_delayed_event_route),
+		)
 		.ruma_route(&client::get_state_events_route)
 		.ruma_route(&client::get_state_events_for_key_route)
 		// Ruma doesn't have support for multiple paths for a single endpoint yet, and these routes
@@ -171,6 +208,10 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 		.ruma_route(&client::get_key_changes_route)
 		.ruma_route(&client::get_pushers_route)
 		.ruma_route(&client::set_pushers_route)
+		.route(
+			"/_conduwuit/email/unsubscribe",
+			get(client::unsubscribe_email_page_route).post(client::unsubscribe_email_route),
+		)
 		.ruma_route(&client::upgrade_room_route)
 		.ruma_route(&client::get_threads_route)
 		.ruma_route(&client::get_relating_events_with_rel_type_and_event_type_route)
@@ -184,7 +225,8 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 			get(client::get_room_summary_legacy)
 		)
 		.ruma_route(&client::well_known_support)
-		.ruma_route(&client::well_known_client)
+		.route("/.well-known/matrix/client", get(client::well_known_client))
+		.route("/_conduwuit/livekit/sfu/get", post(client::livekit_sfu_route))
 		.route("/_conduwuit/server_version", get(client::conduwuit_server_version))
 		.ruma_route(&client::room_initial_sync_route)
 		.route("/client/server.json", get(client::syncv3_client_server_json));
@@ -272,7 +314,36 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 			.route("/_matrix/media/r0/preview_url", any(redirect_legacy_preview));
 	}
 
+	let admin = Router::new()
+		.route("/v1/server_version", get(admin::server_version_route))
+		.route("/v2/users", get(admin::list_users_route))
+		.route("/v2/users/:user_id", get(admin::get_user_route))
+		.route("/v1/deactivate/:user_id", post(admin::deactivate_user_route))
+		.route("/v1/reset_password/:user_id", post(admin::reset_password_route))
+		.route("/v1/rooms", get(admin::list_rooms_route))
+		.route(
+			"/v1/rooms/:room_id",
+			get(admin::get_room_route).delete(admin::delete_room_route),
+		)
+		.route("/v1/rooms/:room_id/members", get(admin::get_room_members_route))
+		.route(
+			"/v1/rooms/:room_id/block",
+			get(admin::get_room_block_route).put(admin::set_room_block_route),
+		)
+		.route("/v1/media/:server_name/:media_id", delete(admin::delete_media_route))
+		.route(
+			"/v1/media/quarantine/:server_name/:media_id",
+			post(admin::quarantine_media_route),
+		)
+		.route(
+			"/v1/media/unquarantine/:server_name/:media_id",
+			post(admin::unquarantine_media_route),
+		)
+		.route("/v1/users/:user_id/media", get(admin::list_user_media_route));
+
 	router
+		.nest("/_synapse/admin", admin.clone())
+		.nest("/_conduwuit/admin", admin)
 }
 
 async fn redirect_legacy_preview(uri: Uri) -> impl IntoResponse {
@@ -9,7 +9,7 @@ use ruma::{
 };
 use service::Services;
 
-use super::{auth, auth::Auth, request, request::Request};
+use super::{auth, auth::Auth, ratelimit, request, request::Request};
 use crate::{service::appservice::RegistrationInfo, State};
 
 /// Extractor for Ruma request structs
@@ -36,6 +36,10 @@ pub(crate) struct Args<T> {
 	/// Parsed JSON content.
 	/// None when body is not a valid string
 	pub(crate) json_body: Option<CanonicalJsonValue>,
+
+	/// Delay in milliseconds before sending the event (MSC4140).
+	/// None when the event is to be sent right away.
+	pub(crate) delay: Option<u64>,
 }
 
 impl<T> Args<T>
@@ -108,6 +112,7 @@ where
 			json_body = Some(CanonicalJsonValue::Object(CanonicalJsonObject::new()));
 		}
 		let auth = auth::auth(services, &mut request, json_body.as_ref(), &T::METADATA).await?;
+		ratelimit::check(services, &mut request, &auth, &T::METADATA).await?;
 		Ok(Self {
 			body: make_body::<T>(services, &mut request, json_body.as_mut(), &auth)?,
 			origin: auth.origin,
@@ -115,6 +120,7 @@ where
 			sender_device: auth.sender_device,
 			appservice_info: auth.appservice_info,
 			json_body,
+			delay: request.query.delay,
 		})
 	}
 }
@@ -221,10 +221,26 @@ async fn auth_appservice(
 		return Err!(Request(Exclusive("User is not in namespace.")));
 	}
 
+	let device_id: Option<OwnedDeviceId> = request.query.device_id.as_deref().map(Into::into);
+	if let Some(device_id) = device_id.as_deref() {
+		if !info.extensions.msc3202 {
+			return Err!(Request(Forbidden("Appservice may not masquerade as a device.")));
+		}
+
+		if services
+			.users
+			.get_device_metadata(&user_id, device_id)
+			.await
+			.is_err()
+		{
+			return Err!(Request(Forbidden("Device {device_id} does not exist for {user_id}.")));
+		}
+	}
+
 	Ok(Auth {
 		origin: None,
 		sender_user: Some(user_id),
-		sender_device: None,
+		sender_device: device_id,
 		appservice_info: Some(*info),
 	})
 }
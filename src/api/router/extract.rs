@@ -0,0 +1,116 @@
+//! Extractors for the routes Ruma has no request type for.
+
+use std::{convert::Infallible, net::IpAddr};
+
+use axum::{
+	async_trait,
+	body::Body,
+	extract::{FromRequest, FromRequestParts},
+	RequestPartsExt,
+};
+use axum_extra::{
+	headers::{authorization::Bearer, Authorization},
+	TypedHeader,
+};
+use conduwuit::{err, Error, Result};
+use http::request::Parts;
+use ruma::{api::client::error::ErrorKind, OwnedUserId};
+use serde::{de::DeserializeOwned, Deserialize};
+
+use super::{ratelimit::client_ip, State};
+
+/// Extractor authenticating a local user by their access token, for routes
+/// Ruma has no request type for.
+pub(crate) struct User {
+	pub(crate) sender_user: OwnedUserId,
+}
+
+/// Extractor for query string parameters.
+pub(crate) struct Query<T>(pub(crate) T);
+
+/// Extractor for a JSON request body; an empty body is read as `{}`.
+pub(crate) struct Json<T>(pub(crate) T);
+
+/// Extractor for the address of the client, as rate limits see it.
+pub(crate) struct ClientIp(pub(crate) IpAddr);
+
+#[derive(Deserialize)]
+struct AccessToken {
+	access_token: Option<String>,
+}
+
+#[async_trait]
+impl FromRequestParts<State> for User {
+	type Rejection = Error;
+
+	async fn from_request_parts(parts: &mut Parts, services: &State) -> Result<Self> {
+		let bearer: Option<TypedHeader<Authorization<Bearer>>> = parts.extract().await?;
+		let query: Option<AccessToken> =
+			serde_html_form::from_str(parts.uri.query().unwrap_or_default()).ok();
+
+		let token = match &bearer {
+			| Some(TypedHeader(Authorization(bearer))) => Some(bearer.token().to_owned()),
+			| None => query.and_then(|query| query.access_token),
+		};
+
+		let Some(token) = token else {
+			return Err(Error::BadRequest(ErrorKind::MissingToken, "Missing access token."));
+		};
+
+		let Ok((sender_user, _)) = services.users.find_from_token(&token).await else {
+			return Err(Error::BadRequest(
+				ErrorKind::UnknownToken { soft_logout: false },
+				"Unknown access token.",
+			));
+		};
+
+		Ok(Self { sender_user })
+	}
+}
+
+#[async_trait]
+impl<T> FromRequestParts<State> for Query<T>
+where
+	T: DeserializeOwned,
+{
+	type Rejection = Error;
+
+	async fn from_request_parts(parts: &mut Parts, _: &State) -> Result<Self> {
+		let query = parts.uri.query().unwrap_or_default();
+		serde_html_form::from_str(query)
+			.map(Self)
+			.map_err(|e| err!(Request(InvalidParam("Failed to read query parameters: {e}"))))
+	}
+}
+
+#[async_trait]
+impl<T> FromRequest<State, Body> for Json<T>
+where
+	T: DeserializeOwned,
+{
+	type Rejection = Error;
+
+	async fn from_request(request: hyper::Request<Body>, services: &State) -> Result<Self> {
+		let max_body_size = services.server.config.max_request_size;
+		let body = axum::body::to_bytes(request.into_body(), max_body_size)
+			.await
+			.map_err(|e| err!(Request(TooLarge("Request body too large: {e}"))))?;
+
+		let body: &[u8] = if body.is_empty() { b"{}" } else { &body };
+		serde_json::from_slice(body)
+			.map(Self)
+			.map_err(|e| err!(Request(BadJson("Invalid JSON body: {e}"))))
+	}
+}
+
+#[async_trait]
+impl FromRequestParts<State> for ClientIp {
+	type Rejection = Infallible;
+
+	async fn from_request_parts(
+		parts: &mut Parts,
+		services: &State,
+	) -> Result<Self, Self::Rejection> {
+		Ok(Self(client_ip(services, parts).await))
+	}
+}
@@ -0,0 +1,118 @@
+mod tests;
+
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+
+use axum::{extract::ConnectInfo, RequestPartsExt};
+use axum_client_ip::InsecureClientIp;
+use conduwuit::Result;
+use http::request::Parts;
+use ruma::api::{
+	client::{
+		account::{
+			check_registration_token_validity, register, request_3pid_management_token_via_email,
+			request_password_change_token_via_email, request_registration_token_via_email,
+		},
+		media::create_content,
+		membership::{invite_user, join_room_by_id, join_room_by_id_or_alias, knock_room},
+		message::send_message_event,
+		session::login,
+		state::send_state_event,
+	},
+	IncomingRequest, Metadata, OwnedDeviceId, OwnedUserId,
+};
+use service::{
+	ratelimit::{Key, Kind},
+	Services,
+};
+
+use super::{auth::Auth, request::Request};
+
+/// Enforces the rate limit applying to the endpoint, if any. Authenticated
+/// requests are limited per device, or per user without one, others per client
+/// IP address.
+pub(super) async fn check(
+	services: &Services,
+	request: &mut Request,
+	auth: &Auth,
+	metadata: &Metadata,
+) -> Result {
+	if !services.server.config.ratelimit {
+		return Ok(());
+	}
+
+	let Some(kind) = kind(metadata) else {
+		return Ok(());
+	};
+
+	if auth
+		.appservice_info
+		.as_ref()
+		.is_some_and(|info| !info.registration.rate_limited.unwrap_or(true))
+	{
+		return Ok(());
+	}
+
+	if let Some(user_id) = &auth.sender_user {
+		if services.ratelimit.is_exempt(user_id).await {
+			return Ok(());
+		}
+	}
+
+	let ip = match auth.sender_user {
+		| Some(_) => None,
+		| None => Some(client_ip(services, &mut request.parts).await),
+	};
+
+	services
+		.ratelimit
+		.check(kind, key(auth.sender_user.clone(), auth.sender_device.clone(), ip))
+}
+
+/// Bucket a request is counted in: the sending device, the sending user when
+/// there is no device such as for appservices, or else the client address.
+fn key(
+	sender_user: Option<OwnedUserId>,
+	sender_device: Option<OwnedDeviceId>,
+	ip: Option<IpAddr>,
+) -> Key {
+	match (sender_user, sender_device) {
+		| (Some(user_id), Some(device_id)) => Key::Device(user_id, device_id),
+		| (Some(user_id), None) => Key::User(user_id),
+		| (None, _) => Key::Ip(ip.unwrap_or(Ipv6Addr::UNSPECIFIED.into())),
+	}
+}
+
+/// Address of the client, taken from the forwarding headers set by a reverse
+/// proxy only if they are trusted. Connections without a peer address, such as
+/// over a UNIX socket, share one bucket.
+pub(super) async fn client_ip(services: &Services, parts: &mut Parts) -> IpAddr {
+	if services.server.config.ratelimit_trust_forwarded_headers {
+		if let Ok(InsecureClientIp(ip)) = parts.extract().await {
+			return ip;
+		}
+	}
+
+	parts
+		.extract::<ConnectInfo<SocketAddr>>()
+		.await
+		.map_or(Ipv6Addr::UNSPECIFIED.into(), |ConnectInfo(addr)| addr.ip())
+}
+
+fn kind(metadata: &Metadata) -> Option<Kind> {
+	match metadata {
+		| &login::v3::Request::METADATA => Some(Kind::Login),
+		| &register::v3::Request::METADATA
+		| &check_registration_token_validity::v1::Request::METADATA => Some(Kind::Register),
+		| &send_message_event::v3::Request::METADATA
+		| &send_state_event::v3::Request::METADATA => Some(Kind::Message),
+		| &join_room_by_id::v3::Request::METADATA
+		| &join_room_by_id_or_alias::v3::Request::METADATA
+		| &knock_room::v3::Request::METADATA => Some(Kind::Join),
+		| &invite_user::v3::Request::METADATA => Some(Kind::Invite),
+		| &create_content::v3::Request::METADATA => Some(Kind::MediaUpload),
+		| &request_3pid_management_token_via_email::v3::Request::METADATA
+		| &request_password_change_token_via_email::v3::Request::METADATA
+		| &request_registration_token_via_email::v3::Request::METADATA => Some(Kind::EmailToken),
+		| _ => None,
+	}
+}
@@ -0,0 +1,53 @@
+#![cfg(test)]
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use ruma::{
+	api::{
+		client::{
+			account::register, media::create_content, membership::invite_user,
+			message::send_message_event, session::login, sync::sync_events,
+		},
+		IncomingRequest,
+	},
+	owned_device_id, owned_user_id,
+};
+use service::ratelimit::{Key, Kind};
+
+use super::{key, kind};
+
+#[test]
+fn endpoints_map_to_their_buckets() {
+	assert_eq!(kind(&login::v3::Request::METADATA), Some(Kind::Login));
+	assert_eq!(kind(&register::v3::Request::METADATA), Some(Kind::Register));
+	assert_eq!(kind(&send_message_event::v3::Request::METADATA), Some(Kind::Message));
+	assert_eq!(kind(&invite_user::v3::Request::METADATA), Some(Kind::Invite));
+	assert_eq!(kind(&create_content::v3::Request::METADATA), Some(Kind::MediaUpload));
+	assert_eq!(kind(&sync_events::v3::Request::METADATA), None);
+}
+
+#[test]
+fn devices_are_keyed_apart() {
+	let user_id = owned_user_id!("@alice:example.com");
+	let phone = key(Some(user_id.clone()), Some(owned_device_id!("PHONE")), None);
+	let laptop = key(Some(user_id.clone()), Some(owned_device_id!("LAPTOP")), None);
+
+	assert_eq!(phone, Key::Device(user_id.clone(), owned_device_id!("PHONE")));
+	assert_ne!(phone, laptop);
+}
+
+#[test]
+fn users_without_device_are_keyed_by_user() {
+	let user_id = owned_user_id!("@bridge:example.com");
+	let ip = IpAddr::from(Ipv4Addr::new(192, 0, 2, 1));
+
+	assert_eq!(key(Some(user_id.clone()), None, Some(ip)), Key::User(user_id));
+}
+
+#[test]
+fn anonymous_requests_are_keyed_by_address() {
+	let ip = IpAddr::from(Ipv4Addr::new(192, 0, 2, 1));
+
+	assert_eq!(key(None, None, Some(ip)), Key::Ip(ip));
+	assert_eq!(key(None, None, None), Key::Ip(Ipv6Addr::UNSPECIFIED.into()));
+}
@@ -11,6 +11,10 @@ use service::Services;
 pub(super) struct QueryParams {
 	pub(super) access_token: Option<String>,
 	pub(super) user_id: Option<String>,
+	#[serde(alias = "org.matrix.msc3202.device_id")]
+	pub(super) device_id: Option<String>,
+	#[serde(rename = "org.matrix.msc4140.delay")]
+	pub(super) delay: Option<u64>,
 }
 
 pub(super) struct Request {
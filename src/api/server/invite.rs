@@ -103,6 +103,12 @@ pub(crate) async fn create_invite_route(
 		return Err!(Request(Forbidden("This room is banned on this homeserver.")));
 	}
 
+	if services.policy_lists.user_ban(sender).is_some()
+		|| services.policy_lists.room_ban(&body.room_id).is_some()
+	{
+		return Err!(Request(Forbidden("Invite is banned by a policy list of this homeserver.")));
+	}
+
 	if services.globals.block_non_admin_invites() && !services.users.is_admin(&invited_user).await
 	{
 		return Err!(Request(Forbidden("This server does not allow room invites.")));
@@ -41,6 +41,10 @@ pub(crate) async fn create_join_event_template_route(
 		.acl_check(body.origin(), &body.room_id)
 		.await?;
 
+	if services.policy_lists.user_ban(&body.user_id).is_some() {
+		return Err!(Request(Forbidden("User is banned by a policy list of this server.")));
+	}
+
 	if services
 		.server
 		.config
@@ -124,6 +124,10 @@ async fn create_join_event(
 		return Err!(Request(Forbidden("Not allowed to join on behalf of another server.")));
 	}
 
+	if services.policy_lists.user_ban(&sender).is_some() {
+		return Err!(Request(Forbidden("User is banned by a policy list of this server.")));
+	}
+
 	let state_key: OwnedUserId = serde_json::from_value(
 		value
 			.get("state_key")
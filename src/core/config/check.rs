@@ -156,6 +156,7 @@ pub fn check(config: &Config) -> Result<()> {
 		&& !config.yes_i_am_very_very_sure_i_want_an_open_registration_server_prone_to_abuse
 		&& config.registration_token.is_none()
 		&& config.registration_token_file.is_none()
+		&& !config.registration_requires_token
 	{
 		return Err!(Config(
 			"registration_token",
@@ -173,6 +174,7 @@ pub fn check(config: &Config) -> Result<()> {
 		&& config.yes_i_am_very_very_sure_i_want_an_open_registration_server_prone_to_abuse
 		&& config.registration_token.is_none()
 		&& config.registration_token_file.is_none()
+		&& !config.registration_requires_token
 	{
 		warn!(
 			"Open registration is enabled via setting \
@@ -183,6 +185,23 @@ pub fn check(config: &Config) -> Result<()> {
 		);
 	}
 
+	if config.ratelimit
+		&& !config.ratelimit_trust_forwarded_headers
+		&& (config.unix_socket_path.is_some()
+			|| config
+				.get_bind_addrs()
+				.iter()
+				.any(|addr| addr.ip().is_loopback()))
+	{
+		warn!(
+			"Rate limiting is enabled without trusting forwarded headers while listening on a \
+			 UNIX socket or a loopback address. If conduwuit is behind a reverse proxy, all \
+			 unauthenticated requests share the proxy's address and one rate limit bucket, \
+			 which can lock everyone out of logging in. Please enable \
+			 'ratelimit_trust_forwarded_headers' if your reverse proxy sets these headers."
+		);
+	}
+
 	if config.allow_outgoing_presence && !config.allow_local_presence {
 		return Err!(Config(
 			"allow_local_presence",
@@ -233,6 +252,72 @@ pub fn check(config: &Config) -> Result<()> {
 		}
 	}
 
+	for (i, provider) in config.oidc_providers.iter().enumerate() {
+		if provider.id.is_empty() {
+			return Err!(Config("oidc_providers", "Provider {i} has an empty id"));
+		}
+
+		if config
+			.oidc_providers
+			.iter()
+			.take(i)
+			.any(|other| other.id == provider.id)
+		{
+			return Err!(Config("oidc_providers", "Provider id {:?} is not unique", provider.id));
+		}
+	}
+
+	match config.media_storage_provider.as_str() {
+		| "filesystem" => {},
+		| "s3" if config.media_s3_endpoint.is_none() || config.media_s3_bucket.is_none() => {
+			return Err!(Config(
+				"media_storage_provider",
+				"The s3 provider requires media_s3_endpoint and media_s3_bucket to be set"
+			));
+		},
+		| "s3" => {},
+		| provider => {
+			return Err!(Config(
+				"media_storage_provider",
+				"Unknown provider {provider:?}, expected \"filesystem\" or \"s3\""
+			));
+		},
+	}
+
+	if config.ldap.enable && config.ldap.uri.is_none() {
+		return Err!(Config("ldap.uri", "LDAP cannot be enabled without a server URI"));
+	}
+
+	if config.ldap.enable && config.ldap.base_dn.is_none() {
+		return Err!(Config("ldap.base_dn", "LDAP cannot be enabled without a base DN"));
+	}
+
+	if config.email.enable && config.email.smtp_url.is_none() {
+		return Err!(Config("email.smtp_url", "Email cannot be enabled without an SMTP server"));
+	}
+
+	if config.email.enable && config.email.from.is_none() {
+		return Err!(Config("email.from", "Email cannot be enabled without a sender address"));
+	}
+
+	if config.email.require_for_registration && !config.email.enable {
+		return Err!(Config(
+			"email.require_for_registration",
+			"Email addresses cannot be required for registration without enabling email"
+		));
+	}
+
+	if let (Some(min), Some(max)) =
+		(config.retention_allowed_min_lifetime, config.retention_allowed_max_lifetime)
+	{
+		if min > max {
+			return Err!(Config(
+				"retention_allowed_min_lifetime",
+				"Must not be longer than retention_allowed_max_lifetime"
+			));
+		}
+	}
+
 	if !Server::available_room_versions()
 		.any(|(version, _)| version == config.default_room_version)
 	{
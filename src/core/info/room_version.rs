@@ -2,9 +2,11 @@
 
 use std::iter::once;
 
-use ruma::{api::client::discovery::get_capabilities::RoomVersionStability, RoomVersionId};
+use ruma::{
+	api::client::discovery::get_capabilities::RoomVersionStability, RoomVersionId, UserId,
+};
 
-use crate::{at, is_equal_to};
+use crate::{at, config::RoomVersionPolicyConfig, is_equal_to};
 
 /// Supported and stable room versions
 pub const STABLE_ROOM_VERSIONS: &[RoomVersionId] = &[
@@ -20,6 +22,15 @@ pub const STABLE_ROOM_VERSIONS: &[RoomVersionId] = &[
 pub const UNSTABLE_ROOM_VERSIONS: &[RoomVersionId] =
 	&[RoomVersionId::V2, RoomVersionId::V3, RoomVersionId::V4, RoomVersionId::V5];
 
+/// Room versions rooms should be upgraded from, with the reason
+pub const DEPRECATED_ROOM_VERSIONS: &[(RoomVersionId, &str)] = &[
+	(RoomVersionId::V1, "resolves state with the original, flawed algorithm"),
+	(RoomVersionId::V2, "event IDs are not derived from the events"),
+	(RoomVersionId::V3, "does not enforce the validity period of signing keys"),
+	(RoomVersionId::V4, "does not enforce the validity period of signing keys"),
+	(RoomVersionId::V5, "does not enforce canonical JSON or integer power levels"),
+];
+
 type RoomVersion = (RoomVersionId, RoomVersionStability);
 
 impl crate::Server {
@@ -40,6 +51,69 @@ impl crate::Server {
 		available_room_versions()
 	}
 
+	/// Whether a user may create rooms of the version or upgrade rooms to it.
+	/// Server admins and appservices may use any supported version.
+	pub fn room_version_allowed(
+		&self,
+		version: &RoomVersionId,
+		creator: &UserId,
+		privileged: bool,
+	) -> bool {
+		let allowed = self
+			.room_version_policy(creator)
+			.map_or(&self.config.allowed_room_versions_for_users, |policy| &policy.allowed);
+
+		self.supported_room_version(version)
+			&& (privileged || allowed.is_empty() || allowed.contains(version))
+	}
+
+	/// Why rooms of the version created by `creator` should be upgraded, if
+	/// they should.
+	pub fn room_version_deprecation(
+		&self,
+		version: &RoomVersionId,
+		creator: &UserId,
+	) -> Option<&'static str> {
+		let upgrade = self
+			.room_version_policy(creator)
+			.map_or(&self.config.upgrade_room_versions, |policy| &policy.upgrade);
+
+		deprecation(version).or_else(|| {
+			upgrade
+				.contains(version)
+				.then_some("flagged for upgrade by the server's configuration")
+		})
+	}
+
+	/// Version a user should upgrade rooms to: the default room version if
+	/// they may use it, else the newest version they may use which is not
+	/// flagged for upgrade itself.
+	pub fn recommended_room_version(&self, user_id: &UserId) -> Option<RoomVersionId> {
+		let usable = |version: &RoomVersionId| {
+			self.room_version_allowed(version, user_id, false)
+				&& self.room_version_deprecation(version, user_id).is_none()
+		};
+
+		let default = &self.config.default_room_version;
+		if usable(default) {
+			return Some(default.clone());
+		}
+
+		STABLE_ROOM_VERSIONS
+			.iter()
+			.rev()
+			.find(|version| usable(version))
+			.cloned()
+	}
+
+	/// Policy for the rooms created by a user, if one matches them.
+	pub fn room_version_policy(&self, creator: &UserId) -> Option<&RoomVersionPolicyConfig> {
+		self.config
+			.room_version_policies
+			.iter()
+			.find(|policy| policy.users.is_match(creator.as_str()))
+	}
+
 	#[inline]
 	fn supported_stability(&self, stability: &RoomVersionStability) -> bool {
 		self.config.allow_unstable_room_versions || *stability == RoomVersionStability::Stable
@@ -58,3 +132,12 @@ pub fn available_room_versions() -> impl Iterator<Item = RoomVersion> {
 		.zip(once(RoomVersionStability::Stable).cycle())
 		.chain(unstable_room_versions)
 }
+
+/// Why conduwuit deprecates the room version, if it does.
+#[must_use]
+pub fn deprecation(version: &RoomVersionId) -> Option<&'static str> {
+	DEPRECATED_ROOM_VERSIONS
+		.iter()
+		.find(|(deprecated, _)| deprecated == version)
+		.map(at!(1))
+}
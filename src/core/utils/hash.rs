@@ -1,10 +1,20 @@
 mod argon;
+mod bcrypt;
 pub mod sha256;
 
 use crate::Result;
 
 pub fn verify_password(password: &str, password_hash: &str) -> Result {
+	if bcrypt::is_bcrypt(password_hash) {
+		return bcrypt::verify_password(password, password_hash);
+	}
+
 	argon::verify_password(password, password_hash)
 }
 
+/// Whether the hash uses an algorithm kept only to verify imported hashes, so
+/// it should be replaced once the password is known.
+#[must_use]
+pub fn is_legacy(password_hash: &str) -> bool { bcrypt::is_bcrypt(password_hash) }
+
 pub fn password(password: &str) -> Result<String> { argon::password(password) }
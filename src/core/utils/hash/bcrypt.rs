@@ -0,0 +1,28 @@
+use crate::{err, Err, Result};
+
+/// Whether the hash is a bcrypt hash, as imported from Synapse.
+pub(super) fn is_bcrypt(password_hash: &str) -> bool {
+	["$2a$", "$2b$", "$2x$", "$2y$"]
+		.iter()
+		.any(|prefix| password_hash.starts_with(prefix))
+}
+
+pub(super) fn verify_password(password: &str, password_hash: &str) -> Result<()> {
+	match ::bcrypt::verify(password, password_hash) {
+		| Ok(true) => Ok(()),
+		| Ok(false) => Err!("password does not match"),
+		| Err(e) => Err(err!("{e}")),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	#[test]
+	fn bcrypt_verify() {
+		use crate::utils::hash;
+		let digest = ::bcrypt::hash("temp123", 4).expect("digest");
+		assert!(hash::is_legacy(&digest), "bcrypt hashes are legacy");
+		hash::verify_password("temp123", &digest).expect("verified");
+		assert!(hash::verify_password("temp321", &digest).is_err(), "unverified");
+	}
+}
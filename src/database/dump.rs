@@ -0,0 +1,380 @@
+//! Logical dumps of the database: every record, by column, in a stream which
+//! does not depend on the version or layout of RocksDB. Dumps can be checked
+//! and inspected, imported into a new database, or restored in part.
+//!
+//! A dump starts with [`MAGIC`] and the big-endian [`VERSION`], followed by a
+//! section for each column: a column frame with its name, a record frame for
+//! each record with the key and value as encoded by `ser`, and an end frame
+//! with the number of records and their SHA-256. The last frame holds the JSON
+//! [`Manifest`] and its SHA-256. Lengths are big-endian u32.
+
+use std::{
+	collections::BTreeSet,
+	fmt::Write as _,
+	io::{Read, Seek, Write},
+};
+
+use conduwuit::{
+	info,
+	ruma::UserId,
+	utils::{self, stream::TryIgnore, ReadyExt},
+	warn, Err, Result,
+};
+use futures::{pin_mut, StreamExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{Database, SEP};
+
+/// Bytes every dump starts with.
+pub const MAGIC: &[u8; 8] = b"CWDBDUMP";
+
+/// Version of the dump format written; dumps of older versions can be read.
+pub const VERSION: u16 = 1;
+
+const COLUMN: u8 = b'C';
+const RECORD: u8 = b'R';
+const END: u8 = b'E';
+const MANIFEST: u8 = b'M';
+
+/// Summary of a dump, written after the last column.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Manifest {
+	/// Format version of the dump.
+	pub version: u16,
+
+	/// Server the database belongs to.
+	pub server_name: String,
+
+	/// When the dump was created, in milliseconds since the unix epoch.
+	pub created: u64,
+
+	pub columns: Vec<Column>,
+}
+
+/// Section of a dump holding the records of one column.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Column {
+	pub name: String,
+
+	pub records: u64,
+
+	/// SHA-256 of the record frames of the section, in hex.
+	pub sha256: String,
+}
+
+/// Parts of an account which can be restored on their own.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Scope {
+	/// Global and room account data
+	AccountData,
+
+	/// Room key backups
+	KeyBackups,
+}
+
+/// Writes a dump frame by frame.
+pub struct Writer<W: Write> {
+	out: W,
+	manifest: Manifest,
+	section: Option<(String, u64, Sha256)>,
+}
+
+/// Writes every record of the database as a dump. A database opened
+/// read-only does not change while it is open, so the dump is consistent.
+pub async fn export<W: Write>(db: &Database, out: W) -> Result<Manifest> {
+	let server_name = db.db.ctx.server.config.server_name.as_str();
+	let mut writer = Writer::new(out, server_name)?;
+	for (name, map) in db.iter() {
+		writer.column(name)?;
+
+		let records = map.raw_stream();
+		pin_mut!(records);
+		while let Some((key, val)) = records.next().await.transpose()? {
+			writer.record(key, val)?;
+		}
+	}
+
+	let manifest = writer.finish()?;
+	let records: u64 = manifest.columns.iter().map(|column| column.records).sum();
+	info!("Dumped {records} records of {} columns", manifest.columns.len());
+
+	Ok(manifest)
+}
+
+/// Writes every record of a dump into an empty database. The dump is checked
+/// before anything is written. Columns unknown to this version are skipped.
+pub async fn import<R: Read + Seek>(db: &Database, mut input: R) -> Result<Manifest> {
+	let server_name = db.db.ctx.server.config.server_name.as_str();
+	let manifest = read(&mut input, |_, _, _| Ok(()))?;
+	if manifest.server_name != server_name {
+		return Err!(Database(
+			"Dump is of {:?} but this server is {server_name:?}.",
+			manifest.server_name
+		));
+	}
+
+	for (name, map) in db.iter() {
+		let keys = map.raw_keys();
+		pin_mut!(keys);
+		if keys.next().await.is_some() {
+			return Err!(Database("Column {name} is not empty; import into a new database."));
+		}
+	}
+
+	input.rewind()?;
+	let mut skipped = BTreeSet::new();
+	read(input, |column, key, val| {
+		match db.get(column) {
+			| Ok(map) => map.insert(key, val),
+			| Err(_) =>
+				if skipped.insert(column.to_owned()) {
+					warn!("Skipping column {column} which this version does not have");
+				},
+		}
+
+		Ok(())
+	})?;
+
+	info!("Imported dump created at {} of {server_name}", manifest.created);
+
+	Ok(manifest)
+}
+
+/// Replaces the records of a user in the columns of `scope` with those of a
+/// dump. The dump is checked before anything is removed. Returns the number
+/// of records restored.
+pub async fn restore<R: Read + Seek>(
+	db: &Database,
+	mut input: R,
+	user_id: &UserId,
+	scope: Scope,
+) -> Result<usize> {
+	read(&mut input, |_, _, _| Ok(()))?;
+
+	for &(name, _) in scope.columns() {
+		let map = db.get(name)?;
+		let keys: Vec<Vec<u8>> = map
+			.raw_keys()
+			.ignore_err()
+			.ready_filter(|key| scope.matches(name, user_id, key))
+			.map(<[u8]>::to_vec)
+			.collect()
+			.await;
+
+		for key in keys {
+			map.remove(&key);
+		}
+	}
+
+	input.rewind()?;
+	let mut restored: usize = 0;
+	read(input, |column, key, val| {
+		if scope.matches(column, user_id, key) {
+			db.get(column)?.insert(key, val);
+			restored = restored.saturating_add(1);
+		}
+
+		Ok(())
+	})?;
+
+	info!("Restored {restored} records of {scope:?} for {user_id}");
+
+	Ok(restored)
+}
+
+/// Reads a dump, passing each record to `f` with the name of its column, and
+/// checks the sections and the manifest against their checksums. Records are
+/// passed as they are read, before the checksum of their section is known.
+pub fn read<R, F>(mut input: R, mut f: F) -> Result<Manifest>
+where
+	R: Read,
+	F: FnMut(&str, &[u8], &[u8]) -> Result,
+{
+	let magic: [u8; 8] = read_array(&mut input)?;
+	if &magic != MAGIC {
+		return Err!(Database("Not a database dump."));
+	}
+
+	let version = u16::from_be_bytes(read_array(&mut input)?);
+	if version > VERSION {
+		return Err!(Database("Dump format version {version} is newer than supported."));
+	}
+
+	let mut columns = Vec::new();
+	let mut section: Option<(String, u64, Sha256)> = None;
+	loop {
+		let [tag]: [u8; 1] = read_array(&mut input)?;
+		match (tag, section.as_mut()) {
+			| (COLUMN, None) => {
+				let name = String::from_utf8(read_bytes(&mut input)?)?;
+				section = Some((name, 0, Sha256::new()));
+			},
+			| (RECORD, Some((name, records, hasher))) => {
+				let key = read_bytes(&mut input)?;
+				let val = read_bytes(&mut input)?;
+				hash_record(hasher, &key, &val)?;
+				*records = records.saturating_add(1);
+				f(name, &key, &val)?;
+			},
+			| (END, Some(_)) => {
+				let (name, records, hasher) = section.take().expect("section started");
+				let expected_records = u64::from_be_bytes(read_array(&mut input)?);
+				let expected: [u8; 32] = read_array(&mut input)?;
+				if records != expected_records || hasher.finalize().as_slice() != expected {
+					return Err!(Database("Checksum of column {name} does not match."));
+				}
+
+				columns.push(Column { name, records, sha256: hex(&expected) });
+			},
+			| (MANIFEST, None) => {
+				let manifest = read_bytes(&mut input)?;
+				let expected: [u8; 32] = read_array(&mut input)?;
+				if Sha256::digest(&manifest).as_slice() != expected {
+					return Err!(Database("Checksum of the manifest does not match."));
+				}
+
+				let manifest: Manifest = serde_json::from_slice(&manifest)?;
+				if manifest.columns != columns {
+					return Err!(Database("Manifest does not match the columns of the dump."));
+				}
+
+				return Ok(manifest);
+			},
+			| (tag, _) => return Err!(Database("Unexpected frame {tag:#04x} in dump.")),
+		}
+	}
+}
+
+impl<W: Write> Writer<W> {
+	pub fn new(mut out: W, server_name: &str) -> Result<Self> {
+		out.write_all(MAGIC)?;
+		out.write_all(&VERSION.to_be_bytes())?;
+
+		Ok(Self {
+			out,
+			manifest: Manifest {
+				version: VERSION,
+				server_name: server_name.to_owned(),
+				created: utils::millis_since_unix_epoch(),
+				columns: Vec::new(),
+			},
+			section: None,
+		})
+	}
+
+	/// Starts the section of a column, ending the previous one.
+	pub fn column(&mut self, name: &str) -> Result {
+		self.end_column()?;
+		self.out.write_all(&[COLUMN])?;
+		write_bytes(&mut self.out, name.as_bytes())?;
+		self.section = Some((name.to_owned(), 0, Sha256::new()));
+
+		Ok(())
+	}
+
+	pub fn record(&mut self, key: &[u8], val: &[u8]) -> Result {
+		let Some((_, records, hasher)) = self.section.as_mut() else {
+			return Err!("Record written before any column.");
+		};
+
+		hash_record(hasher, key, val)?;
+		*records = records.saturating_add(1);
+		self.out.write_all(&[RECORD])?;
+		write_bytes(&mut self.out, key)?;
+		write_bytes(&mut self.out, val)?;
+
+		Ok(())
+	}
+
+	/// Ends the last column and writes the manifest.
+	pub fn finish(mut self) -> Result<Manifest> {
+		self.end_column()?;
+
+		let manifest = serde_json::to_vec(&self.manifest)?;
+		self.out.write_all(&[MANIFEST])?;
+		write_bytes(&mut self.out, &manifest)?;
+		self.out.write_all(&Sha256::digest(&manifest))?;
+		self.out.flush()?;
+
+		Ok(self.manifest)
+	}
+
+	fn end_column(&mut self) -> Result {
+		let Some((name, records, hasher)) = self.section.take() else {
+			return Ok(());
+		};
+
+		let sha256 = hasher.finalize();
+		self.out.write_all(&[END])?;
+		self.out.write_all(&records.to_be_bytes())?;
+		self.out.write_all(&sha256)?;
+		self.manifest
+			.columns
+			.push(Column { name, records, sha256: hex(&sha256) });
+
+		Ok(())
+	}
+}
+
+impl Scope {
+	/// Columns holding the scope, with the position of the user ID among the
+	/// records of their keys.
+	fn columns(self) -> &'static [(&'static str, usize)] {
+		match self {
+			| Self::AccountData =>
+				&[("roomuserdataid_accountdata", 1), ("roomusertype_roomuserdataid", 1)],
+			| Self::KeyBackups =>
+				&[("backupid_algorithm", 0), ("backupid_etag", 0), ("backupkeyid_backup", 0)],
+		}
+	}
+
+	fn matches(self, column: &str, user_id: &UserId, key: &[u8]) -> bool {
+		self.columns().iter().any(|&(name, pos)| {
+			name == column && key.split(|&b| b == SEP).nth(pos) == Some(user_id.as_bytes())
+		})
+	}
+}
+
+fn hash_record(hasher: &mut Sha256, key: &[u8], val: &[u8]) -> Result {
+	hasher.update(u32::try_from(key.len())?.to_be_bytes());
+	hasher.update(key);
+	hasher.update(u32::try_from(val.len())?.to_be_bytes());
+	hasher.update(val);
+
+	Ok(())
+}
+
+fn write_bytes<W: Write>(out: &mut W, bytes: &[u8]) -> Result {
+	out.write_all(&u32::try_from(bytes.len())?.to_be_bytes())?;
+	out.write_all(bytes)?;
+
+	Ok(())
+}
+
+fn read_bytes<R: Read>(input: &mut R) -> Result<Vec<u8>> {
+	let len = u32::from_be_bytes(read_array(input)?);
+
+	// A corrupt length must not allocate up front.
+	let mut bytes = Vec::new();
+	input.by_ref().take(len.into()).read_to_end(&mut bytes)?;
+	if bytes.len() != usize::try_from(len)? {
+		return Err!(Database("Dump ends in the middle of a frame."));
+	}
+
+	Ok(bytes)
+}
+
+fn read_array<R: Read, const N: usize>(input: &mut R) -> Result<[u8; N]> {
+	let mut bytes = [0_u8; N];
+	input.read_exact(&mut bytes)?;
+
+	Ok(bytes)
+}
+
+fn hex(bytes: &[u8]) -> String {
+	bytes.iter().fold(String::new(), |mut s, b| {
+		write!(s, "{b:02x}").expect("writing to string");
+		s
+	})
+}
@@ -93,6 +93,26 @@ impl Engine {
 	#[inline]
 	pub fn corked(&self) -> bool { self.corks.load(Ordering::Relaxed) > 0 }
 
+	/// Number of requests waiting in the queues of the frontend pool.
+	#[inline]
+	#[must_use]
+	pub fn pool_queued(&self) -> usize { self.pool.queued() }
+
+	/// Total capacity of the queues of the frontend pool.
+	#[inline]
+	#[must_use]
+	pub fn pool_capacity(&self) -> usize { self.pool.capacity() }
+
+	/// Number of frontend pool workers currently handling a request.
+	#[inline]
+	#[must_use]
+	pub fn pool_busy(&self) -> usize { self.pool.busy() }
+
+	/// Number of frontend pool workers.
+	#[inline]
+	#[must_use]
+	pub fn pool_workers(&self) -> usize { self.pool.workers() }
+
 	/// Query for database property by null-terminated name which is expected to
 	/// have a result with an integer representation. This is intended for
 	/// low-overhead programmatic use.
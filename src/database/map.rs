@@ -27,7 +27,10 @@ use std::{
 	fmt::{Debug, Display},
 	future::Future,
 	pin::Pin,
-	sync::Arc,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc,
+	},
 };
 
 use conduwuit::Result;
@@ -47,6 +50,8 @@ pub struct Map {
 	write_options: WriteOptions,
 	read_options: ReadOptions,
 	cache_read_options: ReadOptions,
+	cache_hits: AtomicU64,
+	cache_misses: AtomicU64,
 }
 
 impl Map {
@@ -59,6 +64,8 @@ impl Map {
 			write_options: write_options_default(),
 			read_options: read_options_default(),
 			cache_read_options: cache_read_options_default(),
+			cache_hits: AtomicU64::new(0),
+			cache_misses: AtomicU64::new(0),
 		}))
 	}
 
@@ -81,6 +88,16 @@ impl Map {
 	#[inline]
 	pub fn property(&self, name: &str) -> Result<String> { self.db.property(&self.cf(), name) }
 
+	/// Number of point-queries answered from cache and the number which had to
+	/// be dispatched to the pool, respectively.
+	#[inline]
+	pub fn cache_stats(&self) -> (u64, u64) {
+		(
+			self.cache_hits.load(Ordering::Relaxed),
+			self.cache_misses.load(Ordering::Relaxed),
+		)
+	}
+
 	#[inline]
 	pub fn name(&self) -> &str { self.name }
 
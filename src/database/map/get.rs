@@ -1,4 +1,9 @@
-use std::{convert::AsRef, fmt::Debug, io::Write, sync::Arc};
+use std::{
+	convert::AsRef,
+	fmt::Debug,
+	io::Write,
+	sync::{atomic::Ordering, Arc},
+};
 
 use arrayvec::ArrayVec;
 use conduwuit::{err, implement, utils::result::MapExpect, Err, Result};
@@ -72,12 +77,14 @@ where
 
 	let cached = self.get_cached(key);
 	if matches!(cached, Err(_) | Ok(Some(_))) {
+		self.cache_hits.fetch_add(1, Ordering::Relaxed);
 		return task::consume_budget()
 			.map(move |()| cached.map_expect("data found in cache"))
 			.boxed();
 	}
 
 	debug_assert!(matches!(cached, Ok(None)), "expected status Incomplete");
+	self.cache_misses.fetch_add(1, Ordering::Relaxed);
 	let cmd = Get {
 		map: self.clone(),
 		key: [key.as_ref().into()].into(),
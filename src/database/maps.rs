@@ -33,6 +33,10 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "aliasid_alias",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "appserviceid_educount",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "backupid_algorithm",
 		..descriptor::RANDOM_SMALL
@@ -49,6 +53,10 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "bannedroomids",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "delayid_delayedevent",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "disabledroomids",
 		..descriptor::RANDOM_SMALL
@@ -104,6 +112,14 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "mediaid_file",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "mediaid_quarantine",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "mediaid_size",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "mediaid_user",
 		..descriptor::RANDOM_SMALL
@@ -121,6 +137,10 @@ pub(super) static MAPS: &[Descriptor] = &[
 		index_size: 512,
 		..descriptor::SEQUENTIAL
 	},
+	Descriptor {
+		name: "policyroomids",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "presenceid_presence",
 		..descriptor::SEQUENTIAL_SMALL
@@ -137,6 +157,18 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "referencedevents",
 		..descriptor::RANDOM
 	},
+	Descriptor {
+		name: "registrationtoken_info",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "reportid_report",
+		..descriptor::SEQUENTIAL_SMALL
+	},
+	Descriptor {
+		name: "reporttarget_reportid",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "roomid_invitedcount",
 		..descriptor::RANDOM_SMALL
@@ -209,6 +241,10 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "roomusertype_roomuserdataid",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "senderkey_emaildigest",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "senderkey_pusher",
 		..descriptor::RANDOM_SMALL
@@ -264,6 +300,12 @@ pub(super) static MAPS: &[Descriptor] = &[
 		index_size: 512,
 		..descriptor::SEQUENTIAL
 	},
+	Descriptor {
+		name: "shortroomid_searchstats",
+		key_size_hint: Some(8),
+		val_size_hint: Some(16),
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "shortstatehash_statediff",
 		key_size_hint: Some(8),
@@ -311,6 +353,10 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "token_userdeviceid",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "tokenuserid",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "tokenids",
 		block_size: 512,
@@ -348,10 +394,18 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "userid_blurhash",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userid_delayid",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userid_devicelistversion",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userid_directoryentry",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userid_displayname",
 		..descriptor::RANDOM_SMALL
@@ -364,18 +418,34 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "userid_masterkeyid",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userid_mediausage",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userid_password",
 		..descriptor::RANDOM
 	},
+	Descriptor {
+		name: "userthreepid_addedat",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userid_presenceid",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userid_servernoticeroomid",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userid_selfsigningkeyid",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userid_shadowbanned",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userid_usersigningkeyid",
 		..descriptor::RANDOM_SMALL
@@ -392,6 +462,22 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "logintoken_expiresatuserid",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "ssoidentity_userid",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "threepid_userid",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "threepidsecret_sessionid",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "threepidsessionid_session",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userroomid_highlightcount",
 		..descriptor::RANDOM
@@ -8,6 +8,7 @@ conduwuit::rustc_flags_capture! {}
 mod cork;
 mod de;
 mod deserialized;
+pub mod dump;
 mod engine;
 mod handle;
 pub mod keyval;
@@ -155,6 +155,22 @@ pub(crate) fn close(&self) {
 		});
 }
 
+#[implement(Pool)]
+#[inline]
+pub(crate) fn queued(&self) -> usize { self.queues.iter().map(Sender::len).sum() }
+
+#[implement(Pool)]
+#[inline]
+pub(crate) fn capacity(&self) -> usize { self.queues.iter().filter_map(Sender::capacity).sum() }
+
+#[implement(Pool)]
+#[inline]
+pub(crate) fn busy(&self) -> usize { self.busy.load(Ordering::Relaxed) }
+
+#[implement(Pool)]
+#[inline]
+pub(crate) fn workers(&self) -> usize { self.workers.lock().expect("locked").len() }
+
 #[implement(Pool)]
 fn spawn_until(self: &Arc<Self>, recv: &[Receiver<Cmd>], count: usize) -> Result {
 	let mut workers = self.workers.lock().expect("locked");
@@ -7,7 +7,7 @@ use conduwuit::ruma::{serde::Raw, RoomId, UserId};
 use serde::Serialize;
 
 use crate::{
-	de, ser,
+	de, dump, ser,
 	ser::{serialize_to_vec, Json},
 	Ignore, Interfix,
 };
@@ -389,3 +389,75 @@ fn de_complex() {
 
 	assert_eq!(arr, key, "deserialization of serialization does not match");
 }
+
+fn dump_sample() -> Vec<u8> {
+	let user_id: &UserId = "@user:example.com".try_into().unwrap();
+	let room_id: &RoomId = "!room:example.com".try_into().unwrap();
+
+	let mut out = Vec::new();
+	let mut writer = dump::Writer::new(&mut out, "example.com").unwrap();
+	writer.column("backupid_etag").unwrap();
+	writer
+		.record(&serialize_to_vec((user_id, "1")).unwrap(), b"7")
+		.unwrap();
+
+	writer.column("roomusertype_roomuserdataid").unwrap();
+	writer
+		.record(&serialize_to_vec((room_id, user_id, "m.tag")).unwrap(), b"42")
+		.unwrap();
+	writer
+		.record(&serialize_to_vec((room_id, user_id, "m.fully_read")).unwrap(), b"43")
+		.unwrap();
+
+	writer.column("userid_password").unwrap();
+	writer.finish().unwrap();
+
+	out
+}
+
+#[test]
+fn dump_roundtrip() {
+	let mut records = Vec::new();
+	let manifest = dump::read(dump_sample().as_slice(), |column, key, val| {
+		records.push((column.to_owned(), key.to_vec(), val.to_vec()));
+		Ok(())
+	})
+	.expect("dump is valid");
+
+	assert_eq!(manifest.version, dump::VERSION);
+	assert_eq!(manifest.server_name, "example.com");
+
+	let columns: Vec<_> = manifest
+		.columns
+		.iter()
+		.map(|column| (column.name.as_str(), column.records))
+		.collect();
+	assert_eq!(columns, [
+		("backupid_etag", 1),
+		("roomusertype_roomuserdataid", 2),
+		("userid_password", 0)
+	]);
+
+	assert_eq!(records.len(), 3);
+	assert_eq!(records[0].1, b"@user:example.com\xFF1");
+	assert_eq!(records[2].0, "roomusertype_roomuserdataid");
+	assert_eq!(records[2].2, b"43");
+}
+
+#[test]
+fn dump_corruption() {
+	let sample = dump_sample();
+	for i in 0..sample.len() {
+		let mut corrupt = sample.clone();
+		corrupt[i] ^= 0xFF;
+		assert!(
+			dump::read(corrupt.as_slice(), |_, _, _| Ok(())).is_err(),
+			"flipped byte {i} was not detected"
+		);
+
+		assert!(
+			dump::read(&sample[..i], |_, _, _| Ok(())).is_err(),
+			"truncation at {i} was not detected"
+		);
+	}
+}
@@ -10,6 +10,8 @@ use conduwuit::{
 	Err, Result,
 };
 
+use crate::maintenance::Command;
+
 /// Commandline arguments
 #[derive(Parser, Debug)]
 #[clap(version = conduwuit::version(), about, long_about = None, name = "conduwuit")]
@@ -108,6 +110,10 @@ pub(crate) struct Args {
 		require_equals(false),
 	)]
 	pub(crate) gc_muzzy: Option<bool>,
+
+	/// Run a maintenance command on the database instead of the server.
+	#[command(subcommand)]
+	pub(crate) command: Option<Command>,
 }
 
 /// Parse commandline arguments into structured data
@@ -129,6 +135,11 @@ pub(crate) fn update(mut config: Figment, args: &Args) -> Result<Figment> {
 	// Update config with names of any functional-tests
 	config = config.adjoin(("test", &args.test));
 
+	// Maintenance commands which only read can run beside the server
+	if args.command.as_ref().is_some_and(Command::read_only) {
+		config = config.merge(("rocksdb_read_only", true));
+	}
+
 	// All other individual overrides can go last in case we have options which
 	// set multiple conf items at once and the user still needs granular overrides.
 	for option in &args.option {
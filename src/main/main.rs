@@ -1,5 +1,6 @@
 pub(crate) mod clap;
 mod logging;
+mod maintenance;
 mod mods;
 mod restart;
 mod runtime;
@@ -20,6 +21,10 @@ fn main() -> Result<(), Error> {
 	let args = clap::parse();
 	let runtime = runtime::new(&args)?;
 	let server = Server::new(&args, Some(runtime.handle()))?;
+	if let Some(command) = &args.command {
+		return runtime.block_on(maintenance::run(&server, command));
+	}
+
 	runtime.spawn(signal::signal(server.clone()));
 	runtime.block_on(async_main(&server))?;
 
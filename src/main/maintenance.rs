@@ -0,0 +1,403 @@
+//! Maintenance subcommands, run against the database without starting the
+//! router.
+
+#[cfg(feature = "synapse_import")]
+use std::path::Path;
+use std::{
+	fs::File,
+	io::{self, BufReader, BufWriter, Write},
+	path::PathBuf,
+	sync::Arc,
+};
+
+use clap::{Subcommand, ValueEnum};
+use conduwuit::{
+	info,
+	ruma::{events::room::message::RoomMessageEventContent, OwnedRoomId, OwnedUserId},
+	utils::stream::TryIgnore,
+	warn, Err, Result,
+};
+use conduwuit_database::{compact, dump, Database};
+use conduwuit_service::Services;
+use futures::{pin_mut, StreamExt};
+
+use crate::server::Server;
+
+/// Commands run instead of the server
+#[derive(Debug, Subcommand)]
+pub(crate) enum Command {
+	/// Set the password of a local user, generating one if not given.
+	ResetPassword {
+		username: String,
+		password: Option<String>,
+	},
+
+	/// Create a local user, generating a password if not given.
+	CreateUser {
+		username: String,
+		password: Option<String>,
+	},
+
+	/// Grant server-admin privileges to a local user.
+	MakeAdmin {
+		username: String,
+	},
+
+	/// List the rooms known to the server with their names and joined
+	/// members. The database is opened read-only.
+	ListRooms,
+
+	/// Write every event of a room as a line of JSON, oldest first. The
+	/// database is opened read-only.
+	ExportRoom {
+		room_id: OwnedRoomId,
+
+		/// File to write to instead of stdout
+		#[arg(short, long)]
+		output: Option<PathBuf>,
+	},
+
+	/// Import users, devices, keys, account data, media and rooms from the
+	/// SQLite database of a Synapse server of the same server name.
+	#[cfg(feature = "synapse_import")]
+	ImportSynapse {
+		/// Synapse's `homeserver.db`
+		database: PathBuf,
+
+		/// Synapse's `media_store_path`, to import local media
+		#[arg(long)]
+		media_store: Option<PathBuf>,
+
+		/// Synapse's `signing_key_path`, to keep signing with its key
+		#[arg(long)]
+		signing_key: Option<PathBuf>,
+
+		/// File to write the report of skipped records to instead of stdout
+		#[arg(long)]
+		report: Option<PathBuf>,
+	},
+
+	/// Database maintenance
+	#[command(subcommand)]
+	Db(DbCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum DbCommand {
+	/// Create a backup in `database_backup_path`.
+	Backup,
+
+	/// Read every record of every column, reporting any which fail. The
+	/// database is opened read-only.
+	Verify,
+
+	/// Compact every column completely.
+	Compact,
+
+	/// Write a logical dump of the database, which does not depend on the
+	/// version of RocksDB. The database is opened read-only.
+	Dump {
+		output: PathBuf,
+	},
+
+	/// Check a dump against its checksums and print its manifest.
+	CheckDump {
+		input: PathBuf,
+	},
+
+	/// Load a dump into a new, empty database.
+	Import {
+		input: PathBuf,
+	},
+
+	/// Replace part of a user's account with its records in a dump.
+	Restore {
+		input: PathBuf,
+
+		user_id: OwnedUserId,
+
+		#[arg(long, value_enum)]
+		scope: RestoreScope,
+	},
+}
+
+/// Parts of an account `db restore` can replace
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub(crate) enum RestoreScope {
+	AccountData,
+	KeyBackups,
+}
+
+impl Command {
+	/// Whether the command only reads. Such commands open the database
+	/// read-only, so they may run alongside the server; the others need it
+	/// exclusively.
+	pub(crate) fn read_only(&self) -> bool {
+		matches!(
+			self,
+			Self::ListRooms
+				| Self::ExportRoom { .. }
+				| Self::Db(
+					DbCommand::Verify | DbCommand::Dump { .. } | DbCommand::CheckDump { .. }
+				)
+		)
+	}
+}
+
+/// Opens the database and runs the command on it.
+pub(crate) async fn run(server: &Arc<Server>, command: &Command) -> Result {
+	// Dumps only need the database; an import must not find the records the
+	// services initialize a new database with.
+	if let Command::Db(
+		command @ (DbCommand::Dump { .. }
+		| DbCommand::CheckDump { .. }
+		| DbCommand::Import { .. }
+		| DbCommand::Restore { .. }),
+	) = command
+	{
+		let db = Database::open(&server.server).await?;
+		return dump(&db, command).await;
+	}
+
+	let services = Services::build(server.server.clone())
+		.await?
+		.start_offline()
+		.await?;
+
+	conduwuit_admin::init(&services.admin).await;
+	let result = execute(&services, command).await;
+	conduwuit_admin::fini(&services.admin).await;
+	services.stop_offline();
+
+	result
+}
+
+async fn execute(services: &Services, command: &Command) -> Result {
+	match command {
+		| Command::ResetPassword { username, password } =>
+			admin_command(services, "users reset-password", username, password.as_deref()).await,
+		| Command::CreateUser { username, password } =>
+			admin_command(services, "users create-user", username, password.as_deref()).await,
+		| Command::MakeAdmin { username } =>
+			admin_command(services, "users make-user-admin", username, None).await,
+		| Command::ListRooms => list_rooms(services).await,
+		| Command::ExportRoom { room_id, output } =>
+			export_room(services, room_id, output.as_ref()).await,
+		#[cfg(feature = "synapse_import")]
+		| Command::ImportSynapse {
+			database,
+			media_store,
+			signing_key,
+			report,
+		} =>
+			import_synapse(
+				services,
+				database,
+				media_store.as_deref(),
+				signing_key.as_deref(),
+				report.as_ref(),
+			)
+			.await,
+		| Command::Db(DbCommand::Backup) => backup(services),
+		| Command::Db(DbCommand::Verify) => verify(services).await,
+		| Command::Db(DbCommand::Compact) => compact(services),
+		| Command::Db(command) => unreachable!("{command:?} runs without services"),
+	}
+}
+
+/// Runs the admin command of the same purpose, so the outcome matches the
+/// admin room.
+async fn admin_command(
+	services: &Services,
+	command: &str,
+	username: &str,
+	password: Option<&str>,
+) -> Result {
+	if password.is_some_and(|password| password.contains(char::is_whitespace)) {
+		return Err!("Passwords given on the command line cannot contain whitespace.");
+	}
+
+	let line = [Some(command), Some(username), password]
+		.into_iter()
+		.flatten()
+		.collect::<Vec<_>>()
+		.join(" ");
+
+	match services.admin.command_in_place(line, None).await {
+		| Ok(output) => {
+			let body = output.as_ref().map_or("", RoomMessageEventContent::body);
+			writeln!(io::stdout(), "{body}")?;
+			Ok(())
+		},
+		| Err(output) => Err!("{}", output.body()),
+	}
+}
+
+async fn list_rooms(services: &Services) -> Result {
+	let rooms: Vec<OwnedRoomId> = services
+		.rooms
+		.metadata
+		.iter_ids()
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	let mut out = io::stdout().lock();
+	for room_id in rooms {
+		let name = services
+			.rooms
+			.state_accessor
+			.get_name(&room_id)
+			.await
+			.unwrap_or_default();
+
+		let members = services
+			.rooms
+			.state_cache
+			.room_joined_count(&room_id)
+			.await
+			.unwrap_or(0);
+
+		writeln!(out, "{room_id}\t{members}\t{name}")?;
+	}
+
+	Ok(())
+}
+
+async fn export_room(
+	services: &Services,
+	room_id: &OwnedRoomId,
+	output: Option<&PathBuf>,
+) -> Result {
+	if !services.rooms.metadata.exists(room_id).await {
+		return Err!("Room {room_id} is not known to this server.");
+	}
+
+	let mut out: Box<dyn Write> = match output {
+		| Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+		| None => Box::new(BufWriter::new(io::stdout().lock())),
+	};
+
+	let events = services
+		.rooms
+		.timeline
+		.pdus(None, room_id, None)
+		.ignore_err();
+
+	pin_mut!(events);
+	let mut count: usize = 0;
+	while let Some((_, pdu)) = events.next().await {
+		serde_json::to_writer(&mut out, &pdu)?;
+		out.write_all(b"\n")?;
+		count = count.saturating_add(1);
+	}
+
+	out.flush()?;
+	info!("Exported {count} events of {room_id}");
+
+	Ok(())
+}
+
+#[cfg(feature = "synapse_import")]
+async fn import_synapse(
+	services: &Services,
+	database: &Path,
+	media_store: Option<&Path>,
+	signing_key: Option<&Path>,
+	report: Option<&PathBuf>,
+) -> Result {
+	use conduwuit_service::synapse;
+
+	let options = synapse::Options { database, media_store, signing_key };
+
+	let outcome = synapse::import(services, &options).await?;
+	match report {
+		| Some(path) => std::fs::write(path, outcome.to_string())?,
+		| None => write!(io::stdout(), "{outcome}")?,
+	}
+
+	Ok(())
+}
+
+fn backup(services: &Services) -> Result {
+	if services
+		.server
+		.config
+		.database_backup_path
+		.as_ref()
+		.is_none_or(|path| path.as_os_str().is_empty())
+	{
+		return Err!(Config("database_backup_path", "Set a path to back up to."));
+	}
+
+	services.db.db.backup()?;
+	writeln!(io::stdout(), "{}", services.db.db.backup_list()?)?;
+
+	Ok(())
+}
+
+async fn verify(services: &Services) -> Result {
+	let mut failed: usize = 0;
+	for (name, map) in services.db.iter() {
+		let (mut records, mut errors) = (0_usize, 0_usize);
+		let stream = map.raw_stream();
+		pin_mut!(stream);
+		while let Some(result) = stream.next().await {
+			match result {
+				| Ok(_) => records = records.saturating_add(1),
+				| Err(e) => {
+					errors = errors.saturating_add(1);
+					warn!("{name}: {e}");
+				},
+			}
+		}
+
+		writeln!(io::stdout(), "{name}: {records} records, {errors} errors")?;
+		failed = failed.saturating_add(errors);
+	}
+
+	if failed > 0 {
+		return Err!(Database("{failed} records failed to read."));
+	}
+
+	Ok(())
+}
+
+fn compact(services: &Services) -> Result {
+	let options = compact::Options { exhaustive: true, ..Default::default() };
+	for (name, map) in services.db.iter() {
+		info!("Compacting {name}");
+		map.compact_blocking(options.clone())?;
+	}
+
+	Ok(())
+}
+
+async fn dump(db: &Database, command: &DbCommand) -> Result {
+	match command {
+		| DbCommand::Dump { output } => {
+			let out = BufWriter::new(File::create(output)?);
+			dump::export(db, out).await?;
+		},
+		| DbCommand::CheckDump { input } => {
+			let manifest = dump::read(BufReader::new(File::open(input)?), |_, _, _| Ok(()))?;
+			writeln!(io::stdout(), "{}", serde_json::to_string_pretty(&manifest)?)?;
+		},
+		| DbCommand::Import { input } => {
+			dump::import(db, BufReader::new(File::open(input)?)).await?;
+		},
+		| DbCommand::Restore { input, user_id, scope } => {
+			let scope = match scope {
+				| RestoreScope::AccountData => dump::Scope::AccountData,
+				| RestoreScope::KeyBackups => dump::Scope::KeyBackups,
+			};
+
+			let input = BufReader::new(File::open(input)?);
+			let restored = dump::restore(db, input, user_id, scope).await?;
+			writeln!(io::stdout(), "Restored {restored} records.")?;
+		},
+		| command => unreachable!("{command:?} needs the services"),
+	}
+
+	Ok(())
+}
@@ -0,0 +1,314 @@
+//! OpenMetrics exposition served by the optional `metrics_address` listener.
+
+use std::{
+	collections::BTreeMap,
+	fmt,
+	fmt::{Display, Write},
+	net::SocketAddr,
+	sync::{
+		atomic::{AtomicU32, Ordering},
+		Arc,
+	},
+};
+
+use axum::{extract::State, response::IntoResponse, routing::get, Router};
+use axum_server::{bind, Handle as ServerHandle};
+use conduwuit::{info, utils::ReadyExt, Result};
+use conduwuit_service::{sending::Destination, Services};
+use futures::StreamExt;
+use http::header;
+
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Columns backing the caches of `rooms::timeline` and `rooms::short`.
+const CACHED_MAPS: &[&str] = &[
+	"eventid_outlierpdu",
+	"eventid_pduid",
+	"eventid_shorteventid",
+	"pduid_pdu",
+	"roomid_shortroomid",
+	"shorteventid_authchain",
+	"shorteventid_eventid",
+	"shortstatekey_statekey",
+	"statehash_shortstatehash",
+	"statekey_shortstatekey",
+];
+
+pub(crate) async fn serve(
+	services: Arc<Services>,
+	handle: ServerHandle,
+	addr: SocketAddr,
+) -> Result {
+	let app = Router::new()
+		.route("/metrics", get(metrics))
+		.with_state(services);
+
+	info!("Serving metrics on {addr}");
+	bind(addr)
+		.handle(handle)
+		.serve(app.into_make_service())
+		.await?;
+
+	Ok(())
+}
+
+async fn metrics(State(services): State<Arc<Services>>) -> Result<impl IntoResponse> {
+	let mut out = String::new();
+	requests(&mut out, &services)?;
+	runtime(&mut out, &services)?;
+	sending(&mut out, &services).await?;
+	federation(&mut out, &services)?;
+	database(&mut out, &services)?;
+	writeln!(out, "# EOF")?;
+
+	Ok(([(header::CONTENT_TYPE, CONTENT_TYPE)], out))
+}
+
+fn requests(out: &mut String, services: &Services) -> Result {
+	let metrics = &services.server.metrics;
+	let load = |counter: &AtomicU32| counter.load(Ordering::Relaxed);
+
+	gauge(
+		out,
+		"conduwuit_requests_spawn_active",
+		"Request tasks in flight.",
+		load(&metrics.requests_spawn_active),
+	)?;
+	counter(
+		out,
+		"conduwuit_requests_spawn",
+		"Request tasks spawned.",
+		load(&metrics.requests_spawn_finished),
+	)?;
+	gauge(
+		out,
+		"conduwuit_requests_handle_active",
+		"Requests being handled.",
+		load(&metrics.requests_handle_active),
+	)?;
+	counter(
+		out,
+		"conduwuit_requests_handle",
+		"Requests handled.",
+		load(&metrics.requests_handle_finished),
+	)?;
+	counter(
+		out,
+		"conduwuit_requests_panic",
+		"Requests which panicked.",
+		load(&metrics.requests_panic),
+	)?;
+
+	Ok(())
+}
+
+fn runtime(out: &mut String, services: &Services) -> Result {
+	let metrics = &services.server.metrics;
+
+	gauge(
+		out,
+		"conduwuit_runtime_workers",
+		"Tokio runtime worker threads.",
+		metrics.num_workers(),
+	)?;
+
+	#[cfg(tokio_unstable)]
+	if let Some(runtime) = metrics.runtime_metrics() {
+		gauge(
+			out,
+			"conduwuit_runtime_alive_tasks",
+			"Tokio tasks alive.",
+			runtime.num_alive_tasks(),
+		)?;
+		gauge(
+			out,
+			"conduwuit_runtime_global_queue_depth",
+			"Tokio global queue depth.",
+			runtime.global_queue_depth(),
+		)?;
+	}
+
+	Ok(())
+}
+
+async fn sending(out: &mut String, services: &Services) -> Result {
+	type Depths = BTreeMap<(&'static str, String), u64>;
+
+	let count = |mut depths: Depths, dest: Destination| {
+		let depth = depths.entry(destination_labels(&dest)).or_default();
+		*depth = depth.saturating_add(1);
+		depths
+	};
+
+	let queued: Depths = services
+		.sending
+		.db
+		.queued_destinations()
+		.ready_fold(Depths::new(), count)
+		.await;
+
+	let active: Depths = services
+		.sending
+		.db
+		.active_requests()
+		.map(|(_, _, dest)| dest)
+		.ready_fold(Depths::new(), count)
+		.await;
+
+	for (name, help, depths) in [
+		("conduwuit_sending_queued", "Requests queued for a destination.", queued),
+		("conduwuit_sending_active", "Requests in flight to a destination.", active),
+	] {
+		family(out, name, "gauge", help)?;
+		for ((kind, dest), depth) in depths {
+			sample(out, name, &[("kind", kind), ("destination", &dest)], depth)?;
+		}
+	}
+
+	Ok(())
+}
+
+fn federation(out: &mut String, services: &Services) -> Result {
+	let event_handler = &services.rooms.event_handler;
+	let (handling, longest) = event_handler
+		.federation_handletime
+		.read()
+		.expect("locked for reading")
+		.values()
+		.map(|(_, started)| started.elapsed().as_secs_f64())
+		.fold((0_usize, 0.0_f64), |(count, longest), elapsed| {
+			(count.saturating_add(1), longest.max(elapsed))
+		});
+
+	gauge(out, "conduwuit_federation_handling", "Incoming PDUs being handled.", handling)?;
+	gauge(
+		out,
+		"conduwuit_federation_handling_longest_seconds",
+		"Time spent so far on the longest-running incoming PDU.",
+		longest,
+	)?;
+
+	let handled = event_handler.federation_handled.load(Ordering::Relaxed);
+	let micros = event_handler
+		.federation_handled_micros
+		.load(Ordering::Relaxed);
+
+	let name = "conduwuit_federation_handle_seconds";
+	family(out, name, "summary", "Time spent handling incoming PDUs.")?;
+	sample(out, &format!("{name}_count"), &[], handled)?;
+	sample(out, &format!("{name}_sum"), &[], Seconds(micros))?;
+
+	Ok(())
+}
+
+fn database(out: &mut String, services: &Services) -> Result {
+	let engine = &services.db.db;
+
+	gauge(
+		out,
+		"conduwuit_db_pool_queued",
+		"Requests queued for the database pool.",
+		engine.pool_queued(),
+	)?;
+	gauge(
+		out,
+		"conduwuit_db_pool_capacity",
+		"Capacity of the database pool queues.",
+		engine.pool_capacity(),
+	)?;
+	gauge(
+		out,
+		"conduwuit_db_pool_busy",
+		"Database pool workers handling a request.",
+		engine.pool_busy(),
+	)?;
+	gauge(
+		out,
+		"conduwuit_db_pool_workers",
+		"Database pool workers.",
+		engine.pool_workers(),
+	)?;
+
+	// Hit ratios are left to the scraper: hits / (hits + misses).
+	let maps = CACHED_MAPS
+		.iter()
+		.filter_map(|name| services.db.get(name).ok())
+		.map(|map| (map.name(), map.cache_stats()));
+
+	family(out, "conduwuit_db_cache_hits", "counter", "Point-queries answered from cache.")?;
+	for (name, (hits, _)) in maps.clone() {
+		sample(out, "conduwuit_db_cache_hits_total", &[("map", name)], hits)?;
+	}
+
+	family(
+		out,
+		"conduwuit_db_cache_misses",
+		"counter",
+		"Point-queries requiring database I/O.",
+	)?;
+	for (name, (_, misses)) in maps {
+		sample(out, "conduwuit_db_cache_misses_total", &[("map", name)], misses)?;
+	}
+
+	Ok(())
+}
+
+fn destination_labels(dest: &Destination) -> (&'static str, String) {
+	match dest {
+		| Destination::Federation(server) => ("federation", server.to_string()),
+		| Destination::Appservice(id) => ("appservice", id.clone()),
+		// Pushers are aggregated; labelling each user and pushkey would explode
+		// cardinality and leak user identifiers to the scraper.
+		| Destination::Push(..) => ("push", String::new()),
+	}
+}
+
+/// Microseconds presented as fractional seconds.
+struct Seconds(u64);
+
+impl Display for Seconds {
+	fn fmt(&self, out: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(out, "{}.{:06}", self.0 / 1_000_000, self.0 % 1_000_000)
+	}
+}
+
+fn gauge<T: Display>(out: &mut String, name: &str, help: &str, value: T) -> Result {
+	family(out, name, "gauge", help)?;
+	sample(out, name, &[], value)
+}
+
+fn counter<T: Display>(out: &mut String, name: &str, help: &str, value: T) -> Result {
+	family(out, name, "counter", help)?;
+	sample(out, &format!("{name}_total"), &[], value)
+}
+
+fn family(out: &mut String, name: &str, kind: &str, help: &str) -> Result {
+	writeln!(out, "# TYPE {name} {kind}")?;
+	writeln!(out, "# HELP {name} {help}")?;
+
+	Ok(())
+}
+
+fn sample<T: Display>(out: &mut String, name: &str, labels: &[(&str, &str)], value: T) -> Result {
+	write!(out, "{name}")?;
+	if !labels.is_empty() {
+		let labels = labels
+			.iter()
+			.map(|(key, val)| format!("{key}=\"{}\"", escape(val)))
+			.collect::<Vec<_>>()
+			.join(",");
+
+		write!(out, "{{{labels}}}")?;
+	}
+
+	writeln!(out, " {value}")?;
+
+	Ok(())
+}
+
+fn escape(value: &str) -> String {
+	value
+		.replace('\\', "\\\\")
+		.replace('"', "\\\"")
+		.replace('\n', "\\n")
+}
@@ -1,4 +1,5 @@
 mod layers;
+mod metrics;
 mod request;
 mod router;
 mod run;
@@ -7,23 +7,13 @@ use axum::{
 use conduwuit::{debug, debug_error, debug_warn, err, error, trace, Result};
 use conduwuit_service::Services;
 use http::{Method, StatusCode, Uri};
+use tracing::field::Empty;
 
 #[tracing::instrument(
 	parent = None,
 	level = "trace",
 	skip_all,
-	fields(
-		handled = %services
-			.server
-			.metrics
-			.requests_spawn_finished
-			.fetch_add(1, Ordering::Relaxed),
-		active = %services
-			.server
-			.metrics
-			.requests_spawn_active
-			.fetch_add(1, Ordering::Relaxed),
-	)
+	fields(handled = Empty, active = Empty)
 )]
 pub(crate) async fn spawn(
 	State(services): State<Arc<Services>>,
@@ -31,8 +21,15 @@ pub(crate) async fn spawn(
 	next: axum::middleware::Next,
 ) -> Result<Response, StatusCode> {
 	let server = &services.server;
+	let handled = server
+		.metrics
+		.requests_spawn_finished
+		.fetch_add(1, Ordering::Relaxed);
+	let active = server
+		.metrics
+		.requests_spawn_active
+		.fetch_add(1, Ordering::Relaxed);
 
-	#[cfg(debug_assertions)]
 	conduwuit::defer! {{
 		_ = server
 			.metrics
@@ -40,6 +37,10 @@ pub(crate) async fn spawn(
 			.fetch_sub(1, Ordering::Relaxed);
 	}};
 
+	tracing::Span::current()
+		.record("handled", handled)
+		.record("active", active);
+
 	if !server.running() {
 		debug_warn!("unavailable pending shutdown");
 		return Err(StatusCode::SERVICE_UNAVAILABLE);
@@ -53,18 +54,7 @@ pub(crate) async fn spawn(
 #[tracing::instrument(
 	level = "debug",
 	skip_all,
-	fields(
-		handled = %services
-			.server
-			.metrics
-			.requests_handle_finished
-			.fetch_add(1, Ordering::Relaxed),
-		active = %services
-			.server
-			.metrics
-			.requests_handle_active
-			.fetch_add(1, Ordering::Relaxed),
-	)
+	fields(handled = Empty, active = Empty)
 )]
 pub(crate) async fn handle(
 	State(services): State<Arc<Services>>,
@@ -72,8 +62,15 @@ pub(crate) async fn handle(
 	next: axum::middleware::Next,
 ) -> Result<Response, StatusCode> {
 	let server = &services.server;
+	let handled = server
+		.metrics
+		.requests_handle_finished
+		.fetch_add(1, Ordering::Relaxed);
+	let active = server
+		.metrics
+		.requests_handle_active
+		.fetch_add(1, Ordering::Relaxed);
 
-	#[cfg(debug_assertions)]
 	conduwuit::defer! {{
 		_ = server
 			.metrics
@@ -81,6 +78,10 @@ pub(crate) async fn handle(
 			.fetch_sub(1, Ordering::Relaxed);
 	}};
 
+	tracing::Span::current()
+		.record("handled", handled)
+		.record("active", active);
+
 	if !server.running() {
 		debug_warn!(
 			method = %req.method(),
@@ -6,14 +6,41 @@ mod unix;
 use std::sync::Arc;
 
 use axum_server::Handle as ServerHandle;
-use conduwuit::{err, Result};
+use conduwuit::{err, Error, Result};
 use conduwuit_service::Services;
 use tokio::sync::broadcast;
 
-use super::layers;
+use super::{layers, metrics};
 
-/// Serve clients
+/// Serve clients and metrics
 pub(super) async fn serve(
+	services: Arc<Services>,
+	handle: ServerHandle,
+	shutdown: broadcast::Receiver<()>,
+) -> Result {
+	let server = &services.server;
+	let metrics = server
+		.config
+		.metrics_address
+		.filter(|_| server.config.listening)
+		.map(|addr| {
+			let metrics = metrics::serve(services.clone(), handle.clone(), addr);
+			server.runtime().spawn(metrics)
+		});
+
+	let result = serve_clients(services.clone(), handle.clone(), shutdown).await;
+
+	if let Some(metrics) = metrics {
+		// the clients may have stopped without a graceful shutdown (i.e. error)
+		handle.shutdown();
+		metrics.await.map_err(Error::from).unwrap_or_else(Err)?;
+	}
+
+	result
+}
+
+/// Serve clients
+async fn serve_clients(
 	services: Arc<Services>,
 	handle: ServerHandle,
 	mut shutdown: broadcast::Receiver<()>,
@@ -1,26 +1,45 @@
 mod namespace_regex;
 mod registration_info;
 
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	sync::{Arc, RwLock as StdRwLock},
+};
 
 use async_trait::async_trait;
-use conduwuit::{err, utils::stream::TryIgnore, Result};
+use conduwuit::{
+	err,
+	utils::stream::{ReadyExt, TryIgnore},
+	Result,
+};
 use database::Map;
 use futures::{Future, StreamExt, TryStreamExt};
-use ruma::{api::appservice::Registration, RoomAliasId, RoomId, UserId};
+use ruma::{api::appservice::Registration, OwnedUserId, RoomAliasId, RoomId, UserId};
 use tokio::sync::RwLock;
 
-pub use self::{namespace_regex::NamespaceRegex, registration_info::RegistrationInfo};
-use crate::{sending, Dep};
+pub use self::{
+	namespace_regex::NamespaceRegex,
+	registration_info::{Extensions, RegistrationInfo},
+};
+use crate::{sending, users, Dep};
 
 pub struct Service {
 	registration_info: RwLock<BTreeMap<String, RegistrationInfo>>,
+	namespace_users: StdRwLock<BTreeMap<String, NamespaceUsers>>,
 	services: Services,
 	db: Data,
 }
 
 struct Services {
 	sending: Dep<sending::Service>,
+	users: Dep<users::Service>,
+}
+
+/// Local users in the namespace of an appservice using MSC3202, so its
+/// transactions need not go through every user.
+struct NamespaceUsers {
+	info: RegistrationInfo,
+	users: BTreeSet<OwnedUserId>,
 }
 
 struct Data {
@@ -32,8 +51,10 @@ impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			registration_info: RwLock::new(BTreeMap::new()),
+			namespace_users: StdRwLock::new(BTreeMap::new()),
 			services: Services {
 				sending: args.depend::<sending::Service>("sending"),
+				users: args.depend::<users::Service>("users"),
 			},
 			db: Data {
 				id_appserviceregistrations: args.db["id_appserviceregistrations"].clone(),
@@ -43,14 +64,14 @@ impl crate::Service for Service {
 
 	async fn worker(self: Arc<Self>) -> Result<()> {
 		// Inserting registrations into cache
-		for appservice in self.iter_db_ids().await? {
-			self.registration_info.write().await.insert(
-				appservice.0,
-				appservice
-					.1
-					.try_into()
-					.expect("Should be validated on registration"),
-			);
+		for (id, registration) in self.iter_db_ids().await? {
+			let mut info: RegistrationInfo = registration
+				.try_into()
+				.expect("Should be validated on registration");
+
+			info.extensions = self.get_db_extensions(&id).await;
+			self.index_namespace(&info).await;
+			self.registration_info.write().await.insert(id, info);
 		}
 
 		Ok(())
@@ -67,10 +88,13 @@ impl Service {
 		appservice_config_body: &str,
 	) -> Result {
 		//TODO: Check for collisions between exclusive appservice namespaces
+		let mut info: RegistrationInfo = registration.clone().try_into()?;
+		info.extensions = Extensions::parse(appservice_config_body.as_bytes());
+		self.index_namespace(&info).await;
 		self.registration_info
 			.write()
 			.await
-			.insert(registration.id.clone(), registration.clone().try_into()?);
+			.insert(registration.id.clone(), info);
 
 		self.db
 			.id_appserviceregistrations
@@ -92,6 +116,11 @@ impl Service {
 			.remove(appservice_id)
 			.ok_or(err!("Appservice not found"))?;
 
+		self.namespace_users
+			.write()
+			.expect("locked")
+			.remove(appservice_id);
+
 		// remove the appservice from the database
 		self.db.id_appserviceregistrations.del(appservice_id);
 
@@ -156,6 +185,53 @@ impl Service {
 			.any(|info| info.rooms.is_exclusive_match(room_id.as_str()))
 	}
 
+	/// Local users in the namespace of an appservice using MSC3202.
+	#[must_use]
+	pub fn namespace_users(&self, appservice_id: &str) -> Vec<OwnedUserId> {
+		self.namespace_users
+			.read()
+			.expect("locked")
+			.get(appservice_id)
+			.map(|namespace| namespace.users.iter().cloned().collect())
+			.unwrap_or_default()
+	}
+
+	/// Adds a newly created local user to the namespaces it falls in.
+	pub fn index_user(&self, user_id: &UserId) {
+		self.namespace_users
+			.write()
+			.expect("locked")
+			.values_mut()
+			.filter(|namespace| namespace.info.is_user_match(user_id))
+			.for_each(|namespace| {
+				namespace.users.insert(user_id.to_owned());
+			});
+	}
+
+	/// Collects the existing users in the namespace of an appservice using
+	/// MSC3202, once when it is loaded or registered.
+	async fn index_namespace(&self, info: &RegistrationInfo) {
+		let id = &info.registration.id;
+		if !info.extensions.msc3202 {
+			self.namespace_users.write().expect("locked").remove(id);
+			return;
+		}
+
+		let users = self
+			.services
+			.users
+			.stream()
+			.ready_filter(|user_id| info.is_user_match(user_id))
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		self.namespace_users
+			.write()
+			.expect("locked")
+			.insert(id.clone(), NamespaceUsers { info: info.clone(), users });
+	}
+
 	pub fn read(
 		&self,
 	) -> impl Future<Output = tokio::sync::RwLockReadGuard<'_, BTreeMap<String, RegistrationInfo>>>
@@ -175,6 +251,15 @@ impl Service {
 			.map_err(|e| err!(Database("Invalid appservice {id:?} registration: {e:?}")))
 	}
 
+	async fn get_db_extensions(&self, id: &str) -> Extensions {
+		self.db
+			.id_appserviceregistrations
+			.get(id)
+			.await
+			.map(|bytes| Extensions::parse(&bytes))
+			.unwrap_or_default()
+	}
+
 	async fn iter_db_ids(&self) -> Result<Vec<(String, Registration)>> {
 		self.db
 			.id_appserviceregistrations
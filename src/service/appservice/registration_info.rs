@@ -1,5 +1,6 @@
 use conduwuit::Result;
 use ruma::{api::appservice::Registration, UserId};
+use serde::Deserialize;
 
 use super::NamespaceRegex;
 
@@ -7,11 +8,31 @@ use super::NamespaceRegex;
 #[derive(Clone, Debug)]
 pub struct RegistrationInfo {
 	pub registration: Registration,
+	pub extensions: Extensions,
 	pub users: NamespaceRegex,
 	pub aliases: NamespaceRegex,
 	pub rooms: NamespaceRegex,
 }
 
+/// Keys of the registration enabling unstable features which ruma's
+/// `Registration` does not know about.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Extensions {
+	/// MSC3202: device list changes and one-time key counts of the
+	/// appservice's users in transactions, and `device_id` masquerading.
+	#[serde(default, rename = "org.matrix.msc3202")]
+	pub msc3202: bool,
+}
+
+impl Extensions {
+	/// Reads the extensions from the YAML of a registration; unknown or
+	/// invalid keys disable them.
+	#[must_use]
+	pub fn parse(registration_body: &[u8]) -> Self {
+		serde_yaml::from_slice(registration_body).unwrap_or_default()
+	}
+}
+
 impl RegistrationInfo {
 	#[must_use]
 	pub fn is_user_match(&self, user_id: &UserId) -> bool {
@@ -35,6 +56,7 @@ impl TryFrom<Registration> for RegistrationInfo {
 			users: value.namespaces.users.clone().try_into()?,
 			aliases: value.namespaces.aliases.clone().try_into()?,
 			rooms: value.namespaces.rooms.clone().try_into()?,
+			extensions: Extensions::default(),
 			registration: value,
 		})
 	}
@@ -0,0 +1,400 @@
+mod tests;
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use conduwuit::{
+	debug, err, implement,
+	pdu::PduBuilder,
+	utils,
+	utils::stream::{ReadyExt, TryIgnore},
+	warn, Err, Error, Result, Server,
+};
+use database::{Deserialized, Json, Map};
+use futures::StreamExt;
+use http::StatusCode;
+use loole::{Receiver, Sender};
+use ruma::{
+	api::client::error::ErrorKind, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, UserId,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue as RawJsonValue;
+use tokio::{sync::Mutex, time::Instant};
+use tokio_util::time::{delay_queue::Key, DelayQueue};
+
+use crate::{rooms, Dep};
+
+/// Events local users asked to be sent after a delay (MSC4140). They are kept
+/// in the database until sent or cancelled, so pending events survive
+/// restarts, and a timer sends each once its delay runs out. Each event has
+/// one timer, which restarting resets.
+pub struct Service {
+	timer_channel: (Sender<TimerType>, Receiver<TimerType>),
+	/// Serializes taking events out of the queue, so each is sent once.
+	lock: Mutex<()>,
+	db: Data,
+	services: Services,
+}
+
+struct Services {
+	server: Arc<Server>,
+	state: Dep<rooms::state::Service>,
+	timeline: Dep<rooms::timeline::Service>,
+}
+
+struct Data {
+	delayid_delayedevent: Arc<Map>,
+	userid_delayid: Arc<Map>,
+}
+
+/// An event waiting to be sent.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DelayedEvent {
+	pub sender: OwnedUserId,
+	pub room_id: OwnedRoomId,
+	pub event_type: String,
+
+	/// Set for state events.
+	pub state_key: Option<String>,
+
+	pub content: Box<RawJsonValue>,
+
+	/// Delay in milliseconds, counted again from the start when restarted.
+	pub delay: u64,
+
+	/// Time in milliseconds the delay last started.
+	pub running_since: u64,
+}
+
+/// Delay ID and the time in milliseconds its event is due, or `None` once it
+/// is no longer waiting.
+type TimerType = (String, Option<u64>);
+
+/// Longest a timer runs for. Events due later are timed again once it ran out.
+const TIMER_MAX: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// Length of generated delay IDs.
+pub const DELAY_ID_LENGTH: usize = 24;
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			timer_channel: loole::unbounded(),
+			lock: Mutex::new(()),
+			db: Data {
+				delayid_delayedevent: args.db["delayid_delayedevent"].clone(),
+				userid_delayid: args.db["userid_delayid"].clone(),
+			},
+			services: Services {
+				server: args.server.clone(),
+				state: args.depend::<rooms::state::Service>("rooms::state"),
+				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
+			},
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result<()> {
+		let receiver = self.timer_channel.1.clone();
+
+		let mut timers = Timers::default();
+
+		// Events left pending at shutdown are sent right away if they fell due
+		// while the server was down.
+		self.db
+			.delayid_delayedevent
+			.stream()
+			.ignore_err()
+			.ready_for_each(|(delay_id, event): (&str, DelayedEvent)| {
+				timers.set(delay_id.to_owned(), Some(event.send_at()));
+			})
+			.await;
+
+		debug!("Loaded {} delayed events", timers.keys.len());
+		while !receiver.is_closed() {
+			tokio::select! {
+				Some(expired) = timers.queue.next() => {
+					let delay_id = expired.into_inner();
+					timers.keys.remove(&delay_id);
+					self.expire(&delay_id).await;
+				},
+				event = receiver.recv_async() => match event {
+					Err(_) => break,
+					Ok((delay_id, send_at)) => timers.set(delay_id, send_at),
+				},
+			}
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) {
+		let (timer_sender, _) = &self.timer_channel;
+		if !timer_sender.is_closed() {
+			timer_sender.close();
+		}
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl DelayedEvent {
+	/// Time in milliseconds the event is due to be sent.
+	#[must_use]
+	pub fn send_at(&self) -> u64 { self.running_since.saturating_add(self.delay) }
+}
+
+/// Queues an event to be sent by `sender` once `delay` milliseconds have
+/// passed. Returns the delay ID the sender manages it by.
+#[implement(Service)]
+pub async fn schedule(
+	&self,
+	sender: &UserId,
+	room_id: &RoomId,
+	event_type: String,
+	state_key: Option<String>,
+	content: Box<RawJsonValue>,
+	delay: u64,
+) -> Result<String> {
+	let config = &self.services.server.config;
+	if config.max_event_delay == 0 {
+		return Err!(Request(Forbidden("Delayed events are disabled on this server.")));
+	}
+
+	if delay > config.max_event_delay {
+		return Err!(Request(InvalidParam(
+			"Delay of {delay}ms is longer than the maximum of {}ms.",
+			config.max_event_delay
+		)));
+	}
+
+	let _lock = self.lock.lock().await;
+	let pending = self
+		.db
+		.userid_delayid
+		.keys_raw_prefix::<(&UserId, &str), _>(sender)
+		.ignore_err()
+		.count()
+		.await;
+
+	if pending >= config.max_delayed_events_per_user {
+		return Err(Error::Request(
+			ErrorKind::LimitExceeded { retry_after: None },
+			format!("You already have {pending} delayed events waiting to be sent.").into(),
+			StatusCode::TOO_MANY_REQUESTS,
+		));
+	}
+
+	let delay_id = utils::random_string(DELAY_ID_LENGTH);
+	let event = DelayedEvent {
+		sender: sender.to_owned(),
+		room_id: room_id.to_owned(),
+		event_type,
+		state_key,
+		content,
+		delay,
+		running_since: utils::millis_since_unix_epoch(),
+	};
+
+	self.put(&delay_id, &event);
+	self.db.userid_delayid.put_raw((sender, &delay_id), []);
+
+	Ok(delay_id)
+}
+
+#[implement(Service)]
+pub async fn get(&self, delay_id: &str) -> Result<DelayedEvent> {
+	self.db
+		.delayid_delayedevent
+		.get(delay_id)
+		.await
+		.deserialized()
+		.map_err(|_| err!(Request(NotFound("Delayed event {delay_id} does not exist."))))
+}
+
+/// The delayed events of a user waiting to be sent, soonest first.
+#[implement(Service)]
+pub async fn delayed_events(&self, user_id: &UserId) -> Vec<(String, DelayedEvent)> {
+	let delay_ids: Vec<String> = self
+		.db
+		.userid_delayid
+		.keys_raw_prefix(user_id)
+		.ignore_err()
+		.map(|(_, delay_id): (&UserId, &str)| delay_id.to_owned())
+		.collect()
+		.await;
+
+	let mut events = Vec::with_capacity(delay_ids.len());
+	for delay_id in delay_ids {
+		if let Ok(event) = self.get(&delay_id).await {
+			events.push((delay_id, event));
+		}
+	}
+
+	events.sort_by_key(|(_, event)| event.send_at());
+	events
+}
+
+/// Drops a delayed event without sending it.
+#[implement(Service)]
+pub async fn cancel(&self, delay_id: &str) -> Result {
+	self.take(delay_id, false).await.map(|_| ())
+}
+
+/// Cancels the delayed events of a room being purged. Returns the number of
+/// rows removed.
+#[implement(Service)]
+pub async fn purge_room(&self, room_id: &RoomId) -> usize {
+	let delay_ids: Vec<String> = self
+		.db
+		.delayid_delayedevent
+		.stream()
+		.ignore_err()
+		.ready_filter_map(|(delay_id, event): (&str, DelayedEvent)| {
+			(event.room_id == room_id).then(|| delay_id.to_owned())
+		})
+		.collect()
+		.await;
+
+	let mut removed: usize = 0;
+	for delay_id in delay_ids {
+		if self.cancel(&delay_id).await.is_ok() {
+			removed = removed.saturating_add(2);
+		}
+	}
+
+	removed
+}
+
+/// Starts the delay of an event over from now.
+#[implement(Service)]
+pub async fn restart(&self, delay_id: &str) -> Result {
+	let _lock = self.lock.lock().await;
+	let mut event = self.get(delay_id).await?;
+	event.running_since = utils::millis_since_unix_epoch();
+	self.put(delay_id, &event);
+
+	Ok(())
+}
+
+/// Sends a delayed event now rather than when its delay runs out.
+#[implement(Service)]
+pub async fn send(&self, delay_id: &str) -> Result<OwnedEventId> {
+	let event = self.take(delay_id, false).await?;
+	self.send_event(event).await
+}
+
+/// Sends the event of a timer which ran out, unless it was cancelled or sent
+/// in the meantime. Events not due yet are timed again.
+#[implement(Service)]
+async fn expire(&self, delay_id: &str) {
+	let event = match self.take(delay_id, true).await {
+		| Ok(event) => event,
+		| Err(_) => {
+			if let Ok(event) = self.get(delay_id).await {
+				self.time(delay_id, Some(event.send_at()));
+			}
+
+			return;
+		},
+	};
+
+	match self.send_event(event).await {
+		| Ok(event_id) => debug!("Sent delayed event {delay_id} as {event_id}"),
+		| Err(e) => warn!("Failed to send delayed event {delay_id}: {e}"),
+	}
+}
+
+#[implement(Service)]
+async fn send_event(&self, event: DelayedEvent) -> Result<OwnedEventId> {
+	let DelayedEvent {
+		sender,
+		room_id,
+		event_type,
+		state_key,
+		content,
+		..
+	} = event;
+
+	let state_lock = self.services.state.mutex.lock(&room_id).await;
+	self.services
+		.timeline
+		.build_and_append_pdu(
+			PduBuilder {
+				event_type: event_type.into(),
+				content,
+				state_key,
+				..Default::default()
+			},
+			&sender,
+			&room_id,
+			&state_lock,
+		)
+		.await
+}
+
+/// Removes a delayed event from the queue. With `due`, only removes it if its
+/// delay ran out.
+#[implement(Service)]
+async fn take(&self, delay_id: &str, due: bool) -> Result<DelayedEvent> {
+	let _lock = self.lock.lock().await;
+	let event = self.get(delay_id).await?;
+	if due && event.send_at() > utils::millis_since_unix_epoch() {
+		return Err!(Request(NotFound("Delayed event {delay_id} is not due yet.")));
+	}
+
+	self.db.delayid_delayedevent.remove(delay_id);
+	self.db.userid_delayid.del((&event.sender, delay_id));
+	self.time(delay_id, None);
+
+	Ok(event)
+}
+
+/// Stores a delayed event and times its sending.
+#[implement(Service)]
+fn put(&self, delay_id: &str, event: &DelayedEvent) {
+	self.db.delayid_delayedevent.raw_put(delay_id, Json(event));
+	self.time(delay_id, Some(event.send_at()));
+}
+
+/// Sets, resets or clears the timer of a delayed event.
+#[implement(Service)]
+fn time(&self, delay_id: &str, send_at: Option<u64>) {
+	let (timer_sender, _) = &self.timer_channel;
+	if let Err(e) = timer_sender.send((delay_id.to_owned(), send_at)) {
+		warn!("Failed to time delayed event {delay_id}: {e}");
+	}
+}
+
+/// One timer for each delayed event, by delay ID.
+#[derive(Default)]
+struct Timers {
+	queue: DelayQueue<String>,
+	keys: HashMap<String, Key>,
+}
+
+impl Timers {
+	fn set(&mut self, delay_id: String, send_at: Option<u64>) {
+		let Some(send_at) = send_at else {
+			if let Some(key) = self.keys.remove(&delay_id) {
+				self.queue.remove(&key);
+			}
+
+			return;
+		};
+
+		let wait = send_at.saturating_sub(utils::millis_since_unix_epoch());
+		let wait = Duration::from_millis(wait).min(TIMER_MAX);
+		let deadline = Instant::now()
+			.checked_add(wait)
+			.unwrap_or_else(Instant::now);
+
+		match self.keys.get(&delay_id) {
+			| Some(key) => self.queue.reset_at(key, deadline),
+			| None => {
+				let key = self.queue.insert_at(delay_id.clone(), deadline);
+				self.keys.insert(delay_id, key);
+			},
+		}
+	}
+}
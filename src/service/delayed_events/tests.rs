@@ -0,0 +1,85 @@
+#![cfg(test)]
+
+use serde_json::value::to_raw_value;
+
+use super::Timers;
+use crate::tests::{offline_services, TempDir};
+
+#[tokio::test]
+async fn timers_are_reset_in_place() {
+	let mut timers = Timers::default();
+	timers.set("first".to_owned(), Some(u64::MAX));
+	timers.set("first".to_owned(), Some(0));
+	timers.set("second".to_owned(), Some(0));
+	assert_eq!(timers.queue.len(), 2, "restarting keeps one timer per event");
+
+	timers.set("second".to_owned(), None);
+	timers.set("unknown".to_owned(), None);
+	assert_eq!(timers.queue.len(), 1, "clearing removes the timer");
+	assert!(!timers.keys.contains_key("second"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn schedule_restart_send_and_cancel() {
+	let dir = TempDir::new("delayed-events");
+	let services = offline_services(&dir, "example.com")
+		.await
+		.expect("started services offline");
+
+	let delayed_events = &services.delayed_events;
+	let sender = &services.globals.server_user;
+	let room_id = services.admin.get_admin_room().await.expect("admin room");
+	let content = to_raw_value(&serde_json::json!({ "msgtype": "m.text", "body": "later" }))
+		.expect("valid JSON");
+
+	let schedule = |delay| {
+		delayed_events.schedule(
+			sender,
+			&room_id,
+			"m.room.message".to_owned(),
+			None,
+			content.clone(),
+			delay,
+		)
+	};
+
+	let max_event_delay = services.server.config.max_event_delay;
+	assert!(
+		schedule(max_event_delay.saturating_add(1)).await.is_err(),
+		"delays over the maximum are refused"
+	);
+
+	let sent = schedule(60_000).await.expect("scheduled");
+	let cancelled = schedule(120_000).await.expect("scheduled");
+
+	let pending: Vec<_> = delayed_events
+		.delayed_events(sender)
+		.await
+		.into_iter()
+		.map(|(delay_id, _)| delay_id)
+		.collect();
+	assert_eq!(pending, [sent.clone(), cancelled.clone()], "soonest first");
+
+	let before = delayed_events.get(&sent).await.expect("pending");
+	delayed_events.restart(&sent).await.expect("restarted");
+	let after = delayed_events.get(&sent).await.expect("still pending");
+	assert!(after.running_since >= before.running_since, "the delay starts over");
+	assert_eq!(after.delay, before.delay);
+
+	let event_id = delayed_events.send(&sent).await.expect("sent");
+	let pdu = services
+		.rooms
+		.timeline
+		.get_pdu(&event_id)
+		.await
+		.expect("in the timeline");
+	assert_eq!(pdu.sender, *sender);
+	assert!(delayed_events.get(&sent).await.is_err(), "sent events are no longer pending");
+
+	delayed_events.cancel(&cancelled).await.expect("cancelled");
+	assert!(delayed_events.get(&cancelled).await.is_err());
+	assert!(delayed_events.cancel(&cancelled).await.is_err(), "cancelled only once");
+	assert!(delayed_events.delayed_events(sender).await.is_empty());
+
+	services.stop_offline();
+}
@@ -0,0 +1,43 @@
+use conduwuit::{err, Result};
+use lettre::{
+	message::{header::ContentType, Mailbox},
+	AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use url::Url;
+
+/// Sends plain text emails through an SMTP server.
+pub(super) struct Mailer {
+	transport: AsyncSmtpTransport<Tokio1Executor>,
+	from: Mailbox,
+}
+
+impl Mailer {
+	pub(super) fn new(smtp_url: &Url, from: &str) -> Result<Self> {
+		let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(smtp_url.as_str())
+			.map_err(|e| err!(Config("email.smtp_url", "Invalid SMTP URL: {e}")))?
+			.build();
+
+		let from = from
+			.parse()
+			.map_err(|e| err!(Config("email.from", "Invalid sender address: {e}")))?;
+
+		Ok(Self { transport, from })
+	}
+
+	pub(super) async fn send(&self, to: Mailbox, subject: &str, body: String) -> Result {
+		let message = Message::builder()
+			.from(self.from.clone())
+			.to(to)
+			.subject(subject)
+			.header(ContentType::TEXT_PLAIN)
+			.body(body)
+			.map_err(|e| err!("Failed to build email: {e}"))?;
+
+		self.transport
+			.send(message)
+			.await
+			.map_err(|e| err!(error!("Failed to send email: {e}")))?;
+
+		Ok(())
+	}
+}
@@ -0,0 +1,396 @@
+mod mailer;
+mod template;
+mod tests;
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use conduwuit::{debug, err, implement, info, utils, Err, Result, Server};
+use database::{Deserialized, Json, Map};
+use lettre::{message::Mailbox, Address};
+use ruma::{
+	thirdparty::Medium, ClientSecret, OwnedClientSecret, OwnedSessionId, SessionId, UInt,
+};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use self::{mailer::Mailer, template::Template};
+use crate::{
+	ratelimit::{self, Key, Kind},
+	users, Dep,
+};
+
+/// Validates email addresses of users by sending them tokens, and sends other
+/// emails through the configured SMTP server.
+pub struct Service {
+	mailer: Option<Mailer>,
+	templates: BTreeMap<&'static str, Template>,
+	db: Data,
+	services: Services,
+}
+
+struct Services {
+	server: Arc<Server>,
+	ratelimit: Dep<ratelimit::Service>,
+	users: Dep<users::Service>,
+}
+
+struct Data {
+	threepidsessionid_session: Arc<Map>,
+	threepidsecret_sessionid: Arc<Map>,
+}
+
+/// What an email address is being validated for.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Purpose {
+	/// Registering a new account with the address.
+	Registration,
+
+	/// Adding the address to an existing account.
+	AddThreepid,
+
+	/// Resetting the password of the account the address belongs to.
+	PasswordReset,
+}
+
+/// A validation session started by a `requestToken` endpoint.
+#[derive(Debug, Deserialize, Serialize)]
+struct Session {
+	address: String,
+	client_secret: OwnedClientSecret,
+	purpose: Purpose,
+	token: String,
+	send_attempt: UInt,
+	next_link: Option<String>,
+	expires_at: u64,
+	validated: bool,
+}
+
+/// Path of the page validation links point to, which also accepts submitted
+/// tokens.
+pub const VALIDATE_PATH: &str = "/_conduwuit/email/validate";
+
+const SESSION_ID_LENGTH: usize = 32;
+
+const TOKEN_LENGTH: usize = 32;
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		let config = &args.server.config.email;
+		let mailer = match (&config.smtp_url, &config.from) {
+			| (Some(smtp_url), Some(from)) if config.enable => Some(Mailer::new(smtp_url, from)?),
+			| _ => None,
+		};
+
+		Ok(Arc::new(Self {
+			mailer,
+			templates: template::load(config.template_dir.as_deref())?,
+			db: Data {
+				threepidsessionid_session: args.db["threepidsessionid_session"].clone(),
+				threepidsecret_sessionid: args.db["threepidsecret_sessionid"].clone(),
+			},
+			services: Services {
+				server: args.server.clone(),
+				ratelimit: args.depend::<ratelimit::Service>("ratelimit"),
+				users: args.depend::<users::Service>("users"),
+			},
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Whether emails can be sent.
+#[implement(Service)]
+#[inline]
+pub fn enabled(&self) -> bool { self.mailer.is_some() }
+
+/// Sends a validation token to `address` for `purpose`, unless a token was
+/// already sent for this client secret and send attempt. Returns the session
+/// ID. Emails to one address are rate limited, and `next_link` must be allowed
+/// by `next_link_allowlist`.
+#[implement(Service)]
+pub async fn request_token(
+	&self,
+	purpose: Purpose,
+	client_secret: &ClientSecret,
+	address: &str,
+	send_attempt: UInt,
+	next_link: Option<&str>,
+) -> Result<OwnedSessionId> {
+	if !self.enabled() {
+		return Err!(Request(ThreepidDenied("Email is not enabled on this server.")));
+	}
+
+	let address = canonicalize(address)?.to_string();
+	let next_link = next_link
+		.map(|next_link| self.check_next_link(next_link))
+		.transpose()?
+		.flatten();
+
+	let bound = self
+		.services
+		.users
+		.find_from_threepid(&Medium::Email, &address)
+		.await
+		.is_ok();
+
+	match purpose {
+		| Purpose::Registration | Purpose::AddThreepid if bound => {
+			return Err!(Request(ThreepidInUse("Email address is already in use.")));
+		},
+		| Purpose::PasswordReset if !self.services.server.config.email.password_reset => {
+			return Err!(Request(ThreepidDenied("Password reset is not enabled.")));
+		},
+		| Purpose::PasswordReset if !bound => {
+			return Err!(Request(ThreepidNotFound("Email address is not bound to any account.")));
+		},
+		| _ => {},
+	}
+
+	let index = (client_secret, address.as_str());
+	let existing: Option<(OwnedSessionId, Session)> = match self
+		.db
+		.threepidsecret_sessionid
+		.qry(&index)
+		.await
+		.deserialized::<OwnedSessionId>()
+	{
+		| Ok(sid) => self.session(&sid).await.ok().map(|session| (sid, session)),
+		| Err(_) => None,
+	};
+
+	let lifetime = self.services.server.config.email.token_lifetime;
+	let expires_at =
+		utils::millis_since_unix_epoch().saturating_add(lifetime.saturating_mul(1000));
+	let (sid, mut session) = match existing {
+		| Some((sid, session)) if session.purpose == purpose => {
+			if session.send_attempt >= send_attempt {
+				debug!(%sid, "Token was already sent for this attempt");
+				return Ok(sid);
+			}
+
+			(sid, session)
+		},
+		| _ => {
+			let sid = SessionId::parse(utils::random_string(SESSION_ID_LENGTH))
+				.expect("random strings are valid session IDs");
+
+			let session = Session {
+				address: address.clone(),
+				client_secret: client_secret.to_owned(),
+				purpose,
+				token: utils::random_string(TOKEN_LENGTH),
+				send_attempt,
+				next_link: None,
+				expires_at,
+				validated: false,
+			};
+
+			(sid, session)
+		},
+	};
+
+	self.services
+		.ratelimit
+		.check(Kind::EmailToken, Key::Email(address.clone()))?;
+
+	session.send_attempt = send_attempt;
+	session.next_link = next_link.map(Into::into);
+	session.expires_at = expires_at;
+
+	let link = self.validation_link(&sid, client_secret, &session.token)?;
+	let template = match purpose {
+		| Purpose::Registration => "registration",
+		| Purpose::AddThreepid => "verify_email",
+		| Purpose::PasswordReset => "password_reset",
+	};
+
+	self.send(&session.address, template, &[
+		("address", &session.address),
+		("link", link.as_str()),
+	])
+	.await?;
+
+	self.db.threepidsessionid_session.put(&sid, Json(&session));
+	self.db.threepidsecret_sessionid.put(index, &sid);
+
+	info!(%sid, ?purpose, "Sent validation email");
+
+	Ok(sid)
+}
+
+/// Marks the session as validated if the token matches. Returns the link
+/// the user should be sent to next, if the client gave one which is still
+/// allowed.
+#[implement(Service)]
+pub async fn submit_token(
+	&self,
+	sid: &SessionId,
+	client_secret: &ClientSecret,
+	token: &str,
+) -> Result<Option<Url>> {
+	let mut session = self.session(sid).await?;
+	if session.client_secret.as_str() != client_secret.as_str() || session.token != token {
+		return Err!(Request(ThreepidAuthFailed("Invalid validation token.")));
+	}
+
+	session.validated = true;
+	self.db.threepidsessionid_session.put(sid, Json(&session));
+
+	Ok(session
+		.next_link
+		.as_deref()
+		.and_then(|next_link| self.check_next_link(next_link).ok().flatten()))
+}
+
+/// Parses a `next_link`, refusing links which are not https or whose host is
+/// not in `next_link_allowlist`. Returns None if the allowlist is empty, in
+/// which case links are ignored.
+#[implement(Service)]
+pub fn check_next_link(&self, next_link: &str) -> Result<Option<Url>> {
+	let allowlist = &self.services.server.config.email.next_link_allowlist;
+	if allowlist.is_empty() {
+		return Ok(None);
+	}
+
+	let url = Url::parse(next_link)
+		.map_err(|e| err!(Request(InvalidParam("Invalid next_link: {e}"))))?;
+
+	if url.scheme() != "https" {
+		return Err!(Request(InvalidParam("next_link must be an https URL.")));
+	}
+
+	if !url
+		.host_str()
+		.is_some_and(|host| allowlist.iter().any(|allowed| allowed == host))
+	{
+		return Err!(Request(InvalidParam("next_link host is not allowed.")));
+	}
+
+	Ok(Some(url))
+}
+
+/// Returns the address of a validated session, which must have been started
+/// for `purpose` if one is given.
+#[implement(Service)]
+pub async fn validated(
+	&self,
+	sid: &SessionId,
+	client_secret: &ClientSecret,
+	purpose: Option<Purpose>,
+) -> Result<String> {
+	let session = self.session(sid).await?;
+	if session.client_secret.as_str() != client_secret.as_str()
+		|| purpose.is_some_and(|purpose| purpose != session.purpose)
+	{
+		return Err!(Request(ThreepidAuthFailed("Unknown validation session.")));
+	}
+
+	if !session.validated {
+		return Err!(Request(ThreepidAuthFailed("Email address has not been validated yet.")));
+	}
+
+	Ok(session.address)
+}
+
+/// Ends a session once its address was used.
+#[implement(Service)]
+pub async fn consume(&self, sid: &SessionId) {
+	if let Ok(session) = self.session(sid).await {
+		self.remove(sid, &session);
+	}
+}
+
+/// Renders the template `name` with `vars` and sends it to `to`. The
+/// `server_name` placeholder is always available.
+#[implement(Service)]
+pub async fn send(&self, to: &str, name: &str, vars: &[(&str, &str)]) -> Result {
+	let Some(mailer) = &self.mailer else {
+		return Err!("Email is not enabled.");
+	};
+
+	let template = self
+		.templates
+		.get(name)
+		.ok_or_else(|| err!("Unknown email template {name:?}"))?;
+
+	let server_name = self.services.server.config.server_name.as_str();
+	let mut vars = vars.to_vec();
+	vars.push(("server_name", server_name));
+	let Template { subject, body } = template.render(&vars);
+
+	let to = Mailbox::new(None, canonicalize(to)?);
+	mailer.send(to, &subject, body).await
+}
+
+#[implement(Service)]
+async fn session(&self, sid: &SessionId) -> Result<Session> {
+	let session: Session = self
+		.db
+		.threepidsessionid_session
+		.qry(sid)
+		.await
+		.deserialized()
+		.map_err(|_| err!(Request(ThreepidAuthFailed("Unknown validation session."))))?;
+
+	if session.expires_at < utils::millis_since_unix_epoch() {
+		self.remove(sid, &session);
+		return Err!(Request(ThreepidAuthFailed("Validation session has expired.")));
+	}
+
+	Ok(session)
+}
+
+#[implement(Service)]
+fn remove(&self, sid: &SessionId, session: &Session) {
+	self.db.threepidsessionid_session.del(sid);
+	self.db
+		.threepidsecret_sessionid
+		.del((&session.client_secret, session.address.as_str()));
+}
+
+#[implement(Service)]
+fn validation_link(
+	&self,
+	sid: &SessionId,
+	client_secret: &ClientSecret,
+	token: &str,
+) -> Result<Url> {
+	let mut link = self.submit_url()?;
+	link.query_pairs_mut()
+		.append_pair("sid", sid.as_str())
+		.append_pair("client_secret", client_secret.as_str())
+		.append_pair("token", token);
+
+	Ok(link)
+}
+
+/// URL validation tokens can be submitted to, returned as `submit_url` from
+/// `requestToken` endpoints.
+#[implement(Service)]
+#[inline]
+pub fn submit_url(&self) -> Result<Url> { self.link(VALIDATE_PATH) }
+
+/// URL of a page of this server for links in emails.
+#[implement(Service)]
+pub fn link(&self, path: &str) -> Result<Url> {
+	let config = &self.services.server.config;
+	let base = match (&config.email.link_base_url, &config.well_known.client) {
+		| (Some(base), _) | (None, Some(base)) => base.clone(),
+		| (None, None) => Url::parse(&format!("https://{}", config.server_name))
+			.map_err(|e| err!(Config("server_name", "Not usable in a URL: {e}")))?,
+	};
+
+	base.join(path)
+		.map_err(|e| err!(Config("email.link_base_url", "Invalid link URL: {e}")))
+}
+
+/// Checks and lowercases an email address.
+fn canonicalize(address: &str) -> Result<Address> {
+	address
+		.trim()
+		.to_lowercase()
+		.parse()
+		.map_err(|e| err!(Request(InvalidParam("Invalid email address: {e}"))))
+}
@@ -0,0 +1,138 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use conduwuit::{err, Result};
+
+/// Subject and body of an email with `{placeholder}`s.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) struct Template {
+	pub(super) subject: String,
+	pub(super) body: String,
+}
+
+/// Built-in templates by name, each replaceable by a file in `template_dir`.
+const BUILTIN: &[(&str, &str)] = &[
+	(
+		"verify_email",
+		"Subject: Validate your email address on {server_name}
+
+Someone, hopefully you, asked to add {address} to a Matrix account on
+{server_name}.
+
+To validate this address, open the following link and then return to your
+client:
+
+{link}
+
+If this wasn't you, you can ignore this email.
+",
+	),
+	(
+		"registration",
+		"Subject: Complete your registration on {server_name}
+
+Someone, hopefully you, asked to register a Matrix account on {server_name}
+with {address}.
+
+To validate this address, open the following link and then return to your
+client:
+
+{link}
+
+If this wasn't you, you can ignore this email.
+",
+	),
+	(
+		"password_reset",
+		"Subject: Reset your password on {server_name}
+
+Someone, hopefully you, asked to reset the password of the Matrix account on
+{server_name} that {address} belongs to.
+
+To reset it, open the following link and then return to your client:
+
+{link}
+
+If this wasn't you, you can ignore this email. Your password has not been
+changed.
+",
+	),
+	(
+		"notification_digest",
+		"Subject: {unread} unread notifications on {server_name}
+
+You have {unread} unread notifications, {highlights} of them highlighted, as
+{user_id}:
+
+{rooms}
+Open your Matrix client to read them.
+
+To stop these emails, open the following link:
+
+{link}
+",
+	),
+];
+
+impl Template {
+	/// Parses a template whose first line is `Subject: ...`, followed by an
+	/// empty line and the body.
+	pub(super) fn parse(text: &str) -> Result<Self> {
+		let (subject, body) = text.split_once('\n').unwrap_or((text, ""));
+
+		let subject = subject
+			.trim_end_matches('\r')
+			.strip_prefix("Subject:")
+			.ok_or_else(|| err!("Template does not start with a Subject line"))?
+			.trim();
+
+		let body = body
+			.strip_prefix("\r\n")
+			.or_else(|| body.strip_prefix('\n'))
+			.unwrap_or(body);
+
+		Ok(Self {
+			subject: subject.to_owned(),
+			body: body.to_owned(),
+		})
+	}
+
+	/// Replaces each `{name}` with its value.
+	#[must_use]
+	pub(super) fn render(&self, vars: &[(&str, &str)]) -> Self {
+		let replace = |text: &str| {
+			vars.iter().fold(text.to_owned(), |text, (name, value)| {
+				text.replace(&format!("{{{name}}}"), value)
+			})
+		};
+
+		Self {
+			subject: replace(&self.subject),
+			body: replace(&self.body),
+		}
+	}
+}
+
+/// Loads the built-in templates, replaced by those found in `dir`.
+pub(super) fn load(dir: Option<&Path>) -> Result<BTreeMap<&'static str, Template>> {
+	BUILTIN
+		.iter()
+		.map(|&(name, builtin)| {
+			let custom = dir
+				.map(|dir| dir.join(format!("{name}.txt")))
+				.filter(|path| path.exists())
+				.map(|path| {
+					fs::read_to_string(&path).map_err(|e| {
+						err!(Config("email.template_dir", "Failed to read {path:?}: {e}"))
+					})
+				})
+				.transpose()?;
+
+			let template =
+				Template::parse(custom.as_deref().unwrap_or(builtin)).map_err(|e| {
+					err!(Config("email.template_dir", "Invalid template {name:?}: {e}"))
+				})?;
+
+			Ok((name, template))
+		})
+		.collect()
+}
@@ -0,0 +1,59 @@
+#![cfg(test)]
+
+use tokio::net::TcpListener;
+use url::Url;
+
+use super::{mailer::Mailer, template, template::Template};
+use crate::tests::smtp_sink;
+
+#[tokio::test]
+async fn send_to_smtp_sink() {
+	let listener = TcpListener::bind("127.0.0.1:0")
+		.await
+		.expect("bound listener");
+	let port = listener.local_addr().expect("local address").port();
+	let sink = tokio::spawn(smtp_sink(listener));
+
+	let url = Url::parse(&format!("smtp://127.0.0.1:{port}")).expect("valid url");
+	let mailer = Mailer::new(&url, "conduwuit <matrix@example.com>").expect("built mailer");
+	mailer
+		.send(
+			"user@example.com".parse().expect("valid mailbox"),
+			"Validate your email address",
+			"Open the link to continue.\n".to_owned(),
+		)
+		.await
+		.expect("sent email");
+
+	let data = sink.await.expect("sink finished");
+	assert!(data.contains("From: conduwuit <matrix@example.com>"), "{data}");
+	assert!(data.contains("To: user@example.com"), "{data}");
+	assert!(data.contains("Subject: Validate your email address"), "{data}");
+	assert!(data.contains("Open the link to continue."), "{data}");
+}
+
+#[test]
+fn template_parse_and_render() {
+	let template = Template::parse("Subject: Welcome to {server_name}\n\nHi {address},\nbye\n")
+		.expect("valid");
+
+	assert_eq!(
+		template.render(&[("server_name", "example.com"), ("address", "a@b.c")]),
+		Template {
+			subject: "Welcome to example.com".to_owned(),
+			body: "Hi a@b.c,\nbye\n".to_owned(),
+		}
+	);
+
+	assert!(Template::parse("Welcome\n\nHi").is_err());
+}
+
+#[test]
+fn builtin_templates() {
+	let templates = template::load(None).expect("loaded templates");
+
+	for name in ["verify_email", "registration", "password_reset", "notification_digest"] {
+		let template = templates.get(name).expect("template exists");
+		assert!(template.body.contains("{link}"), "{name} has no link");
+	}
+}
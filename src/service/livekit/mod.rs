@@ -0,0 +1,174 @@
+mod tests;
+
+use std::sync::Arc;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use conduwuit::{err, utils, Err, Result, Server};
+use hmac::{Hmac, Mac};
+use ruma::{
+	api::federation::openid::get_openid_userinfo, OwnedUserId, RoomId, ServerName, UserId,
+};
+use serde::Serialize;
+use sha2::Sha256;
+
+use crate::{globals, rooms, sending, users, Dep};
+
+/// Authorizes MatrixRTC calls on a LiveKit SFU, like lk-jwt-service does:
+/// members of a room are handed LiveKit access tokens to join its call.
+pub struct Service {
+	services: Services,
+}
+
+struct Services {
+	server: Arc<Server>,
+	globals: Dep<globals::Service>,
+	sending: Dep<sending::Service>,
+	state_cache: Dep<rooms::state_cache::Service>,
+	users: Dep<users::Service>,
+}
+
+#[derive(Serialize)]
+struct Header {
+	alg: &'static str,
+	typ: &'static str,
+}
+
+#[derive(Serialize)]
+struct Claims<'a> {
+	iss: &'a str,
+	sub: &'a str,
+	nbf: u64,
+	exp: u64,
+	video: Grants<'a>,
+}
+
+/// What the bearer of a token may do in the LiveKit room: what lk-jwt-service
+/// grants, which is joining it to publish and subscribe. Creating rooms is
+/// left to the SFU's `auto_create`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Grants<'a> {
+	room: &'a str,
+	room_join: bool,
+	can_publish: bool,
+	can_subscribe: bool,
+}
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			services: Services {
+				server: args.server.clone(),
+				globals: args.depend::<globals::Service>("globals"),
+				sending: args.depend::<sending::Service>("sending"),
+				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
+				users: args.depend::<users::Service>("users"),
+			},
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl Service {
+	/// Whether a LiveKit SFU is configured.
+	#[must_use]
+	pub fn is_enabled(&self) -> bool {
+		let config = &self.services.server.config.livekit;
+		config.url.is_some() && config.key.is_some() && config.secret.is_some()
+	}
+
+	/// WebSocket URL of the LiveKit SFU.
+	#[must_use]
+	pub fn url(&self) -> Option<&str> { self.services.server.config.livekit.url.as_deref() }
+
+	/// The user an OpenID token was issued to by their homeserver.
+	pub async fn openid_user(
+		&self,
+		server_name: &ServerName,
+		access_token: &str,
+	) -> Result<OwnedUserId> {
+		if self.services.globals.server_is_ours(server_name) {
+			return self
+				.services
+				.users
+				.find_from_openid_token(access_token)
+				.await;
+		}
+
+		let user_id = self
+			.services
+			.sending
+			.send_federation_request(
+				server_name,
+				get_openid_userinfo::v1::Request::new(access_token.to_owned()),
+			)
+			.await
+			.map_err(|e| err!(Request(Unauthorized("Failed to look up OpenID token: {e}"))))?
+			.sub;
+
+		answered_user(server_name, user_id)
+	}
+
+	/// A LiveKit access token letting a device of the user join the call of a
+	/// room they are joined to.
+	pub async fn access_token(
+		&self,
+		user_id: &UserId,
+		device_id: &str,
+		room_id: &RoomId,
+	) -> Result<String> {
+		let config = &self.services.server.config.livekit;
+		let (Some(key), Some(secret)) = (&config.key, &config.secret) else {
+			return Err!(Request(NotFound("No LiveKit SFU is configured.")));
+		};
+
+		if !self.services.state_cache.is_joined(user_id, room_id).await {
+			return Err!(Request(Forbidden("You are not joined to {room_id}.")));
+		}
+
+		let identity = format!("{user_id}:{device_id}");
+		let now = utils::millis_since_unix_epoch() / 1000;
+		let claims = Claims {
+			iss: key,
+			sub: &identity,
+			nbf: now,
+			exp: now.saturating_add(config.token_ttl),
+			video: Grants {
+				room: room_id.as_str(),
+				room_join: true,
+				can_publish: true,
+				can_subscribe: true,
+			},
+		};
+
+		jwt(secret, &claims)
+	}
+}
+
+/// The user a server answered an OpenID token lookup with, which must be one
+/// of its own users.
+fn answered_user(server_name: &ServerName, user_id: OwnedUserId) -> Result<OwnedUserId> {
+	if user_id.server_name() != server_name {
+		return Err!(Request(Unauthorized(
+			"{server_name} answered for {user_id}, who is not one of its users."
+		)));
+	}
+
+	Ok(user_id)
+}
+
+/// Encodes and signs a JWT with HS256.
+fn jwt<T: Serialize>(secret: &str, claims: &T) -> Result<String> {
+	let header = Header { alg: "HS256", typ: "JWT" };
+	let header = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?);
+	let claims = URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims)?);
+	let message = format!("{header}.{claims}");
+
+	let mut mac =
+		Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+	mac.update(message.as_bytes());
+	let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+
+	Ok(format!("{message}.{signature}"))
+}
@@ -0,0 +1,96 @@
+#![cfg(test)]
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use ruma::{server_name, user_id};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+
+use super::{answered_user, jwt};
+use crate::tests::{offline_services_with, TempDir};
+
+#[derive(Serialize)]
+struct Claims {
+	sub: &'static str,
+	name: &'static str,
+	iat: u64,
+}
+
+#[test]
+fn signs_hs256() {
+	let claims = Claims {
+		sub: "1234567890",
+		name: "John Doe",
+		iat: 1_516_239_022,
+	};
+
+	assert_eq!(
+		jwt("your-256-bit-secret", &claims).expect("serializable claims"),
+		"eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.\
+		 eyJzdWIiOiIxMjM0NTY3ODkwIiwibmFtZSI6IkpvaG4gRG9lIiwiaWF0IjoxNTE2MjM5MDIyfQ.\
+		 SflKxwRJSMeKKF2QT4fwpMeJf36POk6yJV_adQssw5c"
+	);
+}
+
+#[test]
+fn remote_answers_only_for_its_users() {
+	let server_name = server_name!("remote.example.org");
+	let user_id = user_id!("@alice:remote.example.org").to_owned();
+	assert_eq!(answered_user(server_name, user_id.clone()).expect("one of its users"), user_id);
+
+	let other = user_id!("@admin:example.com").to_owned();
+	assert!(
+		answered_user(server_name, other).is_err(),
+		"a server cannot vouch for users of another server"
+	);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn access_token_requires_membership() {
+	let dir = TempDir::new("livekit");
+	let services = offline_services_with(
+		&dir,
+		"example.com",
+		r#"
+		[global.livekit]
+		url = "wss://livekit.example.com"
+		key = "key"
+		secret = "secret"
+		"#,
+	)
+	.await
+	.expect("started services offline");
+
+	let livekit = &services.livekit;
+	assert!(livekit.is_enabled());
+
+	let room_id = services.admin.get_admin_room().await.expect("admin room");
+	let member = &services.globals.server_user;
+	let jwt = livekit
+		.access_token(member, "DEVICE", &room_id)
+		.await
+		.expect("members get a token");
+
+	let claims = jwt.split('.').nth(1).expect("JWT has claims");
+	let claims: JsonValue =
+		serde_json::from_slice(&URL_SAFE_NO_PAD.decode(claims).expect("base64"))
+			.expect("JSON claims");
+	assert_eq!(claims["sub"], format!("{member}:DEVICE"));
+	assert_eq!(claims["video"]["room"], room_id.as_str());
+	assert_eq!(claims["video"]["roomJoin"], true);
+	assert!(
+		claims["video"].get("roomCreate").is_none(),
+		"tokens do not let their bearer create rooms"
+	);
+
+	let stranger = user_id!("@stranger:example.com");
+	services.users.create(stranger, None).expect("created user");
+	assert!(
+		livekit
+			.access_token(stranger, "DEVICE", &room_id)
+			.await
+			.is_err(),
+		"users not joined to the room get no token"
+	);
+
+	services.stop_offline();
+}
@@ -5,16 +5,19 @@ use conduwuit::{
 	utils::{str_from_bytes, stream::TryIgnore, string_from_bytes, ReadyExt},
 	Err, Result,
 };
-use database::{Database, Interfix, Map};
+use database::{Database, Deserialized, Interfix, Map};
 use futures::StreamExt;
-use ruma::{http_headers::ContentDisposition, Mxc, OwnedMxcUri, UserId};
+use ruma::{http_headers::ContentDisposition, Mxc, OwnedMxcUri, OwnedUserId, UserId};
 
 use super::{preview::UrlPreviewData, thumbnail::Dim};
 
 pub(crate) struct Data {
 	mediaid_file: Arc<Map>,
+	mediaid_quarantine: Arc<Map>,
+	mediaid_size: Arc<Map>,
 	mediaid_user: Arc<Map>,
 	url_previews: Arc<Map>,
+	userid_mediausage: Arc<Map>,
 }
 
 #[derive(Debug)]
@@ -28,8 +31,11 @@ impl Data {
 	pub(super) fn new(db: &Arc<Database>) -> Self {
 		Self {
 			mediaid_file: db["mediaid_file"].clone(),
+			mediaid_quarantine: db["mediaid_quarantine"].clone(),
+			mediaid_size: db["mediaid_size"].clone(),
 			mediaid_user: db["mediaid_user"].clone(),
 			url_previews: db["url_previews"].clone(),
+			userid_mediausage: db["userid_mediausage"].clone(),
 		}
 	}
 
@@ -78,6 +84,46 @@ impl Data {
 				self.mediaid_user.remove(key);
 			})
 			.await;
+
+		self.mediaid_size.del(mxc);
+	}
+
+	pub(super) fn set_size(&self, mxc: &Mxc<'_>, size: u64) { self.mediaid_size.put(mxc, size); }
+
+	/// Size of the original file in bytes, if it was recorded on upload.
+	pub(super) async fn get_size(&self, mxc: &Mxc<'_>) -> Result<u64> {
+		self.mediaid_size.qry(mxc).await.deserialized()
+	}
+
+	/// Bytes uploaded by the user, once counted.
+	pub(super) async fn get_usage(&self, user: &UserId) -> Result<u64> {
+		self.userid_mediausage.qry(user).await.deserialized()
+	}
+
+	pub(super) fn set_usage(&self, user: &UserId, usage: u64) {
+		self.userid_mediausage.put(user, usage);
+	}
+
+	/// Users recorded as uploaders of the MXC.
+	pub(super) async fn uploaders(&self, mxc: &Mxc<'_>) -> Vec<OwnedUserId> {
+		let prefix = (mxc, Interfix);
+		self.mediaid_user
+			.stream_prefix_raw(&prefix)
+			.ignore_err()
+			.ready_filter_map(|(_, user)| str_from_bytes(user).ok()?.try_into().ok())
+			.collect()
+			.await
+	}
+
+	pub(super) fn quarantine(&self, mxc: &Mxc<'_>, by: &UserId) {
+		self.mediaid_quarantine.put_raw(mxc, by);
+	}
+
+	pub(super) fn unquarantine(&self, mxc: &Mxc<'_>) { self.mediaid_quarantine.del(mxc); }
+
+	/// The user who quarantined the MXC, if it is quarantined.
+	pub(super) async fn quarantined_by(&self, mxc: &Mxc<'_>) -> Result<OwnedUserId> {
+		self.mediaid_quarantine.qry(mxc).await.deserialized()
 	}
 
 	/// Searches for all files with the given MXC
@@ -2,6 +2,7 @@ mod data;
 pub(super) mod migrations;
 mod preview;
 mod remote;
+mod storage;
 mod tests;
 mod thumbnail;
 
@@ -11,18 +12,17 @@ use async_trait::async_trait;
 use base64::{engine::general_purpose, Engine as _};
 use conduwuit::{
 	debug, debug_error, debug_info, debug_warn, err, error, trace,
-	utils::{self, MutexMap},
+	utils::{self, math::usize_from_u64_truncated, MutexMap},
 	warn, Err, Result, Server,
 };
-use ruma::{http_headers::ContentDisposition, Mxc, OwnedMxcUri, UserId};
-use tokio::{
-	fs,
-	io::{AsyncReadExt, AsyncWriteExt, BufReader},
-};
+use ruma::{http_headers::ContentDisposition, Mxc, OwnedMxcUri, OwnedUserId, UserId};
 
-use self::data::{Data, Metadata};
 pub use self::thumbnail::Dim;
-use crate::{client, globals, sending, Dep};
+use self::{
+	data::{Data, Metadata},
+	storage::{Filesystem, Provider},
+};
+use crate::{client, globals, sending, users, Dep};
 
 #[derive(Debug)]
 pub struct FileMeta {
@@ -33,6 +33,8 @@ pub struct FileMeta {
 
 pub struct Service {
 	url_preview_mutex: MutexMap<String, ()>,
+	quota_mutex: MutexMap<OwnedUserId, ()>,
+	storage: Box<dyn Provider>,
 	pub(super) db: Data,
 	services: Services,
 }
@@ -42,6 +44,7 @@ struct Services {
 	client: Dep<client::Service>,
 	globals: Dep<globals::Service>,
 	sending: Dep<sending::Service>,
+	users: Dep<users::Service>,
 }
 
 /// generated MXC ID (`media-id`) length
@@ -56,20 +59,25 @@ pub const CORP_CROSS_ORIGIN: &str = "cross-origin";
 #[async_trait]
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		let client = args.require::<client::Service>("client");
+
 		Ok(Arc::new(Self {
 			url_preview_mutex: MutexMap::new(),
+			quota_mutex: MutexMap::new(),
+			storage: storage::build_default(&args.server.config, &client.default)?,
 			db: Data::new(args.db),
 			services: Services {
 				server: args.server.clone(),
 				client: args.depend::<client::Service>("client"),
 				globals: args.depend::<globals::Service>("globals"),
 				sending: args.depend::<sending::Service>("sending"),
+				users: args.depend::<users::Service>("users"),
 			},
 		}))
 	}
 
 	async fn worker(self: Arc<Self>) -> Result<()> {
-		self.create_media_dir().await?;
+		self.storage.init().await?;
 
 		Ok(())
 	}
@@ -78,7 +86,9 @@ impl crate::Service for Service {
 }
 
 impl Service {
-	/// Uploads a file.
+	/// Uploads a file. Files uploaded by local users to this server count
+	/// towards their `media_user_quota`, which is reserved before the file is
+	/// written.
 	pub async fn create(
 		&self,
 		mxc: &Mxc<'_>,
@@ -87,6 +97,12 @@ impl Service {
 		content_type: Option<&str>,
 		file: &[u8],
 	) -> Result<()> {
+		let size = file.len().try_into()?;
+		let uploader = user.filter(|_| self.services.globals.server_is_ours(mxc.server_name));
+		if let Some(user) = uploader {
+			self.reserve_quota(user, size).await?;
+		}
+
 		// Width, Height = 0 if it's not a thumbnail
 		let key = self.db.create_file_metadata(
 			mxc,
@@ -96,9 +112,16 @@ impl Service {
 			content_type,
 		)?;
 
-		//TODO: Dangling metadata in database if creation fails
-		let mut f = self.create_media_file(&key).await?;
-		f.write_all(file).await?;
+		self.db.set_size(mxc, size);
+
+		if let Err(e) = self.storage.put(&key, file).await {
+			self.db.delete_file_mxc(mxc).await;
+			if let Some(user) = uploader {
+				self.release_quota(user, size).await;
+			}
+
+			return Err(e);
+		}
 
 		Ok(())
 	}
@@ -106,11 +129,19 @@ impl Service {
 	/// Deletes a file in the database and from the media directory via an MXC
 	pub async fn delete(&self, mxc: &Mxc<'_>) -> Result<()> {
 		if let Ok(keys) = self.db.search_mxc_metadata_prefix(mxc).await {
+			if self.services.globals.server_is_ours(mxc.server_name) {
+				if let Ok(size) = self.db.get_size(mxc).await {
+					for user in self.db.uploaders(mxc).await {
+						self.release_quota(&user, size).await;
+					}
+				}
+			}
+
 			for key in keys {
 				trace!(?mxc, "MXC Key: {key:?}");
-				debug_info!(?mxc, "Deleting from filesystem");
+				debug_info!(?mxc, "Deleting from storage");
 
-				if let Err(e) = self.remove_media_file(&key).await {
+				if let Err(e) = self.storage.delete(&key).await {
 					debug_error!(?mxc, "Failed to remove media file: {e}");
 				}
 
@@ -152,16 +183,16 @@ impl Service {
 		Ok(deletion_count)
 	}
 
-	/// Downloads a file.
+	/// Downloads a file. Quarantined media is not found.
 	pub async fn get(&self, mxc: &Mxc<'_>) -> Result<Option<FileMeta>> {
+		if self.is_quarantined(mxc).await {
+			return Err!(Request(NotFound("Media not found.")));
+		}
+
 		if let Ok(Metadata { content_disposition, content_type, key }) =
 			self.db.search_file_metadata(mxc, &Dim::default()).await
 		{
-			let mut content = Vec::with_capacity(8192);
-			let path = self.get_media_file(&key);
-			BufReader::new(fs::File::open(path).await?)
-				.read_to_end(&mut content)
-				.await?;
+			let content = self.storage.get(&key).await?;
 
 			Ok(Some(FileMeta {
 				content: Some(content),
@@ -259,29 +290,10 @@ impl Service {
 				continue;
 			}
 
-			let path = self.get_media_file(&key);
-
-			let file_metadata = match fs::metadata(path.clone()).await {
-				| Ok(file_metadata) => file_metadata,
-				| Err(e) => {
-					error!(
-						"Failed to obtain file metadata for MXC {mxc} at file path \
-						 \"{path:?}\", skipping: {e}"
-					);
-					continue;
-				},
-			};
-
-			trace!(%mxc, ?path, "File metadata: {file_metadata:?}");
-
-			let file_created_at = match file_metadata.created() {
+			let file_created_at = match self.storage.created(&key).await {
 				| Ok(value) => value,
-				| Err(err) if err.kind() == std::io::ErrorKind::Unsupported => {
-					debug!("btime is unsupported, using mtime instead");
-					file_metadata.modified()?
-				},
-				| Err(err) => {
-					error!("Could not delete MXC {mxc} at path {path:?}: {err:?}. Skipping...");
+				| Err(e) => {
+					error!("Failed to obtain creation time of MXC {mxc}, skipping: {e}");
 					continue;
 				},
 			};
@@ -333,46 +345,6 @@ impl Service {
 		Ok(deletion_count)
 	}
 
-	pub async fn create_media_dir(&self) -> Result<()> {
-		let dir = self.get_media_dir();
-		Ok(fs::create_dir_all(dir).await?)
-	}
-
-	async fn remove_media_file(&self, key: &[u8]) -> Result<()> {
-		let path = self.get_media_file(key);
-		let legacy = self.get_media_file_b64(key);
-		debug!(?key, ?path, ?legacy, "Removing media file");
-
-		let file_rm = fs::remove_file(&path);
-		let legacy_rm = fs::remove_file(&legacy);
-		let (file_rm, legacy_rm) = tokio::join!(file_rm, legacy_rm);
-		if let Err(e) = legacy_rm {
-			if self.services.server.config.media_compat_file_link {
-				debug_error!(?key, ?legacy, "Failed to remove legacy media symlink: {e}");
-			}
-		}
-
-		Ok(file_rm?)
-	}
-
-	async fn create_media_file(&self, key: &[u8]) -> Result<fs::File> {
-		let path = self.get_media_file(key);
-		debug!(?key, ?path, "Creating media file");
-
-		let file = fs::File::create(&path).await?;
-		if self.services.server.config.media_compat_file_link {
-			let legacy = self.get_media_file_b64(key);
-			if let Err(e) = fs::symlink(&path, &legacy).await {
-				debug_error!(
-					key = ?encode_key(key), ?path, ?legacy,
-					"Failed to create legacy media symlink: {e}"
-				);
-			}
-		}
-
-		Ok(file)
-	}
-
 	#[inline]
 	pub async fn get_metadata(&self, mxc: &Mxc<'_>) -> Option<FileMeta> {
 		self.db
@@ -386,42 +358,166 @@ impl Service {
 			.ok()
 	}
 
+	/// Blocks serving the media locally and over federation while keeping
+	/// its files and metadata.
+	pub fn quarantine(&self, mxc: &Mxc<'_>, by: &UserId) { self.db.quarantine(mxc, by); }
+
+	pub fn unquarantine(&self, mxc: &Mxc<'_>) { self.db.unquarantine(mxc); }
+
 	#[inline]
-	#[must_use]
-	pub fn get_media_file(&self, key: &[u8]) -> PathBuf { self.get_media_file_sha256(key) }
+	pub async fn is_quarantined(&self, mxc: &Mxc<'_>) -> bool {
+		self.quarantined_by(mxc).await.is_ok()
+	}
+
+	#[inline]
+	pub async fn quarantined_by(&self, mxc: &Mxc<'_>) -> Result<OwnedUserId> {
+		self.db.quarantined_by(mxc).await
+	}
+
+	/// Size of the original file in bytes. Sizes of media uploaded before
+	/// they were recorded are read from storage once.
+	pub async fn size(&self, mxc: &Mxc<'_>) -> Result<u64> {
+		if let Ok(size) = self.db.get_size(mxc).await {
+			return Ok(size);
+		}
+
+		let Metadata { key, .. } = self.db.search_file_metadata(mxc, &Dim::default()).await?;
+		let size = self.storage.get(&key).await?.len().try_into()?;
+		self.db.set_size(mxc, size);
+
+		Ok(size)
+	}
+
+	/// All media uploaded by the user with their sizes in bytes.
+	pub async fn user_uploads(&self, user: &UserId) -> Vec<(OwnedMxcUri, Option<u64>)> {
+		let mut uploads = Vec::new();
+		for mxc in self.db.get_all_user_mxcs(user).await {
+			let size = match mxc.as_str().try_into() {
+				| Ok(parsed) => self.size(&parsed).await.ok(),
+				| Err(e) => {
+					debug_error!(?mxc, "Failed to parse MXC URI from database: {e}");
+					None
+				},
+			};
+
+			uploads.push((mxc, size));
+		}
+
+		uploads
+	}
+
+	/// Total size in bytes of the media uploaded by the user to this server.
+	pub async fn usage(&self, user: &UserId) -> u64 {
+		let _lock = self.quota_mutex.lock(user).await;
+		self.load_usage(user).await
+	}
+
+	/// The usage counter of the user. Users who uploaded before it existed
+	/// have their uploads summed up once, which reads the sizes of media
+	/// uploaded before those were recorded from storage.
+	async fn load_usage(&self, user: &UserId) -> u64 {
+		if let Ok(usage) = self.db.get_usage(user).await {
+			return usage;
+		}
+
+		let mut usage = 0_u64;
+		for mxc in self.db.get_all_user_mxcs(user).await {
+			let size = match Mxc::try_from(mxc.as_str()) {
+				| Ok(mxc) if self.services.globals.server_is_ours(mxc.server_name) =>
+					self.size(&mxc).await.unwrap_or(0),
+				| _ => continue,
+			};
+
+			usage = usage.saturating_add(size);
+		}
+
+		self.db.set_usage(user, usage);
+		usage
+	}
 
-	/// new SHA256 file name media function. requires database migrated. uses
-	/// SHA256 hash of the base64 key as the file name
+	/// Adds `size` bytes to the usage of the user, refusing them if that would
+	/// exceed `media_user_quota`. Server admins are exempt.
+	async fn reserve_quota(&self, user: &UserId, size: u64) -> Result {
+		let _lock = self.quota_mutex.lock(user).await;
+		let usage = self.load_usage(user).await;
+		let reserved = usage.saturating_add(size);
+
+		if let Some(quota) = self.services.server.config.media_user_quota {
+			if reserved > quota && !self.services.users.is_admin(user).await {
+				return Err!(Request(TooLarge(
+					"Upload would exceed your media quota of {} ({} used).",
+					utils::bytes::pretty(usize_from_u64_truncated(quota)),
+					utils::bytes::pretty(usize_from_u64_truncated(usage)),
+				)));
+			}
+		}
+
+		self.db.set_usage(user, reserved);
+
+		Ok(())
+	}
+
+	/// Gives back `size` bytes of the usage of the user.
+	async fn release_quota(&self, user: &UserId, size: u64) {
+		let _lock = self.quota_mutex.lock(user).await;
+		let usage = self.load_usage(user).await;
+		self.db.set_usage(user, usage.saturating_sub(size));
+	}
+
+	/// Copies all media from the storage provider named `from` to the one
+	/// named `to`, deleting the originals if `delete_source` is set. Returns
+	/// the number of files copied and the number of files which failed.
+	pub async fn migrate_storage(
+		&self,
+		from: &str,
+		to: &str,
+		delete_source: bool,
+	) -> Result<(usize, usize)> {
+		let config = &self.services.server.config;
+		let client = &self.services.client.default;
+		let source = storage::build(config, from, client)?;
+		let target = storage::build(config, to, client)?;
+		target.init().await?;
+
+		let (mut copied, mut failed) = (0_usize, 0_usize);
+		for key in self.db.get_all_media_keys().await {
+			let result = async {
+				let content = source.get(&key).await?;
+				target.put(&key, &content).await?;
+				if delete_source {
+					source.delete(&key).await?;
+				}
+
+				Ok::<_, conduwuit::Error>(())
+			};
+
+			match result.await {
+				| Ok(()) => copied = copied.saturating_add(1),
+				| Err(e) => {
+					warn!(key = ?encode_key(&key), "Failed to migrate media file: {e}");
+					failed = failed.saturating_add(1);
+				},
+			}
+		}
+
+		Ok((copied, failed))
+	}
+
+	/// Path of a media file in the media directory.
 	#[must_use]
 	pub fn get_media_file_sha256(&self, key: &[u8]) -> PathBuf {
-		let mut r = self.get_media_dir();
-		// Using the hash of the base64 key as the filename
-		// This is to prevent the total length of the path from exceeding the maximum
-		// length in most filesystems
-		let digest = <sha2::Sha256 as sha2::Digest>::digest(key);
-		let encoded = encode_key(&digest);
-		r.push(encoded);
-		r
+		Filesystem::new(&self.services.server.config).path(key)
 	}
 
-	/// old base64 file name media function
-	/// This is the old version of `get_media_file` that uses the full base64
-	/// key as the filename.
+	/// Legacy path of a media file in the media directory, which uses the
+	/// full base64 key as the file name.
 	#[must_use]
 	pub fn get_media_file_b64(&self, key: &[u8]) -> PathBuf {
-		let mut r = self.get_media_dir();
-		let encoded = encode_key(key);
-		r.push(encoded);
-		r
+		Filesystem::new(&self.services.server.config).legacy_path(key)
 	}
 
 	#[must_use]
-	pub fn get_media_dir(&self) -> PathBuf {
-		let mut r = PathBuf::new();
-		r.push(self.services.server.config.database_path.clone());
-		r.push("media");
-		r
-	}
+	pub fn get_media_dir(&self) -> PathBuf { storage::media_dir(&self.services.server.config) }
 }
 
 #[inline]
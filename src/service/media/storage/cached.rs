@@ -0,0 +1,165 @@
+use std::{sync::Mutex, time::SystemTime};
+
+use async_trait::async_trait;
+use conduwuit::{debug, debug_warn, Result};
+use lru_cache::LruCache;
+use tokio::fs;
+
+use super::{filesystem::file_name, Filesystem, Provider};
+
+/// Keeps local copies of the files of a remote provider. Files are written
+/// through to the provider; the copies are only read. The least recently used
+/// copies are deleted once they take more than `capacity` bytes.
+pub struct Cached {
+	remote: Box<dyn Provider>,
+	cache: Filesystem,
+	capacity: usize,
+	index: Mutex<Index>,
+}
+
+/// Sizes of the cached files by file name, least recently used first.
+struct Index {
+	files: LruCache<String, usize>,
+	size: usize,
+}
+
+impl Cached {
+	#[must_use]
+	pub fn new(remote: Box<dyn Provider>, cache: Filesystem, capacity: usize) -> Self {
+		Self {
+			remote,
+			cache,
+			capacity,
+			index: Mutex::new(Index {
+				files: LruCache::new(usize::MAX),
+				size: 0,
+			}),
+		}
+	}
+
+	async fn fill(&self, key: &[u8], content: &[u8]) {
+		if content.len() > self.capacity {
+			return;
+		}
+
+		if let Err(e) = self.cache.put(key, content).await {
+			debug_warn!(?key, "Failed to cache media file: {e}");
+			self.cache.delete(key).await.ok();
+			return;
+		}
+
+		self.record(file_name(key), content.len()).await;
+	}
+
+	/// Accounts for a cached file and evicts the least recently used files
+	/// until the cache fits its capacity again.
+	async fn record(&self, name: String, len: usize) {
+		let evicted = {
+			let mut index = self.index.lock().expect("locked");
+			if let Some(previous) = index.files.insert(name, len) {
+				index.size = index.size.saturating_sub(previous);
+			}
+
+			index.size = index.size.saturating_add(len);
+
+			let mut evicted = Vec::new();
+			while index.size > self.capacity {
+				let Some((name, len)) = index.files.remove_lru() else {
+					break;
+				};
+
+				index.size = index.size.saturating_sub(len);
+				evicted.push(name);
+			}
+
+			evicted
+		};
+
+		for name in evicted {
+			debug!(?name, "Evicting cached media file");
+			if let Err(e) = fs::remove_file(self.cache.dir().join(&name)).await {
+				debug_warn!(?name, "Failed to evict cached media file: {e}");
+			}
+		}
+	}
+
+	/// Indexes the files cached by a previous run, oldest first.
+	async fn load(&self) -> Result {
+		let mut files = Vec::new();
+		let mut entries = fs::read_dir(self.cache.dir()).await?;
+		while let Some(entry) = entries.next_entry().await? {
+			let metadata = entry.metadata().await?;
+			if !metadata.is_file() {
+				continue;
+			}
+
+			let Ok(name) = entry.file_name().into_string() else {
+				continue;
+			};
+
+			let used = metadata
+				.accessed()
+				.or_else(|_| metadata.modified())
+				.unwrap_or(SystemTime::UNIX_EPOCH);
+
+			let len = usize::try_from(metadata.len()).unwrap_or(usize::MAX);
+			files.push((used, name, len));
+		}
+
+		files.sort_unstable();
+		for (_, name, len) in files {
+			self.record(name, len).await;
+		}
+
+		Ok(())
+	}
+}
+
+#[async_trait]
+impl Provider for Cached {
+	fn name(&self) -> &'static str { self.remote.name() }
+
+	async fn init(&self) -> Result {
+		self.cache.init().await?;
+		self.load().await?;
+		self.remote.init().await
+	}
+
+	async fn put(&self, key: &[u8], content: &[u8]) -> Result {
+		self.remote.put(key, content).await?;
+		self.fill(key, content).await;
+
+		Ok(())
+	}
+
+	async fn get(&self, key: &[u8]) -> Result<Vec<u8>> {
+		if let Ok(content) = self.cache.get(key).await {
+			self.index
+				.lock()
+				.expect("locked")
+				.files
+				.get_mut(&file_name(key));
+
+			return Ok(content);
+		}
+
+		let content = self.remote.get(key).await?;
+		self.fill(key, &content).await;
+
+		Ok(content)
+	}
+
+	async fn delete(&self, key: &[u8]) -> Result {
+		{
+			let mut index = self.index.lock().expect("locked");
+			if let Some(len) = index.files.remove(&file_name(key)) {
+				index.size = index.size.saturating_sub(len);
+			}
+		}
+
+		self.cache.delete(key).await.ok();
+		self.remote.delete(key).await
+	}
+
+	async fn created(&self, key: &[u8]) -> Result<SystemTime> { self.remote.created(key).await }
+}
@@ -0,0 +1,118 @@
+use std::{
+	path::{Path, PathBuf},
+	time::SystemTime,
+};
+
+use async_trait::async_trait;
+use conduwuit::{debug, debug_error, Config, Result};
+use tokio::{
+	fs,
+	io::{AsyncReadExt, AsyncWriteExt, BufReader},
+};
+
+use super::{media_dir, Provider};
+use crate::media::encode_key;
+
+/// Files in a directory, named after the SHA256 hash of their key.
+pub struct Filesystem {
+	dir: PathBuf,
+
+	/// Also link each file under its legacy name for Conduit.
+	compat_link: bool,
+}
+
+impl Filesystem {
+	/// The media directory under the database path.
+	#[must_use]
+	pub fn new(config: &Config) -> Self {
+		Self::with_dir(media_dir(config), config.media_compat_file_link)
+	}
+
+	#[must_use]
+	pub fn with_dir(dir: PathBuf, compat_link: bool) -> Self { Self { dir, compat_link } }
+
+	#[must_use]
+	pub fn dir(&self) -> &Path { &self.dir }
+
+	/// SHA256 file name of a key. Using the hash of the base64 key keeps the
+	/// total length of the path below the maximum of most filesystems.
+	#[must_use]
+	pub fn path(&self, key: &[u8]) -> PathBuf { self.dir.join(file_name(key)) }
+
+	/// Legacy file name of a key, which is the full base64 key.
+	#[must_use]
+	pub fn legacy_path(&self, key: &[u8]) -> PathBuf { self.dir.join(encode_key(key)) }
+}
+
+#[async_trait]
+impl Provider for Filesystem {
+	fn name(&self) -> &'static str { "filesystem" }
+
+	async fn init(&self) -> Result { Ok(fs::create_dir_all(&self.dir).await?) }
+
+	async fn put(&self, key: &[u8], content: &[u8]) -> Result {
+		let path = self.path(key);
+		debug!(?key, ?path, "Creating media file");
+
+		let mut file = fs::File::create(&path).await?;
+		if self.compat_link {
+			let legacy = self.legacy_path(key);
+			if let Err(e) = fs::symlink(&path, &legacy).await {
+				debug_error!(
+					key = ?encode_key(key), ?path, ?legacy,
+					"Failed to create legacy media symlink: {e}"
+				);
+			}
+		}
+
+		file.write_all(content).await?;
+
+		Ok(())
+	}
+
+	async fn get(&self, key: &[u8]) -> Result<Vec<u8>> {
+		let mut content = Vec::with_capacity(8192);
+		let path = self.path(key);
+		BufReader::new(fs::File::open(path).await?)
+			.read_to_end(&mut content)
+			.await?;
+
+		Ok(content)
+	}
+
+	async fn delete(&self, key: &[u8]) -> Result {
+		let path = self.path(key);
+		let legacy = self.legacy_path(key);
+		debug!(?key, ?path, ?legacy, "Removing media file");
+
+		let file_rm = fs::remove_file(&path);
+		let legacy_rm = fs::remove_file(&legacy);
+		let (file_rm, legacy_rm) = tokio::join!(file_rm, legacy_rm);
+		if let Err(e) = legacy_rm {
+			if self.compat_link {
+				debug_error!(?key, ?legacy, "Failed to remove legacy media symlink: {e}");
+			}
+		}
+
+		Ok(file_rm?)
+	}
+
+	async fn created(&self, key: &[u8]) -> Result<SystemTime> {
+		let metadata = fs::metadata(self.path(key)).await?;
+		match metadata.created() {
+			| Ok(created) => Ok(created),
+			| Err(e) if e.kind() == std::io::ErrorKind::Unsupported => {
+				debug!("btime is unsupported, using mtime instead");
+				Ok(metadata.modified()?)
+			},
+			| Err(e) => Err(e.into()),
+		}
+	}
+}
+
+/// SHA256 file name of a key.
+#[must_use]
+pub(super) fn file_name(key: &[u8]) -> String {
+	let digest = <sha2::Sha256 as sha2::Digest>::digest(key);
+	encode_key(&digest)
+}
@@ -0,0 +1,72 @@
+//! Storage of media files
+//!
+//! Media and thumbnails are stored as opaque objects named after their key in
+//! the `mediaid_file` map, either in the media directory or in an
+//! S3-compatible bucket.
+
+mod cached;
+mod filesystem;
+mod s3;
+mod tests;
+
+use std::{path::PathBuf, time::SystemTime};
+
+use async_trait::async_trait;
+use conduwuit::{Config, Err, Result};
+
+pub use self::{cached::Cached, filesystem::Filesystem, s3::S3};
+
+/// A place to store media files in.
+#[async_trait]
+pub trait Provider: Send + Sync {
+	fn name(&self) -> &'static str;
+
+	/// Prepares the storage on startup.
+	async fn init(&self) -> Result { Ok(()) }
+
+	async fn put(&self, key: &[u8], content: &[u8]) -> Result;
+
+	async fn get(&self, key: &[u8]) -> Result<Vec<u8>>;
+
+	async fn delete(&self, key: &[u8]) -> Result;
+
+	/// When the file was stored.
+	async fn created(&self, key: &[u8]) -> Result<SystemTime>;
+}
+
+/// Builds the provider named `name` from the config, without caching.
+pub fn build(config: &Config, name: &str, client: &reqwest::Client) -> Result<Box<dyn Provider>> {
+	match name {
+		| "filesystem" => Ok(Box::new(Filesystem::new(config))),
+		| "s3" => Ok(Box::new(S3::new(config, client.clone())?)),
+		| _ => Err!("Unknown media storage provider {name:?}"),
+	}
+}
+
+/// Builds the configured provider, with a local cache if one is configured.
+pub(super) fn build_default(
+	config: &Config,
+	client: &reqwest::Client,
+) -> Result<Box<dyn Provider>> {
+	let provider = build(config, &config.media_storage_provider, client)?;
+	match &config.media_storage_cache_path {
+		| Some(path) if config.media_storage_provider != "filesystem" =>
+			Ok(Box::new(Cached::new(
+				provider,
+				Filesystem::with_dir(path.clone(), false),
+				config
+					.media_storage_cache_size_mb
+					.saturating_mul(1024 * 1024),
+			))),
+		| _ => Ok(provider),
+	}
+}
+
+/// The media directory under the database path.
+#[must_use]
+pub(super) fn media_dir(config: &Config) -> PathBuf {
+	let mut r = PathBuf::new();
+	r.push(config.database_path.clone());
+	r.push("media");
+	r
+}
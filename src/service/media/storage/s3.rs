@@ -0,0 +1,203 @@
+use std::{fmt::Write, time::SystemTime};
+
+use async_trait::async_trait;
+use conduwuit::{err, utils::time, Config, Err, Result};
+use hmac::{Hmac, Mac};
+use reqwest::{header::LAST_MODIFIED, Method, Response, StatusCode};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use super::{filesystem::file_name, Provider};
+
+/// Objects in a bucket of an S3-compatible object storage service, named like
+/// the files of the [`super::Filesystem`] provider. Requests are signed with
+/// AWS Signature Version 4.
+pub struct S3 {
+	client: reqwest::Client,
+	endpoint: Url,
+	bucket: String,
+	region: String,
+	access_key_id: String,
+	secret_access_key: String,
+	path_style: bool,
+	prefix: String,
+}
+
+impl S3 {
+	pub fn new(config: &Config, client: reqwest::Client) -> Result<Self> {
+		let endpoint = config
+			.media_s3_endpoint
+			.clone()
+			.ok_or_else(|| err!(Config("media_s3_endpoint", "Not set")))?;
+
+		let bucket = config
+			.media_s3_bucket
+			.clone()
+			.ok_or_else(|| err!(Config("media_s3_bucket", "Not set")))?;
+
+		Ok(Self {
+			client,
+			endpoint,
+			bucket,
+			region: config.media_s3_region.clone(),
+			access_key_id: config.media_s3_access_key_id.clone().unwrap_or_default(),
+			secret_access_key: config
+				.media_s3_secret_access_key
+				.clone()
+				.unwrap_or_default(),
+			path_style: config.media_s3_path_style,
+			prefix: config.media_s3_prefix.clone().unwrap_or_default(),
+		})
+	}
+
+	fn url(&self, key: &[u8]) -> Result<Url> {
+		let object = format!("{}{}", self.prefix, file_name(key));
+
+		let mut url = self.endpoint.clone();
+		if !self.path_style {
+			let host = url
+				.host_str()
+				.ok_or_else(|| err!(Config("media_s3_endpoint", "Has no host")))?;
+
+			url.set_host(Some(&format!("{}.{host}", self.bucket)))
+				.map_err(|e| err!(Config("media_s3_bucket", "Not usable as a host name: {e}")))?;
+		}
+
+		url.path_segments_mut()
+			.map_err(|()| err!(Config("media_s3_endpoint", "Not a base URL")))?
+			.pop_if_empty()
+			.extend(self.path_style.then_some(self.bucket.as_str()))
+			.extend(object.split('/'));
+
+		Ok(url)
+	}
+
+	async fn request(&self, method: Method, key: &[u8], body: Vec<u8>) -> Result<Response> {
+		let url = self.url(key)?;
+		let payload_hash = hex(&Sha256::digest(&body));
+		let amz_date = time::format(SystemTime::now(), "%Y%m%dT%H%M%SZ");
+		let credentials = Credentials {
+			access_key_id: &self.access_key_id,
+			secret_access_key: &self.secret_access_key,
+			region: &self.region,
+		};
+		let authorization =
+			authorization(&credentials, method.as_str(), &url, &payload_hash, &amz_date);
+
+		let response = self
+			.client
+			.request(method, url)
+			.header("x-amz-content-sha256", payload_hash)
+			.header("x-amz-date", amz_date)
+			.header("authorization", authorization)
+			.body(body)
+			.send()
+			.await?;
+
+		match response.status() {
+			| status if status.is_success() => Ok(response),
+			| StatusCode::NOT_FOUND =>
+				Err!(Request(NotFound("Media not found in object storage"))),
+			| status => Err!("Object storage responded with {status}"),
+		}
+	}
+}
+
+#[async_trait]
+impl Provider for S3 {
+	fn name(&self) -> &'static str { "s3" }
+
+	async fn put(&self, key: &[u8], content: &[u8]) -> Result {
+		self.request(Method::PUT, key, content.to_vec()).await?;
+
+		Ok(())
+	}
+
+	async fn get(&self, key: &[u8]) -> Result<Vec<u8>> {
+		let response = self.request(Method::GET, key, Vec::new()).await?;
+
+		Ok(response.bytes().await?.to_vec())
+	}
+
+	async fn delete(&self, key: &[u8]) -> Result {
+		self.request(Method::DELETE, key, Vec::new()).await?;
+
+		Ok(())
+	}
+
+	async fn created(&self, key: &[u8]) -> Result<SystemTime> {
+		let response = self.request(Method::HEAD, key, Vec::new()).await?;
+		let last_modified = response
+			.headers()
+			.get(LAST_MODIFIED)
+			.and_then(|value| value.to_str().ok())
+			.ok_or_else(|| err!("Object storage did not return Last-Modified"))?;
+
+		time::parse_rfc2822(last_modified)
+	}
+}
+
+/// Keys signing a request.
+pub(super) struct Credentials<'a> {
+	pub(super) access_key_id: &'a str,
+	pub(super) secret_access_key: &'a str,
+	pub(super) region: &'a str,
+}
+
+/// The `Authorization` header of a request with an empty query string,
+/// signing the host, payload hash and date.
+pub(super) fn authorization(
+	credentials: &Credentials<'_>,
+	method: &str,
+	url: &Url,
+	payload_hash: &str,
+	amz_date: &str,
+) -> String {
+	let host = url.host_str().unwrap_or_default();
+	let host = match url.port() {
+		| Some(port) => format!("{host}:{port}"),
+		| None => host.to_owned(),
+	};
+
+	let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+	let canonical_request = format!(
+		"{method}\n{}\n\nhost:{host}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:\
+		 {amz_date}\n\n{signed_headers}\n{payload_hash}",
+		url.path(),
+	);
+
+	let date = amz_date.get(..8).unwrap_or_default();
+	let scope = format!("{date}/{}/s3/aws4_request", credentials.region);
+	let string_to_sign = format!(
+		"AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+		hex(&Sha256::digest(canonical_request.as_bytes()))
+	);
+
+	let secret = format!("AWS4{}", credentials.secret_access_key);
+	let key = [date, credentials.region, "s3", "aws4_request"]
+		.iter()
+		.fold(secret.into_bytes(), |key, part| hmac(&key, part.as_bytes()));
+
+	let signature = hex(&hmac(&key, string_to_sign.as_bytes()));
+
+	format!(
+		"AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, \
+		 Signature={signature}",
+		credentials.access_key_id
+	)
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+	let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any size");
+	mac.update(data);
+	mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+	bytes
+		.iter()
+		.fold(String::with_capacity(bytes.len().saturating_mul(2)), |mut out, byte| {
+			write!(out, "{byte:02x}").expect("writing to a String never fails");
+			out
+		})
+}
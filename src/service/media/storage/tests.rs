@@ -0,0 +1,74 @@
+#![cfg(test)]
+
+use url::Url;
+
+use super::{
+	s3::{authorization, Credentials},
+	Cached, Filesystem, Provider,
+};
+
+#[test]
+fn s3_signature() {
+	let credentials = Credentials {
+		access_key_id: "conduwuit",
+		secret_access_key: "secret",
+		region: "us-east-1",
+	};
+
+	let url = Url::parse("http://localhost:9000/media/abc").expect("valid url");
+	let payload_hash = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+	assert_eq!(
+		authorization(&credentials, "PUT", &url, payload_hash, "20250102T030405Z"),
+		"AWS4-HMAC-SHA256 Credential=conduwuit/20250102/us-east-1/s3/aws4_request, \
+		 SignedHeaders=host;x-amz-content-sha256;x-amz-date, \
+		 Signature=af96571415b3afe62d411637900800470a2b26d1f892aecc9c3883ae4d453a30"
+	);
+}
+
+#[tokio::test]
+async fn filesystem_roundtrip() {
+	let dir = std::env::temp_dir().join(format!("conduwuit-media-test-{}", std::process::id()));
+	let storage = Filesystem::with_dir(dir.clone(), false);
+	let key = b"mxc://example.com/abc\xFF\0\0\0\0\0\0\0\0\xFF\xFF";
+
+	storage.init().await.expect("created directory");
+	storage.put(key, b"hello").await.expect("stored file");
+	assert_eq!(storage.get(key).await.expect("read file"), b"hello");
+	assert!(storage.created(key).await.is_ok());
+
+	storage.delete(key).await.expect("deleted file");
+	assert!(storage.get(key).await.is_err());
+
+	std::fs::remove_dir(dir).expect("removed directory");
+}
+
+#[tokio::test]
+async fn cache_evicts_least_recently_used() {
+	let dir =
+		std::env::temp_dir().join(format!("conduwuit-media-cache-test-{}", std::process::id()));
+	let (remote_dir, cache_dir) = (dir.join("remote"), dir.join("cache"));
+	let remote = Box::new(Filesystem::with_dir(remote_dir, false));
+	let cache = Filesystem::with_dir(cache_dir.clone(), false);
+	let cached_path = |key: &[u8]| Filesystem::with_dir(cache_dir.clone(), false).path(key);
+	let storage = Cached::new(remote, cache, 10);
+
+	storage.init().await.expect("created directories");
+	storage.put(b"a", b"aaaa").await.expect("stored a");
+	storage.put(b"b", b"bbbb").await.expect("stored b");
+	assert_eq!(storage.get(b"a").await.expect("read a"), b"aaaa");
+
+	// b is now the least recently used and makes room for c
+	storage.put(b"c", b"cccc").await.expect("stored c");
+	assert!(cached_path(b"a").exists());
+	assert!(!cached_path(b"b").exists());
+	assert!(cached_path(b"c").exists());
+
+	// larger than the whole cache, so only stored remotely
+	storage.put(b"d", &[0; 11]).await.expect("stored d");
+	assert!(!cached_path(b"d").exists());
+	assert_eq!(storage.get(b"b").await.expect("read b from remote"), b"bbbb");
+	assert_eq!(storage.get(b"d").await.expect("read d from remote"), [0; 11]);
+
+	std::fs::remove_dir_all(dir).expect("removed directories");
+}
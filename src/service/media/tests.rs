@@ -1,5 +1,17 @@
 #![cfg(test)]
 
+use ruma::{server_name, user_id, Mxc};
+
+use super::Dim;
+use crate::tests::{offline_services_with, TempDir};
+
+fn mxc(media_id: &str) -> Mxc<'_> {
+	Mxc {
+		server_name: server_name!("example.com"),
+		media_id,
+	}
+}
+
 #[tokio::test]
 #[cfg(disable)] //TODO: fixme
 async fn long_file_names_works() {
@@ -103,3 +115,77 @@ async fn long_file_names_works() {
 		r.to_str().unwrap().len()
 	);
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn quota_reserved_and_released() {
+	let dir = TempDir::new("media-quota");
+	let services = offline_services_with(&dir, "example.com", "media_user_quota = 100")
+		.await
+		.expect("started services offline");
+
+	let media = &services.media;
+	let media_dir = media.get_media_dir();
+	std::fs::create_dir_all(&media_dir).expect("created media directory");
+
+	let user = user_id!("@alice:example.com");
+	let (first, second) = (mxc("first"), mxc("second"));
+	media
+		.create(&first, Some(user), None, None, &[0_u8; 60])
+		.await
+		.expect("within the quota");
+	assert_eq!(media.usage(user).await, 60);
+
+	assert!(media
+		.create(&second, Some(user), None, None, &[0_u8; 60])
+		.await
+		.is_err());
+	assert_eq!(media.usage(user).await, 60, "refused uploads are not counted");
+
+	media.delete(&first).await.expect("deleted");
+	assert_eq!(media.usage(user).await, 0, "deleting gives the quota back");
+
+	// Files can no longer be written once the media directory is a file.
+	std::fs::remove_dir_all(&media_dir).expect("removed media directory");
+	std::fs::write(&media_dir, b"").expect("replaced media directory");
+	assert!(media
+		.create(&second, Some(user), None, None, &[0_u8; 60])
+		.await
+		.is_err());
+	assert_eq!(media.usage(user).await, 0, "failed uploads give the quota back");
+
+	services.stop_offline();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn quarantined_media_is_refused() {
+	let dir = TempDir::new("media-quarantine");
+	let services = offline_services_with(&dir, "example.com", "")
+		.await
+		.expect("started services offline");
+
+	let media = &services.media;
+	std::fs::create_dir_all(media.get_media_dir()).expect("created media directory");
+
+	let quarantined = mxc("quarantined");
+	let user = user_id!("@alice:example.com");
+	media
+		.create(&quarantined, Some(user), None, Some("text/plain"), b"hello")
+		.await
+		.expect("uploaded");
+	assert!(media.get(&quarantined).await.expect("found").is_some());
+
+	media.quarantine(&quarantined, user_id!("@admin:example.com"));
+	assert!(media.get(&quarantined).await.is_err(), "download refused");
+	assert!(
+		media
+			.get_thumbnail(&quarantined, &Dim::new(32, 32, None))
+			.await
+			.is_err(),
+		"thumbnail refused"
+	);
+
+	media.unquarantine(&quarantined);
+	assert!(media.get(&quarantined).await.expect("found").is_some());
+
+	services.stop_offline();
+}
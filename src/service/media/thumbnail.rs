@@ -7,12 +7,8 @@
 
 use std::{cmp, num::Saturating as Sat};
 
-use conduwuit::{checked, err, implement, Result};
+use conduwuit::{checked, err, implement, Err, Result};
 use ruma::{http_headers::ContentDisposition, media::Method, Mxc, UInt, UserId};
-use tokio::{
-	fs,
-	io::{AsyncReadExt, AsyncWriteExt},
-};
 
 use super::{data::Metadata, FileMeta};
 
@@ -41,10 +37,7 @@ impl super::Service {
 				.create_file_metadata(mxc, user, dim, content_disposition, content_type)?;
 
 		//TODO: Dangling metadata in database if creation fails
-		let mut f = self.create_media_file(&key).await?;
-		f.write_all(file).await?;
-
-		Ok(())
+		self.storage.put(&key, file).await
 	}
 
 	/// Downloads a file's thumbnail.
@@ -60,8 +53,14 @@ impl super::Service {
 	///
 	/// For width,height <= 96 the server uses another thumbnailing algorithm
 	/// which crops the image afterwards.
+	///
+	/// Thumbnails of quarantined media are not found.
 	#[tracing::instrument(skip(self), name = "thumbnail", level = "debug")]
 	pub async fn get_thumbnail(&self, mxc: &Mxc<'_>, dim: &Dim) -> Result<Option<FileMeta>> {
+		if self.is_quarantined(mxc).await {
+			return Err!(Request(NotFound("Media not found.")));
+		}
+
 		// 0, 0 because that's the original file
 		let dim = dim.normalized();
 
@@ -79,12 +78,7 @@ impl super::Service {
 #[implement(super::Service)]
 #[tracing::instrument(name = "saved", level = "debug", skip(self, data))]
 async fn get_thumbnail_saved(&self, data: Metadata) -> Result<Option<FileMeta>> {
-	let mut content = Vec::new();
-	let path = self.get_media_file(&data.key);
-	fs::File::open(path)
-		.await?
-		.read_to_end(&mut content)
-		.await?;
+	let content = self.storage.get(&data.key).await?;
 
 	Ok(Some(into_filemeta(data, content)))
 }
@@ -99,12 +93,7 @@ async fn get_thumbnail_generate(
 	dim: &Dim,
 	data: Metadata,
 ) -> Result<Option<FileMeta>> {
-	let mut content = Vec::new();
-	let path = self.get_media_file(&data.key);
-	fs::File::open(path)
-		.await?
-		.read_to_end(&mut content)
-		.await?;
+	let content = self.storage.get(&data.key).await?;
 
 	let Ok(image) = image::load_from_memory(&content) else {
 		// Couldn't parse file to generate thumbnail, send original
@@ -131,8 +120,7 @@ async fn get_thumbnail_generate(
 		data.content_type.as_deref(),
 	)?;
 
-	let mut f = self.create_media_file(&thumbnail_key).await?;
-	f.write_all(&thumbnail_bytes).await?;
+	self.storage.put(&thumbnail_key, &thumbnail_bytes).await?;
 
 	Ok(Some(into_filemeta(data, thumbnail_bytes)))
 }
@@ -5,21 +5,23 @@ use conduwuit::{
 	result::NotFound,
 	utils::{
 		stream::{TryExpect, TryIgnore},
-		IterStream, ReadyExt,
+		u64_from_u8x8, IterStream, ReadyExt,
 	},
-	warn, Err, Result,
+	warn, Err, PduEvent, Result,
 };
 use futures::{FutureExt, StreamExt};
 use itertools::Itertools;
 use ruma::{
 	events::{
 		push_rules::PushRulesEvent, room::member::MembershipState, GlobalAccountDataEventType,
+		TimelineEventType,
 	},
 	push::Ruleset,
 	OwnedUserId, RoomId, UserId,
 };
+use serde::Deserialize;
 
-use crate::{media, Services};
+use crate::{media, rooms::timeline::RawPduId, user_directory::Entry, Services};
 
 /// The current schema version.
 /// - If database is opened at greater version we reject with error. The
@@ -73,6 +75,8 @@ async fn fresh(services: &Services) -> Result<()> {
 	db["global"].insert(b"retroactively_fix_bad_data_from_roomuserid_joined", []);
 	db["global"].insert(b"fix_referencedevents_missing_sep", []);
 	db["global"].insert(b"fix_readreceiptid_readreceipt_duplicates", []);
+	db["global"].insert(b"feat_ranked_search_index", []);
+	db["global"].insert(b"feat_user_directory_index", []);
 
 	// Create the admin room and server user on first run
 	crate::admin::create_admin_room(services).boxed().await?;
@@ -106,7 +110,7 @@ async fn migrate(services: &Services) -> Result<()> {
 
 	if db["global"].get(b"feat_sha256_media").await.is_not_found() {
 		media::migrations::migrate_sha256_media(services).await?;
-	} else if config.media_startup_check {
+	} else if config.media_startup_check && config.media_storage_provider == "filesystem" {
 		media::migrations::checkup_sha256_media(services).await?;
 	}
 
@@ -142,6 +146,22 @@ async fn migrate(services: &Services) -> Result<()> {
 		fix_readreceiptid_readreceipt_duplicates(services).await?;
 	}
 
+	if db["global"]
+		.get(b"feat_ranked_search_index")
+		.await
+		.is_not_found()
+	{
+		rebuild_search_index(services).await?;
+	}
+
+	if db["global"]
+		.get(b"feat_user_directory_index")
+		.await
+		.is_not_found()
+	{
+		rebuild_user_directory(services).await?;
+	}
+
 	let version_match = services.globals.db.database_version().await == DATABASE_VERSION
 		|| services.globals.db.database_version().await == CONDUIT_DATABASE_VERSION;
 
@@ -563,3 +583,121 @@ async fn fix_readreceiptid_readreceipt_duplicates(services: &Services) -> Result
 	db["global"].insert(b"fix_readreceiptid_readreceipt_duplicates", []);
 	db.db.sort()
 }
+
+async fn rebuild_search_index(services: &Services) -> Result {
+	#[derive(Deserialize)]
+	struct ExtractBody {
+		body: Option<String>,
+	}
+
+	warn!("Rebuilding the search index with word positions and frequencies...");
+
+	let db = &services.db;
+	let cork = db.cork_and_sync();
+
+	for map in ["tokenids", "shortroomid_searchstats"] {
+		let map = db[map].clone();
+		map.raw_keys()
+			.ignore_err()
+			.ready_for_each(|key| map.remove(key))
+			.await;
+	}
+
+	let indexed = db["pduid_pdu"]
+		.raw_stream()
+		.ignore_err()
+		.ready_filter_map(|(key, val)| {
+			let pdu: PduEvent = serde_json::from_slice(val).ok()?;
+			if pdu.kind != TimelineEventType::RoomMessage {
+				return None;
+			}
+
+			let body = pdu.get_content::<ExtractBody>().ok()?.body?;
+			Some((RawPduId::from(key), body))
+		})
+		.ready_fold(0_usize, |indexed, (pdu_id, body)| {
+			let shortroomid = u64_from_u8x8(pdu_id.shortroomid());
+			services.rooms.search.index_pdu(shortroomid, &pdu_id, &body);
+
+			indexed.saturating_add(1)
+		})
+		.await;
+
+	drop(cork);
+	info!(?indexed, "Rebuilt the search index.");
+
+	db["global"].insert(b"feat_ranked_search_index", []);
+	db.db.sort()
+}
+
+async fn rebuild_user_directory(services: &Services) -> Result {
+	warn!("Building the user directory index...");
+
+	let db = &services.db;
+	let cork = db.cork_and_sync();
+
+	for map in ["tokenuserid", "userid_directoryentry"] {
+		let map = db[map].clone();
+		map.raw_keys()
+			.ignore_err()
+			.ready_for_each(|key| map.remove(key))
+			.await;
+	}
+
+	let users: Vec<OwnedUserId> = services
+		.users
+		.stream()
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	let mut indexed = 0_usize;
+	for user_id in users {
+		let entry = if services.globals.user_is_local(&user_id) {
+			if !services.users.is_active(&user_id).await {
+				continue;
+			}
+
+			Entry {
+				displayname: services.users.displayname(&user_id).await.ok(),
+				avatar_url: services.users.avatar_url(&user_id).await.ok(),
+			}
+		} else {
+			// Remote users are shown as in a room shared with a local user
+			let Some(room_id) = services
+				.rooms
+				.state_cache
+				.rooms_joined(&user_id)
+				.map(ToOwned::to_owned)
+				.boxed()
+				.next()
+				.await
+			else {
+				continue;
+			};
+
+			let Ok(member) = services
+				.rooms
+				.state_accessor
+				.get_member(&room_id, &user_id)
+				.await
+			else {
+				continue;
+			};
+
+			Entry {
+				displayname: member.displayname,
+				avatar_url: member.avatar_url,
+			}
+		};
+
+		services.user_directory.set_entry(&user_id, &entry);
+		indexed = indexed.saturating_add(1);
+	}
+
+	drop(cork);
+	info!(?indexed, "Built the user directory index.");
+
+	db["global"].insert(b"feat_user_directory_index", []);
+	db.db.sort()
+}
@@ -4,25 +4,39 @@ mod manager;
 mod migrations;
 mod service;
 pub mod services;
+mod tests;
 
 pub mod account_data;
 pub mod admin;
 pub mod appservice;
 pub mod client;
+pub mod delayed_events;
+pub mod email;
 pub mod emergency;
 pub mod globals;
 pub mod key_backups;
+pub mod livekit;
 pub mod media;
+pub mod password;
+pub mod policy_lists;
 pub mod presence;
 pub mod pusher;
+pub mod ratelimit;
+pub mod registration_tokens;
+pub mod reports;
 pub mod resolver;
 pub mod rooms;
 pub mod sending;
 pub mod server_keys;
+pub mod server_notices;
+pub mod sso;
+#[cfg(feature = "synapse_import")]
+pub mod synapse;
 pub mod sync;
 pub mod transaction_ids;
 pub mod uiaa;
 pub mod updates;
+pub mod user_directory;
 pub mod users;
 
 extern crate conduwuit_core as conduwuit;
@@ -0,0 +1,105 @@
+use async_trait::async_trait;
+use conduwuit::{config::LdapConfig, debug, err, Err, Result};
+use ldap3::{ldap_escape, LdapConnAsync, LdapResult, Scope, SearchEntry, SearchResult};
+use ruma::UserId;
+
+use super::{Outcome, Profile, Provider};
+
+/// Checks passwords by searching the entry of the user in an LDAP directory
+/// and binding as it.
+pub(super) struct Ldap {
+	config: LdapConfig,
+}
+
+/// Result code of a bind with a wrong password.
+const INVALID_CREDENTIALS: u32 = 49;
+
+impl Ldap {
+	pub(super) fn new(config: &LdapConfig) -> Self { Self { config: config.clone() } }
+}
+
+#[async_trait]
+impl Provider for Ldap {
+	fn name(&self) -> &str { "ldap" }
+
+	fn handles(&self, user_id: &UserId) -> bool {
+		!self
+			.config
+			.local_password_users
+			.iter()
+			.any(|local| local == user_id)
+	}
+
+	fn local_fallback(&self) -> bool { self.config.local_password_fallback }
+
+	fn create_users(&self) -> bool { self.config.auto_create_users }
+
+	async fn authenticate(&self, user_id: &UserId, password: &str) -> Result<Outcome> {
+		let config = &self.config;
+		let uri = config
+			.uri
+			.as_ref()
+			.ok_or_else(|| err!(Config("ldap.uri", "LDAP server URI is not set")))?;
+		let base_dn = config
+			.base_dn
+			.as_deref()
+			.ok_or_else(|| err!(Config("ldap.base_dn", "LDAP base DN is not set")))?;
+
+		let (conn, mut ldap) = LdapConnAsync::new(uri.as_str())
+			.await
+			.map_err(|e| err!("Failed to connect to {uri}: {e}"))?;
+
+		ldap3::drive!(conn);
+
+		if let Some(bind_dn) = &config.bind_dn {
+			let bind_password = config.bind_password.as_deref().unwrap_or_default();
+			ldap.simple_bind(bind_dn, bind_password)
+				.await
+				.and_then(LdapResult::success)
+				.map_err(|e| err!("Failed to bind as {bind_dn}: {e}"))?;
+		}
+
+		let filter = config
+			.filter
+			.replace("{username}", &ldap_escape(user_id.localpart()));
+		let attrs = [config.name_attribute.as_str(), config.mail_attribute.as_str()];
+		let (entries, _) = ldap
+			.search(base_dn, Scope::Subtree, &filter, attrs)
+			.await
+			.and_then(SearchResult::success)
+			.map_err(|e| err!("Failed to search {filter:?}: {e}"))?;
+
+		let mut entries = entries.into_iter();
+		let (Some(entry), None) = (entries.next(), entries.next()) else {
+			debug!(%user_id, %filter, "No single entry matches the user");
+			ldap.unbind().await.ok();
+			return Ok(Outcome::Unknown);
+		};
+
+		let mut entry = SearchEntry::construct(entry);
+		let result = ldap
+			.simple_bind(&entry.dn, password)
+			.await
+			.map_err(|e| err!("Failed to bind as {}: {e}", entry.dn))?;
+
+		ldap.unbind().await.ok();
+
+		match result.rc {
+			| 0 => {},
+			| INVALID_CREDENTIALS => return Ok(Outcome::Rejected),
+			| rc => return Err!("Failed to bind as {}: code {rc}: {}", entry.dn, result.text),
+		}
+
+		let mut attr = |name: &str| {
+			entry
+				.attrs
+				.remove(name)
+				.and_then(|values| values.into_iter().next())
+		};
+
+		Ok(Outcome::Accepted(Profile {
+			displayname: attr(&config.name_attribute),
+			email: attr(&config.mail_attribute),
+		}))
+	}
+}
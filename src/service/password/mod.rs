@@ -0,0 +1,238 @@
+#[cfg(feature = "ldap")]
+mod ldap;
+mod tests;
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use conduwuit::{debug, err, info, utils, utils::hash, warn, Err, Result, Server};
+use ruma::{
+	events::{
+		push_rules::{PushRulesEvent, PushRulesEventContent},
+		GlobalAccountDataEventType,
+	},
+	push::Ruleset,
+	thirdparty::Medium,
+	UserId,
+};
+
+use crate::{account_data, users, Dep};
+
+/// Checks user passwords against external providers, such as an LDAP
+/// directory, before the local password hash.
+pub struct Service {
+	providers: Vec<Box<dyn Provider>>,
+	services: Services,
+}
+
+struct Services {
+	account_data: Dep<account_data::Service>,
+	users: Dep<users::Service>,
+}
+
+/// A backend which can check the password of a user.
+#[async_trait]
+pub trait Provider: Send + Sync {
+	fn name(&self) -> &str;
+
+	/// Whether the user authenticates with this provider. Users it doesn't
+	/// handle are checked against their local password only.
+	fn handles(&self, user_id: &UserId) -> bool;
+
+	/// Whether the local password is checked when the provider doesn't know
+	/// the user or can't be reached.
+	fn local_fallback(&self) -> bool;
+
+	/// Whether an account is created for users it accepts who have none.
+	fn create_users(&self) -> bool;
+
+	async fn authenticate(&self, user_id: &UserId, password: &str) -> Result<Outcome>;
+}
+
+pub enum Outcome {
+	/// The provider has no such user.
+	Unknown,
+
+	/// The password is wrong.
+	Rejected,
+
+	/// The password is correct.
+	Accepted(Profile),
+}
+
+/// Attributes of a user from a provider, used when creating its account.
+#[derive(Debug, Default)]
+pub struct Profile {
+	pub displayname: Option<String>,
+	pub email: Option<String>,
+}
+
+/// Length of the unusable local password of users created by a provider, so
+/// they are not considered deactivated.
+const RANDOM_PASSWORD_LENGTH: usize = 64;
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			providers: providers(args.server)?,
+			services: Services {
+				account_data: args.depend::<account_data::Service>("account_data"),
+				users: args.depend::<users::Service>("users"),
+			},
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl Service {
+	/// Checks the password of a local user against the providers which handle
+	/// it, and then against its local password if none of them decided.
+	/// Creates the account of a user accepted by a provider if allowed.
+	pub async fn authenticate(&self, user_id: &UserId, password: &str) -> Result {
+		if password.is_empty() {
+			return Err!(Request(Forbidden("Wrong username or password.")));
+		}
+
+		let exists = self.services.users.exists(user_id).await;
+		if exists && self.services.users.is_deactivated(user_id).await? {
+			return Err!(Request(UserDeactivated("The user has been deactivated")));
+		}
+
+		if let Decision::Accepted(provider, profile) =
+			decide(&self.providers, user_id, password, exists).await?
+		{
+			if !exists {
+				self.create_user(user_id, provider, profile).await?;
+			}
+
+			return Ok(());
+		}
+
+		let hash = self
+			.services
+			.users
+			.password_hash(user_id)
+			.await
+			.map_err(|_| err!(Request(Forbidden("Wrong username or password."))))?;
+
+		if hash::verify_password(password, &hash).is_err() {
+			return Err!(Request(Forbidden("Wrong username or password.")));
+		}
+
+		if hash::is_legacy(&hash) {
+			debug!(%user_id, "Rehashing imported password");
+			self.services.users.set_password(user_id, Some(password))?;
+		}
+
+		Ok(())
+	}
+
+	async fn create_user(
+		&self,
+		user_id: &UserId,
+		provider: &dyn Provider,
+		profile: Profile,
+	) -> Result {
+		let password = utils::random_string(RANDOM_PASSWORD_LENGTH);
+		self.services.users.create(user_id, Some(&password))?;
+
+		let displayname = profile
+			.displayname
+			.unwrap_or_else(|| user_id.localpart().to_owned());
+
+		self.services
+			.users
+			.set_displayname(user_id, Some(displayname));
+
+		self.services
+			.account_data
+			.update(
+				None,
+				user_id,
+				GlobalAccountDataEventType::PushRules.to_string().into(),
+				&serde_json::to_value(PushRulesEvent {
+					content: PushRulesEventContent { global: Ruleset::server_default(user_id) },
+				})
+				.expect("to json always works"),
+			)
+			.await?;
+
+		if let Some(email) = profile.email {
+			self.services
+				.users
+				.add_threepid(user_id, &Medium::Email, &email.to_lowercase());
+		}
+
+		info!(%user_id, provider = provider.name(), "Created account for user of password provider");
+
+		Ok(())
+	}
+}
+
+/// What the providers handling a user made of its password.
+enum Decision<'a> {
+	/// A provider accepted the password.
+	Accepted(&'a dyn Provider, Profile),
+
+	/// No provider decided, so the local password is checked.
+	Local,
+}
+
+/// Asks the providers handling a user about its password, in order, until one
+/// decides. Fails if the password is rejected, if the user has no account and
+/// the accepting provider doesn't create one, or if no provider decided and
+/// the local password may not be used.
+async fn decide<'a>(
+	providers: &'a [Box<dyn Provider>],
+	user_id: &UserId,
+	password: &str,
+	exists: bool,
+) -> Result<Decision<'a>> {
+	let mut local = true;
+	for provider in providers.iter().filter(|p| p.handles(user_id)) {
+		local &= provider.local_fallback();
+		match provider.authenticate(user_id, password).await {
+			| Ok(Outcome::Accepted(profile)) => {
+				debug!(%user_id, provider = provider.name(), "Password accepted");
+				if !exists && !provider.create_users() {
+					return Err!(Request(Forbidden("{user_id} has no account on this server.")));
+				}
+
+				return Ok(Decision::Accepted(provider.as_ref(), profile));
+			},
+			| Ok(Outcome::Rejected) => {
+				return Err!(Request(Forbidden("Wrong username or password.")));
+			},
+			| Ok(Outcome::Unknown) => continue,
+			| Err(e) => {
+				warn!(%user_id, provider = provider.name(), "Failed to check password: {e}");
+				if !provider.local_fallback() {
+					return Err!(Request(Unknown("Password provider is unavailable.")));
+				}
+			},
+		}
+	}
+
+	if !local {
+		return Err!(Request(Forbidden("Wrong username or password.")));
+	}
+
+	Ok(Decision::Local)
+}
+
+#[allow(clippy::unnecessary_wraps)]
+fn providers(server: &Server) -> Result<Vec<Box<dyn Provider>>> {
+	#[allow(unused_mut)]
+	let mut providers: Vec<Box<dyn Provider>> = Vec::new();
+
+	if server.config.ldap.enable {
+		#[cfg(feature = "ldap")]
+		providers.push(Box::new(ldap::Ldap::new(&server.config.ldap)));
+
+		#[cfg(not(feature = "ldap"))]
+		return Err!(Config("ldap.enable", "conduwuit was built without LDAP support"));
+	}
+
+	Ok(providers)
+}
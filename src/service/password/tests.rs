@@ -0,0 +1,158 @@
+#![cfg(test)]
+
+use async_trait::async_trait;
+use conduwuit::{err, Result};
+use ruma::{user_id, UserId};
+
+use super::{decide, Decision, Outcome, Profile, Provider};
+
+#[derive(Clone, Copy)]
+enum Answer {
+	Unknown,
+	Rejected,
+	Accepted,
+	Unreachable,
+}
+
+struct Mock {
+	name: &'static str,
+	answer: Answer,
+	local_fallback: bool,
+	create_users: bool,
+	local_users: &'static [&'static str],
+}
+
+fn mock(name: &'static str, answer: Answer) -> Mock {
+	Mock {
+		name,
+		answer,
+		local_fallback: false,
+		create_users: false,
+		local_users: &[],
+	}
+}
+
+impl Mock {
+	fn boxed(name: &'static str, answer: Answer) -> Box<dyn Provider> {
+		Box::new(mock(name, answer))
+	}
+}
+
+#[async_trait]
+impl Provider for Mock {
+	fn name(&self) -> &str { self.name }
+
+	fn handles(&self, user_id: &UserId) -> bool { !self.local_users.contains(&user_id.as_str()) }
+
+	fn local_fallback(&self) -> bool { self.local_fallback }
+
+	fn create_users(&self) -> bool { self.create_users }
+
+	async fn authenticate(&self, _user_id: &UserId, _password: &str) -> Result<Outcome> {
+		match self.answer {
+			| Answer::Unknown => Ok(Outcome::Unknown),
+			| Answer::Rejected => Ok(Outcome::Rejected),
+			| Answer::Accepted => Ok(Outcome::Accepted(Profile::default())),
+			| Answer::Unreachable => Err(err!("directory unreachable")),
+		}
+	}
+}
+
+/// Name of the provider which accepted the password, or `None` if the local
+/// password is to be checked.
+async fn decided(providers: &[Box<dyn Provider>], exists: bool) -> Result<Option<String>> {
+	let user_id = user_id!("@alice:example.com");
+	Ok(match decide(providers, user_id, "secret", exists).await? {
+		| Decision::Accepted(provider, _) => Some(provider.name().to_owned()),
+		| Decision::Local => None,
+	})
+}
+
+#[tokio::test]
+async fn first_deciding_provider_wins() {
+	let providers = [Mock::boxed("a", Answer::Unknown), Mock::boxed("b", Answer::Accepted)];
+	assert_eq!(
+		decided(&providers, true)
+			.await
+			.expect("accepted")
+			.as_deref(),
+		Some("b")
+	);
+
+	let providers = [Mock::boxed("a", Answer::Rejected), Mock::boxed("b", Answer::Accepted)];
+	assert!(decided(&providers, true).await.is_err(), "a rejection is final");
+
+	let providers = [Mock::boxed("a", Answer::Accepted), Mock::boxed("b", Answer::Rejected)];
+	assert_eq!(
+		decided(&providers, true)
+			.await
+			.expect("accepted")
+			.as_deref(),
+		Some("a")
+	);
+}
+
+#[tokio::test]
+async fn local_password_fallback() {
+	let providers = [Mock::boxed("a", Answer::Unknown)];
+	assert!(decided(&providers, true).await.is_err(), "unknown users are refused");
+
+	let providers = [Mock::boxed("a", Answer::Unreachable)];
+	assert!(decided(&providers, true).await.is_err(), "an unreachable provider refuses");
+
+	for answer in [Answer::Unknown, Answer::Unreachable] {
+		let providers: [Box<dyn Provider>; 1] = [Box::new(Mock {
+			local_fallback: true,
+			..mock("a", answer)
+		})];
+		assert_eq!(decided(&providers, true).await.expect("local password"), None);
+	}
+
+	let providers: [Box<dyn Provider>; 0] = [];
+	assert_eq!(decided(&providers, true).await.expect("no providers"), None);
+}
+
+#[tokio::test]
+async fn local_password_users() {
+	let providers: [Box<dyn Provider>; 1] = [Box::new(Mock {
+		local_users: &["@alice:example.com"],
+		..mock("a", Answer::Rejected)
+	})];
+
+	assert_eq!(
+		decided(&providers, true).await.expect("local password"),
+		None,
+		"providers which don't handle the user are skipped"
+	);
+}
+
+#[tokio::test]
+async fn create_users_refusal() {
+	let refusing = [Mock::boxed("a", Answer::Accepted)];
+	assert!(decided(&refusing, false).await.is_err(), "no account is created");
+	assert!(decided(&refusing, true).await.is_ok(), "existing accounts may log in");
+
+	let creating: [Box<dyn Provider>; 1] = [Box::new(Mock {
+		create_users: true,
+		..mock("a", Answer::Accepted)
+	})];
+	assert_eq!(decided(&creating, false).await.expect("created").as_deref(), Some("a"));
+}
+
+#[cfg(feature = "ldap")]
+#[test]
+fn ldap_local_password_users() {
+	use conduwuit::config::LdapConfig;
+
+	let ldap = super::ldap::Ldap::new(&LdapConfig {
+		local_password_fallback: true,
+		auto_create_users: true,
+		local_password_users: vec![user_id!("@bot:example.com").to_owned()],
+		..LdapConfig::default()
+	});
+
+	assert!(!ldap.handles(user_id!("@bot:example.com")));
+	assert!(ldap.handles(user_id!("@alice:example.com")));
+	assert!(ldap.local_fallback());
+	assert!(ldap.create_users());
+}
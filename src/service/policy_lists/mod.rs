@@ -0,0 +1,482 @@
+mod tests;
+
+use std::{
+	collections::{HashMap, HashSet},
+	str::Chars,
+	sync::{Arc, RwLock},
+};
+
+use async_trait::async_trait;
+use conduwuit::{
+	debug, info, pdu::PduBuilder, utils::stream::TryIgnore, warn, PduEvent, Result, Server,
+};
+use database::Map;
+use futures::{Stream, StreamExt};
+use loole::{Receiver, Sender};
+use ruma::{
+	events::{
+		room::{
+			power_levels::{RoomPowerLevels, RoomPowerLevelsEventContent},
+			redaction::RoomRedactionEventContent,
+		},
+		StateEventType, TimelineEventType,
+	},
+	OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, ServerName, UserId,
+};
+use serde::Deserialize;
+
+use crate::{globals, rooms, Dep};
+
+/// Moderation policy lists (MSC2313) the server is subscribed to. The ban
+/// recommendations of the subscribed policy rooms are kept in memory, updated
+/// as their rule events arrive, and enforced on users, rooms and servers.
+pub struct Service {
+	rules: RwLock<Rules>,
+	redact_channel: (Sender<Redaction>, Receiver<Redaction>),
+	db: Data,
+	services: Services,
+}
+
+struct Services {
+	server: Arc<Server>,
+	globals: Dep<globals::Service>,
+	state: Dep<rooms::state::Service>,
+	state_accessor: Dep<rooms::state_accessor::Service>,
+	state_cache: Dep<rooms::state_cache::Service>,
+	timeline: Dep<rooms::timeline::Service>,
+}
+
+struct Data {
+	policyroomids: Arc<Map>,
+}
+
+/// Ban rules of the subscribed policy rooms, indexed for lookups by entity.
+#[derive(Default)]
+struct Rules {
+	rules: HashMap<RuleKey, Rule>,
+
+	/// Rules for a single entity, by kind and entity.
+	literal: HashMap<Kind, HashMap<String, HashSet<RuleKey>>>,
+
+	/// Rules with globs in their entity, by kind, which are matched in turn.
+	globs: HashMap<Kind, HashSet<RuleKey>>,
+}
+
+/// Policy room, kind and state key of a rule event, which a later event of
+/// the same key replaces.
+type RuleKey = (OwnedRoomId, Kind, String);
+
+/// An event to redact, and its room.
+type Redaction = (OwnedRoomId, OwnedEventId);
+
+/// A ban recommended by a policy room.
+#[derive(Clone, Debug)]
+pub struct Rule {
+	pub policy_room: OwnedRoomId,
+	pub kind: Kind,
+
+	/// User ID, room ID or server name, with `*` and `?` globs.
+	pub entity: String,
+
+	pub reason: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Kind {
+	User,
+	Room,
+	Server,
+}
+
+#[derive(Deserialize)]
+struct RuleContent {
+	entity: Option<String>,
+	recommendation: Option<String>,
+	reason: Option<String>,
+}
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			rules: RwLock::new(Rules::default()),
+			redact_channel: loole::unbounded(),
+			db: Data {
+				policyroomids: args.db["policyroomids"].clone(),
+			},
+			services: Services {
+				server: args.server.clone(),
+				globals: args.depend::<globals::Service>("globals"),
+				state: args.depend::<rooms::state::Service>("rooms::state"),
+				state_accessor: args
+					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
+				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
+				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
+			},
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result<()> {
+		let policy_rooms: Vec<OwnedRoomId> = self.rooms().map(ToOwned::to_owned).collect().await;
+
+		for room_id in &policy_rooms {
+			self.load(room_id).await;
+		}
+
+		debug!(
+			"Loaded {} policy rules from {} policy rooms",
+			self.rules.read().expect("locked").rules.len(),
+			policy_rooms.len()
+		);
+
+		let receiver = self.redact_channel.1.clone();
+		while let Ok((room_id, event_id)) = receiver.recv_async().await {
+			if let Err(e) = self.redact(&room_id, &event_id).await {
+				warn!("Failed to redact {event_id} of a banned user in {room_id}: {e}");
+			}
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) {
+		let (redact_sender, _) = &self.redact_channel;
+		if !redact_sender.is_closed() {
+			redact_sender.close();
+		}
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl Service {
+	/// Subscribes the server to a policy room it is in, enforcing its rules.
+	pub async fn subscribe(&self, room_id: &RoomId) {
+		self.db.policyroomids.insert(room_id, []);
+		self.load(room_id).await;
+	}
+
+	/// Unsubscribes the server from a policy room, dropping its rules.
+	pub fn unsubscribe(&self, room_id: &RoomId) {
+		self.db.policyroomids.remove(room_id);
+		let mut rules = self.rules.write().expect("locked");
+		let keys: Vec<RuleKey> = rules
+			.rules
+			.keys()
+			.filter(|(policy_room, ..)| policy_room == room_id)
+			.cloned()
+			.collect();
+
+		for key in &keys {
+			rules.remove(key);
+		}
+	}
+
+	/// Unsubscribes from a policy room being purged. Returns the number of
+	/// rows removed.
+	pub async fn purge_room(&self, room_id: &RoomId) -> usize {
+		if !self.is_subscribed(room_id).await {
+			return 0;
+		}
+
+		self.unsubscribe(room_id);
+		1
+	}
+
+	pub async fn is_subscribed(&self, room_id: &RoomId) -> bool {
+		self.db.policyroomids.get(room_id).await.is_ok()
+	}
+
+	/// The policy rooms the server is subscribed to.
+	pub fn rooms(&self) -> impl Stream<Item = &RoomId> + Send + '_ {
+		self.db.policyroomids.keys().ignore_err()
+	}
+
+	/// Number of ban rules of a policy room.
+	#[must_use]
+	pub fn count(&self, policy_room: &RoomId) -> usize {
+		self.rules
+			.read()
+			.expect("locked")
+			.rules
+			.keys()
+			.filter(|(room_id, ..)| room_id == policy_room)
+			.count()
+	}
+
+	/// The rules of the subscribed policy rooms matching a user ID, room ID or
+	/// server name.
+	#[must_use]
+	pub fn rules_matching(&self, entity: &str) -> Vec<Rule> {
+		self.rules
+			.read()
+			.expect("locked")
+			.rules
+			.values()
+			.filter(|rule| glob_match(&rule.entity, entity))
+			.cloned()
+			.collect()
+	}
+
+	/// The rule banning a user, either by their user ID or by their server.
+	#[must_use]
+	pub fn user_ban(&self, user_id: &UserId) -> Option<Rule> {
+		self.ban(Kind::User, user_id.as_str())
+			.or_else(|| self.server_ban(user_id.server_name()))
+	}
+
+	#[must_use]
+	pub fn room_ban(&self, room_id: &RoomId) -> Option<Rule> {
+		self.ban(Kind::Room, room_id.as_str())
+	}
+
+	#[must_use]
+	pub fn server_ban(&self, server_name: &ServerName) -> Option<Rule> {
+		self.ban(Kind::Server, server_name.as_str())
+	}
+
+	/// Keeps the rules up to date with an event added to the timeline of a
+	/// room, and has events of banned users redacted if `policy_lists_redact`
+	/// is enabled.
+	pub async fn update(&self, pdu: &PduEvent) {
+		if let Some(state_key) = &pdu.state_key {
+			if let Some(kind) = kind(&pdu.kind.to_string()) {
+				if self.is_subscribed(&pdu.room_id).await {
+					self.update_rule(&pdu.room_id, kind, state_key, pdu);
+				}
+			}
+
+			return;
+		}
+
+		if !self.services.server.config.policy_lists_redact
+			|| pdu.kind == TimelineEventType::RoomRedaction
+			|| self.user_ban(&pdu.sender).is_none()
+		{
+			return;
+		}
+
+		let (redact_sender, _) = &self.redact_channel;
+		if let Err(e) = redact_sender.send((pdu.room_id.clone(), pdu.event_id.clone())) {
+			warn!("Failed to queue redaction of {}: {e}", pdu.event_id);
+		}
+	}
+
+	/// Reads the rules of a policy room from its current state.
+	async fn load(&self, room_id: &RoomId) {
+		let Ok(state) = self
+			.services
+			.state_accessor
+			.room_state_full_pdus(room_id)
+			.await
+		else {
+			warn!("Failed to read the state of policy room {room_id}");
+			return;
+		};
+
+		for pdu in &state {
+			if let (Some(state_key), Some(kind)) = (&pdu.state_key, kind(&pdu.kind.to_string())) {
+				self.update_rule(room_id, kind, state_key, pdu);
+			}
+		}
+	}
+
+	fn update_rule(&self, room_id: &RoomId, kind: Kind, state_key: &str, pdu: &PduEvent) {
+		let key = (room_id.to_owned(), kind, state_key.to_owned());
+		let mut rules = self.rules.write().expect("locked");
+		match pdu.get_content::<RuleContent>() {
+			| Ok(RuleContent {
+				entity: Some(entity),
+				recommendation: Some(recommendation),
+				reason,
+			}) if is_ban(&recommendation) => {
+				rules.insert(key, Rule {
+					policy_room: room_id.to_owned(),
+					kind,
+					entity,
+					reason,
+				});
+			},
+			| _ => {
+				rules.remove(&key);
+			},
+		}
+	}
+
+	fn ban(&self, kind: Kind, entity: &str) -> Option<Rule> {
+		self.rules
+			.read()
+			.expect("locked")
+			.ban(kind, entity)
+			.cloned()
+	}
+
+	/// Redacts an event as the local member of its room with the highest power
+	/// level among those allowed to redact it.
+	async fn redact(&self, room_id: &RoomId, event_id: &OwnedEventId) -> Result {
+		let power_levels: RoomPowerLevels = self
+			.services
+			.state_accessor
+			.room_state_get_content::<RoomPowerLevelsEventContent>(
+				room_id,
+				&StateEventType::RoomPowerLevels,
+				"",
+			)
+			.await?
+			.into();
+
+		let members: Vec<OwnedUserId> = self
+			.services
+			.state_cache
+			.local_users_in_room(room_id)
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		let Some(redactor) = members
+			.into_iter()
+			.filter(|user_id| power_levels.user_can_redact_event_of_other(user_id))
+			.max_by_key(|user_id| power_levels.for_user(user_id))
+		else {
+			debug!("No local user in {room_id} may redact {event_id}");
+			return Ok(());
+		};
+
+		let reason = format!(
+			"The sender is banned by a policy list of {}.",
+			self.services.globals.server_name()
+		);
+
+		let state_lock = self.services.state.mutex.lock(room_id).await;
+		self.services
+			.timeline
+			.build_and_append_pdu(
+				PduBuilder {
+					redacts: Some(event_id.clone()),
+					..PduBuilder::timeline(&RoomRedactionEventContent {
+						redacts: Some(event_id.clone()),
+						reason: Some(reason),
+					})
+				},
+				&redactor,
+				room_id,
+				&state_lock,
+			)
+			.await?;
+
+		info!("Redacted {event_id} of a user banned by policy in {room_id} as {redactor}");
+
+		Ok(())
+	}
+}
+
+impl Rules {
+	fn insert(&mut self, key: RuleKey, rule: Rule) {
+		self.remove(&key);
+		if is_glob(&rule.entity) {
+			self.globs.entry(rule.kind).or_default().insert(key.clone());
+		} else {
+			self.literal
+				.entry(rule.kind)
+				.or_default()
+				.entry(rule.entity.clone())
+				.or_default()
+				.insert(key.clone());
+		}
+
+		self.rules.insert(key, rule);
+	}
+
+	fn remove(&mut self, key: &RuleKey) {
+		let Some(rule) = self.rules.remove(key) else {
+			return;
+		};
+
+		if is_glob(&rule.entity) {
+			if let Some(keys) = self.globs.get_mut(&rule.kind) {
+				keys.remove(key);
+			}
+		} else if let Some(literal) = self.literal.get_mut(&rule.kind) {
+			if let Some(keys) = literal.get_mut(&rule.entity) {
+				keys.remove(key);
+				if keys.is_empty() {
+					literal.remove(&rule.entity);
+				}
+			}
+		}
+	}
+
+	/// A rule of the kind banning the entity: one for the entity itself if
+	/// any, else the first glob matching it.
+	fn ban(&self, kind: Kind, entity: &str) -> Option<&Rule> {
+		let literal = self
+			.literal
+			.get(&kind)
+			.and_then(|literal| literal.get(entity))
+			.into_iter()
+			.flatten()
+			.find_map(|key| self.rules.get(key));
+
+		literal.or_else(|| {
+			self.globs
+				.get(&kind)
+				.into_iter()
+				.flatten()
+				.filter_map(|key| self.rules.get(key))
+				.find(|rule| glob_match(&rule.entity, entity))
+		})
+	}
+}
+
+/// The kind of entity a policy rule event is about, including the event
+/// types used before MSC2313 was merged.
+fn kind(event_type: &str) -> Option<Kind> {
+	match event_type {
+		| "m.policy.rule.user" | "m.room.rule.user" | "org.matrix.mjolnir.rule.user" =>
+			Some(Kind::User),
+		| "m.policy.rule.room" | "m.room.rule.room" | "org.matrix.mjolnir.rule.room" =>
+			Some(Kind::Room),
+		| "m.policy.rule.server" | "m.room.rule.server" | "org.matrix.mjolnir.rule.server" =>
+			Some(Kind::Server),
+		| _ => None,
+	}
+}
+
+fn is_ban(recommendation: &str) -> bool {
+	matches!(recommendation, "m.ban" | "org.matrix.mjolnir.ban")
+}
+
+fn is_glob(entity: &str) -> bool { entity.contains(['*', '?']) }
+
+/// Matches a glob where `*` stands for any number of characters and `?` for
+/// exactly one.
+fn glob_match(glob: &str, s: &str) -> bool {
+	let (mut glob, mut s) = (glob.chars(), s.chars());
+
+	// The rest of the glob after the last `*`, and of `s` where it started
+	// matching
+	let mut backtrack: Option<(Chars<'_>, Chars<'_>)> = None;
+	while let Some(c) = s.clone().next() {
+		let mut rest = glob.clone();
+		match rest.next() {
+			| Some('*') => {
+				backtrack = Some((rest.clone(), s.clone()));
+				glob = rest;
+			},
+			| Some(g) if g == '?' || g == c => {
+				glob = rest;
+				s.next();
+			},
+			| _ => match &mut backtrack {
+				| Some((after_star, matched)) => {
+					matched.next();
+					glob = after_star.clone();
+					s = matched.clone();
+				},
+				| None => return false,
+			},
+		}
+	}
+
+	glob.all(|g| g == '*')
+}
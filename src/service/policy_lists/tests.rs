@@ -0,0 +1,157 @@
+#![cfg(test)]
+
+use conduwuit::pdu::PduBuilder;
+use ruma::{room_id, server_name, user_id, RoomId};
+use serde_json::{json, value::to_raw_value, Value as JsonValue};
+
+use super::{glob_match, kind, Kind};
+use crate::{
+	tests::{offline_services, TempDir},
+	Services,
+};
+
+/// Sets a rule event in a room as the server user.
+async fn set_rule(
+	services: &Services,
+	room_id: &RoomId,
+	event_type: &str,
+	key: &str,
+	content: JsonValue,
+) {
+	let state_lock = services.rooms.state.mutex.lock(room_id).await;
+	services
+		.rooms
+		.timeline
+		.build_and_append_pdu(
+			PduBuilder {
+				event_type: event_type.into(),
+				content: to_raw_value(&content).expect("valid JSON"),
+				state_key: Some(key.to_owned()),
+				..Default::default()
+			},
+			&services.globals.server_user,
+			room_id,
+			&state_lock,
+		)
+		.await
+		.expect("rule event sent");
+}
+
+#[test]
+fn globs() {
+	assert!(glob_match("@spam:example.com", "@spam:example.com"));
+	assert!(!glob_match("@spam:example.com", "@spam:example.org"));
+	assert!(glob_match("*.example.com", "matrix.example.com"));
+	assert!(!glob_match("*.example.com", "example.com"));
+	assert!(glob_match("@*:evil.com", "@anyone:evil.com"));
+	assert!(glob_match("@spam?:example.com", "@spam1:example.com"));
+	assert!(!glob_match("@spam?:example.com", "@spam:example.com"));
+	assert!(glob_match("*bad*bot*", "@a-bad-little-bot:example.com"));
+	assert!(glob_match("*", ""));
+	assert!(glob_match("@ü?:example.com", "@üñ:example.com"), "? matches one character");
+	assert!(!glob_match("@ü?:example.com", "@ü:example.com"));
+	assert!(!glob_match("", "a"));
+}
+
+#[test]
+fn rule_kinds() {
+	assert_eq!(kind("m.policy.rule.user"), Some(Kind::User));
+	assert_eq!(kind("org.matrix.mjolnir.rule.server"), Some(Kind::Server));
+	assert_eq!(kind("m.room.rule.room"), Some(Kind::Room));
+	assert_eq!(kind("m.room.member"), None);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rules_from_events() {
+	let dir = TempDir::new("policy-lists");
+	let services = offline_services(&dir, "example.com")
+		.await
+		.expect("started services offline");
+
+	let policy_lists = &services.policy_lists;
+	let room_id = services.admin.get_admin_room().await.expect("admin room");
+	let spammer = user_id!("@spam:evil.com");
+
+	set_rule(
+		&services,
+		&room_id,
+		"m.policy.rule.user",
+		"spam",
+		json!({
+			"entity": spammer,
+			"recommendation": "m.ban",
+			"reason": "spam",
+		}),
+	)
+	.await;
+	set_rule(
+		&services,
+		&room_id,
+		"org.matrix.mjolnir.rule.server",
+		"evil",
+		json!({
+			"entity": "*.evil.org",
+			"recommendation": "org.matrix.mjolnir.ban",
+		}),
+	)
+	.await;
+	set_rule(
+		&services,
+		&room_id,
+		"m.policy.rule.room",
+		"other",
+		json!({
+			"entity": "!room:evil.com",
+			"recommendation": "org.example.mute",
+		}),
+	)
+	.await;
+
+	assert!(policy_lists.user_ban(spammer).is_none(), "rules of rooms not subscribed to");
+
+	policy_lists.subscribe(&room_id).await;
+	assert_eq!(policy_lists.count(&room_id), 2, "only bans are loaded");
+
+	let rule = policy_lists.user_ban(spammer).expect("banned by user ID");
+	assert_eq!(rule.kind, Kind::User);
+	assert_eq!(rule.reason.as_deref(), Some("spam"));
+	assert!(policy_lists.user_ban(user_id!("@other:evil.com")).is_none());
+	assert!(policy_lists
+		.server_ban(server_name!("matrix.evil.org"))
+		.is_some());
+	assert_eq!(
+		policy_lists
+			.user_ban(user_id!("@anyone:matrix.evil.org"))
+			.map(|rule| rule.kind),
+		Some(Kind::Server),
+		"users are banned by their server"
+	);
+	assert!(policy_lists.room_ban(room_id!("!room:evil.com")).is_none());
+
+	set_rule(&services, &room_id, "m.policy.rule.user", "spam", json!({})).await;
+	assert!(policy_lists.user_ban(spammer).is_none(), "an empty event lifts the rule");
+
+	set_rule(
+		&services,
+		&room_id,
+		"m.policy.rule.user",
+		"spam",
+		json!({
+			"entity": "@spam*:evil.com",
+			"recommendation": "m.ban",
+		}),
+	)
+	.await;
+	assert!(policy_lists.user_ban(spammer).is_some(), "a rule replaced by a glob");
+	assert_eq!(policy_lists.count(&room_id), 2);
+	assert_eq!(policy_lists.rules_matching(spammer.as_str()).len(), 1);
+
+	policy_lists.unsubscribe(&room_id);
+	assert_eq!(policy_lists.count(&room_id), 0);
+	assert!(policy_lists.user_ban(spammer).is_none());
+	assert!(policy_lists
+		.server_ban(server_name!("matrix.evil.org"))
+		.is_none());
+
+	services.stop_offline();
+}
@@ -0,0 +1,232 @@
+mod tests;
+
+use std::fmt::Write;
+
+use conduwuit::{
+	debug, err, implement, info, utils,
+	utils::{stream::TryIgnore, ReadyExt},
+	warn, Err, Result,
+};
+use database::{Deserialized, Json};
+use futures::StreamExt;
+use ruma::{thirdparty::Medium, OwnedRoomId, OwnedUserId, UserId};
+use serde::{Deserialize, Serialize};
+
+use super::Service;
+
+/// Path of the page unsubscribe links in digests point to.
+pub const UNSUBSCRIBE_PATH: &str = "/_conduwuit/email/unsubscribe";
+
+const UNSUBSCRIBE_TOKEN_LENGTH: usize = 32;
+
+/// State of the digests of an email pusher.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Digest {
+	/// When the oldest notification not emailed yet arrived, in milliseconds.
+	pending_since: Option<u64>,
+
+	/// When a digest was last sent, in milliseconds.
+	last_sent: u64,
+
+	/// Secret of the unsubscribe link, which removes the pusher.
+	unsubscribe_token: String,
+}
+
+/// Checks that the pushkey of a new email pusher is an address of the user,
+/// and prepares its digests.
+#[implement(Service)]
+pub(super) async fn add_email_pusher(&self, sender: &UserId, address: &str) -> Result {
+	if !self.services.email.enabled() {
+		return Err!(Request(InvalidParam("Email is not enabled on this server.")));
+	}
+
+	if !self
+		.services
+		.users
+		.find_from_threepid(&Medium::Email, address)
+		.await
+		.is_ok_and(|user_id| user_id == sender)
+	{
+		return Err!(Request(InvalidParam("Email pushers must use an address of your account.")));
+	}
+
+	let key = (sender, address);
+	if self.db.senderkey_emaildigest.qry(&key).await.is_err() {
+		let digest = Digest {
+			unsubscribe_token: utils::random_string(UNSUBSCRIBE_TOKEN_LENGTH),
+			..Digest::default()
+		};
+
+		self.db.senderkey_emaildigest.put(key, Json(digest));
+	}
+
+	Ok(())
+}
+
+/// Notes a notification for an email pusher; it goes out with the next
+/// digest.
+#[implement(Service)]
+pub(super) async fn queue_email_digest(&self, user_id: &UserId, pushkey: &str) -> Result {
+	let key = (user_id, pushkey);
+	let mut digest: Digest = self
+		.db
+		.senderkey_emaildigest
+		.qry(&key)
+		.await
+		.deserialized()?;
+
+	if digest.pending_since.is_none() {
+		digest.pending_since = Some(utils::millis_since_unix_epoch());
+		self.db.senderkey_emaildigest.put(key, Json(digest));
+	}
+
+	Ok(())
+}
+
+/// Sends the digests which are due: those whose oldest notification waited
+/// for `email.notification_delay`, and whose last digest is older than
+/// `email.notification_interval`.
+#[implement(Service)]
+#[tracing::instrument(skip(self), level = "debug")]
+pub async fn send_email_digests(&self) {
+	let config = &self.services.server.config.email;
+	let delay = config.notification_delay.saturating_mul(1000);
+	let interval = config.notification_interval.saturating_mul(1000);
+	let now = utils::millis_since_unix_epoch();
+
+	let due: Vec<(OwnedUserId, String, Digest)> = self
+		.db
+		.senderkey_emaildigest
+		.stream()
+		.ignore_err()
+		.ready_filter_map(|((user_id, pushkey), digest): ((&UserId, &str), Digest)| {
+			let pending_since = digest.pending_since?;
+			(now >= pending_since.saturating_add(delay)
+				&& now >= digest.last_sent.saturating_add(interval))
+			.then(|| (user_id.to_owned(), pushkey.to_owned(), digest))
+		})
+		.collect()
+		.await;
+
+	for (user_id, pushkey, mut digest) in due {
+		if !self.services.server.running() {
+			break;
+		}
+
+		match self.send_email_digest(&user_id, &pushkey, &digest).await {
+			| Ok(true) => debug!(%user_id, "Sent notification digest"),
+			| Ok(false) => debug!(%user_id, "Notifications were read before the digest"),
+			| Err(e) => warn!(%user_id, "Failed to send notification digest: {e}"),
+		}
+
+		// A failed digest is retried after the interval, like a sent one.
+		digest.pending_since = None;
+		digest.last_sent = now;
+		self.db
+			.senderkey_emaildigest
+			.put((&user_id, pushkey.as_str()), Json(digest));
+	}
+}
+
+/// Emails the user a summary of the rooms with unread notifications. Returns
+/// false if there were none left.
+#[implement(Service)]
+async fn send_email_digest(
+	&self,
+	user_id: &UserId,
+	address: &str,
+	digest: &Digest,
+) -> Result<bool> {
+	let rooms: Vec<OwnedRoomId> = self
+		.services
+		.state_cache
+		.rooms_joined(user_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	let (mut unread, mut highlights, mut summary) = (0_u64, 0_u64, String::new());
+	for room_id in &rooms {
+		let notifications = self
+			.services
+			.user
+			.notification_count(user_id, room_id)
+			.await;
+		if notifications == 0 {
+			continue;
+		}
+
+		let highlight_count = self.services.user.highlight_count(user_id, room_id).await;
+		unread = unread.saturating_add(notifications);
+		highlights = highlights.saturating_add(highlight_count);
+
+		let name = match self.services.state_accessor.get_name(room_id).await {
+			| Ok(name) => name,
+			| Err(_) => self
+				.services
+				.state_accessor
+				.get_canonical_alias(room_id)
+				.await
+				.map_or_else(|_| room_id.to_string(), |alias| alias.to_string()),
+		};
+
+		writeln!(summary, "- {name}: {notifications} unread, {highlight_count} highlighted")?;
+	}
+
+	if unread == 0 {
+		return Ok(false);
+	}
+
+	let mut link = self.services.email.link(UNSUBSCRIBE_PATH)?;
+	link.query_pairs_mut()
+		.append_pair("user_id", user_id.as_str())
+		.append_pair("pushkey", address)
+		.append_pair("token", &digest.unsubscribe_token);
+
+	self.services
+		.email
+		.send(address, "notification_digest", &[
+			("user_id", user_id.as_str()),
+			("unread", &unread.to_string()),
+			("highlights", &highlights.to_string()),
+			("rooms", &summary),
+			("link", link.as_str()),
+		])
+		.await?;
+
+	Ok(true)
+}
+
+/// Removes an email pusher from the unsubscribe link of one of its digests.
+#[implement(Service)]
+pub async fn unsubscribe_email(&self, user_id: &UserId, pushkey: &str, token: &str) -> Result {
+	let key = (user_id, pushkey);
+	let digest: Digest = self
+		.db
+		.senderkey_emaildigest
+		.qry(&key)
+		.await
+		.deserialized()
+		.map_err(|_| err!(Request(NotFound("Unknown email pusher."))))?;
+
+	let token_matches = ring::constant_time::verify_slices_are_equal(
+		digest.unsubscribe_token.as_bytes(),
+		token.as_bytes(),
+	)
+	.is_ok();
+
+	if !token_matches {
+		return Err!(Request(Forbidden("Invalid unsubscribe token.")));
+	}
+
+	self.remove_pusher(user_id, pushkey).await;
+	info!(%user_id, "Email pusher unsubscribed");
+
+	Ok(())
+}
+
+/// Drops the digest state of a removed pusher.
+#[implement(Service)]
+pub(super) fn remove_email_digest(&self, user_id: &UserId, pushkey: &str) {
+	self.db.senderkey_emaildigest.del((user_id, pushkey));
+}
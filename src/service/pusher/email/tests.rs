@@ -0,0 +1,178 @@
+#![cfg(test)]
+
+use std::sync::Arc;
+
+use database::Deserialized;
+use ruma::{
+	api::client::{
+		error::ErrorKind,
+		push::{
+			set_pusher::v3::{PusherAction, PusherPostData},
+			Pusher,
+		},
+	},
+	owned_user_id,
+	thirdparty::Medium,
+	OwnedUserId, UserId,
+};
+use tokio::net::TcpListener;
+
+use super::Digest;
+use crate::{
+	tests::{create_room, offline_services_with, smtp_sink, TempDir},
+	Services,
+};
+
+const ADDRESS: &str = "alice@example.com";
+
+/// Services with email enabled, sending through an SMTP sink on `port`.
+async fn services(dir: &TempDir, port: u16) -> Arc<Services> {
+	let config = format!(
+		"[global.email]\nenable = true\nsmtp_url = \"smtp://127.0.0.1:{port}\"\nfrom = \
+		 \"matrix@example.com\"\nnotification_delay = 0\nnotification_interval = 0\n"
+	);
+
+	offline_services_with(dir, "example.com", &config)
+		.await
+		.expect("started services offline")
+}
+
+/// Creates a user with an email pusher sending to [`ADDRESS`].
+async fn user_with_email_pusher(services: &Services) -> OwnedUserId {
+	let user_id = owned_user_id!("@alice:example.com");
+	services
+		.users
+		.create(&user_id, Some("password"))
+		.expect("created");
+	services
+		.users
+		.add_threepid(&user_id, &Medium::Email, ADDRESS);
+
+	let pusher: Pusher = serde_json::from_value(serde_json::json!({
+		"pushkey": ADDRESS,
+		"kind": "email",
+		"app_id": "m.email",
+		"app_display_name": "Email",
+		"device_display_name": ADDRESS,
+		"lang": "en",
+		"data": {},
+	}))
+	.expect("valid pusher");
+
+	services
+		.pusher
+		.set_pusher(&user_id, &PusherAction::Post(PusherPostData { pusher, append: false }))
+		.await
+		.expect("pusher set");
+
+	user_id
+}
+
+async fn digest(services: &Services, user_id: &UserId) -> Option<Digest> {
+	services
+		.pusher
+		.db
+		.senderkey_emaildigest
+		.qry(&(user_id, ADDRESS))
+		.await
+		.deserialized()
+		.ok()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn digest_sent_once_due() {
+	let listener = TcpListener::bind("127.0.0.1:0")
+		.await
+		.expect("bound listener");
+	let port = listener.local_addr().expect("local address").port();
+
+	let dir = TempDir::new("email-digest");
+	let services = services(&dir, port).await;
+	let user_id = user_with_email_pusher(&services).await;
+
+	let room_id = create_room(&services, None).await;
+	services
+		.rooms
+		.state_cache
+		.mark_as_joined(&user_id, &room_id);
+	services.db["userroomid_notificationcount"].put((&user_id, &room_id), 2_u64);
+
+	services
+		.pusher
+		.queue_email_digest(&user_id, ADDRESS)
+		.await
+		.expect("queued");
+	assert!(digest(&services, &user_id)
+		.await
+		.is_some_and(|digest| digest.pending_since.is_some()));
+
+	let sink = tokio::spawn(smtp_sink(listener));
+	services.pusher.send_email_digests().await;
+	let data = sink.await.expect("sink finished");
+	assert!(data.contains(&format!("To: {ADDRESS}")), "{data}");
+	assert!(data.contains("2 unread"), "{data}");
+
+	let sent = digest(&services, &user_id).await.expect("digest kept");
+	assert!(sent.pending_since.is_none(), "nothing left to send");
+	assert!(sent.last_sent > 0);
+
+	// Nothing is pending, so the next check leaves the digest alone.
+	services.pusher.send_email_digests().await;
+	let unchanged = digest(&services, &user_id).await.expect("digest kept");
+	assert_eq!(unchanged.last_sent, sent.last_sent);
+
+	services.stop_offline();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn unsubscribe_removes_pusher() {
+	let dir = TempDir::new("email-unsubscribe");
+	let services = services(&dir, 1).await;
+	let user_id = user_with_email_pusher(&services).await;
+	let token = digest(&services, &user_id)
+		.await
+		.expect("digest prepared")
+		.unsubscribe_token;
+
+	let wrong = services
+		.pusher
+		.unsubscribe_email(&user_id, ADDRESS, "wrong")
+		.await;
+	assert!(wrong.is_err_and(|e| matches!(e.kind(), ErrorKind::Forbidden { .. })));
+	assert!(services.pusher.get_pusher(&user_id, ADDRESS).await.is_ok());
+
+	services
+		.pusher
+		.unsubscribe_email(&user_id, ADDRESS, &token)
+		.await
+		.expect("unsubscribed");
+	assert!(services.pusher.get_pusher(&user_id, ADDRESS).await.is_err());
+	assert!(digest(&services, &user_id).await.is_none());
+
+	let again = services
+		.pusher
+		.unsubscribe_email(&user_id, ADDRESS, &token)
+		.await;
+	assert!(again.is_err_and(|e| matches!(e.kind(), ErrorKind::NotFound)));
+
+	services.stop_offline();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn removed_address_removes_pusher() {
+	let dir = TempDir::new("email-removed");
+	let services = services(&dir, 1).await;
+	let user_id = user_with_email_pusher(&services).await;
+
+	services
+		.pusher
+		.remove_email_pusher(&user_id, "bob@example.com")
+		.await;
+	assert!(services.pusher.get_pusher(&user_id, ADDRESS).await.is_ok(), "another address");
+
+	services.pusher.remove_email_pusher(&user_id, ADDRESS).await;
+	assert!(services.pusher.get_pusher(&user_id, ADDRESS).await.is_err());
+	assert!(digest(&services, &user_id).await.is_none());
+
+	services.stop_offline();
+}
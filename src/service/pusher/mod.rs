@@ -1,10 +1,13 @@
-use std::{fmt::Debug, mem, sync::Arc};
+mod email;
 
+use std::{fmt::Debug, mem, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
 use bytes::BytesMut;
 use conduwuit::{
 	debug_warn, err, trace,
 	utils::{stream::TryIgnore, string_from_bytes},
-	warn, Err, PduEvent, Result,
+	warn, Err, PduEvent, Result, Server,
 };
 use database::{Deserialized, Ignore, Interfix, Json, Map};
 use futures::{Stream, StreamExt};
@@ -28,45 +31,85 @@ use ruma::{
 	serde::Raw,
 	uint, RoomId, UInt, UserId,
 };
+use tokio::{
+	sync::Notify,
+	time::{interval, MissedTickBehavior},
+};
 
-use crate::{client, globals, rooms, sending, users, Dep};
+pub use self::email::UNSUBSCRIBE_PATH;
+use crate::{client, email as mail, globals, rooms, sending, users, Dep};
 
 pub struct Service {
+	interrupt: Notify,
 	db: Data,
 	services: Services,
 }
 
 struct Services {
+	server: Arc<Server>,
 	globals: Dep<globals::Service>,
 	client: Dep<client::Service>,
+	email: Dep<mail::Service>,
 	state_accessor: Dep<rooms::state_accessor::Service>,
 	state_cache: Dep<rooms::state_cache::Service>,
+	user: Dep<rooms::user::Service>,
 	users: Dep<users::Service>,
 	sending: Dep<sending::Service>,
 }
 
 struct Data {
+	senderkey_emaildigest: Arc<Map>,
 	senderkey_pusher: Arc<Map>,
 }
 
+/// How often email pushers are checked for digests which are due.
+const DIGEST_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+#[async_trait]
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
+			interrupt: Notify::new(),
 			db: Data {
+				senderkey_emaildigest: args.db["senderkey_emaildigest"].clone(),
 				senderkey_pusher: args.db["senderkey_pusher"].clone(),
 			},
 			services: Services {
+				server: args.server.clone(),
 				globals: args.depend::<globals::Service>("globals"),
 				client: args.depend::<client::Service>("client"),
+				email: args.depend::<mail::Service>("email"),
 				state_accessor: args
 					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
 				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
+				user: args.depend::<rooms::user::Service>("rooms::user"),
 				users: args.depend::<users::Service>("users"),
 				sending: args.depend::<sending::Service>("sending"),
 			},
 		}))
 	}
 
+	async fn worker(self: Arc<Self>) -> Result<()> {
+		if !self.services.email.enabled() {
+			return Ok(());
+		}
+
+		let mut i = interval(DIGEST_CHECK_INTERVAL);
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		loop {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			self.send_email_digests().await;
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -122,24 +165,46 @@ impl Service {
 					}
 				}
 
+				if let PusherKind::Email(_) = pusher_kind {
+					self.add_email_pusher(sender, pushkey).await?;
+				}
+
 				let key = (sender, data.pusher.ids.pushkey.as_str());
 				self.db.senderkey_pusher.put(key, Json(pusher));
 			},
 			| set_pusher::v3::PusherAction::Delete(ids) => {
-				let key = (sender, ids.pushkey.as_str());
-				self.db.senderkey_pusher.del(key);
-
-				self.services
-					.sending
-					.cleanup_events(None, Some(sender), Some(ids.pushkey.as_str()))
-					.await
-					.ok();
+				self.remove_pusher(sender, ids.pushkey.as_str()).await;
 			},
 		}
 
 		Ok(())
 	}
 
+	pub async fn remove_pusher(&self, sender: &UserId, pushkey: &str) {
+		let key = (sender, pushkey);
+		self.db.senderkey_pusher.del(key);
+		self.remove_email_digest(sender, pushkey);
+
+		self.services
+			.sending
+			.cleanup_events(None, Some(sender), Some(pushkey))
+			.await
+			.ok();
+	}
+
+	/// Removes the email pusher of a user sending to an address which is no
+	/// longer theirs.
+	pub async fn remove_email_pusher(&self, sender: &UserId, address: &str) {
+		let is_email = self
+			.get_pusher(sender, address)
+			.await
+			.is_ok_and(|pusher| matches!(pusher.kind, PusherKind::Email(_)));
+
+		if is_email {
+			self.remove_pusher(sender, address).await;
+		}
+	}
+
 	pub async fn get_pusher(&self, sender: &UserId, pushkey: &str) -> Result<Pusher> {
 		let senderkey = (sender, pushkey);
 		self.db
@@ -304,7 +369,7 @@ impl Service {
 		}
 
 		if notify == Some(true) {
-			self.send_notice(unread, pusher, tweaks, pdu).await?;
+			self.send_notice(user, unread, pusher, tweaks, pdu).await?;
 		}
 		// Else the event triggered no actions
 
@@ -353,15 +418,15 @@ impl Service {
 		ruleset.get_actions(pdu, &ctx)
 	}
 
-	#[tracing::instrument(skip(self, unread, pusher, tweaks, event))]
+	#[tracing::instrument(skip(self, user, unread, pusher, tweaks, event))]
 	async fn send_notice(
 		&self,
+		user: &UserId,
 		unread: UInt,
 		pusher: &Pusher,
 		tweaks: Vec<Tweak>,
 		event: &PduEvent,
 	) -> Result {
-		// TODO: email
 		match &pusher.kind {
 			| PusherKind::Http(http) => {
 				let url = &http.url;
@@ -471,8 +536,7 @@ impl Service {
 
 				Ok(())
 			},
-			// TODO: Handle email
-			//PusherKind::Email(_) => Ok(()),
+			| PusherKind::Email(_) => self.queue_email_digest(user, &pusher.ids.pushkey).await,
 			| _ => Ok(()),
 		}
 	}
@@ -0,0 +1,198 @@
+mod tests;
+
+use std::{
+	collections::HashMap,
+	fmt::Write,
+	net::IpAddr,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use conduwuit::{debug, http::StatusCode, utils::bytes::pretty, Error, Result, Server};
+use ruma::{
+	api::client::error::{ErrorKind, RetryAfter},
+	OwnedDeviceId, OwnedUserId, UserId,
+};
+use tokio::{
+	sync::Notify,
+	time::{interval, MissedTickBehavior},
+};
+
+use crate::{users, Dep};
+
+pub struct Service {
+	buckets: Mutex<HashMap<(Kind, Key), Bucket>>,
+	interrupt: Notify,
+	services: Services,
+}
+
+struct Services {
+	server: Arc<Server>,
+	users: Dep<users::Service>,
+}
+
+/// Kinds of requests with separate buckets and limits.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Kind {
+	Login,
+	Register,
+	Message,
+	Join,
+	Invite,
+	MediaUpload,
+	EmailToken,
+	Livekit,
+}
+
+/// Identity a bucket is kept for.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Key {
+	Ip(IpAddr),
+	User(OwnedUserId),
+	Device(OwnedUserId, OwnedDeviceId),
+	Email(String),
+}
+
+struct Bucket {
+	tokens: f64,
+	updated: Instant,
+}
+
+/// How often buckets which have refilled completely are dropped.
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			buckets: Mutex::new(HashMap::new()),
+			interrupt: Notify::new(),
+			services: Services {
+				server: args.server.clone(),
+				users: args.depend::<users::Service>("users"),
+			},
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result<()> {
+		if !self.services.server.config.ratelimit {
+			return Ok(());
+		}
+
+		let mut i = interval(CLEANUP_INTERVAL);
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		loop {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			self.cleanup();
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn memory_usage(&self, out: &mut dyn Write) -> Result {
+		let buckets = self.buckets.lock()?.len();
+		let bytes = buckets.saturating_mul(size_of::<((Kind, Key), Bucket)>());
+
+		writeln!(out, "ratelimit_buckets: {buckets} ({})", pretty(bytes))?;
+
+		Ok(())
+	}
+
+	fn clear_cache(&self) { self.buckets.lock().expect("locked").clear(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl Service {
+	/// Takes a token from the bucket of `kind` for `key`, or returns
+	/// M_LIMIT_EXCEEDED with the time until a token becomes available.
+	pub fn check(&self, kind: Kind, key: Key) -> Result {
+		if !self.services.server.config.ratelimit {
+			return Ok(());
+		}
+
+		let (rate, burst) = self.limit(kind);
+		let now = Instant::now();
+		let mut buckets = self.buckets.lock()?;
+		let bucket = buckets
+			.entry((kind, key))
+			.or_insert(Bucket { tokens: burst, updated: now });
+
+		bucket.refill(now, rate, burst);
+		if bucket.tokens >= 1.0 {
+			bucket.tokens -= 1.0;
+			return Ok(());
+		}
+
+		let retry_after = (rate > 0.0)
+			.then(|| Duration::try_from_secs_f64((1.0 - bucket.tokens) / rate).ok())
+			.flatten()
+			.map(RetryAfter::Delay);
+
+		Err(Error::Request(
+			ErrorKind::LimitExceeded { retry_after },
+			"Too many requests.".into(),
+			StatusCode::TOO_MANY_REQUESTS,
+		))
+	}
+
+	/// Whether a user is never rate limited.
+	pub async fn is_exempt(&self, user_id: &UserId) -> bool {
+		let config = &self.services.server.config;
+
+		config
+			.ratelimit_exempt_users
+			.iter()
+			.any(|exempt| exempt == user_id)
+			|| (config.ratelimit_exempt_admins && self.services.users.is_admin(user_id).await)
+	}
+
+	fn cleanup(&self) {
+		let now = Instant::now();
+		let mut buckets = self.buckets.lock().expect("locked");
+		let before = buckets.len();
+		buckets.retain(|(kind, _), bucket| {
+			let (rate, burst) = self.limit(*kind);
+			bucket.refill(now, rate, burst);
+			bucket.tokens < burst
+		});
+
+		debug!(before, after = buckets.len(), "Cleaned up rate limit buckets");
+	}
+
+	fn limit(&self, kind: Kind) -> (f64, f64) {
+		let config = &self.services.server.config;
+		let (rate, burst) = match kind {
+			| Kind::Login => (config.ratelimit_login_per_second, config.ratelimit_login_burst),
+			| Kind::Register =>
+				(config.ratelimit_register_per_second, config.ratelimit_register_burst),
+			| Kind::Message =>
+				(config.ratelimit_message_per_second, config.ratelimit_message_burst),
+			| Kind::Join => (config.ratelimit_join_per_second, config.ratelimit_join_burst),
+			| Kind::Invite => (config.ratelimit_invite_per_second, config.ratelimit_invite_burst),
+			| Kind::MediaUpload =>
+				(config.ratelimit_media_upload_per_second, config.ratelimit_media_upload_burst),
+			| Kind::EmailToken =>
+				(config.ratelimit_email_token_per_second, config.ratelimit_email_token_burst),
+			| Kind::Livekit =>
+				(config.ratelimit_livekit_per_second, config.ratelimit_livekit_burst),
+		};
+
+		(rate.max(0.0), f64::from(burst))
+	}
+}
+
+impl Bucket {
+	fn refill(&mut self, now: Instant, rate: f64, burst: f64) {
+		let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+		self.tokens = elapsed.mul_add(rate, self.tokens).min(burst);
+		self.updated = now;
+	}
+}
@@ -0,0 +1,58 @@
+#![cfg(test)]
+
+use std::net::{IpAddr, Ipv4Addr};
+
+use ruma::{owned_device_id, owned_user_id};
+
+use super::{Key, Kind};
+use crate::tests::{offline_services_with, TempDir};
+
+const CONFIG: &str = r"
+ratelimit = true
+ratelimit_login_per_second = 0.0
+ratelimit_login_burst = 2
+ratelimit_message_per_second = 0.0
+ratelimit_message_burst = 1
+";
+
+#[tokio::test(flavor = "multi_thread")]
+async fn buckets_are_kept_per_kind_and_key() {
+	let dir = TempDir::new("ratelimit");
+	let services = offline_services_with(&dir, "localhost", CONFIG)
+		.await
+		.expect("started services offline");
+
+	let ratelimit = &services.ratelimit;
+	let ip = Key::Ip(IpAddr::from(Ipv4Addr::new(192, 0, 2, 1)));
+	let other_ip = Key::Ip(IpAddr::from(Ipv4Addr::new(192, 0, 2, 2)));
+
+	assert!(ratelimit.check(Kind::Login, ip.clone()).is_ok());
+	assert!(ratelimit.check(Kind::Login, ip.clone()).is_ok());
+	assert!(ratelimit.check(Kind::Login, ip.clone()).is_err(), "burst used up");
+	assert!(ratelimit.check(Kind::Login, other_ip).is_ok(), "other address");
+	assert!(ratelimit.check(Kind::Register, ip).is_ok(), "other kind");
+
+	let user_id = owned_user_id!("@alice:localhost");
+	let phone = Key::Device(user_id.clone(), owned_device_id!("PHONE"));
+	let laptop = Key::Device(user_id.clone(), owned_device_id!("LAPTOP"));
+
+	assert!(ratelimit.check(Kind::Message, phone.clone()).is_ok());
+	assert!(ratelimit.check(Kind::Message, phone).is_err(), "burst used up");
+	assert!(ratelimit.check(Kind::Message, laptop).is_ok(), "other device");
+	assert!(ratelimit.check(Kind::Message, Key::User(user_id)).is_ok(), "device-less user");
+
+	services.stop_offline();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn disabled_by_default() {
+	let dir = TempDir::new("ratelimit-default");
+	let services = offline_services_with(&dir, "localhost", "ratelimit_login_burst = 0")
+		.await
+		.expect("started services offline");
+
+	let ip = Key::Ip(IpAddr::from(Ipv4Addr::new(192, 0, 2, 1)));
+	assert!(services.ratelimit.check(Kind::Login, ip).is_ok());
+
+	services.stop_offline();
+}
@@ -0,0 +1,278 @@
+mod tests;
+
+use std::sync::{Arc, Mutex};
+
+use conduwuit::{
+	err, implement,
+	result::LogErr,
+	utils,
+	utils::{stream::TryIgnore, ReadyExt},
+	Err, Result, Server,
+};
+use database::{Deserialized, Json, Map};
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::{globals, Dep};
+
+/// Tokens which allow registering an account, besides the static
+/// `registration_token` of the config. Each may be limited in uses and time.
+pub struct Service {
+	/// Serializes changes to the counters of tokens.
+	lock: Mutex<()>,
+	db: Data,
+	services: Services,
+}
+
+struct Services {
+	server: Arc<Server>,
+	globals: Dep<globals::Service>,
+}
+
+struct Data {
+	registrationtoken_info: Arc<Map>,
+}
+
+/// Limits and usage of a registration token.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct TokenInfo {
+	/// Registrations the token may be used for, or None for unlimited.
+	pub uses_allowed: Option<u64>,
+
+	/// Registrations completed with the token.
+	pub completed: u64,
+
+	/// Registrations which passed the token stage of UIAA and are not
+	/// completed yet, each holding one of the allowed uses.
+	#[serde(default)]
+	pub pending: u64,
+
+	/// Time in milliseconds after which the token is no longer valid, or None
+	/// if it never expires.
+	pub expiry_time: Option<u64>,
+}
+
+/// Length of tokens generated when none is given.
+pub const TOKEN_LENGTH: usize = 16;
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			lock: Mutex::new(()),
+			db: Data {
+				registrationtoken_info: args.db["registrationtoken_info"].clone(),
+			},
+			services: Services {
+				server: args.server.clone(),
+				globals: args.depend::<globals::Service>("globals"),
+			},
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl TokenInfo {
+	/// Whether the token may be used for another registration at `now`.
+	#[must_use]
+	pub fn is_valid(&self, now: u64) -> bool {
+		self.uses_allowed
+			.is_none_or(|allowed| self.completed.saturating_add(self.pending) < allowed)
+			&& self.expiry_time.is_none_or(|expiry| now < expiry)
+	}
+}
+
+/// Whether registering requires a token.
+#[implement(Service)]
+pub fn required(&self) -> bool {
+	self.services.server.config.registration_requires_token
+		|| self.services.globals.registration_token.is_some()
+}
+
+/// Whether the token may be used to register, without reserving it.
+#[implement(Service)]
+pub async fn is_valid(&self, token: &str) -> bool {
+	self.is_static(token)
+		|| self
+			.get(token)
+			.await
+			.is_ok_and(|info| info.is_valid(utils::millis_since_unix_epoch()))
+}
+
+/// Holds a use of the token for a registration which passed its UIAA stage,
+/// until the registration completes or its session expires. Fails if the
+/// token is no longer valid, so that registrations racing for its last use
+/// cannot all get it.
+#[implement(Service)]
+pub fn reserve(&self, token: &str) -> Result {
+	if self.is_static(token) {
+		return Ok(());
+	}
+
+	self.update(token, |info| {
+		if !info.is_valid(utils::millis_since_unix_epoch()) {
+			return Err!(Request(Forbidden("Invalid registration token.")));
+		}
+
+		info.pending = info.pending.saturating_add(1);
+		Ok(())
+	})
+}
+
+/// Counts the use held for a registration as completed, once the account is
+/// created.
+#[implement(Service)]
+pub fn complete(&self, token: &str) -> Result {
+	if self.is_static(token) {
+		return Ok(());
+	}
+
+	self.update(token, |info| {
+		info.pending = info.pending.saturating_sub(1);
+		info.completed = info.completed.saturating_add(1);
+		Ok(())
+	})
+}
+
+/// Gives back the use held for a registration whose session expired, or
+/// which failed to create the account.
+#[implement(Service)]
+pub fn release(&self, token: &str) -> Result {
+	if self.is_static(token) {
+		return Ok(());
+	}
+
+	self.update(token, |info| {
+		info.pending = info.pending.saturating_sub(1);
+		Ok(())
+	})
+}
+
+/// Gives back the uses held for registrations whose sessions did not
+/// survive a restart.
+#[implement(Service)]
+pub async fn clear_pending(&self) {
+	let tokens: Vec<String> = self
+		.stream()
+		.ready_filter(|(_, info)| info.pending > 0)
+		.map(|(token, _)| token.to_owned())
+		.collect()
+		.await;
+
+	for token in tokens {
+		self.update(&token, |info| {
+			info.pending = 0;
+			Ok(())
+		})
+		.log_err()
+		.ok();
+	}
+}
+
+/// Creates a token, or fails if it already exists.
+#[implement(Service)]
+pub fn create(&self, token: &str, info: &TokenInfo) -> Result {
+	if token.is_empty()
+		|| token.len() > 64
+		|| !token
+			.chars()
+			.all(|c| c.is_ascii_alphanumeric() || "._~-".contains(c))
+	{
+		return Err!(Request(InvalidParam(
+			"Tokens are up to 64 characters of A-Z, a-z, 0-9, '.', '_', '~' and '-'."
+		)));
+	}
+
+	let _lock = self.lock.lock().expect("locked");
+	if self.db.registrationtoken_info.get_blocking(token).is_ok() {
+		return Err!(Request(InvalidParam("Token {token:?} already exists.")));
+	}
+
+	self.db.registrationtoken_info.raw_put(token, Json(info));
+
+	Ok(())
+}
+
+/// Changes the limits of a token, keeping its counters.
+#[implement(Service)]
+pub fn set_limits(
+	&self,
+	token: &str,
+	uses_allowed: Option<Option<u64>>,
+	expiry_time: Option<Option<u64>>,
+) -> Result<TokenInfo> {
+	let mut updated = TokenInfo::default();
+	self.update(token, |info| {
+		if let Some(uses_allowed) = uses_allowed {
+			info.uses_allowed = uses_allowed;
+		}
+
+		if let Some(expiry_time) = expiry_time {
+			info.expiry_time = expiry_time;
+		}
+
+		updated = info.clone();
+		Ok(())
+	})?;
+
+	Ok(updated)
+}
+
+/// Deletes a token. Returns whether it existed.
+#[implement(Service)]
+pub fn delete(&self, token: &str) -> bool {
+	let _lock = self.lock.lock().expect("locked");
+	let existed = self.db.registrationtoken_info.get_blocking(token).is_ok();
+	self.db.registrationtoken_info.remove(token);
+
+	existed
+}
+
+#[implement(Service)]
+pub async fn get(&self, token: &str) -> Result<TokenInfo> {
+	self.db
+		.registrationtoken_info
+		.get(token)
+		.await
+		.deserialized()
+}
+
+/// All tokens with their limits and usage.
+#[implement(Service)]
+pub fn stream(&self) -> impl Stream<Item = (&str, TokenInfo)> + Send + '_ {
+	self.db.registrationtoken_info.stream().ignore_err()
+}
+
+/// Applies `f` to the stored info of a token, atomically with respect to the
+/// other changes to tokens.
+#[implement(Service)]
+fn update<F>(&self, token: &str, f: F) -> Result
+where
+	F: FnOnce(&mut TokenInfo) -> Result,
+{
+	let _lock = self.lock.lock().expect("locked");
+	let mut info: TokenInfo = self
+		.db
+		.registrationtoken_info
+		.get_blocking(token)
+		.deserialized()
+		.map_err(|_| err!(Request(Forbidden("Invalid registration token."))))?;
+
+	f(&mut info)?;
+	self.db.registrationtoken_info.raw_put(token, Json(info));
+
+	Ok(())
+}
+
+#[implement(Service)]
+fn is_static(&self, token: &str) -> bool {
+	self.services
+		.globals
+		.registration_token
+		.as_deref()
+		.is_some_and(|static_token| static_token == token)
+}
+
+/// Generates a token for `create` when the admin gives none.
+#[must_use]
+pub fn generate() -> String { utils::random_string(TOKEN_LENGTH) }
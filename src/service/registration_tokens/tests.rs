@@ -0,0 +1,76 @@
+#![cfg(test)]
+
+use super::TokenInfo;
+use crate::tests::{offline_services, TempDir};
+
+#[test]
+fn token_validity() {
+	let unlimited = TokenInfo::default();
+	assert!(unlimited.is_valid(u64::MAX), "tokens without limits never run out");
+
+	let mut limited = TokenInfo {
+		uses_allowed: Some(2),
+		completed: 1,
+		..TokenInfo::default()
+	};
+	assert!(limited.is_valid(0));
+
+	limited.completed = 2;
+	assert!(!limited.is_valid(0), "tokens run out after their allowed uses");
+
+	let expiring = TokenInfo {
+		expiry_time: Some(1000),
+		..TokenInfo::default()
+	};
+	assert!(expiring.is_valid(999));
+	assert!(!expiring.is_valid(1000), "tokens expire at their expiry time");
+}
+
+#[test]
+fn pending_registrations_hold_uses() {
+	let info = TokenInfo {
+		uses_allowed: Some(2),
+		completed: 1,
+		pending: 1,
+		..TokenInfo::default()
+	};
+	assert!(!info.is_valid(0), "the last use is held by a pending registration");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reserve_complete_release() {
+	let dir = TempDir::new("registration-tokens");
+	let services = offline_services(&dir, "example.com")
+		.await
+		.expect("started services offline");
+
+	let tokens = &services.registration_tokens;
+	let info = TokenInfo {
+		uses_allowed: Some(2),
+		..TokenInfo::default()
+	};
+	tokens.create("token", &info).expect("created");
+	let counters = || async {
+		let info = tokens.get("token").await.expect("token exists");
+		(info.pending, info.completed)
+	};
+
+	tokens.reserve("token").expect("first use held");
+	tokens.reserve("token").expect("second use held");
+	assert!(tokens.reserve("token").is_err(), "every use is held");
+	assert_eq!(counters().await, (2, 0));
+
+	tokens.complete("token").expect("completed");
+	assert_eq!(counters().await, (1, 1));
+
+	tokens.release("token").expect("released");
+	assert_eq!(counters().await, (0, 1));
+	tokens.reserve("token").expect("released use held again");
+
+	tokens.clear_pending().await;
+	assert_eq!(counters().await, (0, 1), "holds do not survive a restart");
+
+	assert!(tokens.reserve("unknown").is_err());
+
+	services.stop_offline();
+}
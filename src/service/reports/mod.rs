@@ -0,0 +1,291 @@
+mod tests;
+
+use std::sync::Arc;
+
+use conduwuit::{
+	err, implement, utils,
+	utils::{stream::TryIgnore, ReadyExt},
+	Err, Result,
+};
+use database::{Deserialized, Json, Map};
+use futures::{Stream, StreamExt};
+use ruma::{OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, UserId};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+/// Reports of events and rooms made by local users, kept in a queue for the
+/// server admins to work through. Reports about the same event or room are
+/// gathered in one until it is resolved.
+pub struct Service {
+	/// Serializes changes to reports.
+	lock: Mutex<()>,
+	db: Data,
+}
+
+struct Data {
+	reportid_report: Arc<Map>,
+	reporttarget_reportid: Arc<Map>,
+}
+
+/// A reported event or room with everyone who reported it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Report {
+	pub target: Target,
+
+	/// Reports made about the target, oldest first, one per reporter.
+	pub submissions: Vec<Submission>,
+
+	pub status: Status,
+
+	/// Time in milliseconds the target was first reported.
+	pub created_at: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Target {
+	Event {
+		room_id: OwnedRoomId,
+		event_id: OwnedEventId,
+		sender: OwnedUserId,
+	},
+	Room {
+		room_id: OwnedRoomId,
+	},
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Submission {
+	pub reporter: OwnedUserId,
+	pub reason: Option<String>,
+
+	/// Offensiveness from 0 (inoffensive) to -100 (most offensive).
+	pub score: Option<i64>,
+
+	/// Time in milliseconds the report was made.
+	pub reported_at: u64,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Status {
+	#[default]
+	Open,
+	Assigned {
+		to: OwnedUserId,
+	},
+	Resolved {
+		by: Option<OwnedUserId>,
+		note: Option<String>,
+		resolved_at: u64,
+	},
+}
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			lock: Mutex::new(()),
+			db: Data {
+				reportid_report: args.db["reportid_report"].clone(),
+				reporttarget_reportid: args.db["reporttarget_reportid"].clone(),
+			},
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl Target {
+	/// Key reports about the same target are gathered under.
+	fn key(&self) -> &str {
+		match self {
+			| Self::Event { event_id, .. } => event_id.as_str(),
+			| Self::Room { room_id } => room_id.as_str(),
+		}
+	}
+
+	/// The reported room, or the room of the reported event.
+	#[must_use]
+	pub fn room_id(&self) -> &RoomId {
+		match self {
+			| Self::Event { room_id, .. } | Self::Room { room_id } => room_id,
+		}
+	}
+}
+
+impl Report {
+	/// Adds a submission, replacing an earlier one by the same reporter.
+	pub fn add(&mut self, submission: Submission) {
+		self.submissions
+			.retain(|existing| existing.reporter != submission.reporter);
+		self.submissions.push(submission);
+	}
+
+	/// The most offensive score given, if any.
+	#[must_use]
+	pub fn score(&self) -> Option<i64> {
+		self.submissions
+			.iter()
+			.filter_map(|submission| submission.score)
+			.min()
+	}
+
+	#[must_use]
+	pub fn is_resolved(&self) -> bool { matches!(self.status, Status::Resolved { .. }) }
+}
+
+/// Files a report, adding it to the unresolved report about the same target
+/// if there is one. Returns the ID of the report and whether it is new.
+#[implement(Service)]
+pub async fn submit(&self, target: Target, submission: Submission) -> Result<(u64, bool)> {
+	let _lock = self.lock.lock().await;
+	if let Ok(id) = self
+		.db
+		.reporttarget_reportid
+		.get(target.key())
+		.await
+		.deserialized::<u64>()
+	{
+		let mut report = self.get(id).await?;
+		report.add(submission);
+		self.put(id, &report);
+
+		return Ok((id, false));
+	}
+
+	let id = self.next_id().await;
+	let report = Report {
+		submissions: vec![submission],
+		status: Status::Open,
+		created_at: utils::millis_since_unix_epoch(),
+		target,
+	};
+
+	self.db
+		.reporttarget_reportid
+		.insert(report.target.key(), id.to_be_bytes());
+	self.put(id, &report);
+
+	Ok((id, true))
+}
+
+#[implement(Service)]
+pub async fn get(&self, id: u64) -> Result<Report> {
+	self.db
+		.reportid_report
+		.get(&id.to_be_bytes())
+		.await
+		.deserialized()
+		.map_err(|_| err!(Request(NotFound("Report {id} does not exist."))))
+}
+
+/// All reports, newest first.
+#[implement(Service)]
+pub fn stream(&self) -> impl Stream<Item = (u64, Report)> + Send + '_ {
+	self.db
+		.reportid_report
+		.rev_stream::<u64, Report>()
+		.ignore_err()
+}
+
+/// Assigns an unresolved report to an admin.
+#[implement(Service)]
+pub async fn claim(&self, id: u64, user_id: &UserId) -> Result<Report> {
+	self.update(id, |report| {
+		if report.is_resolved() {
+			return Err!(Request(InvalidParam("Report {id} is already resolved.")));
+		}
+
+		report.status = Status::Assigned { to: user_id.to_owned() };
+		Ok(())
+	})
+	.await
+}
+
+/// Resolves a report. Later reports about its target start a new report.
+#[implement(Service)]
+pub async fn resolve(
+	&self,
+	id: u64,
+	by: Option<&UserId>,
+	note: Option<String>,
+) -> Result<Report> {
+	let report = self
+		.update(id, |report| {
+			if report.is_resolved() {
+				return Err!(Request(InvalidParam("Report {id} is already resolved.")));
+			}
+
+			report.status = Status::Resolved {
+				by: by.map(ToOwned::to_owned),
+				note,
+				resolved_at: utils::millis_since_unix_epoch(),
+			};
+
+			Ok(())
+		})
+		.await?;
+
+	self.db.reporttarget_reportid.remove(report.target.key());
+
+	Ok(report)
+}
+
+/// Removes the reports about a room or its events, for a room being purged.
+/// Returns the number of rows removed.
+#[implement(Service)]
+pub async fn purge_room(&self, room_id: &RoomId) -> usize {
+	let _lock = self.lock.lock().await;
+	let reports: Vec<(u64, Report)> = self
+		.stream()
+		.ready_filter(|(_, report)| report.target.room_id() == room_id)
+		.collect()
+		.await;
+
+	let mut removed: usize = 0;
+	for (id, report) in reports {
+		self.db.reportid_report.remove(&id.to_be_bytes());
+		removed = removed.saturating_add(1);
+		if !report.is_resolved() {
+			self.db.reporttarget_reportid.remove(report.target.key());
+			removed = removed.saturating_add(1);
+		}
+	}
+
+	removed
+}
+
+/// Applies `f` to a stored report, atomically with respect to the other
+/// changes to reports.
+#[implement(Service)]
+async fn update<F>(&self, id: u64, f: F) -> Result<Report>
+where
+	F: FnOnce(&mut Report) -> Result + Send,
+{
+	let _lock = self.lock.lock().await;
+	let mut report = self.get(id).await?;
+	f(&mut report)?;
+	self.put(id, &report);
+
+	Ok(report)
+}
+
+#[implement(Service)]
+fn put(&self, id: u64, report: &Report) {
+	self.db
+		.reportid_report
+		.raw_put(id.to_be_bytes(), Json(report));
+}
+
+/// IDs count up from 1, so that admins can refer to reports easily.
+#[implement(Service)]
+async fn next_id(&self) -> u64 {
+	self.db
+		.reportid_report
+		.rev_keys::<u64>()
+		.ignore_err()
+		.next()
+		.await
+		.unwrap_or(0)
+		.saturating_add(1)
+}
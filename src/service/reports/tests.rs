@@ -0,0 +1,41 @@
+#![cfg(test)]
+
+use ruma::{owned_room_id, owned_user_id};
+
+use super::{Report, Status, Submission, Target};
+
+fn submission(reporter: &str, score: Option<i64>) -> Submission {
+	Submission {
+		reporter: reporter.try_into().expect("valid user ID"),
+		reason: None,
+		score,
+		reported_at: 0,
+	}
+}
+
+#[test]
+fn submissions() {
+	let mut report = Report {
+		target: Target::Room {
+			room_id: owned_room_id!("!room:example.com"),
+		},
+		submissions: vec![submission("@alice:example.com", Some(-10))],
+		status: Status::Open,
+		created_at: 0,
+	};
+
+	report.add(submission("@bob:example.com", None));
+	report.add(submission("@alice:example.com", Some(-80)));
+
+	let reporters: Vec<_> = report
+		.submissions
+		.iter()
+		.map(|submission| submission.reporter.as_str())
+		.collect();
+	assert_eq!(reporters, ["@bob:example.com", "@alice:example.com"], "one per reporter");
+	assert_eq!(report.score(), Some(-80), "the most offensive score counts");
+
+	assert!(!report.is_resolved());
+	report.status = Status::Assigned { to: owned_user_id!("@admin:example.com") };
+	assert!(!report.is_resolved(), "claimed reports stay in the queue");
+}
@@ -4,10 +4,16 @@ use ruma::{
 	RoomId, ServerName,
 };
 
-/// Returns Ok if the acl allows the server
+/// Returns Ok if the acl allows the server and no policy list the server is
+/// subscribed to bans it
 #[implement(super::Service)]
 #[tracing::instrument(skip_all, level = "debug")]
 pub async fn acl_check(&self, server_name: &ServerName, room_id: &RoomId) -> Result {
+	if let Some(rule) = self.services.policy_lists.server_ban(server_name) {
+		debug!("Server {server_name} is banned by policy list {}", rule.policy_room);
+		return Err!(Request(Forbidden("Server is banned by a policy list")));
+	}
+
 	let Ok(acl_event_content) = self
 		.services
 		.state_accessor
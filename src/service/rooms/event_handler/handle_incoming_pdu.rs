@@ -161,20 +161,13 @@ pub async fn handle_incoming_pdu<'a>(
 	}
 
 	// Done with prev events, now handling the incoming event
-	let start_time = Instant::now();
-	self.federation_handletime
-		.write()
-		.expect("locked")
-		.insert(room_id.to_owned(), (event_id.to_owned(), start_time));
+	let start_time = self.handle_start(room_id, event_id);
 
 	let r = self
 		.upgrade_outlier_to_timeline_pdu(incoming_pdu, val, &create_event, origin, room_id)
 		.await;
 
-	self.federation_handletime
-		.write()
-		.expect("locked")
-		.remove(&room_id.to_owned());
+	self.handle_finish(room_id, start_time);
 
 	r
 }
@@ -1,7 +1,6 @@
 use std::{
 	collections::{BTreeMap, HashMap},
 	sync::Arc,
-	time::Instant,
 };
 
 use conduwuit::{
@@ -66,19 +65,12 @@ pub(super) async fn handle_prev_pdu<'a>(
 			return Ok(());
 		}
 
-		let start_time = Instant::now();
-		self.federation_handletime
-			.write()
-			.expect("locked")
-			.insert(room_id.to_owned(), ((*prev_id).to_owned(), start_time));
+		let start_time = self.handle_start(room_id, prev_id);
 
 		self.upgrade_outlier_to_timeline_pdu(pdu, json, create_event, origin, room_id)
 			.await?;
 
-		self.federation_handletime
-			.write()
-			.expect("locked")
-			.remove(&room_id.to_owned());
+		self.handle_finish(room_id, start_time);
 
 		debug!(
 			elapsed = ?start_time.elapsed(),
@@ -13,7 +13,10 @@ mod upgrade_outlier_pdu;
 use std::{
 	collections::HashMap,
 	fmt::Write,
-	sync::{Arc, RwLock as StdRwLock},
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, RwLock as StdRwLock,
+	},
 	time::Instant,
 };
 
@@ -23,20 +26,23 @@ use conduwuit::{
 };
 use futures::TryFutureExt;
 use ruma::{
-	events::room::create::RoomCreateEventContent, state_res::RoomVersion, OwnedEventId,
+	events::room::create::RoomCreateEventContent, state_res::RoomVersion, EventId, OwnedEventId,
 	OwnedRoomId, RoomId, RoomVersionId,
 };
 
-use crate::{globals, rooms, sending, server_keys, Dep};
+use crate::{globals, policy_lists, rooms, sending, server_keys, Dep};
 
 pub struct Service {
 	pub mutex_federation: RoomMutexMap,
 	pub federation_handletime: StdRwLock<HandleTimeMap>,
+	pub federation_handled: AtomicU64,
+	pub federation_handled_micros: AtomicU64,
 	services: Services,
 }
 
 struct Services {
 	globals: Dep<globals::Service>,
+	policy_lists: Dep<policy_lists::Service>,
 	sending: Dep<sending::Service>,
 	auth_chain: Dep<rooms::auth_chain::Service>,
 	metadata: Dep<rooms::metadata::Service>,
@@ -59,8 +65,11 @@ impl crate::Service for Service {
 		Ok(Arc::new(Self {
 			mutex_federation: RoomMutexMap::new(),
 			federation_handletime: HandleTimeMap::new().into(),
+			federation_handled: AtomicU64::new(0),
+			federation_handled_micros: AtomicU64::new(0),
 			services: Services {
 				globals: args.depend::<globals::Service>("globals"),
+				policy_lists: args.depend::<policy_lists::Service>("policy_lists"),
 				sending: args.depend::<sending::Service>("sending"),
 				auth_chain: args.depend::<rooms::auth_chain::Service>("rooms::auth_chain"),
 				metadata: args.depend::<rooms::metadata::Service>("rooms::metadata"),
@@ -97,6 +106,32 @@ impl crate::Service for Service {
 }
 
 impl Service {
+	fn handle_start(&self, room_id: &RoomId, event_id: &EventId) -> Instant {
+		let start_time = Instant::now();
+		self.federation_handletime
+			.write()
+			.expect("locked")
+			.insert(room_id.to_owned(), (event_id.to_owned(), start_time));
+
+		start_time
+	}
+
+	fn handle_finish(&self, room_id: &RoomId, start_time: Instant) {
+		self.federation_handletime
+			.write()
+			.expect("locked")
+			.remove(room_id);
+
+		let elapsed = start_time
+			.elapsed()
+			.as_micros()
+			.try_into()
+			.unwrap_or(u64::MAX);
+		self.federation_handled_micros
+			.fetch_add(elapsed, Ordering::Relaxed);
+		self.federation_handled.fetch_add(1, Ordering::Relaxed);
+	}
+
 	async fn event_exists(&self, event_id: OwnedEventId) -> bool {
 		self.services.timeline.pdu_exists(&event_id).await
 	}
@@ -0,0 +1,719 @@
+mod tests;
+
+use std::{
+	collections::{HashMap, HashSet},
+	fmt::Debug,
+	sync::Arc,
+};
+
+use conduwuit::{
+	debug, implement, info,
+	utils::{stream::TryIgnore, u64_from_bytes, ReadyExt},
+	Err, PduCount, PduEvent, Result,
+};
+use database::{Database, Deserialized, Interfix};
+use futures::{pin_mut, Future, StreamExt};
+use ruma::{
+	EventId, OwnedEventId, OwnedRoomId, OwnedServerName, OwnedUserId, RoomId, ServerName, UserId,
+};
+use serde::{de::IgnoredAny, Deserialize, Serialize};
+
+use crate::{
+	admin, delayed_events, policy_lists, reports,
+	rooms::{
+		self,
+		short::{ShortEventId, ShortRoomId, ShortStateHash},
+		state_compressor::parse_compressed_state_event,
+		timeline::{PduId, RawPduId},
+	},
+	server_notices, Dep,
+};
+
+/// Removes every row belonging to a room, or to some of its events, from the
+/// database.
+///
+/// Rows shared with other rooms (state keys, users, media) are left alone,
+/// as are `bannedroomids` and `disabledroomids` so a banned room stays banned.
+/// Services keeping rows about rooms of their own remove them through their
+/// `purge_room`.
+/// Outgoing federation transactions still referencing purged events are
+/// skipped by the sender once the events can no longer be found.
+pub struct Service {
+	services: Services,
+	db: Arc<Database>,
+}
+
+struct Services {
+	admin: Dep<admin::Service>,
+	auth_chain: Dep<rooms::auth_chain::Service>,
+	delayed_events: Dep<delayed_events::Service>,
+	policy_lists: Dep<policy_lists::Service>,
+	reports: Dep<reports::Service>,
+	search: Dep<rooms::search::Service>,
+	server_notices: Dep<server_notices::Service>,
+	short: Dep<rooms::short::Service>,
+	spaces: Dep<rooms::spaces::Service>,
+	state: Dep<rooms::state::Service>,
+	state_accessor: Dep<rooms::state_accessor::Service>,
+	state_cache: Dep<rooms::state_cache::Service>,
+	state_compressor: Dep<rooms::state_compressor::Service>,
+	timeline: Dep<rooms::timeline::Service>,
+}
+
+/// Events and state collected from the timeline before the rows referring to
+/// them are removed.
+#[derive(Default)]
+struct Collected {
+	event_ids: HashSet<OwnedEventId>,
+	shorteventids: HashSet<ShortEventId>,
+	shortstatehashes: HashSet<ShortStateHash>,
+	counts: HashSet<u64>,
+}
+
+/// The fields of a stored PDU needed to find it again.
+#[derive(Deserialize)]
+struct PduIds {
+	event_id: Option<OwnedEventId>,
+	room_id: Option<OwnedRoomId>,
+}
+
+#[derive(Deserialize)]
+struct ExtractBody {
+	body: Option<String>,
+}
+
+/// The events a PDU relates to, which hold a relation row pointing back at it.
+#[derive(Deserialize)]
+struct ExtractRelatesTo {
+	#[serde(rename = "m.relates_to")]
+	relates_to: RelatesTo,
+}
+
+#[derive(Deserialize)]
+struct RelatesTo {
+	event_id: Option<OwnedEventId>,
+	#[serde(rename = "m.in_reply_to")]
+	in_reply_to: Option<InReplyTo>,
+}
+
+#[derive(Deserialize)]
+struct InReplyTo {
+	event_id: OwnedEventId,
+}
+
+/// The events a stored read receipt is for.
+#[derive(Deserialize)]
+struct ReceiptIds {
+	content: HashMap<OwnedEventId, IgnoredAny>,
+}
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			services: Services {
+				admin: args.depend::<admin::Service>("admin"),
+				auth_chain: args.depend::<rooms::auth_chain::Service>("rooms::auth_chain"),
+				delayed_events: args.depend::<delayed_events::Service>("delayed_events"),
+				policy_lists: args.depend::<policy_lists::Service>("policy_lists"),
+				reports: args.depend::<reports::Service>("reports"),
+				search: args.depend::<rooms::search::Service>("rooms::search"),
+				server_notices: args.depend::<server_notices::Service>("server_notices"),
+				short: args.depend::<rooms::short::Service>("rooms::short"),
+				spaces: args.depend::<rooms::spaces::Service>("rooms::spaces"),
+				state: args.depend::<rooms::state::Service>("rooms::state"),
+				state_accessor: args
+					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
+				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
+				state_compressor: args
+					.depend::<rooms::state_compressor::Service>("rooms::state_compressor"),
+				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
+			},
+			db: args.db.clone(),
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Purges a room, calling `progress` with the name of each completed phase
+/// and the number of rows it removed. Returns the total number of rows
+/// removed.
+///
+/// Local users must have left the room beforehand; purging the admin room is
+/// refused.
+#[implement(Service)]
+#[tracing::instrument(skip(self, progress), level = "info")]
+pub async fn purge_room<F, Fut>(&self, room_id: &RoomId, progress: F) -> Result<usize>
+where
+	F: Fn(&'static str, usize) -> Fut + Send + Sync,
+	Fut: Future<Output = ()> + Send,
+{
+	if self.services.admin.is_admin_room(room_id).await {
+		return Err!(Request(Forbidden("Not allowed to purge the admin room.")));
+	}
+
+	let local_users = self.services.state_cache.local_users_in_room(room_id);
+	pin_mut!(local_users);
+	if local_users.next().await.is_some() {
+		return Err!(Request(Forbidden(
+			"Local users are still joined to {room_id}; make them leave first."
+		)));
+	}
+
+	let state_lock = self.services.state.mutex.lock(room_id).await;
+	let shortroomid = self.services.short.get_shortroomid(room_id).await.ok();
+
+	let mut total: usize = 0;
+	let mut report = |phase: &'static str, removed: usize| {
+		info!(%room_id, phase, removed, "Purged room rows");
+		total = total.saturating_add(removed);
+		progress(phase, removed)
+	};
+
+	let mut collected = Collected::default();
+	let removed = self
+		.purge_timeline(room_id, shortroomid, &mut collected)
+		.await;
+	report("timeline", removed).await;
+
+	let removed = self.purge_state(room_id, shortroomid, &mut collected).await;
+	report("state", removed).await;
+
+	let removed = self.purge_events(&collected).await;
+	report("events", removed).await;
+
+	let removed = self.purge_membership(room_id).await;
+	report("membership", removed).await;
+
+	let removed = self.purge_user_data(room_id).await;
+	report("receipts and account data", removed).await;
+
+	let removed = self.purge_aliases(room_id).await;
+	report("aliases and directory", removed).await;
+
+	let removed = self.purge_services(room_id).await;
+	report("policies, reports, notices and delayed events", removed).await;
+
+	self.db["roomid_shortroomid"].del(room_id);
+	self.db["roomid_shortstatehash"].del(room_id);
+	drop(state_lock);
+
+	self.clear_caches(room_id).await;
+
+	info!(%room_id, total, "Finished purging room");
+
+	Ok(total)
+}
+
+/// Removes some events of a room's timeline, leaving the rest of the room
+/// alone, along with the rows referring to them: search tokens, relations,
+/// thread roots, read receipts, references, outliers and short event IDs.
+/// Returns the number of rows removed.
+#[implement(Service)]
+#[tracing::instrument(skip(self, pdus), level = "debug")]
+pub async fn purge_timeline_events(
+	&self,
+	room_id: &RoomId,
+	pdus: &[(PduCount, PduEvent)],
+) -> Result<usize> {
+	let shortroomid = self.services.short.get_shortroomid(room_id).await?;
+
+	let mut removed: usize = 0;
+	let mut collected = Collected::default();
+	for (count, pdu) in pdus {
+		let pdu_id: RawPduId = PduId { shortroomid, shorteventid: *count }.into();
+		if let Ok(ExtractBody { body: Some(body) }) = pdu.get_content() {
+			self.services
+				.search
+				.deindex_pdu(shortroomid, &pdu_id, &body);
+		}
+
+		let count = count.into_unsigned().to_be_bytes();
+		for map in ["pduid_pdu", "threadid_userids"] {
+			removed = removed.saturating_add(self.remove_raw_key(map, pdu_id.as_bytes()).await);
+		}
+
+		removed = removed
+			.saturating_add(self.remove_raw_prefix("tofrom_relation", &count).await)
+			.saturating_add(
+				self.remove_key("referencedevents", (room_id, &pdu.event_id))
+					.await,
+			);
+
+		for target in relation_targets(pdu) {
+			if let Ok(target) = self.services.timeline.get_pdu_count(&target).await {
+				let key = [target.into_unsigned().to_be_bytes(), count].concat();
+				removed =
+					removed.saturating_add(self.remove_raw_key("tofrom_relation", &key).await);
+			}
+		}
+
+		if let Ok(shorteventid) = self.services.short.get_shorteventid(&pdu.event_id).await {
+			collected.shorteventids.insert(shorteventid);
+		}
+
+		collected.event_ids.insert(pdu.event_id.clone());
+	}
+
+	removed = removed
+		.saturating_add(self.purge_receipts(room_id, &collected.event_ids).await)
+		.saturating_add(self.purge_events(&collected).await);
+
+	Ok(removed)
+}
+
+/// Removes the PDUs of the room along with their search tokens, relations,
+/// thread and forward extremity rows.
+#[implement(Service)]
+async fn purge_timeline(
+	&self,
+	room_id: &RoomId,
+	shortroomid: Option<ShortRoomId>,
+	collected: &mut Collected,
+) -> usize {
+	let mut removed: usize = 0;
+
+	if let Some(shortroomid) = shortroomid {
+		let prefix = shortroomid.to_be_bytes();
+		let pduid_pdu = &self.db["pduid_pdu"];
+		pduid_pdu
+			.raw_stream_prefix(&prefix)
+			.ignore_err()
+			.ready_for_each(|(key, val)| {
+				let pdu_id: rooms::timeline::RawPduId = key.into();
+				collected.counts.insert(pdu_id.pdu_count().into_unsigned());
+				if let Ok(PduIds { event_id: Some(event_id), .. }) = serde_json::from_slice(val) {
+					collected.event_ids.insert(event_id);
+				}
+
+				pduid_pdu.remove(key);
+				removed = removed.saturating_add(1);
+			})
+			.await;
+
+		for map in ["tokenids", "shortroomid_searchstats", "threadid_userids"] {
+			removed = removed.saturating_add(self.remove_raw_prefix(map, &prefix).await);
+		}
+
+		for count in &collected.counts {
+			removed = removed.saturating_add(
+				self.remove_raw_prefix("tofrom_relation", &count.to_be_bytes())
+					.await,
+			);
+		}
+	}
+
+	let eventid_outlierpdu = &self.db["eventid_outlierpdu"];
+	eventid_outlierpdu
+		.raw_stream()
+		.ignore_err()
+		.ready_for_each(|(key, val)| {
+			let Ok(PduIds { room_id: Some(pdu_room_id), .. }) = serde_json::from_slice(val)
+			else {
+				return;
+			};
+
+			if pdu_room_id != room_id {
+				return;
+			}
+
+			if let Ok(event_id) = std::str::from_utf8(key).map(EventId::parse) {
+				collected.event_ids.extend(event_id);
+			}
+
+			eventid_outlierpdu.remove(key);
+			removed = removed.saturating_add(1);
+		})
+		.await;
+
+	let prefix = (room_id, Interfix);
+	for map in ["referencedevents", "roomid_pduleaves"] {
+		removed = removed.saturating_add(self.remove_prefix(map, &prefix).await);
+	}
+
+	removed
+}
+
+/// Removes the state snapshots of the room, including all the layers they
+/// are compressed against.
+#[implement(Service)]
+async fn purge_state(
+	&self,
+	room_id: &RoomId,
+	shortroomid: Option<ShortRoomId>,
+	collected: &mut Collected,
+) -> usize {
+	let mut removed: usize = 0;
+	let mut roots: HashSet<ShortStateHash> = HashSet::new();
+
+	if let Ok(shortstatehash) = self.db["roomid_shortstatehash"]
+		.get(room_id)
+		.await
+		.deserialized()
+	{
+		roots.insert(shortstatehash);
+	}
+
+	if let Some(shortroomid) = shortroomid {
+		let roomsynctoken_shortstatehash = &self.db["roomsynctoken_shortstatehash"];
+		roomsynctoken_shortstatehash
+			.raw_stream_prefix(&shortroomid.to_be_bytes())
+			.ignore_err()
+			.ready_for_each(|(key, val)| {
+				roots.extend(u64_from_bytes(val).ok());
+				roomsynctoken_shortstatehash.remove(key);
+				removed = removed.saturating_add(1);
+			})
+			.await;
+	}
+
+	for event_id in &collected.event_ids {
+		let Ok(shorteventid) = self.services.short.get_shorteventid(event_id).await else {
+			continue;
+		};
+
+		collected.shorteventids.insert(shorteventid);
+		if let Ok(shortstatehash) = self.db["shorteventid_shortstatehash"]
+			.qry(&shorteventid)
+			.await
+			.deserialized()
+		{
+			roots.insert(shortstatehash);
+		}
+	}
+
+	for root in roots {
+		if collected.shortstatehashes.contains(&root) {
+			continue;
+		}
+
+		let Ok(layers) = self
+			.services
+			.state_compressor
+			.load_shortstatehash_info(root)
+			.await
+		else {
+			debug!(%room_id, ?root, "State snapshot already missing");
+			continue;
+		};
+
+		for layer in layers.iter() {
+			collected.shortstatehashes.insert(layer.shortstatehash);
+			collected.shorteventids.extend(
+				layer
+					.full_state
+					.iter()
+					.copied()
+					.map(parse_compressed_state_event)
+					.map(|(_, shorteventid)| shorteventid),
+			);
+		}
+	}
+
+	let shortstatehash_statediff = &self.db["shortstatehash_statediff"];
+	for shortstatehash in &collected.shortstatehashes {
+		shortstatehash_statediff.del(shortstatehash);
+		removed = removed.saturating_add(1);
+	}
+
+	let statehash_shortstatehash = &self.db["statehash_shortstatehash"];
+	statehash_shortstatehash
+		.raw_stream()
+		.ignore_err()
+		.ready_filter(|(_, val)| {
+			u64_from_bytes(val)
+				.is_ok_and(|shortstatehash| collected.shortstatehashes.contains(&shortstatehash))
+		})
+		.ready_for_each(|(key, _)| {
+			statehash_shortstatehash.remove(key);
+			removed = removed.saturating_add(1);
+		})
+		.await;
+
+	removed
+}
+
+/// Removes the per-event rows of every event seen in the timeline, outliers
+/// and state of the room.
+#[implement(Service)]
+async fn purge_events(&self, collected: &Collected) -> usize {
+	let mut removed: usize = 0;
+	let mut event_ids = collected.event_ids.clone();
+
+	for shorteventid in &collected.shorteventids {
+		if let Ok(event_id) = self.db["shorteventid_eventid"]
+			.qry(shorteventid)
+			.await
+			.deserialized::<OwnedEventId>()
+		{
+			event_ids.insert(event_id);
+		}
+
+		for map in
+			["shorteventid_eventid", "shorteventid_shortstatehash", "shorteventid_authchain"]
+		{
+			removed = removed.saturating_add(self.remove_key(map, shorteventid).await);
+		}
+	}
+
+	for event_id in &event_ids {
+		for map in [
+			"eventid_pduid",
+			"eventid_outlierpdu",
+			"eventid_shorteventid",
+			"softfailedeventids",
+		] {
+			removed = removed.saturating_add(self.remove_key(map, event_id).await);
+		}
+	}
+
+	removed
+}
+
+/// Removes the membership rows of every user and server that was ever in the
+/// room.
+#[implement(Service)]
+async fn purge_membership(&self, room_id: &RoomId) -> usize {
+	let mut removed: usize = 0;
+	let mut users: HashSet<OwnedUserId> = HashSet::new();
+	let prefix = (room_id, Interfix);
+
+	for map in [
+		"roomuserid_joined",
+		"roomuserid_invitecount",
+		"roomuserid_leftcount",
+		"roomuserid_knockedcount",
+		"roomuseroncejoinedids",
+	] {
+		let map = &self.db[map];
+		map.keys_prefix_raw(&prefix)
+			.ignore_err()
+			.ready_for_each(|key| {
+				users.extend(last_segment(key).and_then(|user_id| UserId::parse(user_id).ok()));
+				map.remove(key);
+				removed = removed.saturating_add(1);
+			})
+			.await;
+	}
+
+	for user_id in &users {
+		for map in [
+			"userroomid_joined",
+			"userroomid_invitestate",
+			"userroomid_leftstate",
+			"userroomid_knockedstate",
+			"userroomid_highlightcount",
+			"userroomid_notificationcount",
+		] {
+			removed = removed.saturating_add(self.remove_key(map, (user_id, room_id)).await);
+		}
+	}
+
+	let mut servers: HashSet<OwnedServerName> = HashSet::new();
+	let roomserverids = &self.db["roomserverids"];
+	roomserverids
+		.keys_prefix_raw(&prefix)
+		.ignore_err()
+		.ready_for_each(|key| {
+			servers.extend(last_segment(key).and_then(|server| ServerName::parse(server).ok()));
+			roomserverids.remove(key);
+			removed = removed.saturating_add(1);
+		})
+		.await;
+
+	for server in &servers {
+		removed =
+			removed.saturating_add(self.remove_key("serverroomids", (server, room_id)).await);
+	}
+
+	for map in ["roomid_joinedcount", "roomid_invitedcount", "roomid_inviteviaservers"] {
+		removed = removed.saturating_add(self.remove_key(map, room_id).await);
+	}
+
+	removed
+}
+
+/// Removes read receipts, private read markers, room account data, device key
+/// changes and lazy loading state of the room.
+#[implement(Service)]
+async fn purge_user_data(&self, room_id: &RoomId) -> usize {
+	let mut removed: usize = 0;
+	let prefix = (room_id, Interfix);
+
+	for map in [
+		"readreceiptid_readreceipt",
+		"roomuserid_privateread",
+		"roomuserid_lastprivatereadupdate",
+		"roomuserdataid_accountdata",
+		"roomusertype_roomuserdataid",
+		"keychangeid_userid",
+	] {
+		removed = removed.saturating_add(self.remove_prefix(map, &prefix).await);
+	}
+
+	// keyed by (user_id, device_id, room_id, user_id), so the room can only be
+	// matched by scanning
+	let lazyloadedids = &self.db["lazyloadedids"];
+	lazyloadedids
+		.raw_keys()
+		.ignore_err()
+		.ready_filter(|key| {
+			key.split(|&b| b == database::SEP)
+				.nth(2)
+				.is_some_and(|room| room == room_id.as_bytes())
+		})
+		.ready_for_each(|key| {
+			lazyloadedids.remove(key);
+			removed = removed.saturating_add(1);
+		})
+		.await;
+
+	removed
+}
+
+/// Removes the read receipts of the room which are only for purged events.
+#[implement(Service)]
+async fn purge_receipts(&self, room_id: &RoomId, event_ids: &HashSet<OwnedEventId>) -> usize {
+	let readreceiptid_readreceipt = &self.db["readreceiptid_readreceipt"];
+	readreceiptid_readreceipt
+		.stream_prefix_raw(&(room_id, Interfix))
+		.ignore_err()
+		.ready_filter(|(_, val)| {
+			serde_json::from_slice::<ReceiptIds>(val).is_ok_and(|receipt| {
+				receipt
+					.content
+					.keys()
+					.all(|event_id| event_ids.contains(event_id))
+			})
+		})
+		.ready_fold(0_usize, |removed, (key, _)| {
+			readreceiptid_readreceipt.remove(key);
+			removed.saturating_add(1)
+		})
+		.await
+}
+
+/// Removes the local aliases of the room and its room directory entry.
+#[implement(Service)]
+async fn purge_aliases(&self, room_id: &RoomId) -> usize {
+	let mut removed: usize = 0;
+	let mut aliases: Vec<Vec<u8>> = Vec::new();
+
+	let aliasid_alias = &self.db["aliasid_alias"];
+	aliasid_alias
+		.stream_prefix_raw(&(room_id, Interfix))
+		.ignore_err()
+		.ready_for_each(|(key, alias)| {
+			aliases.push(alias.to_vec());
+			aliasid_alias.remove(key);
+			removed = removed.saturating_add(1);
+		})
+		.await;
+
+	for alias in &aliases {
+		for map in ["alias_roomid", "alias_userid"] {
+			removed = removed.saturating_add(self.remove_raw_key(map, alias).await);
+		}
+	}
+
+	removed.saturating_add(self.remove_key("publicroomids", room_id).await)
+}
+
+/// Removes the rows other services keep about the room: its policy list
+/// subscription, reports, notices room mappings and delayed events.
+#[implement(Service)]
+async fn purge_services(&self, room_id: &RoomId) -> usize {
+	self.services
+		.policy_lists
+		.purge_room(room_id)
+		.await
+		.saturating_add(self.services.reports.purge_room(room_id).await)
+		.saturating_add(self.services.server_notices.purge_room(room_id).await)
+		.saturating_add(self.services.delayed_events.purge_room(room_id).await)
+}
+
+#[implement(Service)]
+async fn clear_caches(&self, room_id: &RoomId) {
+	use crate::Service as _;
+
+	self.services.auth_chain.clear_cache();
+	self.services.state_accessor.clear_cache();
+	self.services.state_compressor.clear_cache();
+	self.services.state_cache.clear_appservice_in_room_cache();
+	self.services
+		.spaces
+		.roomid_spacehierarchy_cache
+		.lock()
+		.await
+		.remove(room_id);
+}
+
+#[implement(Service)]
+async fn remove_prefix<P>(&self, map: &str, prefix: &P) -> usize
+where
+	P: Serialize + ?Sized + Debug,
+{
+	let map = &self.db[map];
+	map.keys_prefix_raw(prefix)
+		.ignore_err()
+		.ready_fold(0_usize, |removed, key| {
+			map.remove(key);
+			removed.saturating_add(1)
+		})
+		.await
+}
+
+#[implement(Service)]
+async fn remove_raw_prefix(&self, map: &str, prefix: &[u8]) -> usize {
+	let map = &self.db[map];
+	map.raw_keys_prefix(prefix)
+		.ignore_err()
+		.ready_fold(0_usize, |removed, key| {
+			map.remove(key);
+			removed.saturating_add(1)
+		})
+		.await
+}
+
+#[implement(Service)]
+async fn remove_key<K>(&self, map: &str, key: K) -> usize
+where
+	K: Serialize + Debug,
+{
+	let map = &self.db[map];
+	if map.qry(&key).await.is_err() {
+		return 0;
+	}
+
+	map.del(key);
+	1
+}
+
+#[implement(Service)]
+async fn remove_raw_key(&self, map: &str, key: &[u8]) -> usize {
+	let map = &self.db[map];
+	if map.get(key).await.is_err() {
+		return 0;
+	}
+
+	map.remove(key);
+	1
+}
+
+fn relation_targets(pdu: &PduEvent) -> impl Iterator<Item = OwnedEventId> {
+	pdu.get_content::<ExtractRelatesTo>()
+		.ok()
+		.into_iter()
+		.flat_map(|content| {
+			let RelatesTo { event_id, in_reply_to } = content.relates_to;
+			event_id
+				.into_iter()
+				.chain(in_reply_to.map(|in_reply_to| in_reply_to.event_id))
+		})
+}
+
+fn last_segment(key: &[u8]) -> Option<&str> {
+	key.rsplit(|&b| b == database::SEP)
+		.next()
+		.and_then(|segment| std::str::from_utf8(segment).ok())
+}
@@ -0,0 +1,144 @@
+#![cfg(test)]
+
+use conduwuit::{pdu::PduBuilder, utils::stream::TryIgnore};
+use futures::StreamExt;
+use ruma::{
+	events::room::{
+		member::{MembershipState, RoomMemberEventContent},
+		message::RoomMessageEventContent,
+	},
+	OwnedEventId, OwnedRoomId,
+};
+use serde_json::value::to_raw_value;
+
+use crate::{
+	reports::{Submission, Target},
+	tests,
+	tests::{offline_services, send, TempDir},
+	Services,
+};
+
+/// Maps keyed by the short room ID rather than the room ID.
+const SHORTROOMID_MAPS: &[&str] = &[
+	"pduid_pdu",
+	"tokenids",
+	"shortroomid_searchstats",
+	"threadid_userids",
+	"roomsynctoken_shortstatehash",
+];
+
+/// Creates a room with a message as the server user, who then leaves it.
+async fn create_room(services: &Services) -> (OwnedRoomId, OwnedEventId) {
+	let room_id = tests::create_room(services, None).await;
+	let event_id = send(
+		services,
+		&room_id,
+		PduBuilder::timeline(&RoomMessageEventContent::text_plain("purged soon")),
+	)
+	.await;
+	send(
+		services,
+		&room_id,
+		PduBuilder::state(
+			services.globals.server_user.to_string(),
+			&RoomMemberEventContent::new(MembershipState::Leave),
+		),
+	)
+	.await;
+
+	(room_id, event_id)
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+	haystack
+		.windows(needle.len())
+		.any(|window| window == needle)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn purge_leaves_no_rows() {
+	let dir = TempDir::new("purge");
+	let services = offline_services(&dir, "example.com")
+		.await
+		.expect("started services offline");
+
+	let sender = &services.globals.server_user;
+	let (room_id, event_id) = create_room(&services).await;
+	let shortroomid = services
+		.rooms
+		.short
+		.get_shortroomid(&room_id)
+		.await
+		.expect("short room ID")
+		.to_be_bytes();
+
+	services.policy_lists.subscribe(&room_id).await;
+	services
+		.reports
+		.submit(
+			Target::Event {
+				room_id: room_id.clone(),
+				event_id,
+				sender: sender.clone(),
+			},
+			Submission {
+				reporter: sender.clone(),
+				reason: Some("spam".to_owned()),
+				score: None,
+				reported_at: 0,
+			},
+		)
+		.await
+		.expect("reported");
+	services
+		.delayed_events
+		.schedule(
+			sender,
+			&room_id,
+			"m.room.message".to_owned(),
+			None,
+			to_raw_value(&serde_json::json!({ "msgtype": "m.text", "body": "later" }))
+				.expect("valid JSON"),
+			60_000,
+		)
+		.await
+		.expect("scheduled");
+	services.db["userid_servernoticeroomid"].insert(sender.as_bytes(), room_id.as_bytes());
+
+	let removed = services
+		.rooms
+		.purge
+		.purge_room(&room_id, |_, _| async {})
+		.await
+		.expect("purged");
+	assert!(removed > 0);
+
+	for (name, map) in services.db.iter() {
+		let rows: Vec<(Vec<u8>, Vec<u8>)> = map
+			.raw_stream()
+			.ignore_err()
+			.map(|(key, val)| (key.to_vec(), val.to_vec()))
+			.collect()
+			.await;
+
+		for (key, val) in rows {
+			assert!(
+				!contains(&key, room_id.as_bytes()) && !contains(&val, room_id.as_bytes()),
+				"{name} still refers to the purged room"
+			);
+			assert!(
+				!SHORTROOMID_MAPS.contains(name) || !key.starts_with(&shortroomid),
+				"{name} still has rows of the purged room"
+			);
+		}
+	}
+
+	assert!(!services.policy_lists.is_subscribed(&room_id).await);
+	assert!(services
+		.delayed_events
+		.delayed_events(sender)
+		.await
+		.is_empty());
+
+	services.stop_offline();
+}
@@ -0,0 +1,223 @@
+mod tests;
+
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use conduwuit::{
+	debug, implement, info, utils,
+	utils::{stream::TryIgnore, ReadyExt},
+	warn, PduCount, PduEvent, Result, Server,
+};
+use futures::StreamExt;
+use ruma::{events::StateEventType, OwnedEventId, OwnedRoomId, RoomId};
+use serde::Deserialize;
+use tokio::{
+	sync::Notify,
+	time::{interval, MissedTickBehavior},
+};
+
+use crate::{rooms, Dep};
+
+/// Deletes events which have outlived the `m.room.retention` policy of their
+/// room, or the server-wide default when the room has none.
+pub struct Service {
+	interrupt: Notify,
+	services: Services,
+}
+
+struct Services {
+	server: Arc<Server>,
+	metadata: Dep<rooms::metadata::Service>,
+	purge: Dep<rooms::purge::Service>,
+	state: Dep<rooms::state::Service>,
+	state_accessor: Dep<rooms::state_accessor::Service>,
+	timeline: Dep<rooms::timeline::Service>,
+}
+
+/// Content of an `m.room.retention` state event. Lifetimes are in
+/// milliseconds.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Policy {
+	pub min_lifetime: Option<u64>,
+	pub max_lifetime: Option<u64>,
+}
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			interrupt: Notify::new(),
+			services: Services {
+				server: args.server.clone(),
+				metadata: args.depend::<rooms::metadata::Service>("rooms::metadata"),
+				purge: args.depend::<rooms::purge::Service>("rooms::purge"),
+				state: args.depend::<rooms::state::Service>("rooms::state"),
+				state_accessor: args
+					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
+				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
+			},
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result<()> {
+		let config = &self.services.server.config;
+		if !config.retention {
+			return Ok(());
+		}
+
+		let mut i = interval(Duration::from_secs(config.retention_prune_interval.max(1)));
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		loop {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			self.prune().await;
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Runs one pass of the pruning worker over all rooms.
+#[implement(Service)]
+#[tracing::instrument(skip(self), level = "debug")]
+pub async fn prune(&self) {
+	let room_ids: Vec<OwnedRoomId> = self
+		.services
+		.metadata
+		.iter_ids()
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	let mut total: usize = 0;
+	for room_id in &room_ids {
+		if !self.services.server.running() {
+			break;
+		}
+
+		let Some(lifetime) = self.max_lifetime(room_id).await else {
+			continue;
+		};
+
+		match self.prune_room(room_id, lifetime).await {
+			| Ok(pruned) => total = total.saturating_add(pruned),
+			| Err(e) => warn!(%room_id, "Failed to prune expired events: {e}"),
+		}
+	}
+
+	if total > 0 {
+		info!(rooms = room_ids.len(), total, "Pruned expired events");
+	}
+}
+
+/// Lifetime after which events in the room are deleted, or None if they are
+/// kept forever.
+#[implement(Service)]
+pub async fn max_lifetime(&self, room_id: &RoomId) -> Option<Duration> {
+	let config = &self.services.server.config;
+	let policy = self.policy(room_id).await;
+
+	clamp_lifetime(
+		&policy,
+		config
+			.retention_default_max_lifetime
+			.map(Duration::from_secs),
+		config
+			.retention_allowed_min_lifetime
+			.map(Duration::from_secs),
+		config
+			.retention_allowed_max_lifetime
+			.map(Duration::from_secs),
+	)
+}
+
+/// The `m.room.retention` policy in the current state of the room.
+#[implement(Service)]
+pub async fn policy(&self, room_id: &RoomId) -> Policy {
+	self.services
+		.state_accessor
+		.room_state_get_content(room_id, &StateEventType::from("m.room.retention"), "")
+		.await
+		.unwrap_or_default()
+}
+
+/// Deletes the events of a room sent longer than `lifetime` ago, along with
+/// the rows referring to them. State events, forward extremities and the
+/// latest event are kept.
+#[implement(Service)]
+#[tracing::instrument(skip(self), level = "debug")]
+pub async fn prune_room(&self, room_id: &RoomId, lifetime: Duration) -> Result<usize> {
+	let lifetime = u64::try_from(lifetime.as_millis()).unwrap_or(u64::MAX);
+	let cutoff = utils::millis_since_unix_epoch().saturating_sub(lifetime);
+
+	let mut keep: HashSet<OwnedEventId> = self
+		.services
+		.state
+		.get_forward_extremities(room_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	if let Ok(latest) = self.services.timeline.latest_pdu_in_room(room_id).await {
+		keep.insert(latest.event_id);
+	}
+
+	let expired: Vec<(PduCount, PduEvent)> = self
+		.services
+		.timeline
+		.pdus(None, room_id, None)
+		.ignore_err()
+		.ready_take_while(|(_, pdu)| u64::from(pdu.origin_server_ts) < cutoff)
+		.ready_filter(|(_, pdu)| pdu.state_key.is_none() && !keep.contains(&pdu.event_id))
+		.collect()
+		.await;
+
+	if expired.is_empty() {
+		return Ok(0);
+	}
+
+	let removed = self
+		.services
+		.purge
+		.purge_timeline_events(room_id, &expired)
+		.await?;
+
+	debug!(%room_id, pruned = expired.len(), removed, "Pruned expired events");
+
+	Ok(expired.len())
+}
+
+/// Lifetime a policy asks for, or `default_max` if it asks for none, clamped
+/// to the range the server allows and to the policy's own `min_lifetime`.
+fn clamp_lifetime(
+	policy: &Policy,
+	default_max: Option<Duration>,
+	allowed_min: Option<Duration>,
+	allowed_max: Option<Duration>,
+) -> Option<Duration> {
+	let requested = policy
+		.max_lifetime
+		.map(Duration::from_millis)
+		.or(default_max);
+
+	let max = match (requested, allowed_max) {
+		| (Some(requested), Some(allowed_max)) => requested.min(allowed_max),
+		| (requested, allowed_max) => requested.or(allowed_max)?,
+	};
+
+	let min = policy
+		.min_lifetime
+		.map(Duration::from_millis)
+		.into_iter()
+		.chain(allowed_min)
+		.max();
+
+	Some(min.map_or(max, |min| max.max(min)))
+}
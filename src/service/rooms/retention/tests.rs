@@ -0,0 +1,109 @@
+#![cfg(test)]
+
+use std::time::Duration;
+
+use conduwuit::{pdu::PduBuilder, utils};
+use ruma::{
+	events::{room::message::RoomMessageEventContent, StateEventType},
+	MilliSecondsSinceUnixEpoch, UInt,
+};
+
+use super::{clamp_lifetime, Policy};
+use crate::tests::{create_room, offline_services, send, TempDir};
+
+fn days(days: u64) -> Duration { Duration::from_secs(days.saturating_mul(24 * 60 * 60)) }
+
+fn policy(min_days: Option<u64>, max_days: Option<u64>) -> Policy {
+	let millis = |days: u64| days.saturating_mul(24 * 60 * 60 * 1000);
+	Policy {
+		min_lifetime: min_days.map(millis),
+		max_lifetime: max_days.map(millis),
+	}
+}
+
+#[test]
+fn lifetime_clamped_to_allowed_range() {
+	let min = Some(days(1));
+	let max = Some(days(30));
+
+	assert_eq!(clamp_lifetime(&policy(None, Some(7)), None, min, max), Some(days(7)));
+	assert_eq!(
+		clamp_lifetime(&policy(None, Some(365)), None, min, max),
+		Some(days(30)),
+		"longer than allowed"
+	);
+	assert_eq!(
+		clamp_lifetime(&policy(None, Some(0)), None, min, max),
+		Some(days(1)),
+		"shorter than allowed"
+	);
+	assert_eq!(
+		clamp_lifetime(&policy(Some(14), Some(7)), None, None, None),
+		Some(days(14)),
+		"never shorter than the policy's own minimum"
+	);
+}
+
+#[test]
+fn lifetime_defaults() {
+	assert_eq!(
+		clamp_lifetime(&Policy::default(), Some(days(7)), None, None),
+		Some(days(7)),
+		"rooms without a policy use the default"
+	);
+	assert_eq!(
+		clamp_lifetime(&Policy::default(), None, None, Some(days(30))),
+		Some(days(30)),
+		"rooms without a policy are held to the allowed maximum"
+	);
+	assert_eq!(
+		clamp_lifetime(&Policy::default(), None, Some(days(1)), None),
+		None,
+		"kept forever"
+	);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn prune_removes_only_expired_events() {
+	let dir = TempDir::new("retention");
+	let services = offline_services(&dir, "example.com")
+		.await
+		.expect("started services offline");
+
+	let now = utils::millis_since_unix_epoch();
+	let long_ago = MilliSecondsSinceUnixEpoch(
+		UInt::new(now.saturating_sub(60 * 24 * 60 * 60 * 1000)).expect("valid timestamp"),
+	);
+
+	let message = |body: &str, timestamp| PduBuilder {
+		timestamp,
+		..PduBuilder::timeline(&RoomMessageEventContent::text_plain(body))
+	};
+
+	let room_id = create_room(&services, Some(long_ago)).await;
+	let expired = send(&services, &room_id, message("old", Some(long_ago))).await;
+	let recent = send(&services, &room_id, message("new", None)).await;
+
+	let timeline = &services.rooms.timeline;
+	let pruned = services
+		.rooms
+		.retention
+		.prune_room(&room_id, days(30))
+		.await
+		.expect("pruned");
+
+	assert_eq!(pruned, 1, "only the old message expired");
+	assert!(timeline.get_pdu(&expired).await.is_err());
+	assert!(timeline.get_pdu(&recent).await.is_ok());
+	assert!(
+		services
+			.rooms
+			.state_accessor
+			.room_state_get(&room_id, &StateEventType::RoomCreate, "")
+			.await
+			.is_ok(),
+		"state events are kept"
+	);
+
+	services.stop_offline();
+}
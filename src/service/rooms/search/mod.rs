@@ -1,35 +1,46 @@
-use std::sync::Arc;
+mod query;
+mod tests;
+
+use std::{
+	cmp::Ordering,
+	collections::HashMap,
+	fmt,
+	str::FromStr,
+	sync::{Arc, Mutex},
+};
 
 use arrayvec::ArrayVec;
 use conduwuit::{
-	implement,
+	err, implement,
 	utils::{
-		set,
 		stream::{TryIgnore, WidebandExt},
-		ArrayVecExt, IterStream, ReadyExt,
+		u64_from_bytes, IterStream, ReadyExt,
 	},
-	PduCount, PduEvent, Result,
+	Error, PduCount, PduEvent, Result,
+};
+use database::Map;
+use futures::{FutureExt, Stream, StreamExt};
+use ruma::{
+	api::client::search::search_events::v3::{Criteria, OrderBy},
+	OwnedRoomId, UserId,
 };
-use database::{keyval::Val, Map};
-use futures::{Stream, StreamExt};
-use ruma::{api::client::search::search_events::v3::Criteria, RoomId, UserId};
 
+use self::query::Term;
 use crate::{
 	rooms,
-	rooms::{
-		short::ShortRoomId,
-		timeline::{PduId, RawPduId},
-	},
+	rooms::{short::ShortRoomId, timeline::RawPduId},
 	Dep,
 };
 
 pub struct Service {
 	db: Data,
+	stats_lock: Mutex<()>,
 	services: Services,
 }
 
 struct Data {
 	tokenids: Arc<Map>,
+	shortroomid_searchstats: Arc<Map>,
 }
 
 struct Services {
@@ -38,25 +49,86 @@ struct Services {
 	timeline: Dep<rooms::timeline::Service>,
 }
 
+/// A search for messages across one or more rooms.
 #[derive(Clone, Debug)]
-pub struct RoomQuery<'a> {
-	pub room_id: &'a RoomId,
+pub struct Query<'a> {
+	pub rooms: &'a [OwnedRoomId],
 	pub user_id: Option<&'a UserId>,
 	pub criteria: &'a Criteria,
 	pub limit: usize,
-	pub skip: usize,
+	pub next_batch: Option<&'a str>,
+}
+
+/// One page of search results.
+#[derive(Debug, Default)]
+pub struct Results {
+	/// Number of events matching the search terms, before filters and
+	/// visibility are applied.
+	pub count: usize,
+	pub hits: Vec<Hit>,
+	pub highlights: Vec<String>,
+	pub next_batch: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Hit {
+	pub pdu: PduEvent,
+	pub count: PduCount,
+	pub rank: f64,
+}
+
+/// Position of a result in the order results are returned in; pagination
+/// tokens resume after it.
+#[derive(Clone, Copy, Debug)]
+struct Cursor {
+	rank: f64,
+	count: PduCount,
+	shortroomid: ShortRoomId,
+}
+
+/// Totals of a room's index, from which results are ranked.
+#[derive(Clone, Copy, Debug, Default)]
+struct Stats {
+	docs: u64,
+	tokens: u64,
+}
+
+/// Where a word occurs in an event, and the number of words in it.
+#[derive(Clone, Debug, Default)]
+struct Posting {
+	len: u32,
+	positions: Vec<u32>,
 }
 
+/// How often a term occurs in an event, and the number of words in it.
+#[derive(Clone, Copy, Debug)]
+struct Match {
+	len: u32,
+	tf: u32,
+}
+
+type Matches = HashMap<RawPduId, Match>;
+
 type TokenId = ArrayVec<u8, TOKEN_ID_MAX_LEN>;
 
 const TOKEN_ID_MAX_LEN: usize =
 	size_of::<ShortRoomId>() + WORD_MAX_LEN + 1 + size_of::<RawPduId>();
 const WORD_MAX_LEN: usize = 50;
 
+/// BM25 term frequency saturation.
+const BM25_K1: f64 = 1.2;
+
+/// BM25 document length normalization.
+const BM25_B: f64 = 0.75;
+
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
-			db: Data { tokenids: args.db["tokenids"].clone() },
+			db: Data {
+				tokenids: args.db["tokenids"].clone(),
+				shortroomid_searchstats: args.db["shortroomid_searchstats"].clone(),
+			},
+			stats_lock: Mutex::new(()),
 			services: Services {
 				short: args.depend::<rooms::short::Service>("rooms::short"),
 				state_accessor: args
@@ -71,167 +143,446 @@ impl crate::Service for Service {
 
 #[implement(Service)]
 pub fn index_pdu(&self, shortroomid: ShortRoomId, pdu_id: &RawPduId, message_body: &str) {
-	let batch = tokenize(message_body)
-		.map(|word| {
-			let mut key = shortroomid.to_be_bytes().to_vec();
-			key.extend_from_slice(word.as_bytes());
-			key.push(0xFF);
-			key.extend_from_slice(pdu_id.as_ref()); // TODO: currently we save the room id a second time here
-			key
+	let (len, positions) = positions(message_body);
+	if positions.is_empty() {
+		return;
+	}
+
+	let batch = positions
+		.iter()
+		.map(|(word, positions)| {
+			let key = make_tokenid(shortroomid, word, pdu_id);
+			let val = Posting { len, positions: positions.clone() }.encode();
+			(key, val)
 		})
 		.collect::<Vec<_>>();
 
 	self.db
 		.tokenids
-		.insert_batch(batch.iter().map(|k| (k.as_slice(), &[])));
+		.insert_batch(batch.iter().map(|(k, v)| (k.as_slice(), v.as_slice())));
+
+	self.update_stats(shortroomid, |stats| Stats {
+		docs: stats.docs.saturating_add(1),
+		tokens: stats.tokens.saturating_add(len.into()),
+	});
 }
 
+/// Removes an event from the index. The statistics of the room are only
+/// adjusted if the event was indexed, as redactions reach events of any kind.
 #[implement(Service)]
 pub fn deindex_pdu(&self, shortroomid: ShortRoomId, pdu_id: &RawPduId, message_body: &str) {
-	let batch = tokenize(message_body).map(|word| {
-		let mut key = shortroomid.to_be_bytes().to_vec();
-		key.extend_from_slice(word.as_bytes());
-		key.push(0xFF);
-		key.extend_from_slice(pdu_id.as_ref()); // TODO: currently we save the room id a second time here
-		key
-	});
+	let (len, positions) = positions(message_body);
+	let mut indexed = false;
+	for word in positions.keys() {
+		let key = make_tokenid(shortroomid, word, pdu_id);
+		indexed |= self.db.tokenids.get_blocking(&key).is_ok();
+		self.db.tokenids.remove(&key);
+	}
 
-	for token in batch {
-		self.db.tokenids.remove(&token);
+	if !indexed {
+		return;
 	}
+
+	self.update_stats(shortroomid, |stats| Stats {
+		docs: stats.docs.saturating_sub(1),
+		tokens: stats.tokens.saturating_sub(len.into()),
+	});
 }
 
+/// Searches the rooms of the query for messages matching its search term,
+/// returning one page of results ordered by rank or recency.
 #[implement(Service)]
-pub async fn search_pdus<'a>(
-	&'a self,
-	query: &'a RoomQuery<'a>,
-) -> Result<(usize, impl Stream<Item = PduEvent> + Send + 'a)> {
-	let pdu_ids: Vec<_> = self.search_pdu_ids(query).await?.collect().await;
+pub async fn search(&self, query: &Query<'_>) -> Result<Results> {
+	let terms = query::parse(&query.criteria.search_term);
+	if terms.is_empty() {
+		return Ok(Results::default());
+	}
+
+	let after: Option<Cursor> = query.next_batch.map(str::parse).transpose()?;
+	let recent = matches!(query.criteria.order_by, Some(OrderBy::Recent));
+
+	// Terms are weighed by how many events of all the searched rooms they are
+	// found in, so that ranks are comparable across rooms.
+	let mut stats = Stats::default();
+	let mut found_in = vec![0_usize; terms.len()];
+	let mut rooms = Vec::with_capacity(query.rooms.len());
+	for room_id in query.rooms {
+		let Ok(shortroomid) = self.services.short.get_shortroomid(room_id).await else {
+			continue;
+		};
+
+		let room_stats = self.stats(shortroomid).await;
+		stats.docs = stats.docs.saturating_add(room_stats.docs);
+		stats.tokens = stats.tokens.saturating_add(room_stats.tokens);
+
+		let mut matches = Vec::with_capacity(terms.len());
+		for (term, total) in terms.iter().zip(found_in.iter_mut()) {
+			let term_matches = self.search_term(shortroomid, term).await;
+			*total = total.saturating_add(term_matches.len());
+			matches.push(term_matches);
+		}
+
+		rooms.push((shortroomid, matches));
+	}
+
+	let mut candidates = Vec::new();
+	for (shortroomid, matches) in rooms {
+		let ranks = rank(&matches, &found_in, stats);
+		candidates.extend(ranks.into_iter().map(|(pdu_id, rank)| {
+			let cursor = Cursor {
+				rank: if recent { 0.0 } else { rank },
+				count: pdu_id.pdu_count(),
+				shortroomid,
+			};
 
-	let count = pdu_ids.len();
-	let pdus = pdu_ids
+			(cursor, pdu_id, rank)
+		}));
+	}
+
+	let count = candidates.len();
+	candidates.sort_unstable_by(|(a, ..), (b, ..)| b.cmp(a));
+
+	let mut hits: Vec<_> = candidates
 		.into_iter()
+		.filter(|(cursor, ..)| after.is_none_or(|after| *cursor < after))
 		.stream()
-		.wide_filter_map(move |result_pdu_id: RawPduId| async move {
-			self.services
-				.timeline
-				.get_pdu_from_id(&result_pdu_id)
-				.await
-				.ok()
+		.wide_filter_map(|(cursor, pdu_id, rank)| async move {
+			let pdu = self.services.timeline.get_pdu_from_id(&pdu_id).await.ok()?;
+			Some((cursor, Hit { pdu, count: cursor.count, rank }))
 		})
-		.ready_filter(|pdu| !pdu.is_redacted())
-		.ready_filter(|pdu| pdu.matches(&query.criteria.filter))
-		.wide_filter_map(move |pdu| async move {
+		.ready_filter(|(_, hit)| !hit.pdu.is_redacted())
+		.ready_filter(|(_, hit)| hit.pdu.matches(&query.criteria.filter))
+		.wide_filter_map(|(cursor, hit)| async move {
 			self.services
 				.state_accessor
-				.user_can_see_event(query.user_id?, &pdu.room_id, &pdu.event_id)
+				.user_can_see_event(query.user_id?, &hit.pdu.room_id, &hit.pdu.event_id)
 				.await
-				.then_some(pdu)
+				.then_some((cursor, hit))
 		})
-		.skip(query.skip)
-		.take(query.limit);
+		.take(query.limit.saturating_add(1))
+		.collect()
+		.await;
+
+	let next_batch = if hits.len() > query.limit {
+		hits.truncate(query.limit);
+		hits.last().map(|(cursor, _)| cursor.to_string())
+	} else {
+		None
+	};
 
-	Ok((count, pdus))
+	Ok(Results {
+		count,
+		hits: hits.into_iter().map(|(_, hit)| hit).collect(),
+		highlights: query::highlights(&terms),
+		next_batch,
+	})
 }
 
-// result is modeled as a stream such that callers don't have to be refactored
-// though an additional async/wrap still exists for now
+/// Finds the events of a room matching a term.
 #[implement(Service)]
-pub async fn search_pdu_ids(
-	&self,
-	query: &RoomQuery<'_>,
-) -> Result<impl Stream<Item = RawPduId> + Send + '_> {
-	let shortroomid = self.services.short.get_shortroomid(query.room_id).await?;
+async fn search_term(&self, shortroomid: ShortRoomId, term: &Term) -> Matches {
+	match term {
+		| Term::Word(word) =>
+			self.postings(shortroomid, word.as_bytes(), true)
+				.map(|(pdu_id, posting)| (pdu_id, posting.as_match()))
+				.collect()
+				.await,
 
-	let pdu_ids = self.search_pdu_ids_query_room(query, shortroomid).await;
+		| Term::Prefix(prefix) =>
+			self.postings(shortroomid, prefix.as_bytes(), false)
+				.ready_fold(Matches::new(), |mut matches, (pdu_id, posting)| {
+					let found = posting.as_match();
+					matches
+						.entry(pdu_id)
+						.and_modify(|m| m.tf = m.tf.saturating_add(found.tf))
+						.or_insert(found);
 
-	let iters = pdu_ids.into_iter().map(IntoIterator::into_iter);
+					matches
+				})
+				.await,
 
-	Ok(set::intersection(iters).stream())
+		| Term::Phrase(words) => self.search_phrase(shortroomid, words).await,
+	}
 }
 
+/// Finds the events of a room containing the words of a phrase in order.
 #[implement(Service)]
-async fn search_pdu_ids_query_room(
-	&self,
-	query: &RoomQuery<'_>,
-	shortroomid: ShortRoomId,
-) -> Vec<Vec<RawPduId>> {
-	tokenize(&query.criteria.search_term)
+async fn search_phrase(&self, shortroomid: ShortRoomId, words: &[(u32, String)]) -> Matches {
+	let postings: Vec<(u32, HashMap<RawPduId, Posting>)> = words
+		.iter()
 		.stream()
-		.wide_then(|word| async move {
-			self.search_pdu_ids_query_words(shortroomid, &word)
-				.collect::<Vec<_>>()
-				.await
+		.then(|(offset, word)| {
+			self.postings(shortroomid, word.as_bytes(), true)
+				.collect()
+				.map(move |postings| (*offset, postings))
 		})
-		.collect::<Vec<_>>()
-		.await
+		.collect()
+		.await;
+
+	let Some(((_, first), _)) = postings.split_first() else {
+		return Matches::new();
+	};
+
+	first
+		.iter()
+		.filter_map(|(pdu_id, posting)| {
+			let positions = postings
+				.iter()
+				.map(|(offset, postings)| {
+					postings
+						.get(pdu_id)
+						.map(|posting| (*offset, posting.positions.as_slice()))
+				})
+				.collect::<Option<Vec<_>>>()?;
+
+			let tf = query::phrase_count(&positions);
+			(tf > 0).then_some((*pdu_id, Match { len: posting.len, tf }))
+		})
+		.collect()
 }
 
-/// Iterate over PduId's containing a word
+/// Iterate over the events of a room containing a word, or any word
+/// starting with it unless `whole` is set, along with where it occurs in them.
 #[implement(Service)]
-fn search_pdu_ids_query_words<'a>(
+fn postings<'a>(
 	&'a self,
 	shortroomid: ShortRoomId,
-	word: &'a str,
-) -> impl Stream<Item = RawPduId> + Send + '_ {
-	self.search_pdu_ids_query_word(shortroomid, word)
-		.map(move |key| -> RawPduId {
-			let key = &key[prefix_len(word)..];
-			key.into()
+	word: &[u8],
+	whole: bool,
+) -> impl Stream<Item = (RawPduId, Posting)> + Send + 'a {
+	let mut prefix = TokenId::new();
+	prefix.extend_from_slice(&shortroomid.to_be_bytes());
+	prefix.extend_from_slice(word);
+	if whole {
+		prefix.push(database::SEP);
+	}
+
+	self.db
+		.tokenids
+		.raw_stream_from(&prefix)
+		.ignore_err()
+		.ready_take_while(move |(key, _)| key.starts_with(&prefix))
+		.ready_filter_map(|(key, val)| {
+			let word_end = key
+				.iter()
+				.skip(size_of::<ShortRoomId>())
+				.position(|&b| b == database::SEP)?;
+
+			let pdu_id = key.get(prefix_len(word_end)..)?;
+			Some((pdu_id.into(), Posting::decode(val)))
 		})
 }
 
-/// Iterate over raw database results for a word
 #[implement(Service)]
-fn search_pdu_ids_query_word(
-	&self,
-	shortroomid: ShortRoomId,
-	word: &str,
-) -> impl Stream<Item = Val<'_>> + Send + '_ {
-	// rustc says const'ing this not yet stable
-	let end_id: RawPduId = PduId {
-		shortroomid,
-		shorteventid: PduCount::max(),
-	}
-	.into();
-
-	// Newest pdus first
-	let end = make_tokenid(shortroomid, word, &end_id);
-	let prefix = make_prefix(shortroomid, word);
+async fn stats(&self, shortroomid: ShortRoomId) -> Stats {
 	self.db
-		.tokenids
-		.rev_raw_keys_from(&end)
-		.ignore_err()
-		.ready_take_while(move |key| key.starts_with(&prefix))
+		.shortroomid_searchstats
+		.get(&shortroomid.to_be_bytes())
+		.await
+		.map(|val| Stats::decode(&val))
+		.unwrap_or_default()
 }
 
-/// Splits a string into tokens used as keys in the search inverted index
+#[implement(Service)]
+fn update_stats<F>(&self, shortroomid: ShortRoomId, update: F)
+where
+	F: FnOnce(Stats) -> Stats,
+{
+	let _lock = self.stats_lock.lock().expect("locked");
+
+	let key = shortroomid.to_be_bytes();
+	let stats = self
+		.db
+		.shortroomid_searchstats
+		.get_blocking(&key)
+		.map(|val| Stats::decode(&val))
+		.unwrap_or_default();
+
+	self.db
+		.shortroomid_searchstats
+		.insert(&key, update(stats).encode());
+}
+
+/// Ranks the events matching every term, given the matches of each term and
+/// the number of events of the searched rooms each term was found in.
+fn rank(matches: &[Matches], found_in: &[usize], stats: Stats) -> HashMap<RawPduId, f64> {
+	let mut terms = matches.iter().zip(found_in);
+	let Some((first, first_found_in)) = terms.next() else {
+		return HashMap::new();
+	};
+
+	let ranks = first
+		.iter()
+		.map(|(pdu_id, found)| (*pdu_id, bm25(found, *first_found_in, stats)))
+		.collect();
+
+	terms.fold(ranks, |ranks: HashMap<RawPduId, f64>, (matches, found_in)| {
+		ranks
+			.into_iter()
+			.filter_map(|(pdu_id, rank)| {
+				matches
+					.get(&pdu_id)
+					.map(|found| (pdu_id, rank + bm25(found, *found_in, stats)))
+			})
+			.collect()
+	})
+}
+
+/// Okapi BM25 weight of a term in an event, given the number of events the
+/// term was found in.
+fn bm25(found: &Match, found_in: usize, stats: Stats) -> f64 {
+	#[allow(clippy::as_conversions, clippy::cast_precision_loss)]
+	let (docs, found_in, avg_len) = {
+		let docs = stats.docs.max(found_in.try_into().unwrap_or(u64::MAX)) as f64;
+		let avg_len = (stats.docs > 0).then(|| stats.tokens as f64 / stats.docs as f64);
+		(docs, found_in as f64, avg_len)
+	};
+
+	let (tf, len) = (f64::from(found.tf), f64::from(found.len));
+	let norm = match avg_len {
+		| Some(avg_len) if avg_len > 0.0 => 1.0 - BM25_B + BM25_B * len / avg_len,
+		| _ => 1.0,
+	};
+
+	let idf = ((docs - found_in + 0.5) / (found_in + 0.5)).ln_1p();
+	idf * tf * (BM25_K1 + 1.0) / (tf + BM25_K1 * norm)
+}
+
+/// Splits a string into the words of the search inverted index along with
+/// their position in it.
 ///
 /// This may be used to tokenize both message bodies (for indexing) or search
 /// queries (for querying).
-fn tokenize(body: &str) -> impl Iterator<Item = String> + Send + '_ {
+fn tokenize(body: &str) -> impl Iterator<Item = (u32, String)> + Send + '_ {
+	words(body)
+		.enumerate()
+		.filter(|(_, word)| word.len() <= WORD_MAX_LEN)
+		.map(|(pos, word)| (pos.try_into().unwrap_or(u32::MAX), word.to_lowercase()))
+}
+
+fn words(body: &str) -> impl Iterator<Item = &str> + Send + '_ {
 	body.split_terminator(|c: char| !c.is_alphanumeric())
 		.filter(|s| !s.is_empty())
-		.filter(|word| word.len() <= WORD_MAX_LEN)
-		.map(str::to_lowercase)
 }
 
-fn make_tokenid(shortroomid: ShortRoomId, word: &str, pdu_id: &RawPduId) -> TokenId {
-	let mut key = make_prefix(shortroomid, word);
-	key.extend_from_slice(pdu_id.as_ref());
-	key
+/// Groups the positions of each word of a message body, along with the number
+/// of words in it.
+fn positions(body: &str) -> (u32, HashMap<String, Vec<u32>>) {
+	let len = words(body).count().try_into().unwrap_or(u32::MAX);
+	let positions = tokenize(body).fold(HashMap::new(), |mut positions, (pos, word)| {
+		positions.entry(word).or_insert_with(Vec::new).push(pos);
+
+		positions
+	});
+
+	(len, positions)
 }
 
-fn make_prefix(shortroomid: ShortRoomId, word: &str) -> TokenId {
+fn make_tokenid(shortroomid: ShortRoomId, word: &str, pdu_id: &RawPduId) -> TokenId {
 	let mut key = TokenId::new();
 	key.extend_from_slice(&shortroomid.to_be_bytes());
 	key.extend_from_slice(word.as_bytes());
 	key.push(database::SEP);
+	key.extend_from_slice(pdu_id.as_ref());
 	key
 }
 
-fn prefix_len(word: &str) -> usize {
+fn prefix_len(word_len: usize) -> usize {
 	size_of::<ShortRoomId>()
-		.saturating_add(word.len())
+		.saturating_add(word_len)
 		.saturating_add(1)
 }
+
+impl Posting {
+	/// Length followed by positions, each a big-endian u32. Entries indexed
+	/// before positions were recorded have no value.
+	fn encode(&self) -> Vec<u8> {
+		std::iter::once(self.len)
+			.chain(self.positions.iter().copied())
+			.flat_map(u32::to_be_bytes)
+			.collect()
+	}
+
+	fn decode(val: &[u8]) -> Self {
+		let mut ints = val
+			.chunks_exact(size_of::<u32>())
+			.filter_map(|int| int.try_into().ok())
+			.map(u32::from_be_bytes);
+
+		Self {
+			len: ints.next().unwrap_or(0),
+			positions: ints.collect(),
+		}
+	}
+
+	fn as_match(&self) -> Match {
+		let tf = self.positions.len().try_into().unwrap_or(u32::MAX);
+		Match { len: self.len, tf: tf.max(1) }
+	}
+}
+
+impl Stats {
+	fn encode(self) -> [u8; 16] {
+		let mut val = [0; 16];
+		let (docs, tokens) = val.split_at_mut(size_of::<u64>());
+		docs.copy_from_slice(&self.docs.to_be_bytes());
+		tokens.copy_from_slice(&self.tokens.to_be_bytes());
+		val
+	}
+
+	fn decode(val: &[u8]) -> Self {
+		let (docs, tokens) = val.split_at(val.len().min(size_of::<u64>()));
+		Self {
+			docs: u64_from_bytes(docs).unwrap_or(0),
+			tokens: u64_from_bytes(tokens).unwrap_or(0),
+		}
+	}
+}
+
+impl Ord for Cursor {
+	fn cmp(&self, other: &Self) -> Ordering {
+		self.rank
+			.total_cmp(&other.rank)
+			.then_with(|| self.count.cmp(&other.count))
+			.then_with(|| self.shortroomid.cmp(&other.shortroomid))
+	}
+}
+
+impl PartialOrd for Cursor {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl PartialEq for Cursor {
+	fn eq(&self, other: &Self) -> bool { self.cmp(other) == Ordering::Equal }
+}
+
+impl Eq for Cursor {}
+
+impl fmt::Display for Cursor {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{:x}_{}_{}", self.rank.to_bits(), self.count, self.shortroomid)
+	}
+}
+
+impl FromStr for Cursor {
+	type Err = Error;
+
+	fn from_str(token: &str) -> Result<Self> {
+		let invalid = || err!(Request(InvalidParam("Invalid next_batch token.")));
+
+		let mut parts = token.split('_');
+		let (Some(rank), Some(count), Some(shortroomid), None) =
+			(parts.next(), parts.next(), parts.next(), parts.next())
+		else {
+			return Err(invalid());
+		};
+
+		Ok(Self {
+			rank: u64::from_str_radix(rank, 16)
+				.map(f64::from_bits)
+				.map_err(|_| invalid())?,
+			count: count.parse().map_err(|_| invalid())?,
+			shortroomid: shortroomid.parse().map_err(|_| invalid())?,
+		})
+	}
+}
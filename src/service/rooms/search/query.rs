@@ -0,0 +1,91 @@
+use super::tokenize;
+
+/// Part of a search query which every result has to match.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(super) enum Term {
+	/// A whole word.
+	Word(String),
+
+	/// Any word starting with this, from a query word ending in `*`.
+	Prefix(String),
+
+	/// Words which have to appear in this order, from a quoted part of the
+	/// query or a word joined by punctuation. Each word is paired with its
+	/// position in the phrase.
+	Phrase(Vec<(u32, String)>),
+}
+
+/// Prefixes shorter than this are searched as whole words, as they would
+/// match most of the index.
+const PREFIX_MIN_LEN: usize = 2;
+
+/// Parses a search term into the terms results have to match.
+pub(super) fn parse(search_term: &str) -> Vec<Term> {
+	search_term
+		.split('"')
+		.enumerate()
+		.flat_map(|(i, part)| {
+			if i % 2 == 1 {
+				vec![term(part, false)]
+			} else {
+				part.split_whitespace()
+					.map(|chunk| term(chunk, chunk.ends_with('*')))
+					.collect()
+			}
+		})
+		.flatten()
+		.collect()
+}
+
+/// Words to highlight in results.
+pub(super) fn highlights(terms: &[Term]) -> Vec<String> {
+	let mut highlights: Vec<_> = terms
+		.iter()
+		.flat_map(|term| match term {
+			| Term::Word(word) | Term::Prefix(word) => vec![word.clone()],
+			| Term::Phrase(words) => words.iter().map(|(_, word)| word.clone()).collect(),
+		})
+		.collect();
+
+	highlights.sort_unstable();
+	highlights.dedup();
+	highlights
+}
+
+/// Counts the occurrences of a phrase in an event, given where each word of
+/// the phrase occurs in it along with the word's position in the phrase.
+pub(super) fn phrase_count(words: &[(u32, &[u32])]) -> u32 {
+	let Some((&(first_offset, first), rest)) = words.split_first() else {
+		return 0;
+	};
+
+	let count = first
+		.iter()
+		.filter_map(|pos| pos.checked_sub(first_offset))
+		.filter(|start| {
+			rest.iter().all(|(offset, positions)| {
+				start
+					.checked_add(*offset)
+					.is_some_and(|pos| positions.binary_search(&pos).is_ok())
+			})
+		})
+		.count();
+
+	count.try_into().unwrap_or(u32::MAX)
+}
+
+fn term(text: &str, prefix: bool) -> Option<Term> {
+	let words: Vec<_> = tokenize(text).collect();
+	match words.as_slice() {
+		| [] => None,
+		| [(_, word)] if prefix && word.len() >= PREFIX_MIN_LEN =>
+			Some(Term::Prefix(word.clone())),
+		| [(_, word)] => Some(Term::Word(word.clone())),
+		| [(first, _), ..] => Some(Term::Phrase(
+			words
+				.iter()
+				.map(|(pos, word)| (pos.saturating_sub(*first), word.clone()))
+				.collect(),
+		)),
+	}
+}
@@ -0,0 +1,131 @@
+#![cfg(test)]
+
+use conduwuit::PduCount;
+
+use super::{
+	bm25, positions,
+	query::{self, Term},
+	rank, Cursor, Match, Matches, Posting, Stats,
+};
+use crate::{
+	rooms::timeline::{PduId, RawPduId},
+	tests::{offline_services, TempDir},
+};
+
+fn pdu_id(shortroomid: u64, count: u64) -> RawPduId {
+	PduId {
+		shortroomid,
+		shorteventid: PduCount::Normal(count),
+	}
+	.into()
+}
+
+#[test]
+fn parse_terms() {
+	let terms = query::parse(r#"Hello wor* "big  Cat" e-mail a*"#);
+	assert_eq!(terms, vec![
+		Term::Word("hello".into()),
+		Term::Prefix("wor".into()),
+		Term::Phrase(vec![(0, "big".into()), (1, "cat".into())]),
+		Term::Phrase(vec![(0, "e".into()), (1, "mail".into())]),
+		Term::Word("a".into()),
+	]);
+
+	assert!(query::parse(r#" "" * "#).is_empty(), "no terms in punctuation");
+}
+
+#[test]
+fn phrase_positions() {
+	let (len, positions) = positions("the cat sat on the mat, the cat");
+	assert_eq!(len, 8, "every word counts towards the length");
+	assert_eq!(positions["the"], vec![0, 4, 6]);
+	assert_eq!(positions["cat"], vec![1, 7]);
+
+	let the_cat = [(0, positions["the"].as_slice()), (1, positions["cat"].as_slice())];
+	assert_eq!(query::phrase_count(&the_cat), 2);
+
+	let cat_the = [(0, positions["cat"].as_slice()), (1, positions["the"].as_slice())];
+	assert_eq!(query::phrase_count(&cat_the), 0);
+}
+
+#[test]
+fn posting_roundtrip() {
+	let posting = Posting { len: 12, positions: vec![3, 7] };
+	let decoded = Posting::decode(&posting.encode());
+	assert_eq!(decoded.len, 12);
+	assert_eq!(decoded.positions, vec![3, 7]);
+
+	let legacy = Posting::decode(&[]);
+	assert_eq!(legacy.as_match().tf, 1, "entries without positions still match");
+}
+
+#[test]
+fn bm25_ranking() {
+	let stats = Stats { docs: 100, tokens: 1000 };
+	let weight = |tf, len, found_in| bm25(&Match { len, tf }, found_in, stats);
+
+	assert!(weight(3, 10, 5) > weight(1, 10, 5), "frequent terms rank higher");
+	assert!(weight(1, 5, 5) > weight(1, 50, 5), "shorter events rank higher");
+	assert!(weight(1, 10, 2) > weight(1, 10, 50), "rarer terms rank higher");
+	assert!(weight(1, 10, 100) > 0.0, "common terms still rank");
+}
+
+#[test]
+fn cursor_roundtrip() {
+	let cursor: Cursor = "3ff8000000000000_42_7".parse().expect("valid token");
+	assert!((cursor.rank - 1.5).abs() < f64::EPSILON, "rank is decoded");
+	assert_eq!(cursor.to_string(), "3ff8000000000000_42_7");
+
+	let lower: Cursor = "3ff8000000000000_41_7".parse().expect("valid token");
+	assert!(lower < cursor, "ties on rank are ordered by recency");
+
+	assert!("42".parse::<Cursor>().is_err(), "offsets are not tokens");
+}
+
+#[test]
+fn ranks_across_rooms() {
+	let stats = Stats { docs: 100, tokens: 1000 };
+	let found = Match { len: 10, tf: 1 };
+	let (a, b, c) = (pdu_id(1, 1), pdu_id(1, 2), pdu_id(2, 1));
+
+	let hello: Matches = [(a, found), (b, found), (c, found)].into();
+	let world: Matches = [(a, found), (c, found)].into();
+	let ranks = rank(&[hello, world], &[3, 2], stats);
+
+	assert_eq!(ranks.len(), 2, "only events matching every term");
+	assert!(!ranks.contains_key(&b));
+	assert!(
+		(ranks[&a] - ranks[&c]).abs() < f64::EPSILON,
+		"equal matches rank equally whichever room they are in"
+	);
+	assert!(rank(&[], &[], stats).is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stats_follow_indexed_events() {
+	let dir = TempDir::new("search");
+	let services = offline_services(&dir, "example.com")
+		.await
+		.expect("started services offline");
+
+	let search = &services.rooms.search;
+	let shortroomid = u64::MAX;
+
+	search.index_pdu(shortroomid, &pdu_id(shortroomid, 1), "hello world");
+	search.index_pdu(shortroomid, &pdu_id(shortroomid, 2), "...");
+	let stats = search.stats(shortroomid).await;
+	assert_eq!((stats.docs, stats.tokens), (1, 2), "events without words are not counted");
+
+	search.deindex_pdu(shortroomid, &pdu_id(shortroomid, 3), "never indexed");
+	let stats = search.stats(shortroomid).await;
+	assert_eq!((stats.docs, stats.tokens), (1, 2), "unindexed events are not subtracted");
+
+	search.deindex_pdu(shortroomid, &pdu_id(shortroomid, 1), "hello world");
+	let stats = search.stats(shortroomid).await;
+	assert_eq!((stats.docs, stats.tokens), (0, 0));
+
+	search.deindex_pdu(shortroomid, &pdu_id(shortroomid, 1), "hello world");
+	assert_eq!(search.stats(shortroomid).await.docs, 0, "removed once");
+
+	services.stop_offline();
+}
@@ -587,6 +587,27 @@ impl Service {
 		}
 	}
 
+	/// Checks if a user may upgrade a room, which takes sending its
+	/// tombstone.
+	pub async fn user_can_upgrade(&self, room_id: &RoomId, user_id: &UserId) -> bool {
+		if let Ok(power_levels) = self
+			.room_state_get_content::<RoomPowerLevelsEventContent>(
+				room_id,
+				&StateEventType::RoomPowerLevels,
+				"",
+			)
+			.await
+		{
+			return RoomPowerLevels::from(power_levels)
+				.user_can_send_state(user_id, StateEventType::RoomTombstone);
+		}
+
+		// Without power levels only the creator may
+		self.room_state_get(room_id, &StateEventType::RoomCreate, "")
+			.await
+			.is_ok_and(|create| create.sender == user_id)
+	}
+
 	/// Returns the join rule (`SpaceRoomJoinRule`) for a given room
 	pub async fn get_join_rule(
 		&self,
@@ -28,7 +28,9 @@ use ruma::{
 	OwnedRoomId, OwnedServerName, RoomId, ServerName, UserId,
 };
 
-use crate::{account_data, appservice::RegistrationInfo, globals, rooms, users, Dep};
+use crate::{
+	account_data, appservice::RegistrationInfo, globals, rooms, user_directory, users, Dep,
+};
 
 pub struct Service {
 	appservice_in_room_cache: AppServiceInRoomCache,
@@ -40,6 +42,7 @@ struct Services {
 	account_data: Dep<account_data::Service>,
 	globals: Dep<globals::Service>,
 	state_accessor: Dep<rooms::state_accessor::Service>,
+	user_directory: Dep<user_directory::Service>,
 	users: Dep<users::Service>,
 }
 
@@ -73,6 +76,7 @@ impl crate::Service for Service {
 				globals: args.depend::<globals::Service>("globals"),
 				state_accessor: args
 					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
+				user_directory: args.depend::<user_directory::Service>("user_directory"),
 				users: args.depend::<users::Service>("users"),
 			},
 			db: Data {
@@ -120,7 +124,7 @@ impl Service {
 		invite_via: Option<Vec<OwnedServerName>>,
 		update_joined_count: bool,
 	) -> Result<()> {
-		let membership = membership_event.membership;
+		let membership = &membership_event.membership;
 
 		// Keep track what remote users exist by adding them as "deactivated" users
 		//
@@ -157,7 +161,7 @@ impl Service {
 			*/
 		}
 
-		match &membership {
+		match membership {
 			| MembershipState::Join => {
 				// Check if the user never joined this room
 				if !self.once_joined(user_id, room_id).await {
@@ -271,6 +275,11 @@ impl Service {
 			| _ => {},
 		}
 
+		self.services
+			.user_directory
+			.update_membership(user_id, &membership_event)
+			.await;
+
 		if update_joined_count {
 			self.update_joined_count(room_id).await;
 		}
@@ -560,6 +569,23 @@ impl Service {
 			.map(|(_, user_id): (Ignore, &UserId)| user_id)
 	}
 
+	/// Returns an iterator over the users who left a room, with the count at
+	/// which they left.
+	#[tracing::instrument(skip(self), level = "debug")]
+	pub fn room_members_left<'a>(
+		&'a self,
+		room_id: &'a RoomId,
+	) -> impl Stream<Item = (&UserId, u64)> + Send + 'a {
+		type KeyVal<'a> = ((Ignore, &'a UserId), u64);
+
+		let prefix = (room_id, Interfix);
+		self.db
+			.roomuserid_leftcount
+			.stream_prefix(&prefix)
+			.ignore_err()
+			.map(|((_, user_id), count): KeyVal<'_>| (user_id, count))
+	}
+
 	/// Returns an iterator over all invited members of a room.
 	#[tracing::instrument(skip(self), level = "debug")]
 	pub fn room_members_invited<'a>(
@@ -47,7 +47,7 @@ pub use self::data::PdusIterItem;
 use crate::{
 	account_data, admin, appservice,
 	appservice::NamespaceRegex,
-	globals, pusher, rooms,
+	globals, policy_lists, pusher, rooms,
 	rooms::{short::ShortRoomId, state_compressor::CompressedStateEvent},
 	sending, server_keys, users, Dep,
 };
@@ -98,6 +98,7 @@ struct Services {
 	user: Dep<rooms::user::Service>,
 	users: Dep<users::Service>,
 	pusher: Dep<pusher::Service>,
+	policy_lists: Dep<policy_lists::Service>,
 	threads: Dep<rooms::threads::Service>,
 	search: Dep<rooms::search::Service>,
 	spaces: Dep<rooms::spaces::Service>,
@@ -129,6 +130,7 @@ impl crate::Service for Service {
 				user: args.depend::<rooms::user::Service>("rooms::user"),
 				users: args.depend::<users::Service>("users"),
 				pusher: args.depend::<pusher::Service>("pusher"),
+				policy_lists: args.depend::<policy_lists::Service>("policy_lists"),
 				threads: args.depend::<rooms::threads::Service>("rooms::threads"),
 				search: args.depend::<rooms::search::Service>("rooms::search"),
 				spaces: args.depend::<rooms::spaces::Service>("rooms::spaces"),
@@ -278,55 +280,7 @@ impl Service {
 			.await
 			.map_err(|_| err!(Database("Room does not exist")))?;
 
-		// Make unsigned fields correct. This is not properly documented in the spec,
-		// but state events need to have previous content in the unsigned field, so
-		// clients can easily interpret things like membership changes
-		if let Some(state_key) = &pdu.state_key {
-			if let CanonicalJsonValue::Object(unsigned) = pdu_json
-				.entry("unsigned".to_owned())
-				.or_insert_with(|| CanonicalJsonValue::Object(BTreeMap::default()))
-			{
-				if let Ok(shortstatehash) = self
-					.services
-					.state_accessor
-					.pdu_shortstatehash(&pdu.event_id)
-					.await
-				{
-					if let Ok(prev_state) = self
-						.services
-						.state_accessor
-						.state_get(shortstatehash, &pdu.kind.to_string().into(), state_key)
-						.await
-					{
-						unsigned.insert(
-							"prev_content".to_owned(),
-							CanonicalJsonValue::Object(
-								utils::to_canonical_object(prev_state.content.clone()).map_err(
-									|e| {
-										error!(
-											"Failed to convert prev_state to canonical JSON: {e}"
-										);
-										Error::bad_database(
-											"Failed to convert prev_state to canonical JSON.",
-										)
-									},
-								)?,
-							),
-						);
-						unsigned.insert(
-							String::from("prev_sender"),
-							CanonicalJsonValue::String(prev_state.sender.to_string()),
-						);
-						unsigned.insert(
-							String::from("replaces_state"),
-							CanonicalJsonValue::String(prev_state.event_id.to_string()),
-						);
-					}
-				}
-			} else {
-				error!("Invalid unsigned type in pdu.");
-			}
-		}
+		self.add_prev_content(pdu, &mut pdu_json).await?;
 
 		// We must keep track of all events that have been referenced.
 		self.services
@@ -449,39 +403,9 @@ impl Service {
 		self.db
 			.increment_notification_counts(&pdu.room_id, notifies, highlights);
 
+		self.index_pdu(pdu, &pdu_id, count2, shortroomid).await?;
+
 		match pdu.kind {
-			| TimelineEventType::RoomRedaction => {
-				use RoomVersionId::*;
-
-				let room_version_id = self.services.state.get_room_version(&pdu.room_id).await?;
-				match room_version_id {
-					| V1 | V2 | V3 | V4 | V5 | V6 | V7 | V8 | V9 | V10 => {
-						if let Some(redact_id) = &pdu.redacts {
-							if self
-								.services
-								.state_accessor
-								.user_can_redact(redact_id, &pdu.sender, &pdu.room_id, false)
-								.await?
-							{
-								self.redact_pdu(redact_id, pdu, shortroomid).await?;
-							}
-						}
-					},
-					| _ => {
-						let content: RoomRedactionEventContent = pdu.get_content()?;
-						if let Some(redact_id) = &content.redacts {
-							if self
-								.services
-								.state_accessor
-								.user_can_redact(redact_id, &pdu.sender, &pdu.room_id, false)
-								.await?
-							{
-								self.redact_pdu(redact_id, pdu, shortroomid).await?;
-							}
-						}
-					},
-				};
-			},
 			| TimelineEventType::SpaceChild =>
 				if let Some(_state_key) = &pdu.state_key {
 					self.services
@@ -491,80 +415,22 @@ impl Service {
 						.await
 						.remove(&pdu.room_id);
 				},
-			| TimelineEventType::RoomMember => {
-				if let Some(state_key) = &pdu.state_key {
-					// if the state_key fails
-					let target_user_id = UserId::parse(state_key)
-						.expect("This state_key was previously validated");
-
-					let content: RoomMemberEventContent = pdu.get_content()?;
-					let stripped_state = match content.membership {
-						| MembershipState::Invite | MembershipState::Knock =>
-							self.services.state.summary_stripped(pdu).await.into(),
-						| _ => None,
-					};
-
-					// Update our membership info, we do this here incase a user is invited or
-					// knocked and immediately leaves we need the DB to record the invite or
-					// knock event for auth
-					self.services
-						.state_cache
-						.update_membership(
-							&pdu.room_id,
-							target_user_id,
-							content,
-							&pdu.sender,
-							stripped_state,
-							None,
-							true,
-						)
-						.await?;
-				}
-			},
 			| TimelineEventType::RoomMessage => {
 				let content: ExtractBody = pdu.get_content()?;
 				if let Some(body) = content.body {
-					self.services.search.index_pdu(shortroomid, &pdu_id, &body);
-
 					if self.services.admin.is_admin_command(pdu, &body).await {
-						self.services
-							.admin
-							.command(body, Some((*pdu.event_id).into()))?;
+						self.services.admin.command(
+							body,
+							Some((*pdu.event_id).into()),
+							Some(pdu.sender.clone()),
+						)?;
 					}
 				}
 			},
 			| _ => {},
 		}
 
-		if let Ok(content) = pdu.get_content::<ExtractRelatesToEventId>() {
-			if let Ok(related_pducount) = self.get_pdu_count(&content.relates_to.event_id).await {
-				self.services
-					.pdu_metadata
-					.add_relation(count2, related_pducount);
-			}
-		}
-
-		if let Ok(content) = pdu.get_content::<ExtractRelatesTo>() {
-			match content.relates_to {
-				| Relation::Reply { in_reply_to } => {
-					// We need to do it again here, because replies don't have
-					// event_id as a top level field
-					if let Ok(related_pducount) = self.get_pdu_count(&in_reply_to.event_id).await
-					{
-						self.services
-							.pdu_metadata
-							.add_relation(count2, related_pducount);
-					}
-				},
-				| Relation::Thread(thread) => {
-					self.services
-						.threads
-						.add_to_thread(&thread.event_id, pdu)
-						.await?;
-				},
-				| _ => {}, // TODO: Aggregate other types
-			}
-		}
+		self.services.policy_lists.update(pdu).await;
 
 		for appservice in self.services.appservice.read().await.values() {
 			if self
@@ -981,6 +847,49 @@ impl Service {
 		Ok(Some(pdu_id))
 	}
 
+	/// Appends an event of history imported from another server. It is stored
+	/// and indexed like any other, with the state before it, but nobody is
+	/// notified of it, it is not sent anywhere and no command in it is run.
+	#[tracing::instrument(level = "debug", skip_all)]
+	pub async fn append_imported_pdu(
+		&self,
+		pdu: &PduEvent,
+		mut pdu_json: CanonicalJsonObject,
+		state_ids_compressed: Arc<HashSet<CompressedStateEvent>>,
+		_state_lock: &RoomMutexGuard, /* Take mutex guard to make sure users get the room
+		                               * state mutex */
+	) -> Result<RawPduId> {
+		self.services
+			.state
+			.set_event_state(&pdu.event_id, &pdu.room_id, state_ids_compressed)
+			.await?;
+
+		let _cork = self.db.db.cork_and_flush();
+
+		let shortroomid = self
+			.services
+			.short
+			.get_shortroomid(&pdu.room_id)
+			.await
+			.map_err(|_| err!(Database("Room does not exist")))?;
+
+		self.add_prev_content(pdu, &mut pdu_json).await?;
+
+		self.services
+			.pdu_metadata
+			.mark_as_referenced(&pdu.room_id, pdu.prev_events.iter().map(AsRef::as_ref));
+
+		let insert_lock = self.mutex_insert.lock(&pdu.room_id).await;
+		let count = PduCount::Normal(self.services.globals.next_count()?);
+		let pdu_id: RawPduId = PduId { shortroomid, shorteventid: count }.into();
+		self.db.append_pdu(&pdu_id, pdu, &pdu_json, count).await;
+		drop(insert_lock);
+
+		self.index_pdu(pdu, &pdu_id, count, shortroomid).await?;
+
+		Ok(pdu_id)
+	}
+
 	/// Returns an iterator over all PDUs in a room. Unknown rooms produce no
 	/// items.
 	#[inline]
@@ -1223,6 +1132,174 @@ impl Service {
 	}
 }
 
+/// Makes the unsigned fields of a state event correct. This is not properly
+/// documented in the spec, but state events need to have previous content in
+/// the unsigned field, so clients can easily interpret things like membership
+/// changes.
+#[implement(Service)]
+async fn add_prev_content(&self, pdu: &PduEvent, pdu_json: &mut CanonicalJsonObject) -> Result {
+	if let Some(state_key) = &pdu.state_key {
+		if let CanonicalJsonValue::Object(unsigned) = pdu_json
+			.entry("unsigned".to_owned())
+			.or_insert_with(|| CanonicalJsonValue::Object(BTreeMap::default()))
+		{
+			if let Ok(shortstatehash) = self
+				.services
+				.state_accessor
+				.pdu_shortstatehash(&pdu.event_id)
+				.await
+			{
+				if let Ok(prev_state) = self
+					.services
+					.state_accessor
+					.state_get(shortstatehash, &pdu.kind.to_string().into(), state_key)
+					.await
+				{
+					unsigned.insert(
+						"prev_content".to_owned(),
+						CanonicalJsonValue::Object(
+							utils::to_canonical_object(prev_state.content.clone()).map_err(
+								|e| {
+									error!("Failed to convert prev_state to canonical JSON: {e}");
+									Error::bad_database(
+										"Failed to convert prev_state to canonical JSON.",
+									)
+								},
+							)?,
+						),
+					);
+					unsigned.insert(
+						String::from("prev_sender"),
+						CanonicalJsonValue::String(prev_state.sender.to_string()),
+					);
+					unsigned.insert(
+						String::from("replaces_state"),
+						CanonicalJsonValue::String(prev_state.event_id.to_string()),
+					);
+				}
+			}
+		} else {
+			error!("Invalid unsigned type in pdu.");
+		}
+	}
+
+	Ok(())
+}
+
+/// Records what an appended event changes in the history of its room:
+/// redactions, memberships, the search index, relations and threads.
+#[implement(Service)]
+async fn index_pdu(
+	&self,
+	pdu: &PduEvent,
+	pdu_id: &RawPduId,
+	count: PduCount,
+	shortroomid: ShortRoomId,
+) -> Result {
+	match pdu.kind {
+		| TimelineEventType::RoomRedaction => {
+			use RoomVersionId::*;
+
+			let room_version_id = self.services.state.get_room_version(&pdu.room_id).await?;
+			match room_version_id {
+				| V1 | V2 | V3 | V4 | V5 | V6 | V7 | V8 | V9 | V10 => {
+					if let Some(redact_id) = &pdu.redacts {
+						if self
+							.services
+							.state_accessor
+							.user_can_redact(redact_id, &pdu.sender, &pdu.room_id, false)
+							.await?
+						{
+							self.redact_pdu(redact_id, pdu, shortroomid).await?;
+						}
+					}
+				},
+				| _ => {
+					let content: RoomRedactionEventContent = pdu.get_content()?;
+					if let Some(redact_id) = &content.redacts {
+						if self
+							.services
+							.state_accessor
+							.user_can_redact(redact_id, &pdu.sender, &pdu.room_id, false)
+							.await?
+						{
+							self.redact_pdu(redact_id, pdu, shortroomid).await?;
+						}
+					}
+				},
+			};
+		},
+		| TimelineEventType::RoomMember => {
+			if let Some(state_key) = &pdu.state_key {
+				// if the state_key fails
+				let target_user_id =
+					UserId::parse(state_key).expect("This state_key was previously validated");
+
+				let content: RoomMemberEventContent = pdu.get_content()?;
+				let stripped_state = match content.membership {
+					| MembershipState::Invite | MembershipState::Knock =>
+						self.services.state.summary_stripped(pdu).await.into(),
+					| _ => None,
+				};
+
+				// Update our membership info, we do this here incase a user is invited or
+				// knocked and immediately leaves we need the DB to record the invite or
+				// knock event for auth
+				self.services
+					.state_cache
+					.update_membership(
+						&pdu.room_id,
+						target_user_id,
+						content,
+						&pdu.sender,
+						stripped_state,
+						None,
+						true,
+					)
+					.await?;
+			}
+		},
+		| TimelineEventType::RoomMessage => {
+			let content: ExtractBody = pdu.get_content()?;
+			if let Some(body) = content.body {
+				self.services.search.index_pdu(shortroomid, pdu_id, &body);
+			}
+		},
+		| _ => {},
+	}
+
+	if let Ok(content) = pdu.get_content::<ExtractRelatesToEventId>() {
+		if let Ok(related_pducount) = self.get_pdu_count(&content.relates_to.event_id).await {
+			self.services
+				.pdu_metadata
+				.add_relation(count, related_pducount);
+		}
+	}
+
+	if let Ok(content) = pdu.get_content::<ExtractRelatesTo>() {
+		match content.relates_to {
+			| Relation::Reply { in_reply_to } => {
+				// We need to do it again here, because replies don't have
+				// event_id as a top level field
+				if let Ok(related_pducount) = self.get_pdu_count(&in_reply_to.event_id).await {
+					self.services
+						.pdu_metadata
+						.add_relation(count, related_pducount);
+				}
+			},
+			| Relation::Thread(thread) => {
+				self.services
+					.threads
+					.add_to_thread(&thread.event_id, pdu)
+					.await?;
+			},
+			| _ => {}, // TODO: Aggregate other types
+		}
+	}
+
+	Ok(())
+}
+
 #[implement(Service)]
 #[tracing::instrument(skip_all, level = "debug")]
 async fn check_pdu_for_admin_room(&self, pdu: &PduEvent, sender: &UserId) -> Result<()> {
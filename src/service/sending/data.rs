@@ -18,6 +18,7 @@ pub(super) type QueueItem = (Key, SendingEvent);
 pub(super) type Key = Vec<u8>;
 
 pub struct Data {
+	appserviceid_educount: Arc<Map>,
 	servercurrentevent_data: Arc<Map>,
 	servernameevent_data: Arc<Map>,
 	servername_educount: Arc<Map>,
@@ -33,6 +34,7 @@ impl Data {
 	pub(super) fn new(args: &crate::Args<'_>) -> Self {
 		let db = &args.db;
 		Self {
+			appserviceid_educount: db["appserviceid_educount"].clone(),
 			servercurrentevent_data: db["servercurrentevent_data"].clone(),
 			servernameevent_data: db["servernameevent_data"].clone(),
 			servername_educount: db["servername_educount"].clone(),
@@ -170,6 +172,16 @@ impl Data {
 			})
 	}
 
+	/// Destination of every queued request which is not yet active; one item
+	/// per request.
+	pub fn queued_destinations(&self) -> impl Stream<Item = Destination> + Send + '_ {
+		self.servernameevent_data
+			.raw_stream()
+			.ignore_err()
+			.ready_filter_map(|(key, val)| parse_servercurrentevent(key, val).ok())
+			.map(at!(0))
+	}
+
 	pub(super) fn set_latest_educount(&self, server_name: &ServerName, last_count: u64) {
 		self.servername_educount.raw_put(server_name, last_count);
 	}
@@ -181,6 +193,18 @@ impl Data {
 			.deserialized()
 			.unwrap_or(0)
 	}
+
+	pub(super) fn set_latest_appservice_educount(&self, appservice_id: &str, last_count: u64) {
+		self.appserviceid_educount.raw_put(appservice_id, last_count);
+	}
+
+	pub async fn get_latest_appservice_educount(&self, appservice_id: &str) -> u64 {
+		self.appserviceid_educount
+			.get(appservice_id)
+			.await
+			.deserialized()
+			.unwrap_or(0)
+	}
 }
 
 fn parse_servercurrentevent(key: &[u8], value: &[u8]) -> Result<(Destination, SendingEvent)> {
@@ -14,14 +14,17 @@ use std::{
 use async_trait::async_trait;
 use conduwuit::{
 	debug, debug_warn, err, error,
-	utils::{available_parallelism, math::usize_from_u64_truncated, ReadyExt, TryReadyExt},
+	utils::{
+		available_parallelism, math::usize_from_u64_truncated, IterStream, ReadyExt, TryReadyExt,
+	},
 	warn, Result, Server,
 };
 use futures::{FutureExt, Stream, StreamExt};
 use ruma::{
 	api::{appservice::Registration, OutgoingRequest},
-	RoomId, ServerName, UserId,
+	OwnedRoomId, RoomId, ServerName, UserId,
 };
+use serde_json::Value as JsonValue;
 use tokio::task::JoinSet;
 
 use self::data::Data;
@@ -65,6 +68,10 @@ struct Msg {
 	queue_id: Vec<u8>,
 }
 
+/// Type of the EDUs wrapping to-device events queued for appservices, which
+/// tells them apart from ephemeral events.
+const TO_DEVICE_EDU_TYPE: &str = "m.direct_to_device";
+
 #[allow(clippy::module_name_repetitions)]
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum SendingEvent {
@@ -246,6 +253,86 @@ impl Service {
 		Ok(())
 	}
 
+	#[tracing::instrument(skip(self, serialized), level = "debug")]
+	pub fn send_edu_appservice(&self, appservice_id: String, serialized: Vec<u8>) -> Result {
+		let dest = Destination::Appservice(appservice_id);
+		let event = SendingEvent::Edu(serialized);
+		let _cork = self.db.db.cork();
+		let keys = self.db.queue_requests(once((&event, &dest)));
+		self.dispatch(Msg {
+			dest,
+			event,
+			queue_id: keys.into_iter().next().expect("request queue key"),
+		})
+	}
+
+	/// Queues a to-device event for the appservices which receive ephemeral
+	/// events and whose namespace includes the recipient (MSC4203).
+	#[tracing::instrument(skip(self, event), level = "debug")]
+	pub async fn send_to_device_appservices(
+		&self,
+		target_user_id: &UserId,
+		event: &JsonValue,
+	) -> Result {
+		let appservices: Vec<_> = self
+			.services
+			.appservice
+			.read()
+			.await
+			.values()
+			.filter(|info| info.registration.receive_ephemeral)
+			.filter(|info| info.is_user_match(target_user_id))
+			.map(|info| info.registration.id.clone())
+			.collect();
+
+		let edu = serde_json::to_vec(&serde_json::json!({
+			"type": TO_DEVICE_EDU_TYPE,
+			"to_user_id": target_user_id,
+			"event": event,
+		}))?;
+
+		for appservice_id in appservices {
+			self.send_edu_appservice(appservice_id, edu.clone())?;
+		}
+
+		Ok(())
+	}
+
+	/// Wakes the appservices which opted into MSC3202 and may be interested in
+	/// a change to the devices of a user: those whose namespace includes the
+	/// user, or which have users in one of the given rooms.
+	#[tracing::instrument(skip(self, rooms), level = "debug")]
+	pub async fn flush_appservices(&self, user_id: &UserId, rooms: &[OwnedRoomId]) -> Result {
+		let appservices: Vec<_> = self
+			.services
+			.appservice
+			.read()
+			.await
+			.values()
+			.filter(|info| info.extensions.msc3202)
+			.cloned()
+			.collect();
+
+		for info in appservices {
+			let interested = info.is_user_match(user_id)
+				|| rooms
+					.iter()
+					.stream()
+					.any(|room_id| self.services.state_cache.appservice_in_room(room_id, &info))
+					.await;
+
+			if interested {
+				self.dispatch(Msg {
+					dest: Destination::Appservice(info.registration.id),
+					event: SendingEvent::Flush,
+					queue_id: Vec::<u8>::new(),
+				})?;
+			}
+		}
+
+		Ok(())
+	}
+
 	#[tracing::instrument(skip(self, room_id), level = "debug")]
 	pub async fn flush_room(&self, room_id: &RoomId) -> Result<()> {
 		let servers = self
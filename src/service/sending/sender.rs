@@ -1,5 +1,7 @@
+mod tests;
+
 use std::{
-	collections::{BTreeMap, HashMap, HashSet},
+	collections::{BTreeMap, BTreeSet, HashMap, HashSet},
 	fmt::Debug,
 	sync::{
 		atomic::{AtomicU64, AtomicUsize, Ordering},
@@ -10,7 +12,7 @@ use std::{
 
 use base64::{engine::general_purpose, Engine as _};
 use conduwuit::{
-	debug, err, error,
+	debug, debug_warn, err, error,
 	result::LogErr,
 	trace,
 	utils::{calculate_hash, continue_exponential_backoff_secs, ReadyExt},
@@ -24,7 +26,7 @@ use futures::{
 };
 use ruma::{
 	api::{
-		appservice::event::push_events::v1::EphemeralData,
+		appservice::event::push_events::v1::{DeviceLists, EphemeralData},
 		federation::transactions::{
 			edu::{
 				DeviceListUpdateContent, Edu, PresenceContent, PresenceUpdate, ReceiptContent,
@@ -36,14 +38,20 @@ use ruma::{
 	device_id,
 	events::{
 		push_rules::PushRulesEvent, receipt::ReceiptType, AnySyncEphemeralRoomEvent,
-		GlobalAccountDataEventType,
+		AnyToDeviceEvent, GlobalAccountDataEventType,
 	},
-	push, uint, CanonicalJsonObject, MilliSecondsSinceUnixEpoch, OwnedRoomId, OwnedServerName,
-	OwnedUserId, RoomId, RoomVersionId, ServerName, UInt,
+	push,
+	serde::Raw,
+	uint, CanonicalJsonObject, MilliSecondsSinceUnixEpoch, OneTimeKeyAlgorithm, OwnedDeviceId,
+	OwnedRoomId, OwnedServerName, OwnedUserId, RoomId, RoomVersionId, ServerName, UInt, UserId,
 };
+use serde::Deserialize;
 use serde_json::value::{to_raw_value, RawValue as RawJsonValue};
 
-use super::{appservice, data::QueueItem, Destination, Msg, SendingEvent, Service};
+use super::{
+	appservice, data::QueueItem, Destination, Msg, SendingEvent, Service, TO_DEVICE_EDU_TYPE,
+};
+use crate::appservice::RegistrationInfo;
 
 #[derive(Debug)]
 enum TransactionStatus {
@@ -57,6 +65,36 @@ type SendingResult = Result<Destination, SendingError>;
 type SendingFuture<'a> = BoxFuture<'a, SendingResult>;
 type SendingFutures<'a> = FuturesUnordered<SendingFuture<'a>>;
 type CurTransactionStatus = HashMap<Destination, TransactionStatus>;
+type OneTimeKeysCounts =
+	BTreeMap<OwnedUserId, BTreeMap<OwnedDeviceId, BTreeMap<OneTimeKeyAlgorithm, UInt>>>;
+type FallbackKeyTypes = BTreeMap<OwnedUserId, BTreeMap<OwnedDeviceId, Vec<OneTimeKeyAlgorithm>>>;
+
+/// The MSC3202 fields of a transaction to an appservice.
+#[derive(Debug, Default)]
+struct Devices {
+	device_lists: DeviceLists,
+	device_one_time_keys_count: OneTimeKeysCounts,
+	device_unused_fallback_key_types: FallbackKeyTypes,
+}
+
+/// An EDU queued for an appservice: an ephemeral event, or a to-device event
+/// wrapped in an EDU of type [`TO_DEVICE_EDU_TYPE`].
+#[derive(Deserialize)]
+struct QueuedEdu {
+	#[serde(rename = "type")]
+	kind: String,
+	to_user_id: Option<OwnedUserId>,
+	event: Option<Raw<AnyToDeviceEvent>>,
+}
+
+impl Devices {
+	fn is_empty(&self) -> bool {
+		self.device_lists.changed.is_empty()
+			&& self.device_lists.left.is_empty()
+			&& self.device_one_time_keys_count.is_empty()
+			&& self.device_unused_fallback_key_types.is_empty()
+	}
+}
 
 const CLEANUP_TIMEOUT_MS: u64 = 3500;
 
@@ -656,13 +694,14 @@ impl Service {
 		id: String,
 		events: Vec<SendingEvent>,
 	) -> SendingResult {
-		let Some(appservice) = self.services.appservice.get_registration(&id).await else {
+		let Some(info) = self.services.appservice.read().await.get(&id).cloned() else {
 			return Err((
 				Destination::Appservice(id.clone()),
 				err!(Database(warn!(?id, "Missing appservice registration"))),
 			));
 		};
 
+		let appservice = &info.registration;
 		let mut pdu_jsons = Vec::with_capacity(
 			events
 				.iter()
@@ -675,6 +714,7 @@ impl Service {
 				.filter(|event| matches!(event, SendingEvent::Edu(_)))
 				.count(),
 		);
+		let mut to_device_jsons: Vec<Raw<AnyToDeviceEvent>> = Vec::new();
 		for event in &events {
 			match event {
 				| SendingEvent::Pdu(pdu_id) => {
@@ -682,44 +722,252 @@ impl Service {
 						pdu_jsons.push(pdu.to_room_event());
 					}
 				},
-				| SendingEvent::Edu(edu) =>
-					if appservice.receive_ephemeral {
+				| SendingEvent::Edu(edu) => match serde_json::from_slice::<QueuedEdu>(edu) {
+					| Ok(QueuedEdu {
+						kind,
+						to_user_id: Some(to_user_id),
+						event: Some(event),
+					}) if kind == TO_DEVICE_EDU_TYPE =>
+						if info.is_user_match(&to_user_id) {
+							to_device_jsons.push(event);
+						},
+					| Ok(QueuedEdu { kind, .. }) if kind == TO_DEVICE_EDU_TYPE => {
+						debug_warn!(?id, "Invalid to-device EDU queued for appservice");
+					},
+					| Ok(_) if appservice.receive_ephemeral => {
 						if let Ok(edu) = serde_json::from_slice(edu) {
 							edu_jsons.push(edu);
 						}
 					},
+					| _ => {},
+				},
 				| SendingEvent::Flush => {}, // flush only; no new content
 			}
 		}
 
-		let txn_hash = calculate_hash(events.iter().filter_map(|e| match e {
-			| SendingEvent::Edu(b) => Some(&**b),
-			| SendingEvent::Pdu(b) => Some(b.as_ref()),
-			| SendingEvent::Flush => None,
-		}));
+		// MSC3202 changes are selected since the last successful transaction, so
+		// a failed transaction is retried with them.
+		let since = self.db.get_latest_appservice_educount(&id).await;
+		let until = self.services.globals.current_count().unwrap_or(since);
+		let devices = if info.extensions.msc3202 {
+			self.select_appservice_devices(&info, (since, until)).await
+		} else {
+			Devices::default()
+		};
+
+		if pdu_jsons.is_empty()
+			&& edu_jsons.is_empty()
+			&& to_device_jsons.is_empty()
+			&& devices.is_empty()
+		{
+			if info.extensions.msc3202 {
+				self.db.set_latest_appservice_educount(&id, until);
+			}
+
+			return Ok(Destination::Appservice(id));
+		}
+
+		let window = [since.to_be_bytes(), until.to_be_bytes()].concat();
+		let txn_hash = calculate_hash(
+			events
+				.iter()
+				.filter_map(|e| match e {
+					| SendingEvent::Edu(b) => Some(&**b),
+					| SendingEvent::Pdu(b) => Some(b.as_ref()),
+					| SendingEvent::Flush => None,
+				})
+				.chain(info.extensions.msc3202.then_some(window.as_slice())),
+		);
 
 		let txn_id = &*general_purpose::URL_SAFE_NO_PAD.encode(txn_hash);
 
-		//debug_assert!(pdu_jsons.len() + edu_jsons.len() > 0, "sending empty
-		// transaction");
 		let client = &self.services.client.appservice;
 		match appservice::send_request(
 			client,
-			appservice,
+			info.registration.clone(),
 			ruma::api::appservice::event::push_events::v1::Request {
 				events: pdu_jsons,
 				txn_id: txn_id.into(),
 				ephemeral: edu_jsons,
-				to_device: Vec::new(), // TODO
+				to_device: to_device_jsons,
+				device_lists: devices.device_lists,
+				device_one_time_keys_count: devices.device_one_time_keys_count,
+				device_unused_fallback_key_types: devices.device_unused_fallback_key_types,
 			},
 		)
 		.await
 		{
-			| Ok(_) => Ok(Destination::Appservice(id)),
+			| Ok(_) => {
+				if info.extensions.msc3202 {
+					self.db.set_latest_appservice_educount(&id, until);
+				}
+
+				Ok(Destination::Appservice(id))
+			},
 			| Err(e) => Err((Destination::Appservice(id), e)),
 		}
 	}
 
+	/// Selects the device list changes and one-time key counts of interest to
+	/// an appservice (MSC3202): device list changes of its users and of users
+	/// sharing rooms with them, users who no longer share a room with them,
+	/// and the key counts of its users' devices.
+	///
+	/// Each change stream is read once from `since` per user or room, and the
+	/// members of the rooms are only read when someone may have stopped
+	/// sharing one with the appservice's users.
+	#[tracing::instrument(name = "devices", level = "debug", skip(self, info))]
+	async fn select_appservice_devices(
+		&self,
+		info: &RegistrationInfo,
+		(since, until): (u64, u64),
+	) -> Devices {
+		let in_window = |count: u64| count > since && count <= until;
+		let users: BTreeSet<OwnedUserId> = self
+			.services
+			.appservice
+			.namespace_users(&info.registration.id)
+			.into_iter()
+			.collect();
+
+		let mut rooms = BTreeSet::<OwnedRoomId>::new();
+		let mut rooms_left = BTreeSet::<OwnedRoomId>::new();
+		let mut changed = BTreeSet::<OwnedUserId>::new();
+		let mut device_one_time_keys_count = OneTimeKeysCounts::new();
+		for user_id in &users {
+			self.services
+				.state_cache
+				.rooms_joined(user_id)
+				.ready_for_each(|room_id| {
+					rooms.insert(room_id.to_owned());
+				})
+				.await;
+
+			let left: Vec<OwnedRoomId> = self
+				.services
+				.state_cache
+				.rooms_left(user_id)
+				.map(|(room_id, _)| room_id)
+				.collect()
+				.await;
+
+			for room_id in left {
+				let left_count = self
+					.services
+					.state_cache
+					.get_left_count(&room_id, user_id)
+					.await
+					.unwrap_or(0);
+
+				if in_window(left_count) {
+					rooms_left.insert(room_id);
+				}
+			}
+
+			self.services
+				.users
+				.keys_changed(user_id, since, Some(until))
+				.ready_for_each(|user_id| {
+					changed.insert(user_id.to_owned());
+				})
+				.await;
+
+			if !in_window(self.services.users.last_one_time_keys_update(user_id).await) {
+				continue;
+			}
+
+			let device_ids: Vec<OwnedDeviceId> = self
+				.services
+				.users
+				.all_device_ids(user_id)
+				.map(ToOwned::to_owned)
+				.collect()
+				.await;
+
+			for device_id in device_ids {
+				let counts = self
+					.services
+					.users
+					.count_one_time_keys(user_id, &device_id)
+					.await;
+
+				device_one_time_keys_count
+					.entry(user_id.clone())
+					.or_default()
+					.insert(device_id, counts);
+			}
+		}
+
+		// Users who may have stopped sharing a room with the appservice's users:
+		// the members of rooms its users left, and the users who left rooms its
+		// users are in.
+		let mut candidates = BTreeSet::<OwnedUserId>::new();
+		for room_id in &rooms_left {
+			self.services
+				.state_cache
+				.room_members(room_id)
+				.ready_for_each(|user_id| {
+					candidates.insert(user_id.to_owned());
+				})
+				.await;
+		}
+
+		for room_id in &rooms {
+			self.services
+				.users
+				.room_keys_changed(room_id, since, Some(until))
+				.ready_for_each(|(user_id, _)| {
+					changed.insert(user_id.to_owned());
+				})
+				.await;
+
+			self.services
+				.state_cache
+				.room_members_left(room_id)
+				.ready_filter(|&(_, left_count)| in_window(left_count))
+				.ready_for_each(|(user_id, _)| {
+					candidates.insert(user_id.to_owned());
+				})
+				.await;
+		}
+
+		candidates.retain(|user_id| !users.contains(user_id));
+
+		// Everyone still sharing a room with the appservice's users, read once for
+		// all the candidates of the transaction.
+		let mut members = HashSet::<OwnedUserId>::new();
+		if !candidates.is_empty() {
+			for room_id in &rooms {
+				self.services
+					.state_cache
+					.room_members(room_id)
+					.ready_for_each(|user_id| {
+						members.insert(user_id.to_owned());
+					})
+					.await;
+			}
+		}
+
+		let left: Vec<OwnedUserId> = candidates
+			.into_iter()
+			.filter(|user_id| !members.contains(user_id))
+			.collect();
+
+		for user_id in &left {
+			changed.remove(user_id);
+		}
+
+		Devices {
+			device_lists: DeviceLists {
+				changed: changed.into_iter().collect(),
+				left,
+			},
+			device_one_time_keys_count,
+			// Fallback keys are not stored, so none are reported as unused.
+			device_unused_fallback_key_types: FallbackKeyTypes::new(),
+		}
+	}
+
 	#[tracing::instrument(
 		name = "push",
 		level = "info",
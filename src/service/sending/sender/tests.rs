@@ -0,0 +1,126 @@
+#![cfg(test)]
+
+use conduwuit::pdu::PduBuilder;
+use ruma::{
+	api::appservice::Registration, device_id, encryption::DeviceKeys,
+	events::room::encryption::RoomEncryptionEventContent, owned_device_id, owned_user_id,
+	serde::Raw, OneTimeKeyAlgorithm, OneTimeKeyId, UInt,
+};
+use serde_json::value::to_raw_value;
+
+use crate::tests::{create_room, offline_services, send, TempDir};
+
+const REGISTRATION: &str = r#"
+id: bridge
+url: "http://localhost:9000"
+as_token: bridge_as_token
+hs_token: bridge_hs_token
+sender_localpart: bridge
+org.matrix.msc3202: true
+namespaces:
+  users:
+    - exclusive: true
+      regex: "@bridge_.*:example\\.com"
+  aliases: []
+  rooms: []
+"#;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn appservice_devices() {
+	let dir = TempDir::new("appservice-devices");
+	let services = offline_services(&dir, "example.com")
+		.await
+		.expect("started services offline");
+
+	let registration: Registration =
+		serde_yaml::from_str(REGISTRATION).expect("valid registration");
+	services
+		.appservice
+		.register_appservice(&registration, REGISTRATION)
+		.await
+		.expect("registered");
+
+	let puppet = owned_user_id!("@bridge_alice:example.com");
+	let device = owned_device_id!("PUPPET");
+	let (alice, bob) = (owned_user_id!("@alice:example.com"), owned_user_id!("@bob:example.com"));
+	for user_id in [&puppet, &alice, &bob] {
+		services
+			.users
+			.create(user_id, Some("password"))
+			.expect("created");
+	}
+
+	let room_id = create_room(&services, None).await;
+	send(
+		&services,
+		&room_id,
+		PduBuilder::state(
+			String::new(),
+			&RoomEncryptionEventContent::with_recommended_defaults(),
+		),
+	)
+	.await;
+	for user_id in [&puppet, &alice, &bob] {
+		services.rooms.state_cache.mark_as_joined(user_id, &room_id);
+	}
+
+	let info = services
+		.appservice
+		.find_from_token("bridge_as_token")
+		.await
+		.expect("appservice found");
+
+	let since = services.globals.current_count().expect("count");
+	services
+		.users
+		.create_device(&puppet, &device, "puppet_token", None, None)
+		.await
+		.expect("device created");
+
+	let key_id: &OneTimeKeyId = "signed_curve25519:AAAAAQ".try_into().expect("valid key ID");
+	let key = Raw::from_json(
+		to_raw_value(&serde_json::json!({ "key": "AAAAAQ" })).expect("valid JSON"),
+	);
+	services
+		.users
+		.add_one_time_key(&puppet, &device, key_id, &key)
+		.await
+		.expect("key added");
+
+	let device_keys: Raw<DeviceKeys> = Raw::from_json(
+		to_raw_value(&serde_json::json!({ "user_id": alice, "device_id": "ALICE" }))
+			.expect("valid JSON"),
+	);
+	services
+		.users
+		.add_device_keys(&alice, device_id!("ALICE"), &device_keys)
+		.await;
+	services.rooms.state_cache.mark_as_left(&bob, &room_id);
+	let until = services.globals.current_count().expect("count");
+
+	let devices = services
+		.sending
+		.select_appservice_devices(&info, (since, until))
+		.await;
+
+	assert_eq!(devices.device_lists.changed, [alice.clone()], "keys changed in a shared room");
+	assert_eq!(devices.device_lists.left, [bob], "no longer shares a room");
+	assert_eq!(
+		devices.device_one_time_keys_count[&puppet][&device]
+			[&OneTimeKeyAlgorithm::SignedCurve25519],
+		UInt::from(1_u32)
+	);
+	assert!(devices.device_one_time_keys_count.get(&alice).is_none(), "not the appservice's");
+	assert!(
+		devices.device_unused_fallback_key_types.is_empty(),
+		"fallback keys are not stored"
+	);
+
+	let quiet = services
+		.sending
+		.select_appservice_devices(&info, (until, until))
+		.await;
+	assert!(quiet.is_empty(), "nothing changed since the last transaction");
+
+	services.stop_offline();
+}
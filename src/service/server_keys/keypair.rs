@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use conduwuit::{debug, debug_info, err, error, utils, utils::string_from_bytes, Result};
+use conduwuit::{debug, debug_info, err, error, utils, utils::string_from_bytes, Err, Result};
 use database::Database;
 use ruma::{api::federation::discovery::VerifyKey, serde::Base64, signatures::Ed25519KeyPair};
 
@@ -58,6 +58,30 @@ fn create(db: &Arc<Database>) -> Result<(String, Vec<u8>)> {
 	Ok(value)
 }
 
+/// Replaces the keypair with an Ed25519 key of another server, such as the
+/// Synapse being imported, given its version and seed. It takes effect on the
+/// next start.
+pub fn import(db: &Arc<Database>, version: &str, seed: &[u8]) -> Result {
+	// PKCS#8 v1 wrapping of an Ed25519 private key (RFC 8410)
+	const PREFIX: [u8; 16] = [
+		0x30, 0x2E, 0x02, 0x01, 0x00, 0x30, 0x05, 0x06, 0x03, 0x2B, 0x65, 0x70, 0x04, 0x22, 0x04,
+		0x20,
+	];
+
+	if seed.len() != 32 {
+		return Err!("Ed25519 seeds are 32 bytes, not {}.", seed.len());
+	}
+
+	let der = [PREFIX.as_slice(), seed].concat();
+	Ed25519KeyPair::from_der(&der, version.to_owned())
+		.map_err(|e| err!("Invalid ed25519 key: {e:?}"))?;
+
+	let value: (String, Vec<u8>) = (version.to_owned(), der);
+	db["global"].raw_put(b"keypair", &value);
+
+	Ok(())
+}
+
 #[inline]
 fn remove(db: &Arc<Database>) {
 	let global = &db["global"];
@@ -23,6 +23,7 @@ use ruma::{
 };
 use serde_json::value::RawValue as RawJsonValue;
 
+pub use self::keypair::import as import_keypair;
 use crate::{globals, sending, Dep};
 
 pub struct Service {
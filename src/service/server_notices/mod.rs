@@ -0,0 +1,421 @@
+mod tests;
+
+use std::{
+	collections::{BTreeMap, HashMap},
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use conduwuit::{
+	debug, err, implement,
+	pdu::PduBuilder,
+	utils::{stream::TryIgnore, MutexMap, ReadyExt},
+	Err, Result, Server,
+};
+use database::{Deserialized, Map};
+use ruma::{
+	events::{
+		room::{
+			avatar::RoomAvatarEventContent,
+			create::RoomCreateEventContent,
+			guest_access::{GuestAccess, RoomGuestAccessEventContent},
+			history_visibility::{HistoryVisibility, RoomHistoryVisibilityEventContent},
+			join_rules::{JoinRule, RoomJoinRulesEventContent},
+			member::{MembershipState, RoomMemberEventContent},
+			message::{
+				LimitType, MessageType, RoomMessageEventContent, ServerNoticeMessageEventContent,
+				ServerNoticeType,
+			},
+			name::RoomNameEventContent,
+			power_levels::RoomPowerLevelsEventContent,
+		},
+		tag::{TagEvent, TagEventContent, TagInfo, TagName},
+		RoomAccountDataEventType,
+	},
+	OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, RoomVersionId, UserId,
+};
+
+use crate::{account_data, globals, rooms, rooms::state::RoomMutexGuard, users, Dep};
+
+/// Messages from the server to local users, each sent in a read-only room of
+/// the user's own which clients show apart through the `m.server_notice` tag.
+pub struct Service {
+	/// User notices are sent by, when notices are enabled.
+	sender: Option<OwnedUserId>,
+
+	/// Last usage limit notice sent to a user for a limit type, for the
+	/// notices sent in the last `LIMIT_NOTICE_INTERVAL`.
+	limit_notices: Mutex<HashMap<(OwnedUserId, String), Instant>>,
+
+	/// Serialises looking up and creating the notices room of a user.
+	room_mutex: MutexMap<OwnedUserId, ()>,
+	db: Data,
+	services: Services,
+}
+
+struct Services {
+	server: Arc<Server>,
+	globals: Dep<globals::Service>,
+	users: Dep<users::Service>,
+	account_data: Dep<account_data::Service>,
+	metadata: Dep<rooms::metadata::Service>,
+	short: Dep<rooms::short::Service>,
+	state: Dep<rooms::state::Service>,
+	state_cache: Dep<rooms::state_cache::Service>,
+	timeline: Dep<rooms::timeline::Service>,
+}
+
+struct Data {
+	userid_servernoticeroomid: Arc<Map>,
+}
+
+/// How often a user is told about reaching the same usage limit.
+const LIMIT_NOTICE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		let config = &args.server.config;
+		let sender = config
+			.server_notices
+			.enable
+			.then(|| {
+				UserId::parse_with_server_name(
+					config.server_notices.localpart.as_str(),
+					&config.server_name,
+				)
+				.map_err(|e| err!(Config("server_notices.localpart", "Invalid localpart: {e}")))
+			})
+			.transpose()?;
+
+		Ok(Arc::new(Self {
+			sender,
+			limit_notices: Mutex::new(HashMap::new()),
+			room_mutex: MutexMap::new(),
+			db: Data {
+				userid_servernoticeroomid: args.db["userid_servernoticeroomid"].clone(),
+			},
+			services: Services {
+				server: args.server.clone(),
+				globals: args.depend::<globals::Service>("globals"),
+				users: args.depend::<users::Service>("users"),
+				account_data: args.depend::<account_data::Service>("account_data"),
+				metadata: args.depend::<rooms::metadata::Service>("rooms::metadata"),
+				short: args.depend::<rooms::short::Service>("rooms::short"),
+				state: args.depend::<rooms::state::Service>("rooms::state"),
+				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
+				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
+			},
+		}))
+	}
+
+	/// Creates the notices user, so that nobody else registers it, and keeps
+	/// its profile in line with the config.
+	async fn worker(self: Arc<Self>) -> Result<()> {
+		let Some(sender) = &self.sender else {
+			return Ok(());
+		};
+
+		let users = &self.services.users;
+		if !users.exists(sender).await {
+			debug!("Creating server notices user {sender}");
+			users.create(sender, None)?;
+		}
+
+		let config = &self.services.server.config.server_notices;
+		users.set_displayname(sender, Some(config.displayname.clone()));
+		users.set_avatar_url(sender, config.avatar_url.clone());
+
+		Ok(())
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Whether notices are enabled by `server_notices.enable`.
+#[implement(Service)]
+#[inline]
+pub fn enabled(&self) -> bool { self.sender.is_some() }
+
+/// The user notices are sent by.
+#[implement(Service)]
+pub fn sender(&self) -> Result<&UserId> {
+	self.sender
+		.as_deref()
+		.ok_or_else(|| err!(Config("server_notices.enable", "Server notices are not enabled.")))
+}
+
+/// Sends a notice to a local user, creating their notices room or inviting
+/// them back to it as needed.
+#[implement(Service)]
+pub async fn send(
+	&self,
+	user_id: &UserId,
+	content: &RoomMessageEventContent,
+) -> Result<OwnedEventId> {
+	let sender = self.sender()?;
+	if !self.services.globals.user_is_local(user_id) {
+		return Err!(Request(InvalidParam("Notices can only be sent to local users.")));
+	}
+
+	if !self.can_receive(user_id) {
+		return Err!(Request(InvalidParam("Notices cannot be sent to {user_id}.")));
+	}
+
+	if self.services.users.is_deactivated(user_id).await? {
+		return Err!(Request(InvalidParam("{user_id} is deactivated.")));
+	}
+
+	let room_id = self.notice_room(sender, user_id).await?;
+	let state_lock = self.services.state.mutex.lock(&room_id).await;
+
+	self.services
+		.timeline
+		.build_and_append_pdu(PduBuilder::timeline(content), sender, &room_id, &state_lock)
+		.await
+}
+
+/// Tells a user they reached a usage limit, unless they were told about the
+/// same limit in the last day. Does nothing if notices are disabled.
+#[implement(Service)]
+pub async fn usage_limit_reached(
+	&self,
+	user_id: &UserId,
+	limit_type: &str,
+	body: String,
+) -> Result {
+	if !self.enabled() {
+		return Ok(());
+	}
+
+	{
+		let key = (user_id.to_owned(), limit_type.to_owned());
+		let mut limit_notices = self.limit_notices.lock()?;
+		if limit_notices
+			.get(&key)
+			.is_some_and(|sent| sent.elapsed() < LIMIT_NOTICE_INTERVAL)
+		{
+			return Ok(());
+		}
+
+		limit_notices.retain(|_, sent| sent.elapsed() < LIMIT_NOTICE_INTERVAL);
+		limit_notices.insert(key, Instant::now());
+	}
+
+	self.send_usage_limit(user_id, limit_type, body)
+		.await
+		.map(|_| ())
+}
+
+/// Sends a `m.server_notice.usage_limit_reached` notice, which clients may
+/// act on, e.g. by showing a banner.
+#[implement(Service)]
+pub async fn send_usage_limit(
+	&self,
+	user_id: &UserId,
+	limit_type: &str,
+	body: String,
+) -> Result<OwnedEventId> {
+	let mut notice =
+		ServerNoticeMessageEventContent::new(body, ServerNoticeType::UsageLimitReached);
+	notice.admin_contact = self
+		.services
+		.server
+		.config
+		.server_notices
+		.admin_contact
+		.clone();
+	notice.limit_type = Some(LimitType::from(limit_type));
+
+	let content = RoomMessageEventContent::new(MessageType::ServerNotice(notice));
+	self.send(user_id, &content).await
+}
+
+/// Whether notices may be sent to a user: anyone but the server user and the
+/// notices user themselves.
+#[implement(Service)]
+pub fn can_receive(&self, user_id: &UserId) -> bool {
+	self.sender.as_deref() != Some(user_id) && user_id != self.services.globals.server_user
+}
+
+/// Forgets the notices rooms mapped to a room being purged, so the next
+/// notice creates a new one. Returns the number of rows removed.
+#[implement(Service)]
+pub async fn purge_room(&self, room_id: &RoomId) -> usize {
+	let userid_servernoticeroomid = &self.db.userid_servernoticeroomid;
+	userid_servernoticeroomid
+		.raw_stream()
+		.ignore_err()
+		.ready_filter(|(_, notice_room)| *notice_room == room_id.as_bytes())
+		.ready_fold(0_usize, |removed, (user_id, _)| {
+			userid_servernoticeroomid.remove(user_id);
+			removed.saturating_add(1)
+		})
+		.await
+}
+
+/// The notices room of a user, created if they have none, or if theirs is
+/// gone or was left by the sender of notices.
+#[implement(Service)]
+async fn notice_room(&self, sender: &UserId, user_id: &UserId) -> Result<OwnedRoomId> {
+	let _lock = self.room_mutex.lock(user_id).await;
+	let Ok(room_id) = self
+		.db
+		.userid_servernoticeroomid
+		.get(user_id)
+		.await
+		.deserialized::<OwnedRoomId>()
+	else {
+		return self.create_notice_room(sender, user_id).await;
+	};
+
+	let state_cache = &self.services.state_cache;
+	if !self.services.metadata.exists(&room_id).await
+		|| !state_cache.is_joined(sender, &room_id).await
+	{
+		debug!(%user_id, %room_id, "Replacing stale server notices room");
+		self.db.userid_servernoticeroomid.remove(user_id);
+		return self.create_notice_room(sender, user_id).await;
+	}
+
+	if !state_cache.is_joined(user_id, &room_id).await
+		&& !state_cache.is_invited(user_id, &room_id).await
+	{
+		let state_lock = self.services.state.mutex.lock(&room_id).await;
+		self.invite(sender, user_id, &room_id, &state_lock).await?;
+	}
+
+	Ok(room_id)
+}
+
+#[implement(Service)]
+async fn create_notice_room(&self, sender: &UserId, user_id: &UserId) -> Result<OwnedRoomId> {
+	let config = &self.services.server.config;
+	let room_id = RoomId::new(self.services.globals.server_name());
+	let room_version = &config.default_room_version;
+
+	self.services
+		.short
+		.get_or_create_shortroomid(&room_id)
+		.await;
+
+	let state_lock = self.services.state.mutex.lock(&room_id).await;
+
+	let create_content = {
+		use RoomVersionId::*;
+		match room_version {
+			| V1 | V2 | V3 | V4 | V5 | V6 | V7 | V8 | V9 | V10 =>
+				RoomCreateEventContent::new_v1(sender.to_owned()),
+			| _ => RoomCreateEventContent::new_v11(),
+		}
+	};
+
+	let mut events = vec![
+		PduBuilder::state(String::new(), &RoomCreateEventContent {
+			federate: false,
+			predecessor: None,
+			room_version: room_version.clone(),
+			..create_content
+		}),
+		PduBuilder::state(
+			sender.to_string(),
+			&RoomMemberEventContent::new(MembershipState::Join),
+		),
+		// Only the notices user may send messages or change the room
+		PduBuilder::state(String::new(), &RoomPowerLevelsEventContent {
+			users: BTreeMap::from_iter([(sender.to_owned(), 100.into())]),
+			events_default: 100.into(),
+			state_default: 100.into(),
+			invite: 100.into(),
+			..Default::default()
+		}),
+		PduBuilder::state(String::new(), &RoomJoinRulesEventContent::new(JoinRule::Invite)),
+		PduBuilder::state(
+			String::new(),
+			&RoomHistoryVisibilityEventContent::new(HistoryVisibility::Shared),
+		),
+		PduBuilder::state(
+			String::new(),
+			&RoomGuestAccessEventContent::new(GuestAccess::Forbidden),
+		),
+		PduBuilder::state(
+			String::new(),
+			&RoomNameEventContent::new(config.server_notices.room_name.clone()),
+		),
+	];
+
+	if let Some(avatar_url) = &config.server_notices.avatar_url {
+		let mut content = RoomAvatarEventContent::new();
+		content.url = Some(avatar_url.clone());
+		events.push(PduBuilder::state(String::new(), &content));
+	}
+
+	for event in events {
+		self.services
+			.timeline
+			.build_and_append_pdu(event, sender, &room_id, &state_lock)
+			.await?;
+	}
+
+	self.invite(sender, user_id, &room_id, &state_lock).await?;
+
+	self.db
+		.userid_servernoticeroomid
+		.insert(user_id.as_bytes(), room_id.as_bytes());
+
+	self.set_tag(user_id, &room_id).await?;
+
+	Ok(room_id)
+}
+
+#[implement(Service)]
+async fn invite(
+	&self,
+	sender: &UserId,
+	user_id: &UserId,
+	room_id: &RoomId,
+	state_lock: &RoomMutexGuard,
+) -> Result {
+	self.services
+		.timeline
+		.build_and_append_pdu(
+			PduBuilder::state(
+				user_id.to_string(),
+				&RoomMemberEventContent::new(MembershipState::Invite),
+			),
+			sender,
+			room_id,
+			state_lock,
+		)
+		.await?;
+
+	Ok(())
+}
+
+#[implement(Service)]
+async fn set_tag(&self, user_id: &UserId, room_id: &RoomId) -> Result {
+	let mut event = self
+		.services
+		.account_data
+		.get_room(room_id, user_id, RoomAccountDataEventType::Tag)
+		.await
+		.unwrap_or(TagEvent {
+			content: TagEventContent { tags: BTreeMap::new() },
+		});
+
+	event
+		.content
+		.tags
+		.insert(TagName::ServerNotice, TagInfo::new());
+
+	self.services
+		.account_data
+		.update(
+			Some(room_id),
+			user_id,
+			RoomAccountDataEventType::Tag,
+			&serde_json::to_value(event)?,
+		)
+		.await
+}
@@ -0,0 +1,52 @@
+#![cfg(test)]
+
+use database::Deserialized;
+use ruma::{events::room::message::RoomMessageEventContent, owned_user_id, OwnedRoomId, RoomId};
+
+use crate::tests::{offline_services_with, TempDir};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stale_notice_room_replaced() {
+	let dir = TempDir::new("server-notices");
+	let services =
+		offline_services_with(&dir, "example.com", "[global.server_notices]\nenable = true\n")
+			.await
+			.expect("started services offline");
+
+	let notices = &services.server_notices;
+	let user_id = owned_user_id!("@alice:example.com");
+	services
+		.users
+		.create(notices.sender().expect("notices enabled"), None)
+		.expect("created sender");
+	services
+		.users
+		.create(&user_id, Some("password"))
+		.expect("created");
+
+	let notice_room = || async {
+		services.db["userid_servernoticeroomid"]
+			.get(user_id.as_str())
+			.await
+			.deserialized::<OwnedRoomId>()
+			.expect("notices room mapped")
+	};
+
+	let content = RoomMessageEventContent::text_plain("hello");
+	notices.send(&user_id, &content).await.expect("sent");
+	let first = notice_room().await;
+
+	notices.send(&user_id, &content).await.expect("sent");
+	assert_eq!(notice_room().await, first, "the room is kept while it exists");
+
+	let gone = RoomId::new(services.globals.server_name());
+	services.db["userid_servernoticeroomid"].insert(user_id.as_bytes(), gone.as_bytes());
+	notices.send(&user_id, &content).await.expect("sent");
+
+	let replaced = notice_room().await;
+	assert_ne!(replaced, gone, "a room which does not exist is replaced");
+	assert_ne!(replaced, first);
+	assert!(services.rooms.metadata.exists(&replaced).await);
+
+	services.stop_offline();
+}
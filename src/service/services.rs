@@ -10,11 +10,13 @@ use database::Database;
 use tokio::sync::Mutex;
 
 use crate::{
-	account_data, admin, appservice, client, emergency, globals, key_backups,
+	account_data, admin, appservice, client, delayed_events, email, emergency, globals,
+	key_backups, livekit,
 	manager::Manager,
-	media, presence, pusher, resolver, rooms, sending, server_keys, service,
+	media, password, policy_lists, presence, pusher, ratelimit, registration_tokens, reports,
+	resolver, rooms, sending, server_keys, server_notices, service,
 	service::{Args, Map, Service},
-	sync, transaction_ids, uiaa, updates, users,
+	sso, sync, transaction_ids, uiaa, updates, user_directory, users,
 };
 
 pub struct Services {
@@ -22,20 +24,31 @@ pub struct Services {
 	pub admin: Arc<admin::Service>,
 	pub appservice: Arc<appservice::Service>,
 	pub client: Arc<client::Service>,
+	pub delayed_events: Arc<delayed_events::Service>,
+	pub email: Arc<email::Service>,
 	pub emergency: Arc<emergency::Service>,
 	pub globals: Arc<globals::Service>,
 	pub key_backups: Arc<key_backups::Service>,
+	pub livekit: Arc<livekit::Service>,
 	pub media: Arc<media::Service>,
+	pub password: Arc<password::Service>,
+	pub policy_lists: Arc<policy_lists::Service>,
 	pub presence: Arc<presence::Service>,
 	pub pusher: Arc<pusher::Service>,
+	pub ratelimit: Arc<ratelimit::Service>,
+	pub registration_tokens: Arc<registration_tokens::Service>,
+	pub reports: Arc<reports::Service>,
 	pub resolver: Arc<resolver::Service>,
 	pub rooms: rooms::Service,
 	pub sending: Arc<sending::Service>,
 	pub server_keys: Arc<server_keys::Service>,
+	pub server_notices: Arc<server_notices::Service>,
+	pub sso: Arc<sso::Service>,
 	pub sync: Arc<sync::Service>,
 	pub transaction_ids: Arc<transaction_ids::Service>,
 	pub uiaa: Arc<uiaa::Service>,
 	pub updates: Arc<updates::Service>,
+	pub user_directory: Arc<user_directory::Service>,
 	pub users: Arc<users::Service>,
 
 	manager: Mutex<Option<Arc<Manager>>>,
@@ -67,12 +80,20 @@ impl Services {
 			appservice: build!(appservice::Service),
 			resolver: build!(resolver::Service),
 			client: build!(client::Service),
+			delayed_events: build!(delayed_events::Service),
+			email: build!(email::Service),
 			emergency: build!(emergency::Service),
 			globals: build!(globals::Service),
 			key_backups: build!(key_backups::Service),
+			livekit: build!(livekit::Service),
 			media: build!(media::Service),
+			password: build!(password::Service),
+			policy_lists: build!(policy_lists::Service),
 			presence: build!(presence::Service),
 			pusher: build!(pusher::Service),
+			ratelimit: build!(ratelimit::Service),
+			registration_tokens: build!(registration_tokens::Service),
+			reports: build!(reports::Service),
 			rooms: rooms::Service {
 				alias: build!(rooms::alias::Service),
 				auth_chain: build!(rooms::auth_chain::Service),
@@ -82,7 +103,9 @@ impl Services {
 				metadata: build!(rooms::metadata::Service),
 				outlier: build!(rooms::outlier::Service),
 				pdu_metadata: build!(rooms::pdu_metadata::Service),
+				purge: build!(rooms::purge::Service),
 				read_receipt: build!(rooms::read_receipt::Service),
+				retention: build!(rooms::retention::Service),
 				search: build!(rooms::search::Service),
 				short: build!(rooms::short::Service),
 				spaces: build!(rooms::spaces::Service),
@@ -97,10 +120,13 @@ impl Services {
 			},
 			sending: build!(sending::Service),
 			server_keys: build!(server_keys::Service),
+			server_notices: build!(server_notices::Service),
+			sso: build!(sso::Service),
 			sync: build!(sync::Service),
 			transaction_ids: build!(transaction_ids::Service),
 			uiaa: build!(uiaa::Service),
 			updates: build!(updates::Service),
+			user_directory: build!(user_directory::Service),
 			users: build!(users::Service),
 
 			manager: Mutex::new(None),
@@ -137,6 +163,20 @@ impl Services {
 		Ok(Arc::clone(self))
 	}
 
+	/// Prepares the services for maintenance commands which use the database
+	/// without running the server: migrations are applied unless the
+	/// database is read-only, but no workers are started.
+	pub async fn start_offline(self: &Arc<Self>) -> Result<Arc<Self>> {
+		self.admin.set_services(Some(Arc::clone(self)).as_ref());
+		if !self.db.is_read_only() {
+			super::migrations::migrations(self).await?;
+		}
+
+		Ok(Arc::clone(self))
+	}
+
+	pub fn stop_offline(&self) { self.admin.set_services(None); }
+
 	pub async fn stop(&self) {
 		info!("Shutting down services...");
 
@@ -0,0 +1,357 @@
+mod oidc;
+mod tests;
+
+use std::{
+	collections::HashMap,
+	fmt::Write,
+	sync::{Arc, Mutex, RwLock},
+	time::{Duration, Instant},
+};
+
+use conduwuit::{config::OidcProviderConfig, debug, err, implement, utils, Err, Result, Server};
+use database::{Deserialized, Map};
+use ruma::{OwnedUserId, UserId};
+use serde_json::{Map as JsonMap, Value as JsonValue};
+use url::Url;
+
+use self::oidc::{Exchange, Metadata};
+use crate::{client, globals, Dep};
+
+/// Single sign-on through OpenID Connect providers using the authorization
+/// code flow with PKCE. A completed flow yields an [`Identity`] which the
+/// client API maps to a local user and exchanges for a login token.
+pub struct Service {
+	sessions: Mutex<HashMap<String, Session>>,
+	discovered: RwLock<HashMap<String, Arc<Metadata>>>,
+	services: Services,
+	db: Data,
+}
+
+struct Services {
+	server: Arc<Server>,
+	client: Dep<client::Service>,
+	globals: Dep<globals::Service>,
+}
+
+struct Data {
+	ssoidentity_userid: Arc<Map>,
+}
+
+/// An authorization request waiting for the user to return from the
+/// provider, keyed by its `state` parameter.
+struct Session {
+	provider: String,
+	verifier: String,
+	nonce: String,
+	redirect_url: String,
+	started: Instant,
+}
+
+/// A user authenticated by a provider.
+#[derive(Debug)]
+pub struct Identity {
+	pub provider: String,
+	pub subject: String,
+	pub claims: JsonMap<String, JsonValue>,
+	pub redirect_url: String,
+}
+
+/// How long a user may take to authenticate at the provider.
+const SESSION_TTL: Duration = Duration::from_secs(10 * 60);
+const STATE_LENGTH: usize = 32;
+const VERIFIER_LENGTH: usize = 64;
+const NONCE_LENGTH: usize = 32;
+
+/// URL schemes a login token is never handed to, whatever the allowlist.
+const FORBIDDEN_REDIRECT_SCHEMES: &[&str] = &["javascript", "data", "vbscript", "file", "blob"];
+
+/// Path of the redirect URI on this server.
+pub const CALLBACK_PATH: &str = "/_conduwuit/oidc/callback";
+
+/// Name of the cookie binding a session to the browser which started it.
+pub const SESSION_COOKIE: &str = "conduwuit_sso_session";
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			sessions: Mutex::new(HashMap::new()),
+			discovered: RwLock::new(HashMap::new()),
+			services: Services {
+				server: args.server.clone(),
+				client: args.depend::<client::Service>("client"),
+				globals: args.depend::<globals::Service>("globals"),
+			},
+			db: Data {
+				ssoidentity_userid: args.db["ssoidentity_userid"].clone(),
+			},
+		}))
+	}
+
+	fn memory_usage(&self, out: &mut dyn Write) -> Result {
+		let sessions = self.sessions.lock()?.len();
+		let discovered = self.discovered.read()?.len();
+
+		writeln!(out, "sso_sessions: {sessions}")?;
+		writeln!(out, "sso_discovered: {discovered}")?;
+
+		Ok(())
+	}
+
+	fn clear_cache(&self) { self.discovered.write().expect("locked").clear(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Whether any provider is configured.
+#[implement(Service)]
+#[must_use]
+pub fn enabled(&self) -> bool { !self.services.server.config.oidc_providers.is_empty() }
+
+/// The configured provider with the given id, or the first one if no id is
+/// given.
+#[implement(Service)]
+pub fn provider(&self, id: Option<&str>) -> Result<OidcProviderConfig> {
+	let providers = &self.services.server.config.oidc_providers;
+	match id {
+		| Some(id) => providers.iter().find(|provider| provider.id == id),
+		| None => providers.first(),
+	}
+	.cloned()
+	.ok_or_else(|| err!(Request(NotFound("Unknown identity provider."))))
+}
+
+/// Parses the URL a client asked to be sent back to after signing in, refusing
+/// it unless it matches `sso_redirect_allowlist`.
+#[implement(Service)]
+pub fn check_redirect_url(&self, redirect_url: &str) -> Result<Url> {
+	let url = Url::parse(redirect_url)
+		.map_err(|e| err!(Request(InvalidParam("Invalid redirect URL: {e}"))))?;
+
+	if FORBIDDEN_REDIRECT_SCHEMES.contains(&url.scheme()) {
+		return Err!(Request(Forbidden("Redirect URL scheme is not allowed.")));
+	}
+
+	let allowlist = &self.services.server.config.sso_redirect_allowlist;
+	if !allowlist.is_empty()
+		&& !allowlist
+			.iter()
+			.any(|prefix| url.as_str().starts_with(prefix.as_str()))
+	{
+		return Err!(Request(Forbidden("Redirect URL is not allowed for single sign-on.")));
+	}
+
+	Ok(url)
+}
+
+/// Path of the redirect URI of a provider, which the session cookie is
+/// scoped to so the browser sends it back to the callback.
+#[implement(Service)]
+pub fn callback_path(&self, provider_id: Option<&str>) -> Result<String> {
+	let provider = self.provider(provider_id)?;
+
+	Ok(self.callback_url(&provider)?.path().to_owned())
+}
+
+/// Starts an authorization request, returning the URL of the provider to
+/// send the user to and the `state` identifying the request.
+#[implement(Service)]
+pub async fn authorize(
+	&self,
+	provider_id: Option<&str>,
+	redirect_url: &str,
+) -> Result<(Url, String)> {
+	let provider = self.provider(provider_id)?;
+	let metadata = self.metadata(&provider).await?;
+
+	let state = utils::random_string(STATE_LENGTH);
+	let verifier = utils::random_string(VERIFIER_LENGTH);
+	let nonce = utils::random_string(NONCE_LENGTH);
+	let location = oidc::authorization_url(
+		&metadata,
+		&provider.client_id,
+		self.callback_url(&provider)?.as_str(),
+		&provider.scopes,
+		&state,
+		&nonce,
+		&verifier,
+	);
+
+	let mut sessions = self.sessions.lock()?;
+	sessions.retain(|_, session| session.started.elapsed() < SESSION_TTL);
+	sessions.insert(state.clone(), Session {
+		provider: provider.id,
+		verifier,
+		nonce,
+		redirect_url: redirect_url.to_owned(),
+		started: Instant::now(),
+	});
+
+	Ok((location, state))
+}
+
+/// Completes the authorization request identified by `state` by exchanging
+/// the authorization `code`, verifying the ID token it yields and fetching the
+/// user's claims.
+#[implement(Service)]
+pub async fn callback(&self, state: &str, code: &str) -> Result<Identity> {
+	let session = self
+		.sessions
+		.lock()?
+		.remove(state)
+		.filter(|session| session.started.elapsed() < SESSION_TTL)
+		.ok_or_else(|| err!(Request(Forbidden("Unknown or expired single sign-on session."))))?;
+
+	let provider = self.provider(Some(&session.provider))?;
+	let metadata = self.metadata(&provider).await?;
+	let callback_url = self.callback_url(&provider)?;
+
+	let claims = oidc::exchange(
+		&self.services.client.default,
+		&metadata,
+		&Exchange {
+			code,
+			redirect_uri: callback_url.as_str(),
+			client_id: &provider.client_id,
+			client_secret: provider.client_secret.as_deref(),
+			verifier: &session.verifier,
+			nonce: &session.nonce,
+		},
+		utils::time::now_millis() / 1000,
+	)
+	.await?;
+
+	let Some(JsonValue::String(subject)) = claims.get("sub").cloned() else {
+		return Err!(Request(Forbidden("Identity provider did not return a subject.")));
+	};
+
+	debug!(provider = %provider.id, %subject, "Authenticated by identity provider");
+
+	Ok(Identity {
+		provider: provider.id,
+		subject,
+		claims,
+		redirect_url: session.redirect_url,
+	})
+}
+
+/// The local user previously linked to an identity.
+#[implement(Service)]
+pub async fn linked_user(&self, identity: &Identity) -> Result<OwnedUserId> {
+	let key = (&identity.provider, &identity.subject);
+	self.db.ssoidentity_userid.qry(&key).await.deserialized()
+}
+
+/// Links an identity to a local user, so later sign ins find the same user
+/// even if the mapped claims change.
+#[implement(Service)]
+pub fn link_user(&self, identity: &Identity, user_id: &UserId) {
+	self.link_subject(&identity.provider, &identity.subject, user_id);
+}
+
+/// Links the subject of a provider to a local user, as [`link_user`]
+/// does once they sign in.
+///
+/// [`link_user`]: Self::link_user
+#[implement(Service)]
+pub fn link_subject(&self, provider: &str, subject: &str, user_id: &UserId) {
+	let key = (provider, subject);
+	self.db.ssoidentity_userid.put(key, user_id);
+}
+
+/// The user ID mapped from the claims of an identity signing in for the
+/// first time.
+#[implement(Service)]
+pub fn map_user_id(&self, identity: &Identity) -> Result<OwnedUserId> {
+	let provider = self.provider(Some(&identity.provider))?;
+	let localpart = render(&provider.localpart_template, &identity.claims)
+		.map(|localpart| sanitize_localpart(&localpart))
+		.filter(|localpart| !localpart.is_empty())
+		.ok_or_else(|| {
+			err!(Request(Forbidden(
+				"Identity provider did not return the claims to map a username."
+			)))
+		})?;
+
+	UserId::parse_with_server_name(localpart, self.services.globals.server_name())
+		.map_err(|e| err!(Request(InvalidUsername("Mapped username is invalid: {e}"))))
+}
+
+/// The display name mapped from the claims of an identity, if the provider
+/// has a template for it.
+#[implement(Service)]
+pub fn map_displayname(&self, identity: &Identity) -> Option<String> {
+	self.provider(Some(&identity.provider))
+		.ok()?
+		.displayname_template
+		.as_deref()
+		.and_then(|template| render(template, &identity.claims))
+}
+
+#[implement(Service)]
+async fn metadata(&self, provider: &OidcProviderConfig) -> Result<Arc<Metadata>> {
+	if let Some(metadata) = self.discovered.read()?.get(&provider.id) {
+		return Ok(metadata.clone());
+	}
+
+	let metadata = oidc::discover(&self.services.client.default, &provider.issuer)
+		.await
+		.map(Arc::new)?;
+
+	self.discovered
+		.write()?
+		.insert(provider.id.clone(), metadata.clone());
+
+	Ok(metadata)
+}
+
+#[implement(Service)]
+fn callback_url(&self, provider: &OidcProviderConfig) -> Result<Url> {
+	if let Some(url) = &provider.callback_url {
+		return Ok(url.clone());
+	}
+
+	let config = &self.services.server.config;
+	let base = match &config.well_known.client {
+		| Some(client) => client.clone(),
+		| None => Url::parse(&format!("https://{}", config.server_name))
+			.map_err(|e| err!(Config("server_name", "Not usable in a URL: {e}")))?,
+	};
+
+	base.join(CALLBACK_PATH)
+		.map_err(|e| err!(Config("oidc_providers", "Invalid callback URL: {e}")))
+}
+
+/// Fills the `{claim}` placeholders of a template. Returns None if a claim
+/// is missing or not a string or number.
+fn render(template: &str, claims: &JsonMap<String, JsonValue>) -> Option<String> {
+	let mut out = String::with_capacity(template.len());
+	let mut rest = template;
+	while let Some(start) = rest.find('{') {
+		let (head, tail) = rest.split_at(start);
+		out.push_str(head);
+
+		let end = tail.find('}')?;
+		match claims.get(tail.get(1..end)?)? {
+			| JsonValue::String(value) => out.push_str(value),
+			| JsonValue::Number(value) => out.push_str(&value.to_string()),
+			| _ => return None,
+		}
+
+		rest = tail.get(end.saturating_add(1)..)?;
+	}
+
+	out.push_str(rest);
+	Some(out)
+}
+
+/// Lowercases a localpart and replaces characters not allowed in user IDs.
+fn sanitize_localpart(localpart: &str) -> String {
+	localpart
+		.to_lowercase()
+		.chars()
+		.map(|c| match c {
+			| 'a'..='z' | '0'..='9' | '.' | '_' | '=' | '-' | '/' => c,
+			| _ => '_',
+		})
+		.collect()
+}
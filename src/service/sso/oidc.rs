@@ -0,0 +1,338 @@
+//! OpenID Connect protocol: discovery, the authorization code exchange and
+//! verification of the ID token it yields.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use conduwuit::{err, Err, Result};
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use ring::signature::{
+	RsaPublicKeyComponents, UnparsedPublicKey, ECDSA_P256_SHA256_FIXED,
+	RSA_PKCS1_2048_8192_SHA256,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{Map as JsonMap, Value as JsonValue};
+use sha2::Sha256;
+use url::Url;
+
+/// Endpoints of a provider from its discovery document.
+#[derive(Debug, Deserialize)]
+pub(super) struct Metadata {
+	pub(super) issuer: String,
+	pub(super) authorization_endpoint: Url,
+	pub(super) token_endpoint: Url,
+	pub(super) userinfo_endpoint: Option<Url>,
+	pub(super) jwks_uri: Url,
+}
+
+/// Everything the token request needs besides the provider's endpoints.
+pub(super) struct Exchange<'a> {
+	pub(super) code: &'a str,
+	pub(super) redirect_uri: &'a str,
+	pub(super) client_id: &'a str,
+	pub(super) client_secret: Option<&'a str>,
+	pub(super) verifier: &'a str,
+	pub(super) nonce: &'a str,
+}
+
+/// What an ID token must have been issued for.
+pub(super) struct Expected<'a> {
+	pub(super) issuer: &'a str,
+	pub(super) client_id: &'a str,
+	pub(super) client_secret: Option<&'a str>,
+	pub(super) nonce: &'a str,
+	pub(super) now: u64,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+	access_token: String,
+	id_token: String,
+}
+
+/// Signing keys published by a provider.
+#[derive(Debug, Default, Deserialize)]
+pub(super) struct Jwks {
+	keys: Vec<Jwk>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Jwk {
+	kty: String,
+	kid: Option<String>,
+	#[serde(rename = "use")]
+	usage: Option<String>,
+	alg: Option<String>,
+	n: Option<String>,
+	e: Option<String>,
+	crv: Option<String>,
+	x: Option<String>,
+	y: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Header {
+	alg: String,
+	kid: Option<String>,
+}
+
+/// Leeway given to the clock of the provider when checking expiry.
+const CLOCK_SKEW: u64 = 60;
+
+/// Fetches the discovery document of an issuer.
+pub(super) async fn discover(client: &Client, issuer: &Url) -> Result<Metadata> {
+	let mut url = issuer.clone();
+	url.path_segments_mut()
+		.map_err(|()| err!(Config("oidc_providers", "Issuer {issuer} is not a base URL")))?
+		.pop_if_empty()
+		.extend([".well-known", "openid-configuration"]);
+
+	let response = client
+		.get(url)
+		.send()
+		.await?
+		.error_for_status()?
+		.bytes()
+		.await?;
+
+	serde_json::from_slice(&response)
+		.map_err(|e| err!(Request(Unknown("Invalid discovery document of {issuer}: {e}"))))
+}
+
+/// URL of the provider to send the user to.
+pub(super) fn authorization_url(
+	metadata: &Metadata,
+	client_id: &str,
+	redirect_uri: &str,
+	scopes: &[String],
+	state: &str,
+	nonce: &str,
+	verifier: &str,
+) -> Url {
+	let mut location = metadata.authorization_endpoint.clone();
+	location
+		.query_pairs_mut()
+		.append_pair("response_type", "code")
+		.append_pair("client_id", client_id)
+		.append_pair("redirect_uri", redirect_uri)
+		.append_pair("scope", &scopes.join(" "))
+		.append_pair("state", state)
+		.append_pair("nonce", nonce)
+		.append_pair("code_challenge", &code_challenge(verifier))
+		.append_pair("code_challenge_method", "S256");
+
+	location
+}
+
+/// Exchanges an authorization code for the claims of the user: those of the
+/// verified ID token, completed by the userinfo endpoint.
+pub(super) async fn exchange(
+	client: &Client,
+	metadata: &Metadata,
+	params: &Exchange<'_>,
+	now: u64,
+) -> Result<JsonMap<String, JsonValue>> {
+	let mut form = vec![
+		("grant_type", "authorization_code"),
+		("code", params.code),
+		("redirect_uri", params.redirect_uri),
+		("client_id", params.client_id),
+		("code_verifier", params.verifier),
+	];
+
+	if let Some(client_secret) = params.client_secret {
+		form.push(("client_secret", client_secret));
+	}
+
+	let response = client
+		.post(metadata.token_endpoint.clone())
+		.form(&form)
+		.send()
+		.await?
+		.error_for_status()
+		.map_err(|e| err!(Request(Forbidden("Identity provider refused the code: {e}"))))?
+		.bytes()
+		.await?;
+
+	let token: TokenResponse = serde_json::from_slice(&response)?;
+
+	let response = client
+		.get(metadata.jwks_uri.clone())
+		.send()
+		.await?
+		.error_for_status()?
+		.bytes()
+		.await?;
+
+	let jwks: Jwks = serde_json::from_slice(&response)?;
+	let mut claims = verify_id_token(&token.id_token, &jwks, &Expected {
+		issuer: &metadata.issuer,
+		client_id: params.client_id,
+		client_secret: params.client_secret,
+		nonce: params.nonce,
+		now,
+	})?;
+
+	let Some(userinfo_endpoint) = &metadata.userinfo_endpoint else {
+		return Ok(claims);
+	};
+
+	let response = client
+		.get(userinfo_endpoint.clone())
+		.bearer_auth(&token.access_token)
+		.send()
+		.await?
+		.error_for_status()
+		.map_err(|e| err!(Request(Forbidden("Failed to fetch user info: {e}"))))?
+		.bytes()
+		.await?;
+
+	let userinfo: JsonMap<String, JsonValue> = serde_json::from_slice(&response)?;
+	if userinfo.get("sub") != claims.get("sub") {
+		return Err!(Request(Forbidden(
+			"Identity provider returned the user info of another subject."
+		)));
+	}
+
+	for (claim, value) in userinfo {
+		claims.entry(claim).or_insert(value);
+	}
+
+	Ok(claims)
+}
+
+/// Checks the signature, issuer, audience, expiry and nonce of an ID token
+/// and returns its claims.
+pub(super) fn verify_id_token(
+	token: &str,
+	jwks: &Jwks,
+	expected: &Expected<'_>,
+) -> Result<JsonMap<String, JsonValue>> {
+	let (message, signature) = token
+		.rsplit_once('.')
+		.ok_or_else(|| err!(Request(Forbidden("Malformed ID token."))))?;
+
+	let (header, payload) = message
+		.split_once('.')
+		.ok_or_else(|| err!(Request(Forbidden("Malformed ID token."))))?;
+
+	let header: Header = decode_json(header)?;
+	let signature = URL_SAFE_NO_PAD
+		.decode(signature)
+		.map_err(|e| err!(Request(Forbidden("Malformed ID token signature: {e}"))))?;
+
+	verify_signature(&header, jwks, expected.client_secret, message.as_bytes(), &signature)?;
+
+	let claims: JsonMap<String, JsonValue> = decode_json(payload)?;
+	if claims.get("iss").and_then(JsonValue::as_str) != Some(expected.issuer) {
+		return Err!(Request(Forbidden("ID token was issued by another provider.")));
+	}
+
+	let audiences: Vec<&str> = match claims.get("aud") {
+		| Some(JsonValue::String(audience)) => vec![audience.as_str()],
+		| Some(JsonValue::Array(audiences)) =>
+			audiences.iter().filter_map(JsonValue::as_str).collect(),
+		| _ => Vec::new(),
+	};
+
+	let authorized_party = claims.get("azp").and_then(JsonValue::as_str);
+	if !audiences.contains(&expected.client_id)
+		|| authorized_party.is_some_and(|azp| azp != expected.client_id)
+	{
+		return Err!(Request(Forbidden("ID token was issued to another client.")));
+	}
+
+	let expires = claims.get("exp").and_then(JsonValue::as_u64).unwrap_or(0);
+
+	if expires.saturating_add(CLOCK_SKEW) < expected.now {
+		return Err!(Request(Forbidden("ID token has expired.")));
+	}
+
+	if claims.get("nonce").and_then(JsonValue::as_str) != Some(expected.nonce) {
+		return Err!(Request(Forbidden("ID token was issued for another sign in.")));
+	}
+
+	if !claims.get("sub").is_some_and(JsonValue::is_string) {
+		return Err!(Request(Forbidden("Identity provider did not return a subject.")));
+	}
+
+	Ok(claims)
+}
+
+fn verify_signature(
+	header: &Header,
+	jwks: &Jwks,
+	client_secret: Option<&str>,
+	message: &[u8],
+	signature: &[u8],
+) -> Result {
+	let verified = match header.alg.as_str() {
+		| "HS256" => client_secret.is_some_and(|secret| {
+			let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+				.expect("HMAC takes keys of any size");
+			mac.update(message);
+			mac.verify_slice(signature).is_ok()
+		}),
+		| "RS256" | "ES256" => jwks
+			.keys
+			.iter()
+			.filter(|key| header.kid.is_none() || key.kid == header.kid)
+			.filter(|key| key.usage.as_deref().is_none_or(|usage| usage == "sig"))
+			.filter(|key| key.alg.as_deref().is_none_or(|alg| alg == header.alg))
+			.any(|key| key.verify(&header.alg, message, signature)),
+		| alg => return Err!(Request(Forbidden("Unsupported ID token algorithm {alg}."))),
+	};
+
+	if !verified {
+		return Err!(Request(Forbidden("Invalid ID token signature.")));
+	}
+
+	Ok(())
+}
+
+impl Jwk {
+	fn verify(&self, alg: &str, message: &[u8], signature: &[u8]) -> bool {
+		let param = |value: &Option<String>| {
+			value
+				.as_deref()
+				.and_then(|value| URL_SAFE_NO_PAD.decode(value).ok())
+		};
+
+		match (alg, self.kty.as_str()) {
+			| ("RS256", "RSA") => {
+				let (Some(n), Some(e)) = (param(&self.n), param(&self.e)) else {
+					return false;
+				};
+
+				RsaPublicKeyComponents { n, e }
+					.verify(&RSA_PKCS1_2048_8192_SHA256, message, signature)
+					.is_ok()
+			},
+			| ("ES256", "EC") if self.crv.as_deref() == Some("P-256") => {
+				let (Some(x), Some(y)) = (param(&self.x), param(&self.y)) else {
+					return false;
+				};
+
+				let point = [&[0x04], x.as_slice(), y.as_slice()].concat();
+				UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, point)
+					.verify(message, signature)
+					.is_ok()
+			},
+			| _ => false,
+		}
+	}
+}
+
+/// The S256 PKCE challenge for a verifier.
+pub(super) fn code_challenge(verifier: &str) -> String {
+	use sha2::Digest;
+
+	URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+fn decode_json<T: DeserializeOwned>(part: &str) -> Result<T> {
+	let json = URL_SAFE_NO_PAD
+		.decode(part)
+		.map_err(|e| err!(Request(Forbidden("Malformed ID token: {e}"))))?;
+
+	serde_json::from_slice(&json).map_err(|e| err!(Request(Forbidden("Malformed ID token: {e}"))))
+}
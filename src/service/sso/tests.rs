@@ -0,0 +1,457 @@
+#![cfg(test)]
+
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use conduwuit::utils::time::now_millis;
+use reqwest::{header::LOCATION, redirect::Policy, Client, StatusCode};
+use ring::{
+	rand::SystemRandom,
+	signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+};
+use serde_json::{json, Value as JsonValue};
+use tokio::{
+	io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+	net::{TcpListener, TcpStream},
+};
+use url::{form_urlencoded, Url};
+
+use super::{
+	oidc::{self, code_challenge, Exchange, Expected, Jwks},
+	render, sanitize_localpart,
+};
+use crate::tests::{offline_services_with, TempDir};
+
+const CLIENT_ID: &str = "conduwuit";
+const REDIRECT_URI: &str = "https://matrix.example.com/_conduwuit/oidc/callback";
+const VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+const ACCESS_TOKEN: &str = "access-token";
+
+/// Signs ID tokens with a P-256 key generated for the test.
+struct Signer {
+	key: EcdsaKeyPair,
+	rng: SystemRandom,
+}
+
+impl Signer {
+	fn new() -> Self {
+		let rng = SystemRandom::new();
+		let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+			.expect("generated key");
+		let key =
+			EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+				.expect("parsed key");
+
+		Self { key, rng }
+	}
+
+	fn sign(&self, claims: &JsonValue) -> String {
+		let header = URL_SAFE_NO_PAD.encode(json!({"alg": "ES256", "kid": "test"}).to_string());
+		let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+		let message = format!("{header}.{payload}");
+		let signature = self
+			.key
+			.sign(&self.rng, message.as_bytes())
+			.expect("signed token");
+
+		format!("{message}.{}", URL_SAFE_NO_PAD.encode(signature.as_ref()))
+	}
+
+	fn jwks(&self) -> JsonValue {
+		let point = self.key.public_key().as_ref();
+		json!({"keys": [{
+			"kty": "EC",
+			"kid": "test",
+			"use": "sig",
+			"alg": "ES256",
+			"crv": "P-256",
+			"x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+			"y": URL_SAFE_NO_PAD.encode(&point[33..65]),
+		}]})
+	}
+}
+
+/// An authorization granted by the mock provider, waiting to be exchanged.
+struct Grant {
+	challenge: String,
+	nonce: String,
+	redirect_uri: String,
+}
+
+/// An identity provider serving discovery, authorization, token, JWKS and
+/// userinfo endpoints over plain HTTP.
+struct MockIdp {
+	issuer: Url,
+	signer: Signer,
+	grants: Mutex<HashMap<String, Grant>>,
+}
+
+impl MockIdp {
+	async fn start() -> Arc<Self> {
+		let listener = TcpListener::bind("127.0.0.1:0")
+			.await
+			.expect("bound listener");
+		let port = listener.local_addr().expect("local address").port();
+		let idp = Arc::new(Self {
+			issuer: Url::parse(&format!("http://127.0.0.1:{port}/")).expect("issuer URL"),
+			signer: Signer::new(),
+			grants: Mutex::default(),
+		});
+
+		let server = idp.clone();
+		tokio::spawn(async move {
+			while let Ok((stream, _)) = listener.accept().await {
+				tokio::spawn(server.clone().handle(stream));
+			}
+		});
+
+		idp
+	}
+
+	async fn handle(self: Arc<Self>, stream: TcpStream) {
+		let mut reader = BufReader::new(stream);
+		let mut line = String::new();
+		reader.read_line(&mut line).await.expect("read request");
+		let target = line.split(' ').nth(1).expect("request target").to_owned();
+
+		let (mut content_length, mut authorization) = (0, String::new());
+		loop {
+			line.clear();
+			reader.read_line(&mut line).await.expect("read header");
+			let Some((name, value)) = line.trim_end().split_once(':') else {
+				break;
+			};
+
+			match name.to_ascii_lowercase().as_str() {
+				| "content-length" => content_length = value.trim().parse().expect("length"),
+				| "authorization" => value.trim().clone_into(&mut authorization),
+				| _ => {},
+			}
+		}
+
+		let mut body = vec![0; content_length];
+		reader.read_exact(&mut body).await.expect("read body");
+
+		let url = self.issuer.join(&target).expect("request URL");
+		let (status, location, json) = self.respond(&url, &body, &authorization);
+		let json = json.to_string();
+		let location = location
+			.map(|location| format!("Location: {location}\r\n"))
+			.unwrap_or_default();
+
+		let response = format!(
+			"HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: \
+			 {}\r\n{location}Connection: close\r\n\r\n{json}",
+			json.len()
+		);
+
+		let mut stream = reader.into_inner();
+		stream
+			.write_all(response.as_bytes())
+			.await
+			.expect("wrote response");
+	}
+
+	fn respond(
+		&self,
+		url: &Url,
+		body: &[u8],
+		authorization: &str,
+	) -> (&'static str, Option<String>, JsonValue) {
+		let base = self.issuer.as_str().trim_end_matches('/');
+		match url.path() {
+			| "/.well-known/openid-configuration" => (
+				"200 OK",
+				None,
+				json!({
+					"issuer": self.issuer,
+					"authorization_endpoint": format!("{base}/authorize"),
+					"token_endpoint": format!("{base}/token"),
+					"userinfo_endpoint": format!("{base}/userinfo"),
+					"jwks_uri": format!("{base}/jwks"),
+				}),
+			),
+			| "/authorize" => {
+				let query: HashMap<_, _> = url.query_pairs().into_owned().collect();
+				assert_eq!(query["client_id"], CLIENT_ID);
+				assert_eq!(query["code_challenge_method"], "S256");
+
+				let code = format!("code-{}", query["state"]);
+				let mut location = Url::parse(&query["redirect_uri"]).expect("redirect URI");
+				location
+					.query_pairs_mut()
+					.append_pair("code", &code)
+					.append_pair("state", &query["state"]);
+
+				self.grants.lock().expect("locked").insert(code, Grant {
+					challenge: query["code_challenge"].clone(),
+					nonce: query["nonce"].clone(),
+					redirect_uri: query["redirect_uri"].clone(),
+				});
+
+				("302 Found", Some(location.into()), json!({}))
+			},
+			| "/token" => {
+				let form: HashMap<_, _> = form_urlencoded::parse(body).into_owned().collect();
+				let grant = self.grants.lock().expect("locked").remove(&form["code"]);
+				let Some(grant) = grant.filter(|grant| {
+					grant.challenge == code_challenge(&form["code_verifier"])
+						&& grant.redirect_uri == form["redirect_uri"]
+				}) else {
+					return ("400 Bad Request", None, json!({"error": "invalid_grant"}));
+				};
+
+				let id_token = self.signer.sign(&json!({
+					"iss": self.issuer,
+					"aud": CLIENT_ID,
+					"sub": "alice-subject",
+					"exp": (now_millis() / 1000).saturating_add(300),
+					"iat": now_millis() / 1000,
+					"nonce": grant.nonce,
+				}));
+
+				(
+					"200 OK",
+					None,
+					json!({
+						"access_token": ACCESS_TOKEN,
+						"token_type": "Bearer",
+						"id_token": id_token,
+					}),
+				)
+			},
+			| "/jwks" => ("200 OK", None, self.signer.jwks()),
+			| "/userinfo" if authorization == format!("Bearer {ACCESS_TOKEN}") => (
+				"200 OK",
+				None,
+				json!({
+					"sub": "alice-subject",
+					"preferred_username": "alice",
+					"name": "Alice Liddell",
+				}),
+			),
+			| _ => ("401 Unauthorized", None, json!({})),
+		}
+	}
+}
+
+/// Runs the authorization code flow against a mock provider up to the token
+/// exchange, which uses `nonce` as the one expected in the ID token.
+async fn sign_in(
+	idp: &MockIdp,
+	nonce: &str,
+) -> conduwuit::Result<serde_json::Map<String, JsonValue>> {
+	let client = Client::builder()
+		.redirect(Policy::none())
+		.build()
+		.expect("built client");
+
+	let metadata = oidc::discover(&client, &idp.issuer)
+		.await
+		.expect("discovered provider");
+
+	let scopes = ["openid".to_owned(), "profile".to_owned()];
+	let location = oidc::authorization_url(
+		&metadata,
+		CLIENT_ID,
+		REDIRECT_URI,
+		&scopes,
+		"state",
+		"nonce",
+		VERIFIER,
+	);
+
+	let response = client.get(location).send().await.expect("authorized");
+	assert_eq!(response.status(), StatusCode::FOUND);
+
+	let callback = response.headers()[LOCATION]
+		.to_str()
+		.expect("location header");
+	let callback = Url::parse(callback).expect("callback URL");
+	assert!(callback.as_str().starts_with(REDIRECT_URI));
+
+	let query: HashMap<_, _> = callback.query_pairs().into_owned().collect();
+	assert_eq!(query["state"], "state");
+
+	oidc::exchange(
+		&client,
+		&metadata,
+		&Exchange {
+			code: &query["code"],
+			redirect_uri: REDIRECT_URI,
+			client_id: CLIENT_ID,
+			client_secret: None,
+			verifier: VERIFIER,
+			nonce,
+		},
+		now_millis() / 1000,
+	)
+	.await
+}
+
+#[tokio::test]
+async fn mock_idp_sign_in() {
+	let idp = MockIdp::start().await;
+	let claims = sign_in(&idp, "nonce").await.expect("signed in");
+
+	assert_eq!(claims["sub"], "alice-subject");
+	assert_eq!(claims["nonce"], "nonce");
+	assert_eq!(claims["preferred_username"], "alice");
+	assert_eq!(render("{name}", &claims).as_deref(), Some("Alice Liddell"));
+}
+
+#[tokio::test]
+async fn mock_idp_nonce_mismatch() {
+	let idp = MockIdp::start().await;
+	sign_in(&idp, "another nonce")
+		.await
+		.expect_err("ID token of another sign in refused");
+}
+
+fn id_token_claims(now: u64) -> JsonValue {
+	json!({
+		"iss": "https://idp.example.com",
+		"aud": [CLIENT_ID, "other"],
+		"azp": CLIENT_ID,
+		"sub": "alice-subject",
+		"exp": now.saturating_add(300),
+		"nonce": "nonce",
+	})
+}
+
+fn verify(
+	signer: &Signer,
+	token: &str,
+	now: u64,
+) -> conduwuit::Result<serde_json::Map<String, JsonValue>> {
+	let jwks: Jwks = serde_json::from_value(signer.jwks()).expect("valid JWKS");
+	oidc::verify_id_token(token, &jwks, &Expected {
+		issuer: "https://idp.example.com",
+		client_id: CLIENT_ID,
+		client_secret: Some("secret"),
+		nonce: "nonce",
+		now,
+	})
+}
+
+#[test]
+fn id_token_valid() {
+	let (signer, now) = (Signer::new(), now_millis() / 1000);
+	let token = signer.sign(&id_token_claims(now));
+
+	let claims = verify(&signer, &token, now).expect("valid token");
+	assert_eq!(claims["sub"], "alice-subject");
+}
+
+#[test]
+fn id_token_rejected_claims() {
+	let (signer, now) = (Signer::new(), now_millis() / 1000);
+	let cases = [
+		("iss", json!("https://evil.example.com")),
+		("aud", json!("other")),
+		("azp", json!("other")),
+		("nonce", json!("other")),
+		("exp", json!(now.saturating_sub(3600))),
+		("sub", json!(null)),
+	];
+
+	for (claim, value) in cases {
+		let mut claims = id_token_claims(now);
+		claims[claim] = value;
+		let token = signer.sign(&claims);
+		assert!(verify(&signer, &token, now).is_err(), "accepted token with bad {claim}");
+	}
+}
+
+#[test]
+fn id_token_rejected_signature() {
+	let (signer, now) = (Signer::new(), now_millis() / 1000);
+	let claims = id_token_claims(now);
+
+	let forged = Signer::new().sign(&claims);
+	assert!(verify(&signer, &forged, now).is_err(), "accepted token of another key");
+
+	let token = signer.sign(&claims);
+	let (message, _) = token.rsplit_once('.').expect("signed token");
+	let mut tampered = id_token_claims(now);
+	tampered["sub"] = json!("mallory-subject");
+	let tampered = format!(
+		"{}.{}.{}",
+		message.split_once('.').expect("header").0,
+		URL_SAFE_NO_PAD.encode(tampered.to_string()),
+		token.rsplit_once('.').expect("signature").1,
+	);
+	assert!(verify(&signer, &tampered, now).is_err(), "accepted tampered token");
+
+	let header = URL_SAFE_NO_PAD.encode(json!({"alg": "none"}).to_string());
+	let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+	let unsigned = format!("{header}.{payload}.");
+	assert!(verify(&signer, &unsigned, now).is_err(), "accepted unsigned token");
+}
+
+#[test]
+fn pkce_challenge() {
+	// RFC 7636 appendix B
+	assert_eq!(
+		code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+		"E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+	);
+}
+
+#[test]
+fn render_template() {
+	let claims = json!({
+		"preferred_username": "Alice",
+		"name": "Alice Liddell",
+		"uid": 1000,
+		"groups": ["admins"],
+	});
+	let claims = claims.as_object().expect("object");
+
+	assert_eq!(render("{preferred_username}", claims).as_deref(), Some("Alice"));
+	assert_eq!(render("{name} ({uid})", claims).as_deref(), Some("Alice Liddell (1000)"));
+	assert_eq!(render("static", claims).as_deref(), Some("static"));
+	assert_eq!(render("{email}", claims), None);
+	assert_eq!(render("{groups}", claims), None);
+	assert_eq!(render("{unterminated", claims), None);
+}
+
+#[test]
+fn sanitize() {
+	assert_eq!(sanitize_localpart("Alice"), "alice");
+	assert_eq!(sanitize_localpart("alice.l-1_=/"), "alice.l-1_=/");
+	assert_eq!(sanitize_localpart("alice liddell@example"), "alice_liddell_example");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cookie_path_follows_callback_url() {
+	let dir = TempDir::new("sso");
+	let services = offline_services_with(
+		&dir,
+		"example.com",
+		r#"
+[[global.oidc_providers]]
+id = "default"
+issuer = "https://idp.example.com"
+client_id = "conduwuit"
+
+[[global.oidc_providers]]
+id = "custom"
+issuer = "https://idp.example.com"
+client_id = "conduwuit"
+callback_url = "https://matrix.example.com/sso/done?from=idp"
+"#,
+	)
+	.await
+	.expect("started services offline");
+
+	let sso = &services.sso;
+	assert_eq!(sso.callback_path(None).expect("first provider"), super::CALLBACK_PATH);
+	assert_eq!(sso.callback_path(Some("custom")).expect("custom provider"), "/sso/done");
+	assert!(sso.callback_path(Some("unknown")).is_err());
+
+	services.stop_offline();
+}
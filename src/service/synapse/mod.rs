@@ -0,0 +1,666 @@
+//! Importer for the SQLite database of a Synapse homeserver, run offline
+//! against a new database of the same server name. Whatever cannot be carried
+//! over is listed in the [`Report`] instead of failing the import.
+
+mod rooms;
+mod source;
+mod tests;
+
+use std::{
+	collections::{BTreeMap, BTreeSet, HashMap},
+	fmt,
+	path::Path,
+};
+
+use base64::{engine::general_purpose, Engine as _};
+use conduwuit::{
+	debug, err, info,
+	utils::{self, content_disposition::make_content_disposition},
+	warn, Err, Result,
+};
+use ruma::{
+	encryption::CrossSigningKey,
+	events::{
+		push_rules::{PushRulesEvent, PushRulesEventContent},
+		GlobalAccountDataEventType,
+	},
+	push::{
+		Action, NewConditionalPushRule, NewPatternedPushRule, NewPushRule, NewSimplePushRule,
+		PushCondition, RuleKind, Ruleset,
+	},
+	serde::Raw,
+	Mxc, OwnedDeviceId, OwnedMxcUri, OwnedUserId, RoomId, UserId,
+};
+use serde_json::{json, value::to_raw_value};
+
+use self::source::Source;
+use crate::Services;
+
+/// Length of the access tokens of devices whose Synapse token is gone.
+const TOKEN_LENGTH: usize = 32;
+
+/// Length of the password nobody knows given to users who sign in through
+/// single sign-on only.
+const RANDOM_PASSWORD_LENGTH: usize = 64;
+
+/// What to import besides the database.
+#[derive(Clone, Copy, Debug)]
+pub struct Options<'a> {
+	/// Synapse's `homeserver.db`
+	pub database: &'a Path,
+
+	/// Synapse's `media_store_path`; media is not imported without it.
+	pub media_store: Option<&'a Path>,
+
+	/// Synapse's `signing_key_path`; the server keeps its own key without it.
+	pub signing_key: Option<&'a Path>,
+}
+
+/// Outcome of an import.
+#[derive(Debug, Default)]
+pub struct Report {
+	/// Records imported, by kind
+	pub imported: BTreeMap<&'static str, usize>,
+
+	/// Records which were not imported, with the reason
+	pub skipped: Vec<String>,
+}
+
+/// Imports the users, their devices, keys and account data, media, and rooms
+/// of a Synapse database. Users and rooms which already exist are skipped.
+pub async fn import(services: &Services, options: &Options<'_>) -> Result<Report> {
+	let source = Source::open(options.database)?;
+	let mut report = Report::default();
+
+	if let Some(path) = options.signing_key {
+		import_signing_key(services, path)?;
+		report.count("signing keys");
+	}
+
+	let users = import_users(services, &source, &mut report).await?;
+	import_sso_identities(services, &source, &users, &mut report)?;
+	import_push_rules(services, &source, &users, &mut report).await?;
+	import_profiles(services, &source, &users, &mut report)?;
+	import_devices(services, &source, &users, &mut report).await?;
+	import_account_data(services, &source, &users, &mut report).await?;
+	import_backups(services, &source, &users, &mut report).await?;
+	if let Some(media_store) = options.media_store {
+		import_media(services, &source, media_store, &mut report).await?;
+	}
+
+	rooms::import(services, &source, &mut report).await?;
+	info!("Imported from Synapse: {report}");
+
+	Ok(report)
+}
+
+impl Report {
+	fn count(&mut self, kind: &'static str) {
+		let count = self.imported.entry(kind).or_default();
+		*count = count.saturating_add(1);
+	}
+
+	fn skip(&mut self, reason: String) {
+		debug!("Skipping {reason}");
+		self.skipped.push(reason);
+	}
+}
+
+impl fmt::Display for Report {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for (kind, count) in &self.imported {
+			writeln!(f, "imported {count} {kind}")?;
+		}
+
+		writeln!(f, "skipped {}:", self.skipped.len())?;
+		for skipped in &self.skipped {
+			writeln!(f, "- {skipped}")?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Replaces the signing key with Synapse's, so that remote servers keep
+/// accepting the events it signed. The file holds one key as
+/// `ed25519 <version> <unpadded base64 seed>`.
+fn import_signing_key(services: &Services, path: &Path) -> Result {
+	let file = std::fs::read_to_string(path)?;
+	let mut fields = file.split_whitespace();
+	let (Some("ed25519"), Some(version), Some(seed)) =
+		(fields.next(), fields.next(), fields.next())
+	else {
+		return Err!("{} is not a Synapse signing key.", path.display());
+	};
+
+	let seed = general_purpose::STANDARD_NO_PAD
+		.decode(seed.trim_end_matches('='))
+		.map_err(|e| err!("Invalid signing key seed: {e}"))?;
+	crate::server_keys::import_keypair(&services.db, version, &seed)?;
+	warn!("Imported signing key ed25519:{version}; it is used from the next start.");
+
+	Ok(())
+}
+
+async fn import_users(
+	services: &Services,
+	source: &Source,
+	report: &mut Report,
+) -> Result<BTreeSet<OwnedUserId>> {
+	let mut imported = BTreeSet::new();
+	for user in source.users()? {
+		let Ok(user_id) = UserId::parse(&user.name) else {
+			report.skip(format!("user {:?}: invalid user ID", user.name));
+			continue;
+		};
+
+		if !services.globals.user_is_local(&user_id) {
+			report.skip(format!("user {user_id}: not of this server"));
+			continue;
+		}
+
+		if user.is_guest {
+			report.skip(format!("user {user_id}: guest account"));
+			continue;
+		}
+
+		if services.users.exists(&user_id).await {
+			report.skip(format!("user {user_id}: already exists"));
+			continue;
+		}
+
+		// Users without a password sign in through single sign-on only; their
+		// account stays active with a password nobody knows.
+		let password_hash = user
+			.password_hash
+			.as_deref()
+			.filter(|hash| !hash.is_empty());
+
+		let random_password = (password_hash.is_none() && !user.deactivated)
+			.then(|| utils::random_string(RANDOM_PASSWORD_LENGTH));
+
+		services
+			.users
+			.create(&user_id, random_password.as_deref())?;
+
+		if let Some(hash) = password_hash.filter(|_| !user.deactivated) {
+			services.users.set_password_hash(&user_id, hash);
+		}
+
+		services
+			.account_data
+			.update(
+				None,
+				&user_id,
+				GlobalAccountDataEventType::PushRules.to_string().into(),
+				&serde_json::to_value(PushRulesEvent {
+					content: PushRulesEventContent {
+						global: Ruleset::server_default(&user_id),
+					},
+				})?,
+			)
+			.await?;
+
+		if user.admin && !user.deactivated {
+			services.admin.make_user_admin(&user_id).await?;
+		}
+
+		report.count("users");
+		imported.insert(user_id);
+	}
+
+	Ok(imported)
+}
+
+/// Links the users who signed in through single sign-on to their identity at
+/// the providers configured here. Synapse names the providers of its
+/// `oidc_providers` `oidc-<idp_id>`.
+fn import_sso_identities(
+	services: &Services,
+	source: &Source,
+	users: &BTreeSet<OwnedUserId>,
+	report: &mut Report,
+) -> Result {
+	for external_id in source.external_ids()? {
+		let Some(user_id) = imported_user(users, &external_id.user_id) else {
+			continue;
+		};
+
+		let provider = &external_id.auth_provider;
+		let provider = provider.strip_prefix("oidc-").unwrap_or(provider);
+		if services.sso.provider(Some(provider)).is_err() {
+			report.skip(format!(
+				"identity of {user_id} at {}: provider not configured",
+				external_id.auth_provider
+			));
+			continue;
+		}
+
+		services
+			.sso
+			.link_subject(provider, &external_id.external_id, user_id);
+
+		report.count("single sign-on identities");
+	}
+
+	Ok(())
+}
+
+/// Replaces the default push rules given to imported users with the rules
+/// they had on Synapse.
+async fn import_push_rules(
+	services: &Services,
+	source: &Source,
+	users: &BTreeSet<OwnedUserId>,
+	report: &mut Report,
+) -> Result {
+	let mut rulesets: BTreeMap<&UserId, Ruleset> = BTreeMap::new();
+	for rule in source.push_rules()? {
+		let Some(user_id) = imported_user(users, &rule.user_id) else {
+			continue;
+		};
+
+		let ruleset = rulesets
+			.entry(user_id)
+			.or_insert_with(|| Ruleset::server_default(user_id));
+
+		if let Err(e) = import_push_rule(ruleset, &rule) {
+			report.skip(format!("push rule {} of {user_id}: {e}", rule.rule_id));
+		}
+	}
+
+	for rule in source.push_rules_enabled()? {
+		let Some(user_id) = imported_user(users, &rule.user_id) else {
+			continue;
+		};
+
+		let ruleset = rulesets
+			.entry(user_id)
+			.or_insert_with(|| Ruleset::server_default(user_id));
+
+		let found = split_rule_id(&rule.rule_id).is_some_and(|(kind, rule_id)| {
+			ruleset.set_enabled(kind, rule_id, rule.enabled).is_ok()
+		});
+
+		if !found {
+			report.skip(format!("push rule {} of {user_id}: not found", rule.rule_id));
+		}
+	}
+
+	for (user_id, global) in rulesets {
+		services
+			.account_data
+			.update(
+				None,
+				user_id,
+				GlobalAccountDataEventType::PushRules.to_string().into(),
+				&serde_json::to_value(PushRulesEvent {
+					content: PushRulesEventContent { global },
+				})?,
+			)
+			.await?;
+
+		report.count("push rules");
+	}
+
+	Ok(())
+}
+
+/// Adds a rule to a ruleset. Rules come lowest priority first, as each goes
+/// before the rules of its kind added so far. Synapse keeps changes to the
+/// actions of default rules as rules of their own.
+fn import_push_rule(ruleset: &mut Ruleset, rule: &source::PushRule) -> Result {
+	let (kind, rule_id) =
+		split_rule_id(&rule.rule_id).ok_or_else(|| err!("unexpected rule ID"))?;
+
+	let actions: Vec<Action> = serde_json::from_str(&rule.actions)?;
+	if rule_id.starts_with('.') {
+		return ruleset
+			.set_actions(kind, rule_id, actions)
+			.map_err(|_| err!("no such default rule"));
+	}
+
+	let conditions: Vec<serde_json::Value> = serde_json::from_str(&rule.conditions)?;
+	let new_rule = match kind {
+		| RuleKind::Override | RuleKind::Underride => {
+			let conditions = conditions
+				.into_iter()
+				.map(serde_json::from_value)
+				.collect::<Result<Vec<PushCondition>, _>>()?;
+
+			let rule = NewConditionalPushRule::new(rule_id.to_owned(), conditions, actions);
+			if kind == RuleKind::Override {
+				NewPushRule::Override(rule)
+			} else {
+				NewPushRule::Underride(rule)
+			}
+		},
+		| RuleKind::Content => {
+			let pattern = conditions
+				.iter()
+				.find_map(|condition| condition.get("pattern")?.as_str())
+				.ok_or_else(|| err!("content rule without a pattern"))?;
+
+			NewPushRule::Content(NewPatternedPushRule::new(
+				rule_id.to_owned(),
+				pattern.to_owned(),
+				actions,
+			))
+		},
+		| RuleKind::Room =>
+			NewPushRule::Room(NewSimplePushRule::new(RoomId::parse(rule_id)?, actions)),
+		| RuleKind::Sender =>
+			NewPushRule::Sender(NewSimplePushRule::new(UserId::parse(rule_id)?, actions)),
+		| kind => return Err!("unknown kind {kind}"),
+	};
+
+	ruleset
+		.insert(new_rule, None, None)
+		.map_err(|e| err!("{e}"))
+}
+
+/// Splits a rule ID of Synapse, `global/<kind>/<rule ID>`.
+fn split_rule_id(rule_id: &str) -> Option<(RuleKind, &str)> {
+	let (kind, rule_id) = rule_id.strip_prefix("global/")?.split_once('/')?;
+
+	Some((kind.into(), rule_id))
+}
+
+fn import_profiles(
+	services: &Services,
+	source: &Source,
+	users: &BTreeSet<OwnedUserId>,
+	report: &mut Report,
+) -> Result {
+	let server_name = services.globals.server_name();
+	for profile in source.profiles()? {
+		// Older schemas key profiles by localpart
+		let user_id = if profile.user_id.starts_with('@') {
+			UserId::parse(profile.user_id.as_str())
+		} else {
+			UserId::parse_with_server_name(profile.user_id.as_str(), server_name)
+		};
+
+		let Some(user_id) = user_id.ok().filter(|user_id| users.contains(user_id)) else {
+			continue;
+		};
+
+		if let Some(avatar_url) = profile.avatar_url.map(OwnedMxcUri::from) {
+			if avatar_url.is_valid() {
+				services.users.set_avatar_url(&user_id, Some(avatar_url));
+			} else {
+				report.skip(format!("avatar of {user_id}: invalid URL {avatar_url}"));
+			}
+		}
+
+		services
+			.users
+			.set_displayname(&user_id, profile.displayname);
+
+		report.count("profiles");
+	}
+
+	Ok(())
+}
+
+async fn import_devices(
+	services: &Services,
+	source: &Source,
+	users: &BTreeSet<OwnedUserId>,
+	report: &mut Report,
+) -> Result {
+	for device in source.devices()? {
+		let Some(user_id) = imported_user(users, &device.user_id) else {
+			continue;
+		};
+
+		let device_id: OwnedDeviceId = device.device_id.into();
+		let token = device
+			.token
+			.unwrap_or_else(|| utils::random_string(TOKEN_LENGTH));
+
+		services
+			.users
+			.create_device(user_id, &device_id, &token, device.display_name, device.ip)
+			.await?;
+
+		report.count("devices");
+	}
+
+	for keys in source.device_keys()? {
+		let Some(user_id) = imported_user(users, &keys.user_id) else {
+			continue;
+		};
+
+		let Ok(device_keys) = serde_json::from_str(&keys.key_json) else {
+			report.skip(format!("keys of device {} of {user_id}: invalid JSON", keys.device_id));
+			continue;
+		};
+
+		let device_id: OwnedDeviceId = keys.device_id.into();
+		services
+			.users
+			.add_device_keys(user_id, &device_id, &device_keys)
+			.await;
+
+		report.count("device keys");
+	}
+
+	let mut cross_signing: HashMap<&UserId, HashMap<String, Raw<CrossSigningKey>>> =
+		HashMap::new();
+	let keys = source.cross_signing_keys()?;
+	for key in &keys {
+		let Some(user_id) = imported_user(users, &key.user_id) else {
+			continue;
+		};
+
+		match serde_json::from_str(&key.keydata) {
+			| Ok(keydata) => {
+				cross_signing
+					.entry(user_id)
+					.or_default()
+					.insert(key.keytype.clone(), keydata);
+			},
+			| Err(_) => report.skip(format!("{} key of {user_id}: invalid JSON", key.keytype)),
+		}
+	}
+
+	for (user_id, mut keys) in cross_signing {
+		let Some(master) = keys.remove("master") else {
+			report.skip(format!("cross-signing keys of {user_id}: no master key"));
+			continue;
+		};
+
+		let self_signing = keys.remove("self_signing");
+		let user_signing = keys.remove("user_signing");
+		if let Err(e) = services
+			.users
+			.add_cross_signing_keys(user_id, &master, &self_signing, &user_signing, false)
+			.await
+		{
+			report.skip(format!("cross-signing keys of {user_id}: {e}"));
+			continue;
+		}
+
+		report.count("cross-signing keys");
+	}
+
+	Ok(())
+}
+
+async fn import_account_data(
+	services: &Services,
+	source: &Source,
+	users: &BTreeSet<OwnedUserId>,
+	report: &mut Report,
+) -> Result {
+	for data in source.account_data()? {
+		let Some(user_id) = imported_user(users, &data.user_id) else {
+			continue;
+		};
+
+		let room_id = match data.room_id.as_deref().map(RoomId::parse).transpose() {
+			| Ok(room_id) => room_id,
+			| Err(_) => {
+				report.skip(format!("{} of {user_id}: invalid room ID", data.kind));
+				continue;
+			},
+		};
+
+		let Ok(content) = serde_json::from_str::<serde_json::Value>(&data.content) else {
+			report.skip(format!("{} of {user_id}: invalid JSON", data.kind));
+			continue;
+		};
+
+		// The rules made for the user on import stand in for Synapse's, which
+		// live in their own tables.
+		if data.kind == GlobalAccountDataEventType::PushRules.to_string() {
+			continue;
+		}
+
+		let event = json!({ "type": data.kind, "content": content });
+		services
+			.account_data
+			.update(room_id, user_id, data.kind.as_str().into(), &event)
+			.await?;
+
+		report.count("account data");
+	}
+
+	Ok(())
+}
+
+async fn import_backups(
+	services: &Services,
+	source: &Source,
+	users: &BTreeSet<OwnedUserId>,
+	report: &mut Report,
+) -> Result {
+	// Versions are numbered anew, as they are counters of this server.
+	let mut versions: HashMap<(OwnedUserId, String), String> = HashMap::new();
+	for backup in source.backups()? {
+		let Some(user_id) = imported_user(users, &backup.user_id) else {
+			continue;
+		};
+
+		let Ok(auth_data) = serde_json::from_str::<serde_json::Value>(&backup.auth_data) else {
+			report.skip(format!("key backup {} of {user_id}: invalid JSON", backup.version));
+			continue;
+		};
+
+		let algorithm = json!({ "algorithm": backup.algorithm, "auth_data": auth_data });
+		let algorithm = Raw::from_json(to_raw_value(&algorithm)?);
+		let version = services.key_backups.create_backup(user_id, &algorithm)?;
+		versions.insert((user_id.to_owned(), backup.version), version);
+
+		report.count("key backups");
+	}
+
+	for key in source.backup_keys()? {
+		let Some(user_id) = imported_user(users, &key.user_id) else {
+			continue;
+		};
+
+		let Some(version) = versions.get(&(user_id.to_owned(), key.version.clone())) else {
+			continue;
+		};
+
+		let (Ok(room_id), Ok(session_data)) = (
+			RoomId::parse(&key.room_id),
+			serde_json::from_str::<serde_json::Value>(&key.session_data),
+		) else {
+			report.skip(format!("backed up key {} of {user_id}: invalid", key.session_id));
+			continue;
+		};
+
+		let key_data = json!({
+			"first_message_index": key.first_message_index,
+			"forwarded_count": key.forwarded_count,
+			"is_verified": key.is_verified,
+			"session_data": session_data,
+		});
+
+		services
+			.key_backups
+			.add_key(
+				user_id,
+				version,
+				room_id,
+				&key.session_id,
+				&Raw::from_json(to_raw_value(&key_data)?),
+			)
+			.await?;
+
+		report.count("backed up keys");
+	}
+
+	Ok(())
+}
+
+/// Copies local media from Synapse's media store, keeping the media IDs so
+/// that `mxc://` URIs in events and profiles stay valid.
+async fn import_media(
+	services: &Services,
+	source: &Source,
+	media_store: &Path,
+	report: &mut Report,
+) -> Result {
+	let server_name = services.globals.server_name();
+	for media in source.media()? {
+		let id = &media.media_id;
+		if media.quarantined {
+			report.skip(format!("media {id}: quarantined"));
+			continue;
+		}
+
+		if id.len() < 5 || !id.is_ascii() {
+			report.skip(format!("media {id:?}: unexpected ID"));
+			continue;
+		}
+
+		let path = media_store
+			.join("local_content")
+			.join(&id[..2])
+			.join(&id[2..4])
+			.join(&id[4..]);
+
+		let file = match tokio::fs::read(&path).await {
+			| Ok(file) => file,
+			| Err(e) => {
+				report.skip(format!("media {id}: {}: {e}", path.display()));
+				continue;
+			},
+		};
+
+		let user_id = media
+			.user_id
+			.as_deref()
+			.and_then(|id| UserId::parse(id).ok());
+		let content_disposition = make_content_disposition(
+			None,
+			media.media_type.as_deref(),
+			media.upload_name.as_deref(),
+		);
+
+		services
+			.media
+			.create(
+				&Mxc { server_name, media_id: id.as_str() },
+				user_id.as_deref(),
+				Some(&content_disposition),
+				media.media_type.as_deref(),
+				&file,
+			)
+			.await?;
+
+		report.count("media");
+	}
+
+	Ok(())
+}
+
+/// The user, if it was imported; data of other users is left out silently.
+fn imported_user<'a>(users: &'a BTreeSet<OwnedUserId>, user_id: &str) -> Option<&'a UserId> {
+	let user_id = <&UserId>::try_from(user_id).ok()?;
+	users.get(user_id).map(AsRef::as_ref)
+}
@@ -0,0 +1,302 @@
+//! Importing the rooms of a Synapse database with their events as Synapse
+//! stored them, keeping event IDs and signatures.
+
+use std::{
+	borrow::Borrow,
+	collections::{HashMap, HashSet},
+	sync::Arc,
+};
+
+use conduwuit::{PduEvent, Result};
+use futures::StreamExt;
+use ruma::{
+	events::StateEventType, CanonicalJsonObject, CanonicalJsonValue, OwnedEventId, RoomAliasId,
+	RoomId, UserId,
+};
+use serde::Deserialize;
+
+use super::{
+	source::{Source, State},
+	Report,
+};
+use crate::{
+	rooms::{
+		short::ShortStateKey, state::RoomMutexGuard, state_compressor::HashSetCompressStateEvent,
+	},
+	Services,
+};
+
+/// Flags Synapse keeps beside each event.
+#[derive(Debug, Default, Deserialize)]
+struct InternalMetadata {
+	#[serde(default)]
+	outlier: bool,
+
+	#[serde(default)]
+	soft_failed: bool,
+}
+
+pub(super) async fn import(services: &Services, source: &Source, report: &mut Report) -> Result {
+	for room in source.rooms()? {
+		let Ok(room_id) = RoomId::parse(&room.room_id) else {
+			report.skip(format!("room {:?}: invalid room ID", room.room_id));
+			continue;
+		};
+
+		if services.rooms.metadata.exists(&room_id).await {
+			report.skip(format!("room {room_id}: already exists"));
+			continue;
+		}
+
+		if !import_room(services, source, &room_id, report).await? {
+			report.skip(format!("room {room_id}: no events"));
+			continue;
+		}
+
+		if room.is_public {
+			services.rooms.directory.set_public(&room_id);
+		}
+
+		report.count("rooms");
+	}
+
+	for alias in source.aliases()? {
+		let Ok(alias_id) = RoomAliasId::parse(&alias.alias) else {
+			report.skip(format!("alias {:?}: invalid alias", alias.alias));
+			continue;
+		};
+
+		if !services.globals.server_is_ours(alias_id.server_name()) {
+			continue;
+		}
+
+		let Ok(room_id) = RoomId::parse(&alias.room_id) else {
+			continue;
+		};
+
+		if !services.rooms.metadata.exists(&room_id).await
+			|| services
+				.rooms
+				.alias
+				.resolve_local_alias(&alias_id)
+				.await
+				.is_ok()
+		{
+			report.skip(format!("alias {alias_id}: room not imported or alias taken"));
+			continue;
+		}
+
+		let creator = alias
+			.creator
+			.as_deref()
+			.and_then(|creator| UserId::parse(creator).ok())
+			.unwrap_or_else(|| services.globals.server_user.clone());
+
+		services
+			.rooms
+			.alias
+			.set_alias(&alias_id, &room_id, &creator)?;
+
+		report.count("aliases");
+	}
+
+	Ok(())
+}
+
+/// Appends the events of a room in Synapse's order, as history: members are
+/// not notified of them and they are not sent to anyone. Forks are linearized:
+/// the state before each event is the state the previous events built, and
+/// the room ends at the state Synapse resolved. Returns false if the room had
+/// no events.
+async fn import_room(
+	services: &Services,
+	source: &Source,
+	room_id: &RoomId,
+	report: &mut Report,
+) -> Result<bool> {
+	let events = source.events(room_id.as_str())?;
+	if events.is_empty() {
+		return Ok(false);
+	}
+
+	let state_lock = services.rooms.state.mutex.lock(room_id).await;
+	services
+		.rooms
+		.short
+		.get_or_create_shortroomid(room_id)
+		.await;
+
+	// Every event is stored as an outlier first, so that auth and prev events
+	// resolve whatever order the timeline is appended in.
+	let mut timeline = Vec::with_capacity(events.len());
+	for event in events {
+		let event_id = event.event_id;
+		if let Some(reason) = event.rejection {
+			report.skip(format!("event {event_id} in {room_id}: rejected by Synapse: {reason}"));
+			continue;
+		}
+
+		let Ok(mut json) = serde_json::from_str::<CanonicalJsonObject>(&event.json) else {
+			report.skip(format!("event {event_id} in {room_id}: invalid JSON"));
+			continue;
+		};
+
+		let Ok(event_id) = OwnedEventId::try_from(event_id.as_str()) else {
+			report.skip(format!("event {event_id:?} in {room_id}: invalid event ID"));
+			continue;
+		};
+
+		json.insert("event_id".into(), CanonicalJsonValue::String(event_id.to_string()));
+		services.rooms.outlier.add_pdu_outlier(&event_id, &json);
+		report.count("events");
+
+		let metadata: InternalMetadata =
+			serde_json::from_str(&event.internal_metadata).unwrap_or_default();
+
+		if !event.outlier && !metadata.outlier && !metadata.soft_failed {
+			timeline.push((event_id, json));
+		}
+	}
+
+	let mut state: HashMap<ShortStateKey, OwnedEventId> = HashMap::new();
+	let mut seeded = false;
+	for (event_id, json) in timeline {
+		let pdu = match PduEvent::from_id_val(&event_id, json.clone()) {
+			| Ok(pdu) => pdu,
+			| Err(e) => {
+				report.skip(format!("event {event_id} in {room_id}: {e}"));
+				continue;
+			},
+		};
+
+		// Synapse may have purged the start of the history; the first event then
+		// starts from the state Synapse kept for it.
+		if !seeded {
+			seeded = true;
+			let mut seed = source.state_after(event_id.as_str())?.unwrap_or_default();
+			if let Some(state_key) = &pdu.state_key {
+				seed.remove(&(pdu.kind.to_string(), state_key.clone()));
+			}
+
+			state = compress_state(services, &seed).await;
+		}
+
+		let state_before: HashSet<_> = services
+			.rooms
+			.state_compressor
+			.compress_state_events(state.iter().map(|(ssk, eid)| (ssk, eid.borrow())))
+			.collect()
+			.await;
+
+		services
+			.rooms
+			.timeline
+			.append_imported_pdu(&pdu, json, Arc::new(state_before), &state_lock)
+			.await?;
+
+		if let Some(state_key) = &pdu.state_key {
+			let shortstatekey = services
+				.rooms
+				.short
+				.get_or_create_shortstatekey(&pdu.kind.to_string().into(), state_key)
+				.await;
+
+			state.insert(shortstatekey, event_id);
+			let state_after: HashSet<_> = services
+				.rooms
+				.state_compressor
+				.compress_state_events(state.iter().map(|(ssk, eid)| (ssk, eid.borrow())))
+				.collect()
+				.await;
+
+			let HashSetCompressStateEvent { shortstatehash, .. } = services
+				.rooms
+				.state_compressor
+				.save_state(room_id, Arc::new(state_after))
+				.await?;
+
+			services
+				.rooms
+				.state
+				.set_room_state(room_id, shortstatehash, &state_lock);
+		}
+	}
+
+	force_state(services, room_id, &source.current_state(room_id.as_str())?, &state_lock).await?;
+
+	let extremities = source
+		.forward_extremities(room_id.as_str())?
+		.into_iter()
+		.filter_map(|event_id| OwnedEventId::try_from(event_id).ok())
+		.collect();
+
+	services
+		.rooms
+		.state
+		.set_forward_extremities(room_id, extremities, &state_lock)
+		.await;
+
+	Ok(true)
+}
+
+/// Sets the state of the room to Synapse's.
+async fn force_state(
+	services: &Services,
+	room_id: &RoomId,
+	state: &State,
+	state_lock: &RoomMutexGuard,
+) -> Result {
+	let state = compress_state(services, state).await;
+	let compressed: HashSet<_> = services
+		.rooms
+		.state_compressor
+		.compress_state_events(state.iter().map(|(ssk, eid)| (ssk, eid.borrow())))
+		.collect()
+		.await;
+
+	let HashSetCompressStateEvent { shortstatehash, added, removed } = services
+		.rooms
+		.state_compressor
+		.save_state(room_id, Arc::new(compressed))
+		.await?;
+
+	services
+		.rooms
+		.state
+		.force_state(room_id, shortstatehash, added, removed, state_lock)
+		.await
+}
+
+/// Keys Synapse's state by short state key, leaving out events which were
+/// not imported.
+async fn compress_state(
+	services: &Services,
+	state: &State,
+) -> HashMap<ShortStateKey, OwnedEventId> {
+	let mut compressed = HashMap::with_capacity(state.len());
+	for ((kind, state_key), event_id) in state {
+		let Ok(event_id) = OwnedEventId::try_from(event_id.as_str()) else {
+			continue;
+		};
+
+		if services
+			.rooms
+			.outlier
+			.get_outlier_pdu_json(&event_id)
+			.await
+			.is_err()
+		{
+			continue;
+		}
+
+		let shortstatekey = services
+			.rooms
+			.short
+			.get_or_create_shortstatekey(&StateEventType::from(kind.as_str()), state_key)
+			.await;
+
+		compressed.insert(shortstatekey, event_id);
+	}
+
+	compressed
+}
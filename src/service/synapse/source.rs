@@ -0,0 +1,436 @@
+//! Reading the tables of a Synapse SQLite database
+
+use std::{collections::BTreeMap, path::Path};
+
+use conduwuit::{err, Error, Result};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Params, Row};
+
+/// Synapse database, opened read-only.
+pub(super) struct Source {
+	conn: Connection,
+}
+
+pub(super) struct User {
+	pub(super) name: String,
+	pub(super) password_hash: Option<String>,
+	pub(super) admin: bool,
+	pub(super) deactivated: bool,
+	pub(super) is_guest: bool,
+}
+
+/// Identity of a user at a single sign-on provider.
+pub(super) struct ExternalId {
+	pub(super) auth_provider: String,
+	pub(super) external_id: String,
+	pub(super) user_id: String,
+}
+
+pub(super) struct Profile {
+	/// Localpart of the user, or their full ID in newer schemas
+	pub(super) user_id: String,
+	pub(super) displayname: Option<String>,
+	pub(super) avatar_url: Option<String>,
+}
+
+pub(super) struct Device {
+	pub(super) user_id: String,
+	pub(super) device_id: String,
+	pub(super) display_name: Option<String>,
+	pub(super) ip: Option<String>,
+
+	/// Latest access token of the device, so its session stays valid
+	pub(super) token: Option<String>,
+}
+
+pub(super) struct DeviceKeys {
+	pub(super) user_id: String,
+	pub(super) device_id: String,
+	pub(super) key_json: String,
+}
+
+pub(super) struct CrossSigningKey {
+	pub(super) user_id: String,
+	pub(super) keytype: String,
+	pub(super) keydata: String,
+}
+
+pub(super) struct AccountData {
+	pub(super) user_id: String,
+	pub(super) room_id: Option<String>,
+	pub(super) kind: String,
+	pub(super) content: String,
+}
+
+pub(super) struct Backup {
+	pub(super) user_id: String,
+	pub(super) version: String,
+	pub(super) algorithm: String,
+	pub(super) auth_data: String,
+}
+
+pub(super) struct BackupKey {
+	pub(super) user_id: String,
+	pub(super) version: String,
+	pub(super) room_id: String,
+	pub(super) session_id: String,
+	pub(super) first_message_index: i64,
+	pub(super) forwarded_count: i64,
+	pub(super) is_verified: bool,
+	pub(super) session_data: String,
+}
+
+pub(super) struct Media {
+	pub(super) media_id: String,
+	pub(super) media_type: Option<String>,
+	pub(super) upload_name: Option<String>,
+	pub(super) user_id: Option<String>,
+	pub(super) quarantined: bool,
+}
+
+/// Push rule made by a user, or a change to the actions of a default rule.
+pub(super) struct PushRule {
+	pub(super) user_id: String,
+
+	/// `global/<kind>/<rule ID>`
+	pub(super) rule_id: String,
+	pub(super) conditions: String,
+	pub(super) actions: String,
+}
+
+/// Rule a user enabled or disabled.
+pub(super) struct PushRuleEnabled {
+	pub(super) user_id: String,
+	pub(super) rule_id: String,
+	pub(super) enabled: bool,
+}
+
+pub(super) struct Room {
+	pub(super) room_id: String,
+	pub(super) is_public: bool,
+}
+
+pub(super) struct Alias {
+	pub(super) alias: String,
+	pub(super) room_id: String,
+	pub(super) creator: Option<String>,
+}
+
+pub(super) struct Event {
+	pub(super) event_id: String,
+	pub(super) outlier: bool,
+	pub(super) json: String,
+	pub(super) internal_metadata: String,
+	pub(super) rejection: Option<String>,
+}
+
+/// State of a room by type and state key, as event IDs.
+pub(super) type State = BTreeMap<(String, String), String>;
+
+impl Source {
+	pub(super) fn open(path: &Path) -> Result<Self> {
+		let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+			.map_err(map_err)?;
+
+		Ok(Self { conn })
+	}
+
+	#[cfg(test)]
+	pub(super) fn from_connection(conn: Connection) -> Self { Self { conn } }
+
+	pub(super) fn users(&self) -> Result<Vec<User>> {
+		self.query(
+			"SELECT name, password_hash, admin, deactivated, is_guest FROM users ORDER BY \
+			 creation_ts, name",
+			[],
+			|row| {
+				Ok(User {
+					name: row.get(0)?,
+					password_hash: row.get(1)?,
+					admin: row.get(2)?,
+					deactivated: row.get(3)?,
+					is_guest: row.get(4)?,
+				})
+			},
+		)
+	}
+
+	pub(super) fn external_ids(&self) -> Result<Vec<ExternalId>> {
+		self.query(
+			"SELECT auth_provider, external_id, user_id FROM user_external_ids",
+			[],
+			|row| {
+				Ok(ExternalId {
+					auth_provider: row.get(0)?,
+					external_id: row.get(1)?,
+					user_id: row.get(2)?,
+				})
+			},
+		)
+	}
+
+	pub(super) fn profiles(&self) -> Result<Vec<Profile>> {
+		self.query("SELECT user_id, displayname, avatar_url FROM profiles", [], |row| {
+			Ok(Profile {
+				user_id: row.get(0)?,
+				displayname: row.get(1)?,
+				avatar_url: row.get(2)?,
+			})
+		})
+	}
+
+	pub(super) fn devices(&self) -> Result<Vec<Device>> {
+		self.query(
+			"SELECT d.user_id, d.device_id, d.display_name, d.ip, (SELECT t.token FROM \
+			 access_tokens t WHERE t.user_id = d.user_id AND t.device_id = d.device_id ORDER BY \
+			 t.id DESC LIMIT 1) FROM devices d WHERE NOT d.hidden",
+			[],
+			|row| {
+				Ok(Device {
+					user_id: row.get(0)?,
+					device_id: row.get(1)?,
+					display_name: row.get(2)?,
+					ip: row.get(3)?,
+					token: row.get(4)?,
+				})
+			},
+		)
+	}
+
+	pub(super) fn device_keys(&self) -> Result<Vec<DeviceKeys>> {
+		self.query("SELECT user_id, device_id, key_json FROM e2e_device_keys_json", [], |row| {
+			Ok(DeviceKeys {
+				user_id: row.get(0)?,
+				device_id: row.get(1)?,
+				key_json: row.get(2)?,
+			})
+		})
+	}
+
+	/// Latest cross-signing keys of each user.
+	pub(super) fn cross_signing_keys(&self) -> Result<Vec<CrossSigningKey>> {
+		self.query(
+			"SELECT k.user_id, k.keytype, k.keydata FROM e2e_cross_signing_keys k WHERE \
+			 k.stream_id = (SELECT MAX(l.stream_id) FROM e2e_cross_signing_keys l WHERE \
+			 l.user_id = k.user_id AND l.keytype = k.keytype)",
+			[],
+			|row| {
+				Ok(CrossSigningKey {
+					user_id: row.get(0)?,
+					keytype: row.get(1)?,
+					keydata: row.get(2)?,
+				})
+			},
+		)
+	}
+
+	/// Global and room account data, oldest first.
+	pub(super) fn account_data(&self) -> Result<Vec<AccountData>> {
+		self.query(
+			"SELECT user_id, NULL, account_data_type, content, stream_id FROM account_data \
+			 UNION ALL SELECT user_id, room_id, account_data_type, content, stream_id FROM \
+			 room_account_data ORDER BY 5",
+			[],
+			|row| {
+				Ok(AccountData {
+					user_id: row.get(0)?,
+					room_id: row.get(1)?,
+					kind: row.get(2)?,
+					content: row.get(3)?,
+				})
+			},
+		)
+	}
+
+	/// Key backups which were not deleted, oldest first.
+	pub(super) fn backups(&self) -> Result<Vec<Backup>> {
+		self.query(
+			"SELECT user_id, CAST(version AS TEXT), algorithm, auth_data FROM \
+			 e2e_room_keys_versions WHERE NOT deleted ORDER BY CAST(version AS INTEGER)",
+			[],
+			|row| {
+				Ok(Backup {
+					user_id: row.get(0)?,
+					version: row.get(1)?,
+					algorithm: row.get(2)?,
+					auth_data: row.get(3)?,
+				})
+			},
+		)
+	}
+
+	pub(super) fn backup_keys(&self) -> Result<Vec<BackupKey>> {
+		self.query(
+			"SELECT user_id, CAST(version AS TEXT), room_id, session_id, first_message_index, \
+			 forwarded_count, is_verified, session_data FROM e2e_room_keys",
+			[],
+			|row| {
+				Ok(BackupKey {
+					user_id: row.get(0)?,
+					version: row.get(1)?,
+					room_id: row.get(2)?,
+					session_id: row.get(3)?,
+					first_message_index: row.get(4)?,
+					forwarded_count: row.get(5)?,
+					is_verified: row.get(6)?,
+					session_data: row.get(7)?,
+				})
+			},
+		)
+	}
+
+	/// Media uploaded by local users, without the URL preview cache.
+	pub(super) fn media(&self) -> Result<Vec<Media>> {
+		self.query(
+			"SELECT media_id, media_type, upload_name, user_id, quarantined_by IS NOT NULL FROM \
+			 local_media_repository WHERE url_cache IS NULL",
+			[],
+			|row| {
+				Ok(Media {
+					media_id: row.get(0)?,
+					media_type: row.get(1)?,
+					upload_name: row.get(2)?,
+					user_id: row.get(3)?,
+					quarantined: row.get(4)?,
+				})
+			},
+		)
+	}
+
+	pub(super) fn rooms(&self) -> Result<Vec<Room>> {
+		self.query("SELECT room_id, is_public FROM rooms", [], |row| {
+			Ok(Room {
+				room_id: row.get(0)?,
+				is_public: row.get(1)?,
+			})
+		})
+	}
+
+	pub(super) fn aliases(&self) -> Result<Vec<Alias>> {
+		self.query("SELECT room_alias, room_id, creator FROM room_aliases", [], |row| {
+			Ok(Alias {
+				alias: row.get(0)?,
+				room_id: row.get(1)?,
+				creator: row.get(2)?,
+			})
+		})
+	}
+
+	/// Events of a room in an order consistent with the graph: by depth, then
+	/// by the order they were received in.
+	pub(super) fn events(&self, room_id: &str) -> Result<Vec<Event>> {
+		self.query(
+			"SELECT e.event_id, e.outlier, j.json, j.internal_metadata, r.reason FROM events e \
+			 JOIN event_json j ON j.event_id = e.event_id LEFT JOIN rejections r ON r.event_id \
+			 = e.event_id WHERE e.room_id = ?1 ORDER BY e.topological_ordering, \
+			 e.stream_ordering",
+			params![room_id],
+			|row| {
+				Ok(Event {
+					event_id: row.get(0)?,
+					outlier: row.get(1)?,
+					json: row.get(2)?,
+					internal_metadata: row.get(3)?,
+					rejection: row.get(4)?,
+				})
+			},
+		)
+	}
+
+	/// State of the room after an event, if Synapse computed it.
+	pub(super) fn state_after(&self, event_id: &str) -> Result<Option<State>> {
+		let group: Option<i64> = self
+			.conn
+			.query_row(
+				"SELECT state_group FROM event_to_state_groups WHERE event_id = ?1",
+				params![event_id],
+				|row| row.get(0),
+			)
+			.optional()
+			.map_err(map_err)?;
+
+		let Some(group) = group else {
+			return Ok(None);
+		};
+
+		// State groups are stored as deltas on their previous group. Rows are
+		// read from the oldest group so that newer ones replace them.
+		let rows = self.query(
+			"WITH RECURSIVE chain(state_group, depth) AS (SELECT ?1, 0 UNION ALL SELECT \
+			 e.prev_state_group, c.depth + 1 FROM state_group_edges e JOIN chain c ON \
+			 e.state_group = c.state_group) SELECT s.type, s.state_key, s.event_id FROM \
+			 state_groups_state s JOIN chain c ON s.state_group = c.state_group ORDER BY \
+			 c.depth DESC",
+			params![group],
+			state_row,
+		)?;
+
+		Ok(Some(rows.into_iter().collect()))
+	}
+
+	/// State of the room as Synapse resolved it last.
+	pub(super) fn current_state(&self, room_id: &str) -> Result<State> {
+		let rows = self.query(
+			"SELECT type, state_key, event_id FROM current_state_events WHERE room_id = ?1",
+			params![room_id],
+			state_row,
+		)?;
+
+		Ok(rows.into_iter().collect())
+	}
+
+	pub(super) fn forward_extremities(&self, room_id: &str) -> Result<Vec<String>> {
+		self.query(
+			"SELECT event_id FROM event_forward_extremities WHERE room_id = ?1",
+			params![room_id],
+			|row| row.get(0),
+		)
+	}
+
+	/// Push rules of every user, lowest priority first.
+	pub(super) fn push_rules(&self) -> Result<Vec<PushRule>> {
+		self.query(
+			"SELECT user_name, rule_id, conditions, actions FROM push_rules ORDER BY \
+			 priority_class, priority",
+			[],
+			|row| {
+				Ok(PushRule {
+					user_id: row.get(0)?,
+					rule_id: row.get(1)?,
+					conditions: row.get(2)?,
+					actions: row.get(3)?,
+				})
+			},
+		)
+	}
+
+	pub(super) fn push_rules_enabled(&self) -> Result<Vec<PushRuleEnabled>> {
+		self.query("SELECT user_name, rule_id, enabled FROM push_rules_enable", [], |row| {
+			Ok(PushRuleEnabled {
+				user_id: row.get(0)?,
+				rule_id: row.get(1)?,
+				enabled: row.get(2)?,
+			})
+		})
+	}
+
+	fn query<T, P, F>(&self, sql: &str, params: P, f: F) -> Result<Vec<T>>
+	where
+		P: Params,
+		F: FnMut(&Row<'_>) -> rusqlite::Result<T>,
+	{
+		let mut statement = self.conn.prepare(sql).map_err(map_err)?;
+		let rows = statement
+			.query_map(params, f)
+			.map_err(map_err)?
+			.collect::<rusqlite::Result<_>>()
+			.map_err(map_err)?;
+
+		Ok(rows)
+	}
+}
+
+fn state_row(row: &Row<'_>) -> rusqlite::Result<((String, String), String)> {
+	Ok(((row.get(0)?, row.get(1)?), row.get(2)?))
+}
+
+fn map_err(e: rusqlite::Error) -> Error { err!(Database("Synapse database: {e}")) }
@@ -0,0 +1,268 @@
+#![cfg(test)]
+
+use ruma::{
+	events::{push_rules::PushRulesEvent, GlobalAccountDataEventType},
+	push::RuleKind,
+	room_id, user_id, EventId,
+};
+use rusqlite::Connection;
+
+use super::{source::Source, Options};
+use crate::tests::{offline_services, TempDir};
+
+/// The tables the importer reads, with only the columns it reads, holding a
+/// small server.
+const FIXTURE: &str = r#"
+CREATE TABLE users (name TEXT, password_hash TEXT, creation_ts BIGINT, admin SMALLINT,
+	is_guest SMALLINT, deactivated SMALLINT);
+CREATE TABLE user_external_ids (auth_provider TEXT, external_id TEXT, user_id TEXT);
+CREATE TABLE profiles (user_id TEXT, displayname TEXT, avatar_url TEXT);
+CREATE TABLE devices (user_id TEXT, device_id TEXT, display_name TEXT, ip TEXT,
+	hidden BOOLEAN);
+CREATE TABLE access_tokens (id BIGINT, user_id TEXT, device_id TEXT, token TEXT);
+CREATE TABLE e2e_device_keys_json (user_id TEXT, device_id TEXT, key_json TEXT);
+CREATE TABLE e2e_cross_signing_keys (user_id TEXT, keytype TEXT, keydata TEXT,
+	stream_id BIGINT);
+CREATE TABLE account_data (user_id TEXT, account_data_type TEXT, content TEXT,
+	stream_id BIGINT);
+CREATE TABLE room_account_data (user_id TEXT, room_id TEXT, account_data_type TEXT,
+	content TEXT, stream_id BIGINT);
+CREATE TABLE e2e_room_keys_versions (user_id TEXT, version BIGINT, algorithm TEXT,
+	auth_data TEXT, deleted SMALLINT);
+CREATE TABLE e2e_room_keys (user_id TEXT, version BIGINT, room_id TEXT, session_id TEXT,
+	first_message_index INT, forwarded_count INT, is_verified BOOLEAN, session_data TEXT);
+CREATE TABLE rooms (room_id TEXT, is_public BOOLEAN);
+CREATE TABLE room_aliases (room_alias TEXT, room_id TEXT, creator TEXT);
+CREATE TABLE events (event_id TEXT, room_id TEXT, topological_ordering BIGINT,
+	stream_ordering INTEGER, outlier BOOLEAN);
+CREATE TABLE event_json (event_id TEXT, room_id TEXT, internal_metadata TEXT, json TEXT);
+CREATE TABLE rejections (event_id TEXT, reason TEXT);
+CREATE TABLE event_to_state_groups (event_id TEXT, state_group BIGINT);
+CREATE TABLE state_group_edges (state_group BIGINT, prev_state_group BIGINT);
+CREATE TABLE state_groups_state (state_group BIGINT, room_id TEXT, type TEXT,
+	state_key TEXT, event_id TEXT);
+CREATE TABLE current_state_events (room_id TEXT, type TEXT, state_key TEXT, event_id TEXT);
+CREATE TABLE event_forward_extremities (event_id TEXT, room_id TEXT);
+CREATE TABLE push_rules (user_name TEXT, rule_id TEXT, priority_class SMALLINT,
+	priority INTEGER, conditions TEXT, actions TEXT);
+CREATE TABLE push_rules_enable (user_name TEXT, rule_id TEXT, enabled SMALLINT);
+
+INSERT INTO users VALUES
+	('@bob:example.com', NULL, 2, 0, 0, 0),
+	('@alice:example.com', '$2b$12$hash', 1, 1, 0, 0),
+	('@gone:example.com', '', 3, 0, 0, 1);
+INSERT INTO profiles VALUES ('alice', 'Alice', 'mxc://example.com/avatar');
+INSERT INTO devices VALUES
+	('@alice:example.com', 'PHONE', 'Phone', '10.0.0.1', 0),
+	('@alice:example.com', 'HIDDEN', NULL, NULL, 1);
+INSERT INTO access_tokens VALUES
+	(1, '@alice:example.com', 'PHONE', 'old'),
+	(2, '@alice:example.com', 'PHONE', 'new');
+INSERT INTO e2e_room_keys_versions VALUES
+	('@alice:example.com', 10, 'm.megolm_backup.v1', '{}', 0),
+	('@alice:example.com', 9, 'm.megolm_backup.v1', '{}', 0),
+	('@alice:example.com', 2, 'm.megolm_backup.v1', '{}', 1);
+INSERT INTO rooms VALUES ('!room:example.com', 1);
+INSERT INTO events VALUES
+	('$create', '!room:example.com', 1, 1, 0),
+	('$join', '!room:example.com', 2, 2, 0),
+	('$bad', '!room:example.com', 3, 4, 0),
+	('$message', '!room:example.com', 3, 3, 0),
+	('$bob', '!room:example.com', 3, 5, 0),
+	('$hello', '!room:example.com', 4, 6, 0);
+INSERT INTO event_json VALUES
+	('$create', '!room:example.com', '{}', '{"type":"m.room.create","state_key":"",
+		"content":{"creator":"@alice:example.com","room_version":"10"},"prev_events":[],
+		"auth_events":[],"depth":1,"room_id":"!room:example.com",
+		"sender":"@alice:example.com","origin_server_ts":1,"hashes":{"sha256":""}}'),
+	('$join', '!room:example.com', '{}', '{"type":"m.room.member",
+		"state_key":"@alice:example.com","content":{"membership":"join"},
+		"prev_events":["$create"],"auth_events":["$create"],"depth":2,
+		"room_id":"!room:example.com","sender":"@alice:example.com","origin_server_ts":2,
+		"hashes":{"sha256":""}}'),
+	('$bad', '!room:example.com', '{}', '{"type":"m.room.message"}'),
+	('$message', '!room:example.com', '{"soft_failed":true}', '{"type":"m.room.message"}'),
+	('$bob', '!room:example.com', '{}', '{"type":"m.room.member",
+		"state_key":"@bob:example.com","content":{"membership":"join"},
+		"prev_events":["$join"],"auth_events":["$create"],"depth":3,
+		"room_id":"!room:example.com","sender":"@bob:example.com","origin_server_ts":3,
+		"hashes":{"sha256":""}}'),
+	('$hello', '!room:example.com', '{}', '{"type":"m.room.message",
+		"content":{"msgtype":"m.text","body":"hello bob"},"prev_events":["$bob"],
+		"auth_events":["$create","$join"],"depth":4,"room_id":"!room:example.com",
+		"sender":"@alice:example.com","origin_server_ts":4,"hashes":{"sha256":""}}');
+INSERT INTO rejections VALUES ('$bad', 'auth_error');
+INSERT INTO event_to_state_groups VALUES ('$create', 1), ('$join', 2);
+INSERT INTO state_group_edges VALUES (2, 1);
+INSERT INTO state_groups_state VALUES
+	(1, '!room:example.com', 'm.room.create', '', '$create'),
+	(1, '!room:example.com', 'm.room.member', '@alice:example.com', '$old'),
+	(2, '!room:example.com', 'm.room.member', '@alice:example.com', '$join');
+INSERT INTO current_state_events VALUES
+	('!room:example.com', 'm.room.create', '', '$create'),
+	('!room:example.com', 'm.room.member', '@alice:example.com', '$join'),
+	('!room:example.com', 'm.room.member', '@bob:example.com', '$bob');
+INSERT INTO event_forward_extremities VALUES ('$hello', '!room:example.com');
+INSERT INTO push_rules VALUES
+	('@alice:example.com', 'global/content/dogs', 4, 0,
+		'[{"kind":"event_match","key":"content.body","pattern":"dogs"}]', '["notify"]'),
+	('@alice:example.com', 'global/content/cats', 4, 1,
+		'[{"kind":"event_match","key":"content.body","pattern":"cats"}]', '["notify"]'),
+	('@alice:example.com', 'global/underride/.m.rule.message', -1, 1, '[]', '[]');
+INSERT INTO push_rules_enable VALUES
+	('@alice:example.com', 'global/content/dogs', 0),
+	('@bob:example.com', 'global/override/.m.rule.master', 1);
+"#;
+
+fn fixture() -> Source {
+	let conn = Connection::open_in_memory().expect("in-memory database");
+	conn.execute_batch(FIXTURE).expect("fixture schema");
+
+	Source::from_connection(conn)
+}
+
+#[test]
+fn users() {
+	let source = fixture();
+	let users = source.users().expect("users");
+	let names: Vec<_> = users.iter().map(|user| user.name.as_str()).collect();
+	assert_eq!(names, ["@alice:example.com", "@bob:example.com", "@gone:example.com"]);
+
+	assert!(users[0].admin);
+	assert_eq!(users[0].password_hash.as_deref(), Some("$2b$12$hash"));
+	assert!(users[1].password_hash.is_none());
+	assert!(users[2].deactivated);
+
+	let profiles = source.profiles().expect("profiles");
+	assert_eq!(profiles[0].user_id, "alice", "older schemas key profiles by localpart");
+}
+
+#[test]
+fn push_rules() {
+	let rules = fixture().push_rules().expect("push rules");
+	let ids: Vec<_> = rules.iter().map(|rule| rule.rule_id.as_str()).collect();
+	assert_eq!(
+		ids,
+		["global/underride/.m.rule.message", "global/content/dogs", "global/content/cats"],
+		"lowest priority first"
+	);
+}
+
+#[test]
+fn devices() {
+	let devices = fixture().devices().expect("devices");
+	assert_eq!(devices.len(), 1, "hidden devices are left out");
+	assert_eq!(devices[0].device_id, "PHONE");
+	assert_eq!(devices[0].token.as_deref(), Some("new"), "the latest token is kept");
+}
+
+#[test]
+fn backups() {
+	let backups = fixture().backups().expect("backups");
+	let versions: Vec<_> = backups
+		.iter()
+		.map(|backup| backup.version.as_str())
+		.collect();
+	assert_eq!(versions, ["9", "10"], "deleted backups are left out, numeric order");
+}
+
+#[test]
+fn events() {
+	let events = fixture().events("!room:example.com").expect("events");
+	let ids: Vec<_> = events.iter().map(|event| event.event_id.as_str()).collect();
+	assert_eq!(ids, ["$create", "$join", "$message", "$bad", "$bob", "$hello"]);
+	assert_eq!(events[3].rejection.as_deref(), Some("auth_error"));
+	assert!(events[2].internal_metadata.contains("soft_failed"));
+}
+
+#[test]
+fn state_groups() {
+	let source = fixture();
+	let state = source
+		.state_after("$join")
+		.expect("state")
+		.expect("state group");
+
+	assert_eq!(state.len(), 2);
+	assert_eq!(
+		state[&("m.room.member".to_owned(), "@alice:example.com".to_owned())],
+		"$join",
+		"deltas replace the state of their previous group"
+	);
+
+	assert!(source.state_after("$message").expect("state").is_none());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn import() {
+	let dir = TempDir::new("synapse-import");
+	let database = dir.path().join("homeserver.db");
+	Connection::open(&database)
+		.expect("created database")
+		.execute_batch(FIXTURE)
+		.expect("fixture schema");
+
+	let services = offline_services(&dir, "example.com")
+		.await
+		.expect("started services");
+
+	let options = Options {
+		database: &database,
+		media_store: None,
+		signing_key: None,
+	};
+
+	super::import(&services, &options).await.expect("imported");
+
+	let (alice, bob, gone) = (
+		user_id!("@alice:example.com"),
+		user_id!("@bob:example.com"),
+		user_id!("@gone:example.com"),
+	);
+
+	assert_eq!(services.users.password_hash(alice).await.expect("hash"), "$2b$12$hash");
+	assert!(services.users.is_active(bob).await, "users without a password stay active");
+	assert!(!services.users.is_active(gone).await);
+
+	let rules: PushRulesEvent = services
+		.account_data
+		.get_global(alice, GlobalAccountDataEventType::PushRules)
+		.await
+		.expect("push rules of alice");
+
+	let content: Vec<_> = rules
+		.content
+		.global
+		.content
+		.iter()
+		.filter(|rule| !rule.default)
+		.map(|rule| (rule.rule_id.as_str(), rule.enabled))
+		.collect();
+	assert_eq!(content, [("cats", true), ("dogs", false)], "highest priority first");
+
+	let message = rules
+		.content
+		.global
+		.get(RuleKind::Underride, ".m.rule.message")
+		.expect("default rule");
+	assert!(message.actions().is_empty(), "changed actions of default rules are kept");
+
+	let room_id = room_id!("!room:example.com");
+	assert!(services.rooms.state_cache.is_joined(bob, room_id).await);
+
+	let hello = <&EventId>::try_from("$hello").expect("event ID");
+	let message = <&EventId>::try_from("$message").expect("event ID");
+	assert!(services.rooms.timeline.get_pdu_id(hello).await.is_ok());
+	assert!(
+		services.rooms.timeline.get_pdu_id(message).await.is_err(),
+		"soft-failed events stay out of the timeline"
+	);
+
+	assert_eq!(
+		services.rooms.user.notification_count(bob, room_id).await,
+		0,
+		"imported history does not notify"
+	);
+
+	services.stop_offline();
+}
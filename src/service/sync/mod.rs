@@ -13,7 +13,8 @@ use ruma::{
 		v4::{ExtensionsConfig, SyncRequestList},
 		v5,
 	},
-	DeviceId, OwnedDeviceId, OwnedRoomId, OwnedUserId, UserId,
+	events::TimelineEventType,
+	DeviceId, OwnedDeviceId, OwnedRoomId, OwnedUserId, RoomId, UInt, UserId,
 };
 
 use crate::{rooms, Dep};
@@ -60,6 +61,12 @@ struct SnakeSyncCache {
 	subscriptions: BTreeMap<OwnedRoomId, v5::request::RoomSubscription>,
 	known_rooms: BTreeMap<String, BTreeMap<OwnedRoomId, u64>>,
 	extensions: v5::request::Extensions,
+	/// For every room, its bump stamp and the timeline count it was found at
+	bump_stamps: BTreeMap<OwnedRoomId, (u64, Option<UInt>)>,
+	/// Event types the bump stamps were found for
+	bump_types: Vec<TimelineEventType>,
+	/// For every room, the members whose membership was sent for `$LAZY`
+	lazy_members: BTreeMap<OwnedRoomId, BTreeSet<OwnedUserId>>,
 }
 
 type DbConnections<K, V> = Mutex<BTreeMap<K, V>>;
@@ -486,4 +493,73 @@ impl Service {
 
 		cached.subscriptions = subscriptions;
 	}
+
+	/// Bump stamps a connection was sent, with the timeline count of the room
+	/// each was found at, and the event types they were found for.
+	pub fn snake_bump_stamps(
+		&self,
+		user_id: &UserId,
+		device_id: &DeviceId,
+		conn_id: Option<String>,
+	) -> (Vec<TimelineEventType>, BTreeMap<OwnedRoomId, (u64, Option<UInt>)>) {
+		let cached = self.snake_connection(user_id, device_id, conn_id);
+		let cached = cached.lock().expect("locked");
+
+		(cached.bump_types.clone(), cached.bump_stamps.clone())
+	}
+
+	pub fn update_snake_bump_stamps(
+		&self,
+		user_id: &UserId,
+		device_id: &DeviceId,
+		conn_id: Option<String>,
+		bump_types: Vec<TimelineEventType>,
+		bump_stamps: BTreeMap<OwnedRoomId, (u64, Option<UInt>)>,
+	) {
+		let cached = self.snake_connection(user_id, device_id, conn_id);
+		let cached = &mut cached.lock().expect("locked");
+
+		cached.bump_types = bump_types;
+		cached.bump_stamps = bump_stamps;
+	}
+
+	/// Remembers the members whose membership a connection is sent in a room
+	/// for `$LAZY`, returning those it was not sent before. The room starts
+	/// over when `initial`.
+	pub fn update_snake_lazy_members(
+		&self,
+		user_id: &UserId,
+		device_id: &DeviceId,
+		conn_id: Option<String>,
+		room_id: &RoomId,
+		initial: bool,
+		members: BTreeSet<OwnedUserId>,
+	) -> BTreeSet<OwnedUserId> {
+		let cached = self.snake_connection(user_id, device_id, conn_id);
+		let cached = &mut cached.lock().expect("locked");
+
+		let sent = cached.lazy_members.entry(room_id.to_owned()).or_default();
+		if initial {
+			sent.clear();
+		}
+
+		members
+			.into_iter()
+			.filter(|member| sent.insert(member.clone()))
+			.collect()
+	}
+
+	fn snake_connection(
+		&self,
+		user_id: &UserId,
+		device_id: &DeviceId,
+		conn_id: Option<String>,
+	) -> SnakeConnectionsVal {
+		let mut cache = self.snake_connections.lock().expect("locked");
+		Arc::clone(
+			cache
+				.entry((user_id.to_owned(), device_id.to_owned(), conn_id))
+				.or_insert_with(|| Arc::new(Mutex::new(SnakeSyncCache::default()))),
+		)
+	}
 }
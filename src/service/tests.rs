@@ -0,0 +1,198 @@
+#![cfg(test)]
+
+use std::{
+	collections::BTreeMap,
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+
+use conduwuit::{
+	config::Config,
+	log::{capture, Log, LogLevelReloadHandles},
+	pdu::PduBuilder,
+	Result, Server,
+};
+use ruma::{
+	events::room::{
+		create::RoomCreateEventContent,
+		member::{MembershipState, RoomMemberEventContent},
+		power_levels::RoomPowerLevelsEventContent,
+	},
+	MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId, RoomId, RoomVersionId,
+};
+use tokio::{
+	io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+	net::TcpListener,
+	runtime::Handle,
+};
+
+use crate::{migrations::DATABASE_VERSION, Services};
+
+/// Directory removed with everything in it once dropped.
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+	pub(crate) fn new(name: &str) -> Self {
+		let path =
+			std::env::temp_dir().join(format!("conduwuit-{name}-test-{}", std::process::id()));
+
+		std::fs::create_dir_all(&path).expect("created temporary directory");
+		Self(path)
+	}
+
+	pub(crate) fn path(&self) -> &Path { &self.0 }
+}
+
+impl Drop for TempDir {
+	fn drop(&mut self) { std::fs::remove_dir_all(&self.0).ok(); }
+}
+
+/// Services of `server_name` over a new database in `dir`, started the way
+/// the maintenance commands start them: without the router or any worker.
+pub(crate) async fn offline_services(dir: &TempDir, server_name: &str) -> Result<Arc<Services>> {
+	offline_services_with(dir, server_name, "").await
+}
+
+/// Like `offline_services`, with more of the `[global]` config as TOML.
+pub(crate) async fn offline_services_with(
+	dir: &TempDir,
+	server_name: &str,
+	config: &str,
+) -> Result<Arc<Services>> {
+	let config_path = dir.path().join("conduwuit.toml");
+	let database_path = dir.path().join("database");
+	std::fs::write(
+		&config_path,
+		format!(
+			"[global]\nserver_name = {server_name:?}\ndatabase_path = {:?}\n{config}\n",
+			database_path.display().to_string()
+		),
+	)?;
+
+	let config =
+		Config::load([config_path.as_path()].into_iter()).and_then(|raw| Config::new(&raw))?;
+
+	let log = Log {
+		reload: LogLevelReloadHandles::default(),
+		capture: Arc::new(capture::State::new()),
+	};
+
+	let server = Arc::new(Server::new(config, Some(Handle::current()), log));
+
+	Services::build(server).await?.start_offline().await
+}
+
+/// Creates a room joined by the server user only, with its first events sent
+/// at `timestamp` if given.
+pub(crate) async fn create_room(
+	services: &Services,
+	timestamp: Option<MilliSecondsSinceUnixEpoch>,
+) -> OwnedRoomId {
+	let sender = &services.globals.server_user;
+	let room_id = RoomId::new(services.globals.server_name());
+	services
+		.rooms
+		.short
+		.get_or_create_shortroomid(&room_id)
+		.await;
+
+	let events = [
+		PduBuilder::state(String::new(), &RoomCreateEventContent {
+			room_version: RoomVersionId::V11,
+			..RoomCreateEventContent::new_v11()
+		}),
+		PduBuilder::state(
+			sender.to_string(),
+			&RoomMemberEventContent::new(MembershipState::Join),
+		),
+		PduBuilder::state(String::new(), &RoomPowerLevelsEventContent {
+			users: BTreeMap::from_iter([(sender.clone(), 100.into())]),
+			..Default::default()
+		}),
+	];
+
+	for event in events {
+		send(services, &room_id, PduBuilder { timestamp, ..event }).await;
+	}
+
+	room_id
+}
+
+/// Sends an event to a room as the server user.
+pub(crate) async fn send(
+	services: &Services,
+	room_id: &RoomId,
+	event: PduBuilder,
+) -> OwnedEventId {
+	let state_lock = services.rooms.state.mutex.lock(room_id).await;
+	services
+		.rooms
+		.timeline
+		.build_and_append_pdu(event, &services.globals.server_user, room_id, &state_lock)
+		.await
+		.expect("event sent")
+}
+
+/// Accepts one SMTP session and returns the message data it received.
+pub(crate) async fn smtp_sink(listener: TcpListener) -> String {
+	let (stream, _) = listener.accept().await.expect("accepted connection");
+	let (read, mut write) = stream.into_split();
+	let mut lines = BufReader::new(read).lines();
+	write
+		.write_all(b"220 localhost ESMTP sink\r\n")
+		.await
+		.expect("wrote greeting");
+
+	let (mut data, mut in_data) = (String::new(), false);
+	while let Some(line) = lines.next_line().await.expect("read line") {
+		if in_data {
+			if line == "." {
+				in_data = false;
+				write.write_all(b"250 OK\r\n").await.expect("wrote reply");
+			} else {
+				data.push_str(&line);
+				data.push('\n');
+			}
+
+			continue;
+		}
+
+		let command = line.to_ascii_uppercase();
+		let response: &[u8] = if command.starts_with("EHLO") {
+			b"250-localhost\r\n250 OK\r\n"
+		} else if command == "DATA" {
+			in_data = true;
+			b"354 End data with <CR><LF>.<CR><LF>\r\n"
+		} else if command == "QUIT" {
+			write.write_all(b"221 Bye\r\n").await.expect("wrote reply");
+			break;
+		} else {
+			b"250 OK\r\n"
+		};
+
+		write.write_all(response).await.expect("wrote reply");
+	}
+
+	data
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn offline_start_and_stop() {
+	let dir = TempDir::new("offline");
+	let services = offline_services(&dir, "localhost")
+		.await
+		.expect("started services offline");
+
+	assert_eq!(
+		services.globals.db.database_version().await,
+		DATABASE_VERSION,
+		"a new database is initialized at the current version"
+	);
+	assert!(
+		services.users.exists(&services.globals.server_user).await,
+		"the server user is created on first start"
+	);
+	assert!(services.admin.get_admin_room().await.is_ok(), "the admin room is created");
+
+	services.stop_offline();
+}
@@ -1,33 +1,48 @@
+mod tests;
+
 use std::{
 	collections::BTreeMap,
 	sync::{Arc, RwLock},
+	time::{Duration, Instant},
 };
 
+use async_trait::async_trait;
 use conduwuit::{
-	err, error, implement, utils,
-	utils::{hash, string::EMPTY},
-	Error, Result,
+	err, error, implement, result::LogErr, utils, utils::string::EMPTY, Error, Result,
 };
 use database::{Deserialized, Json, Map};
 use ruma::{
 	api::client::{
 		error::ErrorKind,
-		uiaa::{AuthData, AuthType, Password, UiaaInfo, UserIdentifier},
+		uiaa::{
+			AuthData, AuthType, EmailIdentity, Password, ThirdpartyIdCredentials, UiaaInfo,
+			UserIdentifier,
+		},
 	},
-	CanonicalJsonValue, DeviceId, OwnedDeviceId, OwnedUserId, UserId,
+	CanonicalJsonValue, DeviceId, OwnedClientSecret, OwnedDeviceId, OwnedSessionId, OwnedUserId,
+	UserId,
+};
+use tokio::{
+	sync::Notify,
+	time::{interval, MissedTickBehavior},
 };
 
-use crate::{globals, users, Dep};
+use crate::{email, globals, password, registration_tokens, Dep};
 
 pub struct Service {
 	userdevicesessionid_uiaarequest: RwLock<RequestMap>,
+	sessionid_threepidcreds: RwLock<ThreepidCredsMap>,
+	sessionid_registrationtoken: RwLock<RegistrationTokenMap>,
+	interrupt: Notify,
 	db: Data,
 	services: Services,
 }
 
 struct Services {
+	email: Dep<email::Service>,
 	globals: Dep<globals::Service>,
-	users: Dep<users::Service>,
+	password: Dep<password::Service>,
+	registration_tokens: Dep<registration_tokens::Service>,
 }
 
 struct Data {
@@ -36,23 +51,59 @@ struct Data {
 
 type RequestMap = BTreeMap<RequestKey, CanonicalJsonValue>;
 type RequestKey = (OwnedUserId, OwnedDeviceId, String);
+type ThreepidCredsMap = BTreeMap<String, (OwnedSessionId, OwnedClientSecret)>;
+type RegistrationTokenMap = BTreeMap<String, (String, Instant)>;
 
 pub const SESSION_ID_LENGTH: usize = 32;
 
+/// How long a session which passed the registration token stage holds a use
+/// of the token for its registration to complete.
+const REGISTRATION_TOKEN_HOLD: Duration = Duration::from_secs(60 * 60);
+
+/// How often expired holds on registration tokens are given back.
+const REGISTRATION_TOKEN_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+#[async_trait]
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			userdevicesessionid_uiaarequest: RwLock::new(RequestMap::new()),
+			sessionid_threepidcreds: RwLock::new(ThreepidCredsMap::new()),
+			sessionid_registrationtoken: RwLock::new(RegistrationTokenMap::new()),
+			interrupt: Notify::new(),
 			db: Data {
 				userdevicesessionid_uiaainfo: args.db["userdevicesessionid_uiaainfo"].clone(),
 			},
 			services: Services {
+				email: args.depend::<email::Service>("email"),
 				globals: args.depend::<globals::Service>("globals"),
-				users: args.depend::<users::Service>("users"),
+				password: args.depend::<password::Service>("password"),
+				registration_tokens: args
+					.depend::<registration_tokens::Service>("registration_tokens"),
 			},
 		}))
 	}
 
+	async fn worker(self: Arc<Self>) -> Result<()> {
+		// No session survives a restart, nor do the uses of tokens they held.
+		self.services.registration_tokens.clear_pending().await;
+
+		let mut i = interval(REGISTRATION_TOKEN_CHECK_INTERVAL);
+		i.set_missed_tick_behavior(MissedTickBehavior::Delay);
+		loop {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = i.tick() => (),
+			}
+
+			self.expire_registration_tokens();
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -137,36 +188,60 @@ pub async fn try_auth(
 			.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "User ID is invalid."))?;
 
 			// Check if password is correct
-			if let Ok(hash) = self.services.users.password_hash(&user_id).await {
-				let hash_matches = hash::verify_password(password, &hash).is_ok();
-				if !hash_matches {
-					uiaainfo.auth_error = Some(ruma::api::client::error::StandardErrorBody {
-						kind: ErrorKind::forbidden(),
-						message: "Invalid username or password.".to_owned(),
-					});
-					return Ok((false, uiaainfo));
-				}
+			if let Err(e) = self
+				.services
+				.password
+				.authenticate(&user_id, password)
+				.await
+			{
+				uiaainfo.auth_error = Some(ruma::api::client::error::StandardErrorBody {
+					kind: e.kind(),
+					message: e.sanitized_message(),
+				});
+				return Ok((false, uiaainfo));
 			}
 
 			// Password was correct! Let's add it to `completed`
 			uiaainfo.completed.push(AuthType::Password);
 		},
 		| AuthData::RegistrationToken(t) => {
-			if self
+			let session = uiaainfo.session.as_deref().expect("session is always set");
+			if let Err(e) = self.hold_registration_token(session, t.token.trim()) {
+				uiaainfo.auth_error = Some(ruma::api::client::error::StandardErrorBody {
+					kind: e.kind(),
+					message: e.sanitized_message(),
+				});
+				return Ok((false, uiaainfo));
+			}
+
+			uiaainfo.completed.push(AuthType::RegistrationToken);
+		},
+		| AuthData::EmailIdentity(EmailIdentity {
+			thirdparty_id_creds: ThirdpartyIdCredentials { sid, client_secret, .. },
+			..
+		}) => {
+			if let Err(e) = self
 				.services
-				.globals
-				.registration_token
-				.as_ref()
-				.is_some_and(|reg_token| t.token.trim() == reg_token)
+				.email
+				.validated(sid, client_secret, None)
+				.await
 			{
-				uiaainfo.completed.push(AuthType::RegistrationToken);
-			} else {
 				uiaainfo.auth_error = Some(ruma::api::client::error::StandardErrorBody {
-					kind: ErrorKind::forbidden(),
-					message: "Invalid registration token.".to_owned(),
+					kind: e.kind(),
+					message: e.sanitized_message(),
 				});
 				return Ok((false, uiaainfo));
 			}
+
+			self.sessionid_threepidcreds
+				.write()
+				.expect("locked for writing")
+				.insert(
+					uiaainfo.session.clone().expect("session is always set"),
+					(sid.clone(), client_secret.clone()),
+				);
+
+			uiaainfo.completed.push(AuthType::EmailIdentity);
 		},
 		| AuthData::Dummy(_) => {
 			uiaainfo.completed.push(AuthType::Dummy);
@@ -174,6 +249,17 @@ pub async fn try_auth(
 		| k => error!("type not supported: {:?}", k),
 	}
 
+	// The stage is passed again once the hold on the token expired, or was lost
+	// to a restart.
+	let session = uiaainfo.session.as_deref().expect("session is always set");
+	if uiaainfo.completed.contains(&AuthType::RegistrationToken)
+		&& !self.holds_registration_token(session)
+	{
+		uiaainfo
+			.completed
+			.retain(|stage| *stage != AuthType::RegistrationToken);
+	}
+
 	// Check if a flow now succeeds
 	let mut completed = false;
 	'flows: for flow in &mut uiaainfo.flows {
@@ -208,6 +294,85 @@ pub async fn try_auth(
 	Ok((true, uiaainfo))
 }
 
+/// Takes the credentials of the email address validated in a session, to
+/// find the address with [`email::Service::validated`].
+#[implement(Service)]
+pub fn take_threepid_creds(&self, session: &str) -> Option<(OwnedSessionId, OwnedClientSecret)> {
+	self.sessionid_threepidcreds
+		.write()
+		.expect("locked for writing")
+		.remove(session)
+}
+
+/// Takes the registration token given in a session, whose held use is then
+/// completed or released with [`registration_tokens::Service`].
+#[implement(Service)]
+pub fn take_registration_token(&self, session: &str) -> Option<String> {
+	self.sessionid_registrationtoken
+		.write()
+		.expect("locked for writing")
+		.remove(session)
+		.map(|(token, _)| token)
+}
+
+/// Holds a use of the token for the registration of a session, giving back
+/// the use held for a token given before.
+#[implement(Service)]
+fn hold_registration_token(&self, session: &str, token: &str) -> Result {
+	let mut holds = self
+		.sessionid_registrationtoken
+		.write()
+		.expect("locked for writing");
+
+	if let Some((previous, _)) = holds.remove(session) {
+		self.services
+			.registration_tokens
+			.release(&previous)
+			.log_err()
+			.ok();
+	}
+
+	self.services.registration_tokens.reserve(token)?;
+	holds.insert(session.to_owned(), (token.to_owned(), Instant::now()));
+
+	Ok(())
+}
+
+#[implement(Service)]
+fn holds_registration_token(&self, session: &str) -> bool {
+	self.sessionid_registrationtoken
+		.read()
+		.expect("locked for reading")
+		.get(session)
+		.is_some_and(|(_, held_since)| held_since.elapsed() < REGISTRATION_TOKEN_HOLD)
+}
+
+/// Gives back the uses of tokens held by sessions for longer than
+/// [`REGISTRATION_TOKEN_HOLD`].
+#[implement(Service)]
+fn expire_registration_tokens(&self) {
+	let mut expired = Vec::new();
+	self.sessionid_registrationtoken
+		.write()
+		.expect("locked for writing")
+		.retain(|_, (token, held_since)| {
+			let held = held_since.elapsed() < REGISTRATION_TOKEN_HOLD;
+			if !held {
+				expired.push(token.clone());
+			}
+
+			held
+		});
+
+	for token in expired {
+		self.services
+			.registration_tokens
+			.release(&token)
+			.log_err()
+			.ok();
+	}
+}
+
 #[implement(Service)]
 fn set_uiaa_request(
 	&self,
@@ -0,0 +1,69 @@
+#![cfg(test)]
+
+use ruma::{
+	api::client::uiaa::{AuthData, AuthFlow, AuthType, RegistrationToken, UiaaInfo},
+	user_id,
+};
+
+use crate::{
+	registration_tokens::TokenInfo,
+	tests::{offline_services, TempDir},
+};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn registration_token_stage_holds_a_use() {
+	let dir = TempDir::new("uiaa");
+	let services = offline_services(&dir, "example.com")
+		.await
+		.expect("started services offline");
+
+	let info = TokenInfo {
+		uses_allowed: Some(1),
+		..TokenInfo::default()
+	};
+	services
+		.registration_tokens
+		.create("token", &info)
+		.expect("created");
+
+	let uiaainfo = UiaaInfo {
+		flows: vec![AuthFlow {
+			stages: vec![AuthType::RegistrationToken],
+		}],
+		completed: Vec::new(),
+		params: Box::default(),
+		session: None,
+		auth_error: None,
+	};
+	let auth = AuthData::RegistrationToken(RegistrationToken::new("token".to_owned()));
+	let user_id = user_id!("@:example.com");
+
+	let (worked, first) = services
+		.uiaa
+		.try_auth(user_id, "".into(), &auth, &uiaainfo)
+		.await
+		.expect("authenticated");
+	assert!(worked);
+
+	let (worked, second) = services
+		.uiaa
+		.try_auth(user_id, "".into(), &auth, &uiaainfo)
+		.await
+		.expect("authenticated");
+	assert!(!worked, "the only use is held by the first session");
+	assert!(second.auth_error.is_some());
+
+	let session = first.session.as_deref().expect("session set");
+	assert_eq!(services.uiaa.take_registration_token(session).as_deref(), Some("token"));
+	assert_eq!(
+		services
+			.registration_tokens
+			.get("token")
+			.await
+			.expect("token exists")
+			.pending,
+		1
+	);
+
+	services.stop_offline();
+}
@@ -0,0 +1,365 @@
+mod tests;
+
+use std::{
+	collections::{BTreeSet, HashMap},
+	sync::{Arc, Mutex},
+};
+
+use conduwuit::{
+	implement,
+	utils::{stream::TryIgnore, ReadyExt, TryFutureExtExt},
+	Result,
+};
+use database::{Deserialized, Ignore, Interfix, Json, Map};
+use futures::StreamExt;
+use ruma::{
+	events::{
+		room::{
+			join_rules::{JoinRule, RoomJoinRulesEventContent},
+			member::{MembershipState, RoomMemberEventContent},
+		},
+		StateEventType,
+	},
+	OwnedMxcUri, OwnedUserId, UserId,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{globals, rooms, Dep};
+
+/// Index of the users people can find through the user directory: local
+/// users by their profile, and remote users sharing a room with a local user
+/// by their latest membership event. Users are found by prefixes of the words
+/// of their localpart and display name.
+pub struct Service {
+	/// Serializes changes to a user's entry and its words.
+	lock: Mutex<()>,
+	db: Data,
+	services: Services,
+}
+
+struct Services {
+	globals: Dep<globals::Service>,
+	state_accessor: Dep<rooms::state_accessor::Service>,
+	state_cache: Dep<rooms::state_cache::Service>,
+}
+
+struct Data {
+	tokenuserid: Arc<Map>,
+	userid_directoryentry: Arc<Map>,
+	userid_password: Arc<Map>,
+}
+
+/// What the directory shows of a user.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Entry {
+	pub displayname: Option<String>,
+	pub avatar_url: Option<OwnedMxcUri>,
+}
+
+/// Score of a search term equal to a word of a user.
+const EXACT_SCORE: u32 = 2;
+
+/// Score of a search term which only starts a word of a user.
+const PREFIX_SCORE: u32 = 1;
+
+/// Most words looked at for each search term, which keeps short terms like
+/// "a" cheap on large servers. Exact matches are looked up on their own, as
+/// they sort after the longer words they start.
+const TERM_MATCHES_MAX: usize = 1000;
+
+const WORD_MAX_LEN: usize = 50;
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			lock: Mutex::new(()),
+			db: Data {
+				tokenuserid: args.db["tokenuserid"].clone(),
+				userid_directoryentry: args.db["userid_directoryentry"].clone(),
+				userid_password: args.db["userid_password"].clone(),
+			},
+			services: Services {
+				globals: args.depend::<globals::Service>("globals"),
+				state_accessor: args
+					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
+				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
+			},
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Searches the directory for users with words starting with each word of
+/// `term`, leaving out users the sender may not see. A full user ID finds
+/// that user first, then others by its localpart. Results are ranked by how
+/// well they match; the flag tells whether there were more than `limit`.
+#[implement(Service)]
+pub async fn search(
+	&self,
+	sender: &UserId,
+	term: &str,
+	limit: usize,
+) -> (Vec<(OwnedUserId, Entry)>, bool) {
+	let user_id = UserId::parse(term.trim()).ok();
+	let term = user_id.as_ref().map_or(term, |user_id| user_id.localpart());
+
+	let mut matches = Vec::new();
+	for term in words(term).collect::<BTreeSet<_>>() {
+		matches.push(self.term_matches(&term).await);
+	}
+
+	let mut ranked = rank(matches);
+	if let Some(user_id) = user_id {
+		ranked.retain(|(other, _)| *other != user_id);
+		ranked.insert(0, (user_id, u32::MAX));
+	}
+
+	let mut results = Vec::with_capacity(limit.min(ranked.len()));
+	for (user_id, _) in ranked {
+		let Ok(entry) = self.entry(&user_id).await else {
+			continue;
+		};
+
+		if !self.visible_to(sender, &user_id).await {
+			continue;
+		}
+
+		if results.len() == limit {
+			return (results, true);
+		}
+
+		results.push((user_id, entry));
+	}
+
+	(results, false)
+}
+
+/// The directory entry of a user.
+#[implement(Service)]
+pub async fn entry(&self, user_id: &UserId) -> Result<Entry> {
+	self.db
+		.userid_directoryentry
+		.get(user_id)
+		.await
+		.deserialized()
+}
+
+/// Adds or replaces a user in the directory, unless they are deactivated.
+#[implement(Service)]
+pub fn set_entry(&self, user_id: &UserId, entry: &Entry) {
+	let _lock = self.lock.lock().expect("locked");
+	if self.is_deactivated(user_id) {
+		return;
+	}
+
+	let old = self.entry_blocking(user_id);
+	self.put(user_id, old.as_ref(), entry);
+}
+
+/// Updates the display name of an active local user, or of a remote user
+/// already in the directory.
+#[implement(Service)]
+pub fn set_displayname(&self, user_id: &UserId, displayname: Option<&str>) {
+	self.update(user_id, |entry| {
+		entry.displayname = displayname.map(ToOwned::to_owned);
+	});
+}
+
+/// Updates the avatar of an active local user, or of a remote user already
+/// in the directory.
+#[implement(Service)]
+pub fn set_avatar_url(&self, user_id: &UserId, avatar_url: Option<&OwnedMxcUri>) {
+	self.update(user_id, |entry| {
+		entry.avatar_url = avatar_url.cloned();
+	});
+}
+
+/// Keeps remote users in the directory while they share a room with a local
+/// user, by the profile of their latest join. Called once the membership is
+/// recorded.
+#[implement(Service)]
+pub async fn update_membership(&self, user_id: &UserId, content: &RoomMemberEventContent) {
+	if self.services.globals.user_is_local(user_id) {
+		return;
+	}
+
+	match content.membership {
+		| MembershipState::Join => self.set_entry(user_id, &Entry {
+			displayname: content.displayname.clone(),
+			avatar_url: content.avatar_url.clone(),
+		}),
+		| MembershipState::Leave | MembershipState::Ban => {
+			let joined = self
+				.services
+				.state_cache
+				.rooms_joined(user_id)
+				.boxed()
+				.next()
+				.await
+				.is_some();
+
+			if !joined {
+				self.remove(user_id);
+			}
+		},
+		| _ => {},
+	}
+}
+
+/// Removes a user from the directory.
+#[implement(Service)]
+pub fn remove(&self, user_id: &UserId) {
+	let _lock = self.lock.lock().expect("locked");
+	if let Some(old) = self.entry_blocking(user_id) {
+		for token in tokens(user_id, old.displayname.as_deref()) {
+			self.db.tokenuserid.del((&token, user_id));
+		}
+	}
+
+	self.db.userid_directoryentry.remove(user_id);
+}
+
+#[implement(Service)]
+fn update<F>(&self, user_id: &UserId, f: F)
+where
+	F: FnOnce(&mut Entry),
+{
+	let _lock = self.lock.lock().expect("locked");
+	if self.is_deactivated(user_id) {
+		return;
+	}
+
+	let old = self.entry_blocking(user_id);
+	if old.is_none() && !self.services.globals.user_is_local(user_id) {
+		return;
+	}
+
+	let mut entry = old.clone().unwrap_or_default();
+	f(&mut entry);
+	self.put(user_id, old.as_ref(), &entry);
+}
+
+#[implement(Service)]
+fn put(&self, user_id: &UserId, old: Option<&Entry>, entry: &Entry) {
+	let new_tokens = tokens(user_id, entry.displayname.as_deref());
+	if let Some(old) = old {
+		for token in tokens(user_id, old.displayname.as_deref()).difference(&new_tokens) {
+			self.db.tokenuserid.del((token, user_id));
+		}
+	}
+
+	for token in &new_tokens {
+		self.db.tokenuserid.put_raw((token, user_id), []);
+	}
+
+	self.db.userid_directoryentry.raw_put(user_id, Json(entry));
+}
+
+/// Whether a local user is deactivated, which keeps them out of the directory
+/// whatever becomes of their profile.
+#[implement(Service)]
+fn is_deactivated(&self, user_id: &UserId) -> bool {
+	self.db
+		.userid_password
+		.get_blocking(user_id)
+		.is_ok_and(|password| password.is_empty())
+}
+
+#[implement(Service)]
+fn entry_blocking(&self, user_id: &UserId) -> Option<Entry> {
+	self.db
+		.userid_directoryentry
+		.get_blocking(user_id)
+		.deserialized()
+		.ok()
+}
+
+/// Users with a word starting with `term`, scored by how well it matches.
+#[implement(Service)]
+async fn term_matches(&self, term: &str) -> HashMap<OwnedUserId, u32> {
+	let matches = self
+		.db
+		.tokenuserid
+		.keys_prefix(&(term, Interfix))
+		.ignore_err()
+		.take(TERM_MATCHES_MAX)
+		.ready_fold(HashMap::new(), |mut matches, (_, user_id): (Ignore, &UserId)| {
+			matches.insert(user_id.to_owned(), EXACT_SCORE);
+			matches
+		})
+		.await;
+
+	self.db
+		.tokenuserid
+		.keys_raw_prefix(term)
+		.ignore_err()
+		.ready_filter(|(token, _): &(&str, &UserId)| *token != term)
+		.take(TERM_MATCHES_MAX)
+		.ready_fold(matches, |mut matches, (_, user_id): (&str, &UserId)| {
+			matches.entry(user_id.to_owned()).or_insert(PREFIX_SCORE);
+			matches
+		})
+		.await
+}
+
+/// Whether the sender shares a room with the user, or the user is in a room
+/// anyone may join.
+#[implement(Service)]
+async fn visible_to(&self, sender: &UserId, user_id: &UserId) -> bool {
+	let state_cache = &self.services.state_cache;
+	if sender == user_id || state_cache.user_sees_user(sender, user_id).await {
+		return true;
+	}
+
+	state_cache
+		.rooms_joined(user_id)
+		.any(|room_id| {
+			self.services
+				.state_accessor
+				.room_state_get_content::<RoomJoinRulesEventContent>(
+					room_id,
+					&StateEventType::RoomJoinRules,
+					"",
+				)
+				.map_ok_or(false, |content| content.join_rule == JoinRule::Public)
+		})
+		.await
+}
+
+/// Users matching every search term, best first. Each term is scored by its
+/// best match among a user's words, and the scores summed.
+fn rank(matches: Vec<HashMap<OwnedUserId, u32>>) -> Vec<(OwnedUserId, u32)> {
+	let mut matches = matches.into_iter();
+	let Some(first) = matches.next() else {
+		return Vec::new();
+	};
+
+	let mut ranked: Vec<_> = matches
+		.fold(first, |mut ranked, term| {
+			ranked.retain(|user_id, _| term.contains_key(user_id));
+			for (user_id, score) in &mut ranked {
+				*score = score.saturating_add(term[user_id]);
+			}
+
+			ranked
+		})
+		.into_iter()
+		.collect();
+
+	ranked.sort_unstable_by(|(a, a_score), (b, b_score)| b_score.cmp(a_score).then(a.cmp(b)));
+	ranked
+}
+
+/// Words a user is found by: those of their localpart and display name.
+fn tokens(user_id: &UserId, displayname: Option<&str>) -> BTreeSet<String> {
+	words(user_id.localpart())
+		.chain(displayname.into_iter().flat_map(words))
+		.collect()
+}
+
+fn words(s: &str) -> impl Iterator<Item = String> + '_ {
+	s.split_terminator(|c: char| !c.is_alphanumeric())
+		.filter(|word| !word.is_empty() && word.len() <= WORD_MAX_LEN)
+		.map(str::to_lowercase)
+}
@@ -0,0 +1,68 @@
+#![cfg(test)]
+
+use std::collections::HashMap;
+
+use ruma::{user_id, OwnedUserId, UserId};
+
+use super::{rank, tokens, Entry, EXACT_SCORE, PREFIX_SCORE, TERM_MATCHES_MAX};
+use crate::tests::{offline_services, TempDir};
+
+#[test]
+fn user_tokens() {
+	let tokens = tokens(user_id!("@alice.smith:example.org"), Some("Alice Smith (she/her)"));
+	let tokens: Vec<_> = tokens.iter().map(String::as_str).collect();
+	assert_eq!(
+		tokens,
+		["alice", "her", "she", "smith"],
+		"words are lowercased and deduplicated"
+	);
+}
+
+#[test]
+fn rank_requires_every_term() {
+	let alice: OwnedUserId = user_id!("@alice:example.org").to_owned();
+	let alan: OwnedUserId = user_id!("@alan:example.org").to_owned();
+	let albert: OwnedUserId = user_id!("@albert:example.org").to_owned();
+
+	let al = HashMap::from([
+		(alice.clone(), PREFIX_SCORE),
+		(alan.clone(), PREFIX_SCORE),
+		(albert.clone(), PREFIX_SCORE),
+	]);
+	let smith = HashMap::from([(alice.clone(), EXACT_SCORE), (albert.clone(), PREFIX_SCORE)]);
+
+	let ranked = rank(vec![al, smith]);
+	assert_eq!(ranked, vec![
+		(alice, PREFIX_SCORE + EXACT_SCORE),
+		(albert, PREFIX_SCORE + PREFIX_SCORE),
+	]);
+
+	assert!(rank(Vec::new()).is_empty(), "no terms match nobody");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn term_matches_exact_past_prefix_limit() {
+	let dir = TempDir::new("user-directory");
+	let services = offline_services(&dir, "example.com")
+		.await
+		.expect("started services offline");
+
+	let directory = &services.user_directory;
+	for i in 0..=TERM_MATCHES_MAX {
+		let user_id = UserId::parse(format!("@alice{i}:remote.example.org")).expect("valid");
+		directory.set_entry(&user_id, &Entry::default());
+	}
+
+	let alice = user_id!("@alice:remote.example.org");
+	directory.set_entry(alice, &Entry::default());
+
+	let matches = directory.term_matches("alice").await;
+	assert_eq!(
+		matches.get(alice),
+		Some(&EXACT_SCORE),
+		"exact matches are found past the prefix matches"
+	);
+	assert_eq!(matches.len(), TERM_MATCHES_MAX.saturating_add(1), "prefix matches are limited");
+
+	services.stop_offline();
+}
@@ -1,7 +1,9 @@
 use std::{collections::BTreeMap, mem, mem::size_of, sync::Arc};
 
 use conduwuit::{
-	debug_warn, err, trace,
+	debug_warn, err,
+	result::LogErr,
+	trace,
 	utils::{self, stream::TryIgnore, string::Unquoted, ReadyExt},
 	Err, Error, Result, Server,
 };
@@ -14,12 +16,13 @@ use ruma::{
 		ignored_user_list::IgnoredUserListEvent, AnyToDeviceEvent, GlobalAccountDataEventType,
 	},
 	serde::Raw,
+	thirdparty::Medium,
 	DeviceId, KeyId, MilliSecondsSinceUnixEpoch, OneTimeKeyAlgorithm, OneTimeKeyId,
 	OneTimeKeyName, OwnedDeviceId, OwnedKeyId, OwnedMxcUri, OwnedUserId, RoomId, UInt, UserId,
 };
 use serde_json::json;
 
-use crate::{account_data, admin, globals, rooms, Dep};
+use crate::{account_data, admin, appservice, globals, rooms, sending, user_directory, Dep};
 
 pub struct Service {
 	services: Services,
@@ -31,9 +34,12 @@ struct Services {
 	db: Arc<Database>,
 	account_data: Dep<account_data::Service>,
 	admin: Dep<admin::Service>,
+	appservice: Dep<appservice::Service>,
 	globals: Dep<globals::Service>,
+	sending: Dep<sending::Service>,
 	state_accessor: Dep<rooms::state_accessor::Service>,
 	state_cache: Dep<rooms::state_cache::Service>,
+	user_directory: Dep<user_directory::Service>,
 }
 
 struct Data {
@@ -41,6 +47,7 @@ struct Data {
 	keyid_key: Arc<Map>,
 	onetimekeyid_onetimekeys: Arc<Map>,
 	openidtoken_expiresatuserid: Arc<Map>,
+	threepid_userid: Arc<Map>,
 	logintoken_expiresatuserid: Arc<Map>,
 	todeviceid_events: Arc<Map>,
 	token_userdeviceid: Arc<Map>,
@@ -55,7 +62,9 @@ struct Data {
 	userid_masterkeyid: Arc<Map>,
 	userid_password: Arc<Map>,
 	userid_selfsigningkeyid: Arc<Map>,
+	userid_shadowbanned: Arc<Map>,
 	userid_usersigningkeyid: Arc<Map>,
+	userthreepid_addedat: Arc<Map>,
 	useridprofilekey_value: Arc<Map>,
 }
 
@@ -67,16 +76,20 @@ impl crate::Service for Service {
 				db: args.db.clone(),
 				account_data: args.depend::<account_data::Service>("account_data"),
 				admin: args.depend::<admin::Service>("admin"),
+				appservice: args.depend::<appservice::Service>("appservice"),
 				globals: args.depend::<globals::Service>("globals"),
+				sending: args.depend::<sending::Service>("sending"),
 				state_accessor: args
 					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
 				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
+				user_directory: args.depend::<user_directory::Service>("user_directory"),
 			},
 			db: Data {
 				keychangeid_userid: args.db["keychangeid_userid"].clone(),
 				keyid_key: args.db["keyid_key"].clone(),
 				onetimekeyid_onetimekeys: args.db["onetimekeyid_onetimekeys"].clone(),
 				openidtoken_expiresatuserid: args.db["openidtoken_expiresatuserid"].clone(),
+				threepid_userid: args.db["threepid_userid"].clone(),
 				logintoken_expiresatuserid: args.db["logintoken_expiresatuserid"].clone(),
 				todeviceid_events: args.db["todeviceid_events"].clone(),
 				token_userdeviceid: args.db["token_userdeviceid"].clone(),
@@ -91,7 +104,9 @@ impl crate::Service for Service {
 				userid_masterkeyid: args.db["userid_masterkeyid"].clone(),
 				userid_password: args.db["userid_password"].clone(),
 				userid_selfsigningkeyid: args.db["userid_selfsigningkeyid"].clone(),
+				userid_shadowbanned: args.db["userid_shadowbanned"].clone(),
 				userid_usersigningkeyid: args.db["userid_usersigningkeyid"].clone(),
+				userthreepid_addedat: args.db["userthreepid_addedat"].clone(),
 				useridprofilekey_value: args.db["useridprofilekey_value"].clone(),
 			},
 		}))
@@ -126,6 +141,7 @@ impl Service {
 	/// Create a new user account on this homeserver.
 	#[inline]
 	pub fn create(&self, user_id: &UserId, password: Option<&str>) -> Result<()> {
+		self.services.appservice.index_user(user_id);
 		self.set_password(user_id, password)
 	}
 
@@ -142,7 +158,14 @@ impl Service {
 		// account is deactivated.
 		self.set_password(user_id, None)?;
 
-		// TODO: Unhook 3PID
+		// Unbind third-party identifiers so they can be used by another account
+		let threepids: Vec<_> = self.threepids(user_id).collect().await;
+		for (medium, address, _) in threepids {
+			self.remove_threepid(user_id, &medium, &address).await;
+		}
+
+		self.services.user_directory.remove(user_id);
+
 		Ok(())
 	}
 
@@ -172,6 +195,22 @@ impl Service {
 		self.services.globals.user_is_local(user_id) && self.is_active(user_id).await
 	}
 
+	/// Check if a user is shadow-banned: the events they send are dropped
+	/// while they are answered as if they were sent.
+	#[inline]
+	pub async fn is_shadow_banned(&self, user_id: &UserId) -> bool {
+		self.db.userid_shadowbanned.get(user_id).await.is_ok()
+	}
+
+	/// Shadow-bans a user, or lifts their shadow-ban.
+	pub fn set_shadow_banned(&self, user_id: &UserId, shadow_banned: bool) {
+		if shadow_banned {
+			self.db.userid_shadowbanned.insert(user_id, []);
+		} else {
+			self.db.userid_shadowbanned.remove(user_id);
+		}
+	}
+
 	/// Returns the number of users registered on this server.
 	#[inline]
 	pub async fn count(&self) -> usize { self.db.userid_password.count().await }
@@ -210,6 +249,12 @@ impl Service {
 		self.db.userid_password.get(user_id).await.deserialized()
 	}
 
+	/// Sets a password hash made elsewhere, such as by a server the user was
+	/// imported from.
+	pub fn set_password_hash(&self, user_id: &UserId, password_hash: &str) {
+		self.db.userid_password.insert(user_id, password_hash);
+	}
+
 	/// Hash and set the user's password to the Argon2 hash
 	pub fn set_password(&self, user_id: &UserId, password: Option<&str>) -> Result<()> {
 		password
@@ -234,6 +279,10 @@ impl Service {
 	/// Sets a new displayname or removes it if displayname is None. You still
 	/// need to nofify all rooms of this change.
 	pub fn set_displayname(&self, user_id: &UserId, displayname: Option<String>) {
+		self.services
+			.user_directory
+			.set_displayname(user_id, displayname.as_deref());
+
 		if let Some(displayname) = displayname {
 			self.db.userid_displayname.insert(user_id, displayname);
 		} else {
@@ -248,6 +297,10 @@ impl Service {
 
 	/// Sets a new avatar_url or removes it if avatar_url is None.
 	pub fn set_avatar_url(&self, user_id: &UserId, avatar_url: Option<OwnedMxcUri>) {
+		self.services
+			.user_directory
+			.set_avatar_url(user_id, avatar_url.as_ref());
+
 		if let Some(avatar_url) = avatar_url {
 			self.db.userid_avatarurl.insert(user_id, &avatar_url);
 		} else {
@@ -412,6 +465,13 @@ impl Service {
 		let count = self.services.globals.next_count().unwrap();
 		self.db.userid_lastonetimekeyupdate.raw_put(user_id, count);
 
+		self.services
+			.sending
+			.flush_appservices(user_id, &[])
+			.await
+			.log_err()
+			.ok();
+
 		Ok(())
 	}
 
@@ -468,6 +528,13 @@ impl Service {
 			.next()
 			.await;
 
+		self.services
+			.sending
+			.flush_appservices(user_id, &[])
+			.await
+			.log_err()
+			.ok();
+
 		one_time_key.ok_or_else(|| err!(Request(NotFound("No one-time-key found"))))
 	}
 
@@ -687,19 +754,30 @@ impl Service {
 	pub async fn mark_device_key_update(&self, user_id: &UserId) {
 		let count = self.services.globals.next_count().unwrap();
 
-		self.services
+		let rooms: Vec<_> = self
+			.services
 			.state_cache
 			.rooms_joined(user_id)
 			// Don't send key updates to unencrypted rooms
 			.filter(|room_id| self.services.state_accessor.is_encrypted_room(room_id))
-			.ready_for_each(|room_id| {
-				let key = (room_id, count);
-				self.db.keychangeid_userid.put_raw(key, user_id);
-			})
+			.map(ToOwned::to_owned)
+			.collect()
 			.await;
 
+		for room_id in &rooms {
+			let key = (room_id, count);
+			self.db.keychangeid_userid.put_raw(key, user_id);
+		}
+
 		let key = (user_id, count);
 		self.db.keychangeid_userid.put_raw(key, user_id);
+
+		self.services
+			.sending
+			.flush_appservices(user_id, &rooms)
+			.await
+			.log_err()
+			.ok();
 	}
 
 	pub async fn get_device_keys<'a>(
@@ -777,6 +855,23 @@ impl Service {
 	) {
 		let count = self.services.globals.next_count().unwrap();
 
+		if self.services.globals.user_is_local(target_user_id) {
+			let event = json!({
+				"type": event_type,
+				"sender": sender,
+				"content": content,
+				"to_user_id": target_user_id,
+				"to_device_id": target_device_id,
+			});
+
+			self.services
+				.sending
+				.send_to_device_appservices(target_user_id, &event)
+				.await
+				.log_err()
+				.ok();
+		}
+
 		let key = (target_user_id, target_device_id, count);
 		self.db.todeviceid_events.put(
 			key,
@@ -978,6 +1073,66 @@ impl Service {
 		Ok(user_id)
 	}
 
+	/// Binds a third-party identifier such as an email address to a user.
+	pub fn add_threepid(&self, user_id: &UserId, medium: &Medium, address: &str) {
+		let added_at = utils::millis_since_unix_epoch();
+		self.db
+			.threepid_userid
+			.put((medium.as_str(), address), user_id);
+		self.db
+			.userthreepid_addedat
+			.put((user_id, medium.as_str(), address), added_at);
+	}
+
+	/// Unbinds a third-party identifier from a user. Returns whether it was
+	/// bound to them.
+	pub async fn remove_threepid(
+		&self,
+		user_id: &UserId,
+		medium: &Medium,
+		address: &str,
+	) -> bool {
+		if self
+			.find_from_threepid(medium, address)
+			.await
+			.is_ok_and(|bound| bound == user_id)
+		{
+			self.db.threepid_userid.del((medium.as_str(), address));
+		}
+
+		let key = (user_id, medium.as_str(), address);
+		let was_bound = self.db.userthreepid_addedat.qry(&key).await.is_ok();
+		self.db.userthreepid_addedat.del(key);
+
+		was_bound
+	}
+
+	/// Lists the third-party identifiers bound to a user with the time they
+	/// were added at in milliseconds.
+	pub fn threepids<'a>(
+		&'a self,
+		user_id: &'a UserId,
+	) -> impl Stream<Item = (Medium, String, u64)> + Send + 'a {
+		let prefix = (user_id, Interfix);
+		self.db
+			.userthreepid_addedat
+			.stream_prefix(&prefix)
+			.ignore_err()
+			.map(|((_, medium, address), added_at): ((Ignore, &str, &str), u64)| {
+				(medium.into(), address.to_owned(), added_at)
+			})
+	}
+
+	/// Finds the user a third-party identifier is bound to.
+	pub async fn find_from_threepid(
+		&self,
+		medium: &Medium,
+		address: &str,
+	) -> Result<OwnedUserId> {
+		let key = (medium.as_str(), address);
+		self.db.threepid_userid.qry(&key).await.deserialized()
+	}
+
 	/// Gets a specific user profile key
 	pub async fn profile_key(
 		&self,